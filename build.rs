@@ -114,9 +114,25 @@ fn generate_manpages(out_dir: PathBuf) -> std::io::Result<()> {
 
     generate_command_manpage(crunchy_cli_core::Cli::command(), &out_dir, "")?;
     generate_command_manpage(crunchy_cli_core::Archive::command(), &out_dir, "archive")?;
+    generate_command_manpage(crunchy_cli_core::Check::command(), &out_dir, "check")?;
     generate_command_manpage(crunchy_cli_core::Download::command(), &out_dir, "download")?;
+    generate_command_manpage(crunchy_cli_core::Export::command(), &out_dir, "export")?;
+    generate_command_manpage(
+        crunchy_cli_core::ListDownloaded::command(),
+        &out_dir,
+        "list-downloaded",
+    )?;
     generate_command_manpage(crunchy_cli_core::Login::command(), &out_dir, "login")?;
+    generate_command_manpage(crunchy_cli_core::Probe::command(), &out_dir, "probe")?;
+    generate_command_manpage(crunchy_cli_core::Queue::command(), &out_dir, "queue")?;
+    generate_command_manpage(crunchy_cli_core::Repair::command(), &out_dir, "repair")?;
     generate_command_manpage(crunchy_cli_core::Search::command(), &out_dir, "search")?;
+    generate_command_manpage(
+        crunchy_cli_core::TemplateTest::command(),
+        &out_dir,
+        "template-test",
+    )?;
+    generate_command_manpage(crunchy_cli_core::Watch::command(), &out_dir, "watch")?;
 
     Ok(())
 }
@@ -1,28 +1,58 @@
+use crate::utils::artwork::ArtworkWriter;
+use crate::utils::checkpoint::MuxCheckpoint;
+use crate::utils::checksum::{verify_existing_checksum, Checksum};
+use crate::utils::clap::is_default_value;
 use crate::utils::context::Context;
 use crate::utils::download::{
-    DownloadBuilder, DownloadFormat, DownloadFormatMetadata, MergeBehavior,
+    DownloadBuilder, DownloadFormat, DownloadFormatMetadata, MergeBehavior, StreamOptions,
 };
-use crate::utils::ffmpeg::FFmpegPreset;
-use crate::utils::filter::{Filter, FilterMediaScope};
-use crate::utils::format::{Format, SingleFormat};
-use crate::utils::locale::{all_locale_in_locales, resolve_locales, LanguageTagging};
-use crate::utils::log::progress;
-use crate::utils::os::{free_file, has_ffmpeg, is_special_file};
-use crate::utils::parse::parse_url;
-use crate::utils::video::stream_data_from_stream;
+use crate::utils::event::CliEventSink;
+use crate::utils::ffmpeg::{AudioCodec, ExternalStream, FFmpegLog, FFmpegPreset};
+use crate::utils::filter::{Filter, FilterMediaScope, WatchFilter};
+use crate::utils::format::{
+    is_single_episode, Format, GroupBy, NamingScheme, SingleFormat, ANIME_TEMPLATE,
+    JELLYFIN_TEMPLATE, KODI_TEMPLATE, PLEX_TEMPLATE,
+};
+use crate::utils::hook::PostHook;
+use crate::utils::locale::{
+    all_locale_in_locales, resolve_locales, LanguageTagging, LocaleMismatch,
+};
+use crate::utils::log::{progress, LogFile};
+use crate::utils::nfo::NfoWriter;
+use crate::utils::os::{
+    check_dir_writable, free_file, has_ffmpeg, is_special_file, set_mtime, temp_directory,
+};
+use crate::utils::parse::parse_url_or_crunchylist;
+use crate::utils::pause::listen_for_pause_keys;
+use crate::utils::preset_map::PresetMap;
+use crate::utils::state::{record_failure, BatchState, BatchUrlStatus};
+use crate::utils::subtitle::{
+    parse_subtitle_encoding, SubtitleFormat, SubtitleOffset, SubtitleStyles, DEFAULT_SIGN_STYLES,
+};
+use crate::utils::summary::Summary;
+use crate::utils::timing::Phase;
+use crate::utils::video::{list_variants, max_resolution, stream_data_from_stream};
+use crate::utils::webhook::{self, WebhookOn};
 use crate::Execute;
+use anyhow::anyhow;
 use anyhow::bail;
 use anyhow::Result;
-use chrono::Duration;
+use chrono::{Duration, NaiveDate};
+use clap::{CommandFactory, FromArgMatches};
 use crunchyroll_rs::media::{Resolution, Subtitle};
 use crunchyroll_rs::Locale;
-use log::{debug, warn};
+use dialoguer::console::Term;
+use encoding_rs::{Encoding, UTF_8};
+use log::{debug, info, warn};
 use regex::Regex;
 use std::fmt::{Display, Formatter};
+use std::fs;
+use std::iter;
 use std::iter::zip;
 use std::ops::Sub;
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
+use std::sync::Arc;
 
 #[derive(Clone, Debug, clap::Parser)]
 #[clap(about = "Archive a video")]
@@ -49,9 +79,13 @@ pub struct Archive {
     #[arg(long_help = "Name of the output file. \
     If you use one of the following pattern they will get replaced:\n  \
       {title}                    → Title of the video\n  \
+      {episode_description}      → Description of the video\n  \
       {series_name}              → Name of the series\n  \
+      {series_description}       → Description of the series\n  \
       {season_name}              → Name of the season\n  \
       {audio}                    → Audio language of the video\n  \
+      {audio_locale}             → Alias for '{audio}'\n  \
+      {subtitle}                 → Subtitle language(s) of the video\n  \
       {width}                    → Width of the video\n  \
       {height}                   → Height of the video\n  \
       {season_number}            → Number of the season\n  \
@@ -64,9 +98,18 @@ pub struct Archive {
       {release_day}              → Release day of the video\n  \
       {series_id}                → ID of the series\n  \
       {season_id}                → ID of the season\n  \
-      {episode_id}               → ID of the episode")]
+      {episode_id}               → ID of the episode\n  \
+      {artist}                   → Artist of the music video or concert\n  \
+      {track}                    → Title of the music video or concert\n  \
+      {type}                     → Type of the video (episode, movie, music video or concert)\n\
+    Number placeholders are padded with zeros to a length of 2. \
+    A custom length can be set by appending it to the placeholder name, e.g. '{episode_number:03}'. \
+    With ':auto' (e.g. '{episode_number:auto}') the length is the digit count of the largest episode number of the season, but at least 2")]
     #[arg(short, long, default_value = "{title}.mkv")]
     pub(crate) output: String,
+    /// If no output template is given, so `output` is the default template.
+    #[arg(skip)]
+    pub(crate) default_output: bool,
     #[arg(help = "Name of the output file if the episode is a special")]
     #[arg(long_help = "Name of the output file if the episode is a special. \
     If not set, the '-o'/'--output' flag will be used as name template")]
@@ -77,6 +120,38 @@ pub struct Archive {
     This option only affects template options and not static characters.")]
     #[arg(long, default_value_t = false)]
     pub(crate) universal_output: bool,
+    #[arg(help = "Name season directories by the season title instead of the season number")]
+    #[arg(
+        long_help = "Name season directories by the season title instead of the season number. \
+    Replaces '{season_number}' in all directory components of the output template(s) with '{season_name}', the file name itself is not changed. \
+    Useful if the season numbers of a series are inconsistent (e.g. because a season is split into multiple cours)"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) season_folder_from_title: bool,
+    #[arg(help = "Directory structure of the output files. \
+    Supported are 'series', 'season' and 'flat'")]
+    #[arg(
+        long_help = "Directory structure of the output files, independent of the directories of the output template(s). Supported are: \n  \
+    series: '{series_name}/<file>'\n  \
+    season: '{series_name}/Season {season_number:02}/<file>'\n  \
+    flat: all files are stored in the same directory\n\
+    Only the leading directories of the output template which don't contain any placeholder are kept, the file name itself is not changed. \
+    E.g. with '--group-by season', '/media/anime/{series_name}/{title}.mkv' becomes '/media/anime/{series_name}/Season {season_number:02}/{title}.mkv'"
+    )]
+    #[arg(long)]
+    #[arg(value_parser = GroupBy::parse)]
+    pub(crate) group_by: Option<GroupBy>,
+    #[arg(help = "Use the output template of a media server naming scheme. \
+    Supported are 'plex', 'jellyfin', 'kodi' and 'anime'")]
+    #[arg(long_help = format!("Use a well-known output template instead of typing it via `-o`. Supported are: \n  \
+    plex: '{}'\n  \
+    jellyfin: '{}'\n  \
+    kodi: '{}'\n  \
+    anime: '{}'\n\
+    The file extension is '.mkv'. If an output template is given via `-o`, it's used instead of the naming scheme", PLEX_TEMPLATE, JELLYFIN_TEMPLATE, KODI_TEMPLATE, ANIME_TEMPLATE))]
+    #[arg(long)]
+    #[arg(value_parser = NamingScheme::parse)]
+    pub(crate) naming_scheme: Option<NamingScheme>,
 
     #[arg(help = "Video resolution")]
     #[arg(long_help = "The video resolution. \
@@ -87,6 +162,50 @@ pub struct Archive {
     #[arg(short, long, default_value = "best")]
     #[arg(value_parser = crate::utils::clap::clap_parse_resolution)]
     pub(crate) resolution: Resolution,
+    #[arg(
+        help = "Download the lowest video variant which has at least the given resolution (e.g. 720p)"
+    )]
+    #[arg(
+        long_help = "Download the lowest video variant which has at least the given resolution. \
+    The resolution can be specified like with `-r`/`--resolution` (e.g. 720p or 1280x720), a plain number (e.g. 720) is interpreted as height. \
+    If no variant meets the minimum, the best available variant is used. \
+    Takes precedence over 'best' and 'worst' of `-r`/`--resolution` and can't be used together with a specific resolution"
+    )]
+    #[arg(long)]
+    #[arg(value_parser = crate::utils::clap::clap_parse_min_resolution)]
+    pub(crate) min_resolution: Option<Resolution>,
+    #[arg(help = "Only download video variants which are at most the given number of pixels high")]
+    #[arg(
+        long_help = "Only download video variants which are at most the given number of pixels high. \
+    Variants which are higher are ignored, `-r`/`--resolution` and `--min-resolution` choose among the remaining variants (e.g. 'best' chooses the largest variant within the bounds). \
+    Can be combined with `--max-width`. If no variant fits, the smallest available variant is used"
+    )]
+    #[arg(long)]
+    pub(crate) max_height: Option<u64>,
+    #[arg(help = "Only download video variants which are at most the given number of pixels wide")]
+    #[arg(
+        long_help = "Only download video variants which are at most the given number of pixels wide. \
+    Variants which are wider are ignored, `-r`/`--resolution` and `--min-resolution` choose among the remaining variants (e.g. 'best' chooses the largest variant within the bounds). \
+    Can be combined with `--max-height`. If no variant fits, the smallest available variant is used"
+    )]
+    #[arg(long)]
+    pub(crate) max_width: Option<u64>,
+    #[arg(
+        help = "Download the video variant whose bitrate is closest to the given value (in kbps)"
+    )]
+    #[arg(
+        long_help = "Download the video variant whose bitrate is closest to the given value (in kbps). \
+    Takes precedence over `-r`/`--resolution`. Use `--list` to see all available variants and `-v` to see which one was chosen"
+    )]
+    #[arg(long)]
+    pub(crate) bitrate: Option<u64>,
+    #[arg(help = "List the available video variants of every video instead of downloading it")]
+    #[arg(
+        long_help = "List the available video variants (resolution, bitrate and codecs) of every video instead of downloading it. \
+    The listed bitrate can be passed to `--bitrate` to download exactly this variant"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) list: bool,
 
     #[arg(
         help = "Sets the behavior of the stream merging. Valid behaviors are 'auto', 'sync', 'audio' and 'video'"
@@ -114,6 +233,19 @@ pub struct Archive {
     )]
     #[arg(long, default_value_t = 4)]
     pub(crate) merge_sync_precision: u32,
+    #[arg(
+        help = "Store all audios in one output file ('combined') or every audio in its own file ('separate')"
+    )]
+    #[arg(
+        long_help = "Store all audios in one output file ('combined') or every audio in its own file ('separate'). \
+    With 'separate', one output file is created for every audio, containing the video, this audio and all subtitles. \
+    The video is only downloaded once and used for all files. \
+    The output template must contain the '{audio}' placeholder so that every file gets its own name. \
+    Can only be used if `--merge` is set to 'audio' or 'sync'"
+    )]
+    #[arg(long, default_value = "combined", conflicts_with = "mux_into")]
+    #[arg(value_parser = AudioLayout::parse)]
+    pub(crate) audio_layout: AudioLayout,
 
     #[arg(
         help = "Specified which language tagging the audio and subtitle tracks and language specific format options should have. \
@@ -145,6 +277,70 @@ pub struct Archive {
     )]
     #[arg(long)]
     pub(crate) ffmpeg_threads: Option<usize>,
+    #[arg(
+        help = "Json or toml file with additional ffmpeg output arguments for specific episodes"
+    )]
+    #[arg(
+        long_help = "Json or toml file with additional ffmpeg output arguments for specific episodes, e.g. '{\"S01E05\": \"-c:v libx264 -vf yadif\"}'. \
+    Episodes are identified by their id (e.g. 'G6JQ1WJ2R'), by season and episode number (e.g. 'S01E05') or by episode number only (e.g. '5'). \
+    If multiple keys match an episode, the most specific one (in this order) is used. \
+    The arguments are appended to the ones of `--ffmpeg-preset`, options which are given in both take the value of the file. \
+    Episodes without a matching key only use `--ffmpeg-preset`"
+    )]
+    #[arg(long)]
+    pub(crate) preset_map: Option<PathBuf>,
+    #[arg(skip)]
+    loaded_preset_map: PresetMap,
+
+    #[arg(help = "Abort if a stream would be re-encoded instead of only being copied")]
+    #[arg(
+        long_help = "Abort if a stream would be re-encoded instead of only being copied. \
+    Some options (e.g. burning in subtitles because the output container doesn't support softsubs, `--loudnorm` or `--cut-intro`) require the streams to be re-encoded, which takes considerably longer. \
+    With this flag set, the command is aborted in this case, before anything is downloaded. \
+    Streams which are re-encoded because of the chosen `--ffmpeg-preset` are not affected"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) strict_copy: bool,
+    #[arg(
+        help = "When the output of ffmpeg is shown. Valid modes are 'hidden', 'on-error' (default) and 'always'"
+    )]
+    #[arg(
+        long_help = "When the output of ffmpeg is shown while generating the output file. \
+    'hidden' never shows it, if ffmpeg fails only its exit status is reported. \
+    'on-error' shows it if ffmpeg fails. \
+    'always' additionally logs every line while ffmpeg is running, which is visible with `--verbose`"
+    )]
+    #[arg(long, default_value = "on-error")]
+    #[arg(value_parser = FFmpegLog::parse)]
+    pub(crate) ffmpeg_log: FFmpegLog,
+    #[arg(
+        help = "Normalize the audio loudness (EBU R128). Optionally takes the target loudness in LUFS, default is -23"
+    )]
+    #[arg(
+        long_help = "Normalize the audio loudness with ffmpeg's 'loudnorm' filter, following the EBU R128 recommendation. \
+    Optionally takes the target integrated loudness in LUFS (between -70 and -5), default is -23 LUFS. \
+    Because the audio has to be re-encoded for this, it only has an effect if the output gets re-encoded anyway (e.g. when using `--ffmpeg-preset` or burning in subtitles). \
+    If the streams are only remuxed, the normalization is skipped"
+    )]
+    #[arg(long, num_args = 0..=1, default_missing_value = "-23", allow_negative_numbers = true)]
+    #[arg(value_parser = crate::utils::clap::clap_parse_loudness_target)]
+    pub(crate) loudnorm: Option<f32>,
+    #[arg(help = "Codec of the output audio. Valid codecs are 'copy', 'aac' and 'opus'")]
+    #[arg(
+        long_help = "Codec of the output audio. Valid codecs are 'copy', 'aac' and 'opus'. \
+    By default, the audio is copied if possible and encoded with aac if it has to be re-encoded (e.g. because of `--loudnorm` or `--cut-intro`). \
+    'copy' fails if the audio has to be re-encoded and can't be combined with `--loudnorm`, `--cut-intro` or `--cut-outro`. 'aac' and 'opus' always re-encode the audio. \
+    'opus' can't be used with mov / mp4-audio outputs"
+    )]
+    #[arg(long)]
+    #[arg(value_parser = AudioCodec::parse)]
+    pub(crate) audio_codec: Option<AudioCodec>,
+    #[arg(help = "Bitrate of the output audio in kbps if it's re-encoded")]
+    #[arg(long_help = "Bitrate of the output audio in kbps (e.g. 128). \
+    Only used if the audio is re-encoded, either because of `--audio-codec` or because an option like `--loudnorm` requires it. \
+    Default is the default of the ffmpeg encoder")]
+    #[arg(long, value_parser = clap::value_parser!(u32).range(8..=1024))]
+    pub(crate) audio_bitrate: Option<u32>,
 
     #[arg(
         help = "Set which subtitle language should be set as default / auto shown when starting a video"
@@ -166,9 +362,133 @@ pub struct Archive {
     #[arg(long, default_value_t = false)]
     pub(crate) include_chapters: bool,
 
+    #[arg(help = "Cut the intro out of the video. \
+    This re-encodes the video and changes its timestamps")]
+    #[arg(
+        long_help = "Cut the intro out of the video, based on the same timing information which is used for chapters. \
+    This is destructive: the video and audio are re-encoded, the subtitles and chapters are shifted accordingly and the timestamps of the output don't match the original episode anymore. \
+    Episodes without intro timing are downloaded as they are"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) cut_intro: bool,
+    #[arg(help = "Cut the outro (credits) out of the video. \
+    This re-encodes the video and changes its timestamps")]
+    #[arg(
+        long_help = "Cut the outro (credits) out of the video, based on the same timing information which is used for chapters. \
+    This is destructive: the video and audio are re-encoded, the subtitles and chapters are shifted accordingly and the timestamps of the output don't match the original episode anymore. \
+    Episodes without outro timing are downloaded as they are"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) cut_outro: bool,
+
     #[arg(help = "Omit closed caption subtitles in the downloaded file")]
     #[arg(long, default_value_t = false)]
     pub(crate) no_closed_caption: bool,
+    #[arg(help = "Prefer the subtitles matching the script of a dub over the regular ones")]
+    #[arg(
+        long_help = "Prefer the subtitles matching the script of a dub over the regular ones. \
+    For some dubs, Crunchyroll provides closed captions which are a transcript of the dub, while the regular subtitles of the same language are translated from the japanese script. \
+    If a subtitle language is also archived as audio and its dub provides such closed captions, only they are added for this language. \
+    Has no effect if the dub doesn't provide them"
+    )]
+    #[arg(long, default_value_t = false, conflicts_with = "no_closed_caption")]
+    pub(crate) prefer_dub_subs: bool,
+
+    #[arg(help = "Add the requested audios and subtitles to an existing matroska file")]
+    #[arg(
+        long_help = "Add the requested audios and subtitles to a copy of an existing matroska file instead of downloading the video. \
+    Audios and subtitles which are already existing in the file are skipped. \
+    The streams of the existing file are copied as they are, so this can't be used together with `--ffmpeg-preset` or `--loudnorm`. \
+    The copy is written to the path specified with `-o`/`--output`, which must not be the existing file itself. \
+    Only works if the given url(s) resolve to a single episode"
+    )]
+    #[arg(long)]
+    pub(crate) mux_into: Option<PathBuf>,
+
+    #[arg(help = "Format of the subtitles. Valid formats are 'ass', 'srt' and 'vtt'")]
+    #[arg(
+        long_help = "Format in which the subtitles are stored in the output file. Valid formats are 'ass' (default, the format Crunchyroll provides), 'srt' and 'vtt'. \
+    When converting to 'srt' or 'vtt', all styling is stripped and overlapping dialogues are merged into a single cue. \
+    Only works with matroska / '.mkv' output files, burnt-in subtitles are always rendered from the original ass subtitles"
+    )]
+    #[arg(long, default_value_t = SubtitleFormat::default())]
+    #[arg(value_parser = SubtitleFormat::parse)]
+    pub(crate) subtitle_format: SubtitleFormat,
+    #[arg(
+        help = "Charset of the subtitles, e.g. 'shift_jis' or 'windows-1252'. Default is 'utf-8'"
+    )]
+    #[arg(
+        long_help = "Charset the subtitles are encoded with, e.g. 'shift_jis' or 'windows-1252'. Default is 'utf-8'. \
+    Characters which can't be represented in the charset are replaced with '?'. \
+    Containers like mkv and mp4 always store subtitles as utf-8, so this can only be used together with `--raw`, to encode the subtitle files stored by it"
+    )]
+    #[arg(long)]
+    #[arg(value_parser = parse_subtitle_encoding)]
+    pub(crate) subtitle_encoding: Option<&'static Encoding>,
+
+    #[arg(
+        help = "Shift the timings of the subtitles by the given milliseconds. \
+    Can be set for a specific subtitle language via '<language>=<milliseconds>'"
+    )]
+    #[arg(
+        long_help = "Shift the timings of the subtitles by the given milliseconds, e.g. '-250' to show them earlier or '500' to show them later. \
+    Some subtitles of Crunchyroll are offset by a fixed amount relative to the video. \
+    To only shift the subtitles of a specific language, use '<language>=<milliseconds>' (e.g. 'de-DE=500'). \
+    Can be used multiple times, an offset for a specific language takes precedence over an offset for all languages. \
+    Subtitles which would start before the video are shown from its beginning"
+    )]
+    #[arg(long, allow_negative_numbers = true)]
+    #[arg(value_parser = SubtitleOffset::parse)]
+    pub(crate) subtitle_offset: Vec<SubtitleOffset>,
+    #[arg(help = "Add an audio file to the output. Must be in format '<file>:<locale>'")]
+    #[arg(
+        long_help = "Add an audio file (e.g. a better dub) to the output, in addition to the audio from Crunchyroll. \
+    Must be in format '<file>:<locale>' (e.g. 'dub.m4a:de-DE'), the locale is used as language of the audio track. \
+    Can be used multiple times. If the file is a video, only its audio is used. \
+    The file is added as it is, so it must match the timings of the episode. \
+    Only works if the url(s) resolve to a single episode"
+    )]
+    #[arg(long, value_parser = ExternalStream::parse)]
+    pub(crate) external_audio: Vec<ExternalStream>,
+    #[arg(help = "Add a subtitle file to the output. Must be in format '<file>:<locale>'")]
+    #[arg(
+        long_help = "Add a subtitle file (e.g. a fansub) to the output, in addition to the subtitles from Crunchyroll. \
+    Must be in format '<file>:<locale>' (e.g. 'fansub.ass:en-US'), the locale is used as language of the subtitle track. \
+    Can be used multiple times. The file is added as it is, so it must match the timings of the episode. \
+    Only works if the url(s) resolve to a single episode and the output container supports soft subtitles"
+    )]
+    #[arg(long, value_parser = ExternalStream::parse)]
+    pub(crate) external_subtitle: Vec<ExternalStream>,
+    #[arg(
+        help = "Which lines of the subtitles are kept. Valid values are 'all' (default) and 'dialogue-only'"
+    )]
+    #[arg(
+        long_help = "Which lines of the subtitles are kept. Valid values are 'all' (default) and 'dialogue-only'. \
+    With 'dialogue-only', all lines whose style is a sign, typesetting or song style (e.g. 'Signs', 'OS', 'OP', 'Song') are removed, see `--sign-styles`. \
+    Timings and all other lines are unchanged"
+    )]
+    #[arg(long, default_value = "all")]
+    #[arg(value_parser = SubtitleStyles::parse)]
+    pub(crate) subtitle_styles: SubtitleStyles,
+    #[arg(help = format!("Style names which are removed with `--subtitle-styles dialogue-only`. \
+    Default is: {}", DEFAULT_SIGN_STYLES.join(", ")))]
+    #[arg(long_help = format!("Style names which are removed with `--subtitle-styles dialogue-only`, separated by commas. \
+    A line is removed if any word of its style name is one of the given names (case-insensitive), e.g. 'signs' matches 'Signs_Top'. \
+    Default is: {}", DEFAULT_SIGN_STYLES.join(", ")))]
+    #[arg(long, value_delimiter = ',')]
+    pub(crate) sign_styles: Vec<String>,
+
+    #[arg(
+        help = "Skip episodes which aren't available with exactly the requested audio and subtitle languages"
+    )]
+    #[arg(
+        long_help = "Skip episodes which aren't available with exactly the requested audio and subtitle languages, instead of downloading them with other or fewer languages. \
+    Skipped episodes are listed in the summary"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) strict_locale: bool,
+    #[arg(skip)]
+    strict_subtitle_locale: bool,
 
     #[arg(help = "Skip files which are already existing by their name")]
     #[arg(long, default_value_t = false)]
@@ -186,6 +506,203 @@ pub struct Archive {
     #[arg(help = "Skip special episodes")]
     #[arg(long, default_value_t = false)]
     pub(crate) skip_specials: bool,
+    #[arg(help = "Only download episodes which aired on or after the given date (YYYY-MM-DD)")]
+    #[arg(long)]
+    pub(crate) since: Option<NaiveDate>,
+    #[arg(help = "Only download episodes which aired on or before the given date (YYYY-MM-DD)")]
+    #[arg(long)]
+    pub(crate) until: Option<NaiveDate>,
+    #[arg(help = "Only download episodes whose title matches the given regex (case-insensitive)")]
+    #[arg(long, value_parser = crate::utils::clap::clap_parse_title_regex)]
+    pub(crate) episode_matches: Option<Regex>,
+    #[arg(help = "Only download episodes whose title contains the given text (case-insensitive)")]
+    #[arg(long, value_parser = crate::utils::clap::clap_parse_title_contains)]
+    pub(crate) title_contains: Option<Regex>,
+    #[arg(help = "Only archive episodes which were already watched with the logged in account")]
+    #[arg(
+        long_help = "Only archive episodes which were already watched with the logged in account. \
+    An episode counts as watched if it was fully watched or if at least `--watched-threshold` percent of it was watched. \
+    Requires to be logged in with an account"
+    )]
+    #[arg(long, default_value_t = false, conflicts_with = "unwatched_only")]
+    pub(crate) watched_only: bool,
+    #[arg(help = "Only archive episodes which weren't watched yet with the logged in account")]
+    #[arg(
+        long_help = "Only archive episodes which weren't watched yet with the logged in account. \
+    Episodes which were only partially watched, less than `--watched-threshold` percent, are considered unwatched. \
+    Requires to be logged in with an account"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) unwatched_only: bool,
+    #[arg(
+        help = "Percentage of an episode which must be watched until it counts as watched for `--watched-only` and `--unwatched-only`"
+    )]
+    #[arg(long, default_value_t = 90, value_parser = clap::value_parser!(u8).range(1..=100))]
+    pub(crate) watched_threshold: u8,
+
+    #[arg(help = "Record which of the given urls were already processed completely in a file")]
+    #[arg(
+        long_help = "Record which of the given urls were already processed completely in a file. \
+    The file is updated after every url, so if the batch gets aborted, it can be continued with `--resume-batch` afterwards. \
+    Without `--resume-batch`, the progress of an already existing file gets reset"
+    )]
+    #[arg(long)]
+    pub(crate) state_file: Option<PathBuf>,
+    #[arg(help = "Skip urls which are marked as completed in the file given via `--state-file`")]
+    #[arg(long, default_value_t = false, requires = "state_file")]
+    pub(crate) resume_batch: bool,
+    #[arg(
+        help = "Only retry the videos which failed in previous runs according to the file given via `--state-file`"
+    )]
+    #[arg(
+        long_help = "Only retry the videos which failed in previous runs according to the file given via `--state-file`. \
+    If a state file is used, a video which fails to download doesn't abort the run but is recorded in the state file and the remaining videos are downloaded. \
+    With this flag, only the recorded videos are downloaded again, no url has to be given. Videos which now succeed are removed from the state file"
+    )]
+    #[arg(
+        long,
+        default_value_t = false,
+        requires = "state_file",
+        conflicts_with = "resume_batch"
+    )]
+    pub(crate) retry_failed: bool,
+    #[arg(
+        help = "Only download seasons of series which weren't downloaded before according to the file given via `--state-file`"
+    )]
+    #[arg(
+        long_help = "Only download seasons of series which weren't downloaded before according to the file given via `--state-file`. \
+    All seasons of which episodes were downloaded are recorded in the state file, on the next run only seasons which aren't recorded yet are downloaded. \
+    Useful to regularly check a series for new seasons (cours). \
+    Only applies to series urls"
+    )]
+    #[arg(long, default_value_t = false, requires = "state_file")]
+    pub(crate) only_new_seasons: bool,
+    #[arg(
+        help = "Download already existing files again if Crunchyroll updated the video since it was downloaded"
+    )]
+    #[arg(
+        long_help = "Download already existing files again if Crunchyroll updated the video since it was downloaded. \
+    A fingerprint of the streams (their bitrate, codecs, resolution and duration) of every downloaded video is recorded in the file given via `--state-file`. \
+    When a video is skipped because its file already exists, the fingerprint of its current streams is compared against the recorded one and the file gets replaced if they differ. \
+    Only the stream metadata is requested for this, nothing is downloaded for unchanged videos. \
+    Files which were downloaded before a fingerprint was recorded are considered as unchanged"
+    )]
+    #[arg(
+        long,
+        default_value_t = false,
+        requires_all = ["state_file", "skip_existing"]
+    )]
+    pub(crate) check_updates: bool,
+
+    #[arg(help = "Write NFO files for media servers like Kodi or Jellyfin")]
+    #[arg(
+        long_help = "Write NFO files for media servers like Kodi or Jellyfin. \
+    Besides a NFO file next to every episode, a 'tvshow.nfo' with the series metadata is written to the series directory and a 'season.nfo' to the season directory. \
+    If the directory of the output file contains a season placeholder (e.g. '{series_name}/Season {season_number}/{title}.mkv'), its parent is used as series directory, otherwise the directory of the output file. \
+    Existing NFO files are only rewritten if their content has changed"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) write_nfo: bool,
+    #[arg(help = "Download the series poster and fanart for media servers like Kodi or Jellyfin")]
+    #[arg(
+        long_help = "Download the series artwork for media servers like Kodi or Jellyfin. \
+    The largest available tall poster is stored as 'poster.jpg' and the largest wide poster as 'fanart.jpg' in the series directory, which is determined like with `--write-nfo`. \
+    Crunchyroll provides no banners and no season posters, so neither 'banner.jpg' nor 'seasonNN-poster.jpg' are written. \
+    Already existing images are kept"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) write_artwork: bool,
+    #[arg(
+        help = "Embed metadata like title, series and release date as tags into the output file"
+    )]
+    #[arg(
+        long_help = "Embed metadata as tags into the output file, which can be read by most players and media servers. \
+    The title, series name, season number, episode number, release date and description are embedded. \
+    This complements `--write-nfo`, which writes the metadata into separate files"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) embed_metadata: bool,
+
+    #[arg(help = "Write a checksum file next to every downloaded file. \
+    Valid algorithms are 'sha256' and 'crc32'")]
+    #[arg(
+        long_help = "Write a checksum file next to every downloaded file (e.g. 'My Episode.mkv.sha256'), which can be verified with tools like `sha256sum -c`. \
+    Valid algorithms are 'sha256' and 'crc32'. \
+    The checksum is calculated by reading the finished file once more after ffmpeg has written it, which takes additional disk I/O for every file. \
+    If used together with `--skip-existing`, already existing files which have a checksum file are verified against it and downloaded again if the checksum doesn't match"
+    )]
+    #[arg(long)]
+    #[arg(value_parser = Checksum::parse)]
+    pub(crate) checksum: Option<Checksum>,
+
+    #[arg(
+        help = "Set the modification time of every downloaded file to the release date of the video"
+    )]
+    #[arg(
+        long_help = "Set the modification time of every downloaded file to the release date of the video (e.g. the air date of an episode). \
+    Useful for media servers and file browsers which sort by modification time. \
+    Files of videos without a known release date are not changed"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) set_mtime: bool,
+    #[arg(
+        help = "Also set the access time of every downloaded file to the release date of the video"
+    )]
+    #[arg(long, default_value_t = false, requires = "set_mtime")]
+    pub(crate) set_atime: bool,
+
+    #[arg(
+        help = "Store the downloaded streams together with a manifest instead of muxing them into the output file"
+    )]
+    #[arg(
+        long_help = "Store the downloaded streams together with a manifest instead of muxing them into the output file. \
+    The streams are written to a directory next to the output file (e.g. 'My Episode.mkv.raw'). \
+    The output file can be created from it later on with `crunchy-cli repair`, without downloading anything again"
+    )]
+    #[arg(long, default_value_t = false, conflicts_with_all = ["checksum", "mux_into"])]
+    pub(crate) raw: bool,
+
+    #[arg(help = "Run a command after every successfully downloaded file")]
+    #[arg(
+        long_help = "Run a command after every successfully downloaded file, e.g. to trigger a library scan of a media server. \
+    The command is executed by the system shell ('sh' or 'cmd' on Windows). \
+    Information about the downloaded file are passed as environment variables: \
+    CRUNCHY_OUTPUT, CRUNCHY_TITLE, CRUNCHY_SERIES_ID, CRUNCHY_SERIES_NAME, CRUNCHY_SERIES_DESCRIPTION, CRUNCHY_SEASON_ID, CRUNCHY_SEASON_NAME, CRUNCHY_SEASON_NUMBER, CRUNCHY_EPISODE_ID, CRUNCHY_EPISODE_NUMBER, CRUNCHY_EPISODE_DESCRIPTION and CRUNCHY_AUDIO. \
+    If the command fails, a warning is shown but the download continues"
+    )]
+    #[arg(long)]
+    pub(crate) post_hook: Option<String>,
+    #[arg(help = "Seconds after which the `--post-hook` command gets killed")]
+    #[arg(long, default_value_t = 300, requires = "post_hook")]
+    pub(crate) post_hook_timeout: u64,
+    #[arg(help = "Abort if the `--post-hook` command fails instead of only showing a warning")]
+    #[arg(long, default_value_t = false, requires = "post_hook")]
+    pub(crate) post_hook_fail: bool,
+    #[arg(help = "Send json events about completed and failed downloads to the given url")]
+    #[arg(
+        long_help = "Send json events about completed and failed downloads to the given url via POST requests, e.g. to get notified on a headless server. \
+    Events are sent for every completed or failed episode and when the run finishes. \
+    Failed requests are retried a few times, but never fail the archive. \
+    Custom headers (`--header`) aren't sent to the webhook, only the download traffic proxy of `--proxy` is used. \
+    Pending events are sent for at most 30 seconds when the run finishes. \
+    See the README for the payload schema"
+    )]
+    #[arg(long)]
+    pub(crate) webhook: Option<String>,
+    #[arg(help = "Which events are sent to `--webhook`. \
+    Valid options: complete (completed episodes and runs), fail (failed episodes and runs), all")]
+    #[arg(long, default_value = "all", requires = "webhook")]
+    #[arg(value_parser = WebhookOn::parse)]
+    pub(crate) webhook_on: WebhookOn,
+
+    #[arg(help = "Interactively select the seasons and episodes to download")]
+    #[arg(
+        long_help = "Interactively select the seasons and episodes to download. \
+    After the url is resolved, a checklist of all seasons and afterwards of all episodes of the selected seasons is shown. \
+    Requires an interactive terminal and can't be used together with `-y`/`--yes` or `-q`/`--quiet`"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) interactive: bool,
 
     #[arg(help = "Skip any interactive input")]
     #[arg(short, long, default_value_t = false)]
@@ -195,13 +712,138 @@ pub struct Archive {
     #[arg(short, long, default_value_t = num_cpus::get())]
     pub(crate) threads: usize,
 
-    #[arg(help = "Crunchyroll series url(s)")]
-    #[arg(required = true)]
+    #[arg(help = "Crunchyroll series or crunchylist url(s)")]
+    #[arg(required_unless_present = "retry_failed")]
     pub(crate) urls: Vec<String>,
 }
 
+impl Archive {
+    /// Parses the command from `args` like [`clap::Parser::try_parse_from`], but additionally
+    /// records if the default output template is used.
+    pub(crate) fn try_parse_args(args: Vec<String>) -> Result<Self, clap::Error> {
+        let matches = Self::command().try_get_matches_from(args)?;
+        let mut archive = Self::from_arg_matches(&matches)?;
+        archive.default_output = is_default_value(&matches, "output");
+        Ok(archive)
+    }
+}
+
 impl Execute for Archive {
     fn pre_check(&mut self) -> Result<()> {
+        if let Some(preset_map) = &self.preset_map {
+            self.loaded_preset_map = match PresetMap::load(preset_map) {
+                Ok(preset_map) => preset_map,
+                Err(e) => bail!(
+                    "Failed to load preset map {}: {}",
+                    preset_map.to_string_lossy(),
+                    e
+                ),
+            }
+        }
+
+        if self.audio_bitrate.is_some() && self.audio_codec == Some(AudioCodec::Copy) {
+            bail!("`--audio-bitrate` can't be used together with `--audio-codec copy`")
+        }
+        if self.audio_codec == Some(AudioCodec::Copy) {
+            // all of them filter the audio, which requires it to be re-encoded
+            if self.loudnorm.is_some() {
+                bail!("`--loudnorm` can't be used together with `--audio-codec copy`")
+            } else if self.cut_intro || self.cut_outro {
+                bail!("`--cut-intro` and `--cut-outro` can't be used together with `--audio-codec copy`")
+            }
+        }
+        if let Some(audio_codec) = &self.audio_codec {
+            for output in [Some(&self.output), self.output_specials.as_ref()]
+                .into_iter()
+                .flatten()
+            {
+                let extension = Path::new(output)
+                    .extension()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .to_lowercase();
+                if !audio_codec.supports_container(&extension) {
+                    bail!(
+                        "The audio codec '{}' isn't supported by '.{}' output files",
+                        audio_codec,
+                        extension
+                    )
+                }
+            }
+        }
+        if self.interactive {
+            if self.yes {
+                bail!("`--interactive` can't be used together with `-y`/`--yes` or `-q`/`--quiet`")
+            } else if !Term::stdout().is_term() {
+                bail!("`--interactive` requires an interactive terminal")
+            }
+        }
+
+        if let (Some(since), Some(until)) = (self.since, self.until) {
+            if since > until {
+                bail!("`--since` must not be after `--until`")
+            }
+        }
+
+        if self.min_resolution.is_some()
+            && self.resolution.height != u64::MAX
+            && self.resolution.height != u64::MIN
+        {
+            bail!("`--min-resolution` can't be used together with a specific resolution for `-r`/`--resolution`")
+        }
+        if let (Some(min_resolution), Some(max_height)) = (&self.min_resolution, self.max_height) {
+            if min_resolution.height > max_height {
+                bail!("`--min-resolution` must not be higher than `--max-height`")
+            }
+        }
+
+        if self.checksum.is_some()
+            && [Some(&self.output), self.output_specials.as_ref()]
+                .into_iter()
+                .flatten()
+                .any(|o| is_special_file(o) || o == "-")
+        {
+            bail!("`--checksum` can only be used if the output is a regular file")
+        }
+
+        if self.raw
+            && [Some(&self.output), self.output_specials.as_ref()]
+                .into_iter()
+                .flatten()
+                .any(|o| is_special_file(o) || o == "-")
+        {
+            bail!("`--raw` can only be used if the output is a regular file")
+        }
+
+        if self.cut_intro || self.cut_outro {
+            warn!("`--cut-intro` and `--cut-outro` re-encode the video and remove parts of it. The timestamps of the output files won't match the original episodes")
+        }
+
+        if self.strict_copy {
+            // matroska supports softsubs, so subtitles are never burned in
+            // the episodes without an entry in the preset map only use the preset
+            for extra_args in iter::once(&vec![]).chain(self.loaded_preset_map.all_args()) {
+                let stream_args = StreamOptions {
+                    ffmpeg_preset: self.ffmpeg_preset.clone().unwrap_or_default(),
+                    ffmpeg_extra_args: extra_args.clone(),
+                    mux_into: self.mux_into.is_some(),
+                    audio_codec: self.audio_codec.clone(),
+                    loudnorm: self.loudnorm,
+                    // the cut ranges aren't known yet, but only if something is cut at all
+                    // decides if the streams are re-encoded
+                    cut: (self.cut_intro || self.cut_outro).then(String::new),
+                    ..Default::default()
+                }
+                .build()?;
+                if !stream_args.reencoded.is_empty() {
+                    bail!(
+                        "The streams would be re-encoded ({}), aborting as `--strict-copy` is set",
+                        stream_args.reasons.join(", ")
+                    )
+                }
+            }
+        }
+
         if !has_ffmpeg() {
             bail!("FFmpeg is needed to run this command")
         } else if PathBuf::from(&self.output)
@@ -232,13 +874,110 @@ impl Execute for Archive {
         {
             bail!("`--include-chapters` can only be used if `--merge` is set to 'audio' or 'sync'")
         }
+        if (self.cut_intro || self.cut_outro)
+            && !matches!(self.merge, MergeBehavior::Sync)
+            && !matches!(self.merge, MergeBehavior::Audio)
+        {
+            bail!("`--cut-intro` and `--cut-outro` can only be used if `--merge` is set to 'audio' or 'sync'")
+        }
+        if self.audio_layout == AudioLayout::Separate {
+            if !matches!(self.merge, MergeBehavior::Sync)
+                && !matches!(self.merge, MergeBehavior::Audio)
+            {
+                bail!("`--audio-layout separate` can only be used if `--merge` is set to 'audio' or 'sync'")
+            }
+            // without the audio in the file name, all audio outputs would have the same name
+            for output in [Some(&self.output), self.output_specials.as_ref()]
+                .into_iter()
+                .flatten()
+            {
+                if !output.contains("{audio}") && !output.contains("{audio_locale}") {
+                    bail!(
+                        "`--audio-layout separate` requires the output template '{}' to contain the '{{audio}}' placeholder",
+                        output
+                    )
+                }
+            }
+        }
+
+        for external in &self.external_audio {
+            external.check_stream_type("Audio")?
+        }
+        for external in &self.external_subtitle {
+            external.check_stream_type("Subtitle")?
+        }
+
+        if let Some(mux_into) = &self.mux_into {
+            if !mux_into.is_file() {
+                bail!("'{}' does not exist", mux_into.to_string_lossy())
+            } else if mux_into.extension().unwrap_or_default().to_string_lossy() != "mkv" {
+                bail!("`--mux-into` only supports matroska / '.mkv' files")
+            } else if self.urls.len() > 1 {
+                bail!("`--mux-into` can only be used with a single url")
+            } else if self.ffmpeg_preset.is_some() {
+                bail!("`--mux-into` can't be used together with `--ffmpeg-preset`")
+            } else if self.loudnorm.is_some() {
+                bail!("`--mux-into` can't be used together with `--loudnorm`")
+            } else if self.audio_codec.is_some() || self.audio_bitrate.is_some() {
+                bail!(
+                    "`--mux-into` can't be used together with `--audio-codec` or `--audio-bitrate`"
+                )
+            } else if matches!(self.merge, MergeBehavior::Sync) {
+                bail!("`--mux-into` can't be used if `--merge` is set to 'sync'")
+            } else if self.include_chapters {
+                bail!("`--mux-into` can't be used together with `--include-chapters`, the chapters of the existing file are kept")
+            } else if self.cut_intro || self.cut_outro {
+                bail!("`--mux-into` can't be used together with `--cut-intro` or `--cut-outro`")
+            }
+        }
+
+        if self.subtitle_encoding.is_some_and(|e| e != UTF_8) && !self.raw {
+            bail!("`--subtitle-encoding` can only be used together with `--raw`, muxed subtitles are always stored as utf-8")
+        }
+        if self.subtitle_format == SubtitleFormat::Vtt
+            && self.subtitle_encoding.is_some_and(|e| e != UTF_8)
+        {
+            warn!("WebVTT subtitles must be utf-8 encoded, some players may not be able to read them with `--subtitle-encoding`")
+        }
 
         if !self.skip_existing_method.is_empty() && !self.skip_existing {
             warn!("`--skip-existing-method` has no effect if `--skip-existing` is not set")
         }
 
+        if let Some(naming_scheme) = &self.naming_scheme {
+            // the naming scheme is only a shortcut for the output template, an explicitly given
+            // template has precedence
+            if self.default_output {
+                self.output = naming_scheme.template("mkv")
+            } else {
+                warn!("`--naming-scheme` is ignored as an output template is given via `-o`")
+            }
+        }
+
+        if let Some(group_by) = &self.group_by {
+            let group =
+                |o: &String| Format::group_by_template(o, group_by, self.season_folder_from_title);
+            self.output = group(&self.output);
+            self.output_specials = self.output_specials.as_ref().map(group);
+        }
+
+        if self.season_folder_from_title {
+            let output = Format::season_folder_from_title(&self.output);
+            if output == self.output && self.group_by.is_none() {
+                warn!("`--season-folder-from-title` has no effect as the output template has no directory containing '{{season_number}}'")
+            }
+            self.output = output;
+            self.output_specials = self
+                .output_specials
+                .as_ref()
+                .map(Format::season_folder_from_title);
+        }
+
         self.audio = all_locale_in_locales(self.audio.clone());
         self.subtitle = all_locale_in_locales(self.subtitle.clone());
+        // the default are all subtitles, which are only available for very few episodes. thus
+        // `--strict-locale` only checks subtitles which were set explicitly
+        self.strict_subtitle_locale = self.strict_locale && self.subtitle != Locale::all();
 
         if let Some(language_tagging) = &self.language_tagging {
             self.audio = resolve_locales(&self.audio);
@@ -264,26 +1003,98 @@ impl Execute for Archive {
     }
 
     async fn execute(self, ctx: Context) -> Result<()> {
+        if let Some(url) = &self.webhook {
+            webhook::start(
+                ctx.webhook_client.clone(),
+                url.clone(),
+                self.webhook_on.clone(),
+                "archive",
+            )
+        }
+
+        // all streams are downloaded into the temp directory first
+        check_dir_writable(&temp_directory()).map_err(|e| {
+            anyhow!(
+                "{} (the temp directory can be changed via the `CRUNCHY_CLI_TEMP_DIR` environment variable)",
+                e
+            )
+        })?;
+
         if !ctx.crunchy.premium().await {
             warn!("You may not be able to download all requested videos when logging in anonymously or using a non-premium account")
         }
 
+        let post_hook = self.post_hook.clone().map(|command| PostHook {
+            command,
+            timeout: std::time::Duration::from_secs(self.post_hook_timeout),
+            fail_on_error: self.post_hook_fail,
+        });
+
+        let mut nfo_writer = self.write_nfo.then(NfoWriter::default);
+        let mut artwork_writer = self
+            .write_artwork
+            .then(|| ArtworkWriter::new(ctx.client.clone()));
+
+        let mut summary = Summary::default();
+        listen_for_pause_keys();
+
+        let mut batch_state = match &self.state_file {
+            Some(path) if self.retry_failed => Some(BatchState::load(path.clone(), &[])?),
+            Some(path) if self.resume_batch => Some(BatchState::load(path.clone(), &self.urls)?),
+            Some(path) => Some(BatchState::new(path.clone(), &self.urls)?),
+            None => None,
+        };
+
         let mut parsed_urls = vec![];
 
-        for (i, url) in self.urls.clone().into_iter().enumerate() {
+        let urls = if self.retry_failed {
+            let failed_urls = batch_state.as_ref().unwrap().failed_urls();
+            if failed_urls.is_empty() {
+                info!("No failed videos found in the state file");
+                return Ok(());
+            }
+            info!("Retrying {} failed video(s)", failed_urls.len());
+            failed_urls
+        } else {
+            self.urls.clone()
+        };
+
+        for (i, url) in urls.into_iter().enumerate() {
+            if batch_state
+                .as_ref()
+                .is_some_and(|state| state.is_completed(&url))
+            {
+                info!("Skipping url {} as it was already completed", i + 1);
+                continue;
+            }
+
             let progress_handler = progress!("Parsing url {}", i + 1);
-            match parse_url(&ctx.crunchy, url.clone(), true).await {
-                Ok((media_collection, url_filter)) => {
+            match parse_url_or_crunchylist(&ctx.crunchy, url.clone(), true).await {
+                Ok(parsed) => {
                     progress_handler.stop(format!("Parsed url {}", i + 1));
-                    parsed_urls.push((media_collection, url_filter))
+                    // the url is only completed if all items of a crunchylist are completed
+                    let len = parsed.len();
+                    for (j, (media_collection, url_filter)) in parsed.into_iter().enumerate() {
+                        // the urls of failed videos aren't tracked as batch urls
+                        let last_of_url = j + 1 == len && !self.retry_failed;
+                        parsed_urls.push((
+                            i,
+                            url.clone(),
+                            media_collection,
+                            url_filter,
+                            last_of_url,
+                        ))
+                    }
                 }
                 Err(e) => bail!("url {} could not be parsed: {}", url, e),
             };
         }
 
-        for (i, (media_collection, url_filter)) in parsed_urls.into_iter().enumerate() {
+        let parsed_urls_len = parsed_urls.len();
+        for (i, url, media_collection, url_filter, last_of_url) in parsed_urls {
             let progress_handler = progress!("Fetching series details");
-            let single_format_collection = Filter::new(
+            let metadata_timer = Phase::Metadata.start(format!("url {}", i + 1));
+            let mut single_format_collection = Filter::new(
                 url_filter,
                 self.audio.clone(),
                 self.subtitle.clone(),
@@ -330,23 +1141,111 @@ impl Execute for Archive {
                 self.skip_specials,
                 ctx.crunchy.premium().await,
             )
+            .air_date_range(self.since, self.until)
+            .title_patterns(
+                [self.episode_matches.clone(), self.title_contains.clone()]
+                    .into_iter()
+                    .flatten()
+                    .collect(),
+            )
+            .watch_status(
+                (self.watched_only || self.unwatched_only).then(|| WatchFilter {
+                    crunchy: ctx.crunchy.clone(),
+                    watched: self.watched_only,
+                    threshold: self.watched_threshold,
+                }),
+            )
+            .fetch_series_description(
+                self.post_hook.is_some()
+                    || Format::has_series_description_fmt(&self.output)
+                    || self
+                        .output_specials
+                        .as_ref()
+                        .is_some_and(Format::has_series_description_fmt),
+            )
+            .only_new_seasons(
+                self.only_new_seasons
+                    .then(|| batch_state.as_ref().map(|s| s.known_seasons()))
+                    .flatten(),
+            )
+            .auto_padding(
+                Format::has_auto_padding_fmt(&self.output)
+                    || self
+                        .output_specials
+                        .as_ref()
+                        .is_some_and(Format::has_auto_padding_fmt),
+            )
             .visit(media_collection)
             .await?;
 
             if single_format_collection.is_empty() {
                 progress_handler.stop(format!("Skipping url {} (no matching videos found)", i + 1));
+                if let Some(batch_state) = batch_state.as_mut().filter(|_| last_of_url) {
+                    batch_state.set_status(&url, BatchUrlStatus::Completed)?
+                }
                 continue;
             }
+            metadata_timer.stop();
             progress_handler.stop(format!("Loaded series information for url {}", i + 1));
 
+            if self.interactive {
+                single_format_collection.interactive_select();
+                if single_format_collection.is_empty() {
+                    info!("Nothing selected for url {}", i + 1);
+                    continue;
+                }
+            }
+
+            if self.list {
+                for single_formats in single_format_collection.into_iter() {
+                    for single_format in single_formats {
+                        list_variants(&single_format).await?
+                    }
+                }
+                continue;
+            }
+
             single_format_collection.full_visual_output();
 
+            let single_episode = is_single_episode(parsed_urls_len, single_format_collection.len());
+            if self.mux_into.is_some() && !single_episode {
+                bail!("`--mux-into` only works if the url(s) resolve to a single episode")
+            }
+            if (!self.external_audio.is_empty() || !self.external_subtitle.is_empty())
+                && !single_episode
+            {
+                bail!("`--external-audio` and `--external-subtitle` only work if the url(s) resolve to a single episode")
+            }
+
             let download_builder =
                 DownloadBuilder::new(ctx.client.clone(), ctx.rate_limiter.clone())
                     .default_subtitle(self.default_subtitle.clone())
                     .download_fonts(self.include_fonts)
                     .ffmpeg_preset(self.ffmpeg_preset.clone().unwrap_or_default())
                     .ffmpeg_threads(self.ffmpeg_threads)
+                    .strict_copy(self.strict_copy)
+                    .ffmpeg_log(self.ffmpeg_log.clone())
+                    .external_audios(self.external_audio.clone())
+                    .external_subtitles(self.external_subtitle.clone())
+                    .loudnorm(self.loudnorm)
+                    .audio_codec(self.audio_codec.clone())
+                    .audio_bitrate(self.audio_bitrate)
+                    .subtitle_format(self.subtitle_format.clone())
+                    .subtitle_encoding(self.subtitle_encoding.filter(|e| *e != UTF_8))
+                    .subtitle_offsets(self.subtitle_offset.clone())
+                    .subtitle_drop_styles(match self.subtitle_styles {
+                        SubtitleStyles::All => vec![],
+                        SubtitleStyles::DialogueOnly if self.sign_styles.is_empty() => {
+                            DEFAULT_SIGN_STYLES.iter().map(|s| s.to_string()).collect()
+                        }
+                        SubtitleStyles::DialogueOnly => self.sign_styles.clone(),
+                    })
+                    .raw(self.raw)
+                    .include_chapters(self.include_chapters)
+                    .cut_intro(self.cut_intro)
+                    .cut_outro(self.cut_outro)
+                    .event_sink(Some(Arc::new(CliEventSink::default())))
+                    .mux_into(self.mux_into.clone())
                     .output_format(Some("matroska".to_string()))
                     .audio_sort(Some(self.audio.clone()))
                     .subtitle_sort(Some(self.subtitle.clone()))
@@ -367,32 +1266,108 @@ impl Execute for Archive {
                         zip(self.subtitle.clone(), self.output_subtitle_locales.clone()).collect(),
                     );
 
+            let season_ids = single_format_collection.season_ids();
+
             for single_formats in single_format_collection.into_iter() {
-                let (download_formats, mut format) = get_format(&self, &single_formats).await?;
+                let metadata_timer = Phase::Metadata.start(&single_formats[0].title);
+                let (download_formats, mut format) = match get_format(&self, &single_formats).await
+                {
+                    Ok(ok) => ok,
+                    Err(e) => {
+                        if let Some(mismatch) = e.downcast_ref::<LocaleMismatch>() {
+                            warn!("Skipping {}", mismatch);
+                            summary.locale_mismatch(mismatch.title.clone());
+                            continue;
+                        }
+                        record_failure(batch_state.as_mut(), &single_formats[0], e)?;
+                        continue;
+                    }
+                };
+                metadata_timer.stop();
 
-                let mut downloader = download_builder.clone().build();
+                let mut downloader = download_builder
+                    .clone()
+                    .container_tags(if self.embed_metadata {
+                        format.container_tags()
+                    } else {
+                        vec![]
+                    })
+                    .ffmpeg_extra_args(
+                        self.loaded_preset_map
+                            .args_for(&single_formats[0])
+                            .cloned()
+                            .unwrap_or_default(),
+                    )
+                    .build();
+                let fingerprint = self.check_updates.then(|| {
+                    download_formats
+                        .iter()
+                        .map(|f| f.fingerprint())
+                        .collect::<Vec<String>>()
+                        .join(",")
+                });
                 for download_format in download_formats {
                     downloader.add_format(download_format)
                 }
 
-                let formatted_path = if format.is_special() {
-                    format.format_path(
-                        self.output_specials
-                            .as_ref()
-                            .map_or((&self.output).into(), |so| so.into()),
-                        self.universal_output,
-                        self.language_tagging.as_ref(),
-                    )
+                // with the separate audio layout, every audio gets its own output. the output of the
+                // first audio is handled like the regular output and the others are added to it
+                let audio_formats: Vec<Format> = if self.audio_layout == AudioLayout::Separate {
+                    let mut audio_formats: Vec<Format> = format
+                        .locales
+                        .iter()
+                        .map(|locale| {
+                            let mut audio_format = format.clone();
+                            audio_format.locales = vec![locale.clone()];
+                            audio_format
+                        })
+                        .collect();
+                    audio_formats
+                        .sort_by_key(|f| self.audio.iter().position(|l| l == &f.locales[0].0));
+                    audio_formats
                 } else {
-                    format.format_path(
-                        (&self.output).into(),
-                        self.universal_output,
-                        self.language_tagging.as_ref(),
-                    )
+                    vec![]
                 };
+                let output_format = audio_formats.first().unwrap_or(&format);
+                let template = if output_format.is_special() {
+                    self.output_specials.as_ref().unwrap_or(&self.output)
+                } else {
+                    &self.output
+                };
+                let formatted_path = output_format.format_path(
+                    template.into(),
+                    self.universal_output,
+                    self.language_tagging.as_ref(),
+                );
                 let (mut path, changed) = free_file(formatted_path.clone());
+                // a previous run was interrupted after muxing the existing file, so it's finished
+                // instead of being downloaded again
+                let resumed = changed && MuxCheckpoint::exists(&formatted_path);
+                // the existing file is replaced if crunchyroll updated the video since it was
+                // downloaded
+                let updated = !resumed
+                    && changed
+                    && self.skip_existing
+                    && match (&mut batch_state, &fingerprint) {
+                        (Some(batch_state), Some(fingerprint)) => {
+                            batch_state.is_updated(&single_formats[0].url(), fingerprint)?
+                        }
+                        _ => false,
+                    };
 
-                if changed && self.skip_existing {
+                if resumed {
+                    debug!(
+                        "Resuming already muxed file '{}'",
+                        formatted_path.to_string_lossy()
+                    );
+                    path.clone_from(&formatted_path)
+                } else if updated {
+                    info!(
+                        "'{}' was updated on Crunchyroll, downloading it again",
+                        formatted_path.to_string_lossy()
+                    );
+                    path.clone_from(&formatted_path)
+                } else if changed && self.skip_existing {
                     let mut skip = true;
 
                     if !self.skip_existing_method.is_empty() {
@@ -407,7 +1382,7 @@ impl Execute for Archive {
                                 .contains(&SkipExistingMethod::Subtitle);
 
                             let audio_differ = if method_audio {
-                                format
+                                output_format
                                     .locales
                                     .iter()
                                     .any(|(a, _)| !audio_locales.contains(a))
@@ -415,7 +1390,7 @@ impl Execute for Archive {
                                 false
                             };
                             let subtitle_differ = if method_subtitle {
-                                format
+                                output_format
                                     .locales
                                     .clone()
                                     .into_iter()
@@ -441,11 +1416,26 @@ impl Execute for Archive {
                         }
                     }
 
+                    if skip && !verify_existing_checksum(self.checksum.as_ref(), &formatted_path)? {
+                        skip = false;
+                        path.clone_from(&formatted_path)
+                    }
+
                     if skip {
                         debug!(
                             "Skipping already existing file '{}'",
                             formatted_path.to_string_lossy()
                         );
+                        summary.skipped();
+                        continue;
+                    }
+                }
+
+                // the output directory is checked before anything is downloaded, as writing the
+                // output file only happens after all streams were downloaded
+                if !is_special_file(&path) && path.to_string_lossy() != "-" {
+                    if let Err(e) = check_dir_writable(path.parent().unwrap_or(Path::new(""))) {
+                        record_failure(batch_state.as_mut(), &single_formats[0], e.into())?;
                         continue;
                     }
                 }
@@ -465,16 +1455,127 @@ impl Execute for Archive {
                     })
                 }
 
-                format.visual_output(&path);
+                // all files which are created for the episode, with their format
+                let mut outputs = vec![(path.clone(), audio_formats.first().unwrap_or(&format))];
+                if !audio_formats.is_empty() {
+                    downloader
+                        .add_audio_output(path.clone(), audio_formats[0].locales[0].0.clone());
+                    for audio_format in &audio_formats[1..] {
+                        let formatted_audio_path = audio_format.format_path(
+                            template.into(),
+                            self.universal_output,
+                            self.language_tagging.as_ref(),
+                        );
+                        let audio_path = if resumed || updated {
+                            formatted_audio_path
+                        } else {
+                            free_file(formatted_audio_path).0
+                        };
+                        downloader.add_audio_output(
+                            audio_path.clone(),
+                            audio_format.locales[0].0.clone(),
+                        );
+                        outputs.push((audio_path, audio_format));
+                    }
+                }
+
+                if let Some(mux_into) = &self.mux_into {
+                    if fs::canonicalize(mux_into)? == fs::canonicalize(&path).unwrap_or_default() {
+                        bail!("The output file must not be the same file as specified with `--mux-into`")
+                    }
+                }
+
+                let log_file = LogFile::open(&path);
+                log_file.clone().sync_scope(|| format.visual_output(&path));
+
+                if let Err(e) = log_file.clone().scope(downloader.download(&path)).await {
+                    record_failure(batch_state.as_mut(), &single_formats[0], e)?;
+                    continue;
+                }
+                summary.downloaded();
+
+                // the messages of the post steps are part of the log file of the episode too
+                log_file
+                    .scope(async {
+                        for (output, output_format) in &outputs {
+                            if let Some(checksum) = &self.checksum {
+                                checksum.write_sidecar(output)?;
+                            }
 
-                downloader.download(&path).await?
+                            if self.set_mtime {
+                                set_mtime(output, output_format.release_date(), self.set_atime)?
+                            }
+
+                            if let Some(nfo_writer) = &mut nfo_writer {
+                                nfo_writer
+                                    .write(template, output, &single_formats[0], output_format)
+                                    .await?
+                            }
+                            if let Some(artwork_writer) = &mut artwork_writer {
+                                artwork_writer
+                                    .write(template, output, &single_formats[0])
+                                    .await?
+                            }
+
+                            if let Some(post_hook) = &post_hook {
+                                post_hook.run(output, output_format).await?
+                            }
+                        }
+                        Ok::<(), anyhow::Error>(())
+                    })
+                    .await?;
+
+                MuxCheckpoint::remove(&path);
+                if let Some(batch_state) = &mut batch_state {
+                    if let Some(fingerprint) = fingerprint {
+                        batch_state.set_fingerprint(single_formats[0].url(), fingerprint)?
+                    }
+                    batch_state.remove_failed(&single_formats[0].url())?
+                }
+                webhook::episode_completed(
+                    &single_formats[0],
+                    outputs.into_iter().map(|(output, _)| output).collect(),
+                );
+            }
+
+            if let Some(batch_state) = &mut batch_state {
+                batch_state.add_known_seasons(season_ids)?;
+                if last_of_url {
+                    batch_state.set_status(&url, BatchUrlStatus::Completed)?
+                }
             }
         }
 
+        if let Some(failed) = batch_state
+            .map(|s| s.failed_urls().len())
+            .filter(|failed| *failed > 0)
+        {
+            bail!(
+                "{} video(s) failed to download, retry them with `--retry-failed`",
+                failed
+            )
+        }
+
         Ok(())
     }
 }
 
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) enum AudioLayout {
+    Combined,
+    Separate,
+}
+
+impl AudioLayout {
+    fn parse(s: &str) -> Result<Self, String> {
+        match s.to_lowercase().as_str() {
+            "combined" => Ok(Self::Combined),
+            "separate" => Ok(Self::Separate),
+            _ => Err(format!("invalid audio layout '{}'", s)),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub(crate) enum SkipExistingMethod {
     Audio,
@@ -514,8 +1615,15 @@ async fn get_format(
 
     for single_format in single_formats {
         let stream = single_format.stream().await?;
-        let Some((video, audio, _)) =
-            stream_data_from_stream(&stream, &archive.resolution, None).await?
+        let Some((video, audio, _)) = stream_data_from_stream(
+            &stream,
+            &archive.resolution,
+            archive.min_resolution.as_ref(),
+            max_resolution(archive.max_width, archive.max_height).as_ref(),
+            archive.bitrate,
+            None,
+        )
+        .await?
         else {
             if single_format.is_episode() {
                 bail!(
@@ -564,6 +1672,58 @@ async fn get_format(
         stream.invalidate().await?
     }
 
+    if archive.prefer_dub_subs {
+        for locale in &archive.subtitle {
+            let has_dub_subtitle = format_pairs.iter().any(|(f, _, _, subtitles)| {
+                &f.audio == locale
+                    && f.audio != Locale::ja_JP
+                    && subtitles.iter().any(|(s, cc)| *cc && &s.locale == locale)
+            });
+            if !has_dub_subtitle {
+                debug!(
+                    "{} has no {} subtitles matching the dub, keeping the regular ones",
+                    single_formats[0].title, locale
+                );
+                continue;
+            }
+            // only the closed captions of the dub itself are kept for this language
+            let keep = |audio: &Locale, subtitle: &Subtitle, cc: bool| {
+                &subtitle.locale != locale || (audio == locale && cc)
+            };
+            for (f, _, _, subtitles) in format_pairs.iter_mut() {
+                subtitles.retain(|(s, cc)| keep(&f.audio, s, *cc))
+            }
+            for (f, _, subtitles) in single_format_to_format_pairs.iter_mut() {
+                subtitles.retain(|(s, cc)| keep(&f.audio, s, *cc))
+            }
+        }
+    }
+
+    if archive.strict_locale {
+        let mut reasons = vec![];
+        for locale in &archive.audio {
+            if !format_pairs.iter().any(|(f, _, _, _)| &f.audio == locale) {
+                reasons.push(format!("no {} audio", locale))
+            }
+        }
+        if archive.strict_subtitle_locale {
+            for locale in &archive.subtitle {
+                if !format_pairs
+                    .iter()
+                    .any(|(_, _, _, subtitles)| subtitles.iter().any(|(s, _)| &s.locale == locale))
+                {
+                    reasons.push(format!("no {} subtitles", locale))
+                }
+            }
+        }
+        if !reasons.is_empty() {
+            bail!(LocaleMismatch {
+                title: single_formats[0].title.clone(),
+                reasons,
+            })
+        }
+    }
+
     let mut download_formats = vec![];
 
     match archive.merge {
@@ -573,7 +1733,11 @@ async fn get_format(
                     video: (video, single_format.audio.clone()),
                     audios: vec![(audio, single_format.audio.clone())],
                     subtitles,
-                    metadata: DownloadFormatMetadata { skip_events: None },
+                    forced_subtitle: None,
+                    metadata: DownloadFormatMetadata {
+                        skip_events: None,
+                        lyrics: single_format.is_music(),
+                    },
                 })
             }
         }
@@ -592,12 +1756,14 @@ async fn get_format(
                 .iter()
                 .flat_map(|(_, _, _, subtitles)| subtitles.clone())
                 .collect(),
+            forced_subtitle: None,
             metadata: DownloadFormatMetadata {
-                skip_events: if archive.include_chapters {
+                skip_events: if archive.include_chapters || archive.cut_intro || archive.cut_outro {
                     format_pairs.first().unwrap().0.skip_events().await?
                 } else {
                     None
                 },
+                lyrics: format_pairs.first().unwrap().0.is_music(),
             },
         }),
         MergeBehavior::Auto | MergeBehavior::Sync => {
@@ -633,12 +1799,17 @@ async fn get_format(
                                 video: (video, single_format.audio.clone()),
                                 audios: vec![(audio, single_format.audio.clone())],
                                 subtitles,
+                                forced_subtitle: None,
                                 metadata: DownloadFormatMetadata {
-                                    skip_events: if archive.include_chapters {
+                                    skip_events: if archive.include_chapters
+                                        || archive.cut_intro
+                                        || archive.cut_outro
+                                    {
                                         single_format.skip_events().await?
                                     } else {
                                         None
                                     },
+                                    lyrics: single_format.is_music(),
                                 },
                             },
                         ));
@@ -0,0 +1,193 @@
+use crate::utils::context::Context;
+use crate::utils::filter::url_filtered_episodes;
+use crate::utils::log::progress;
+use crate::utils::parse::parse_url;
+use crate::Execute;
+use anyhow::{bail, Result};
+use crunchyroll_rs::{Episode, Locale};
+use serde::Serialize;
+
+#[derive(Clone, Debug, Default)]
+pub enum CheckOutput {
+    #[default]
+    Table,
+    Json,
+}
+
+impl CheckOutput {
+    pub fn parse(s: &str) -> Result<CheckOutput, String> {
+        Ok(match s.to_lowercase().as_str() {
+            "table" => CheckOutput::Table,
+            "json" => CheckOutput::Json,
+            _ => return Err(format!("'{}' is not a valid output format", s)),
+        })
+    }
+}
+
+#[derive(Debug, clap::Parser)]
+#[clap(
+    about = "Check if episodes can be downloaded with the current account without downloading them"
+)]
+#[command(arg_required_else_help(true))]
+pub struct Check {
+    #[arg(help = format!("Audio languages to check. Can be used multiple times. \
+    Available languages are: {}", Locale::all().into_iter().map(|l| l.to_string()).collect::<Vec<String>>().join(", ")))]
+    #[arg(short, long, default_values_t = vec![crate::utils::locale::system_locale()])]
+    audio: Vec<Locale>,
+    #[arg(help = format!("Subtitle languages to check. Can be used multiple times. \
+    Available languages are: {}", Locale::all().into_iter().map(|l| l.to_string()).collect::<Vec<String>>().join(", ")))]
+    #[arg(short, long)]
+    subtitle: Vec<Locale>,
+
+    #[arg(help = "Don't request the streams of the episodes")]
+    #[arg(long_help = "Don't request the streams of the episodes. \
+    This is faster, but episodes which aren't available in your region or are blocked for other reasons might be reported as downloadable")]
+    #[arg(long, default_value_t = false)]
+    skip_stream_check: bool,
+
+    #[arg(
+        help = "Format in which the result is printed. Valid formats are 'table' (default) and 'json'"
+    )]
+    #[arg(short, long, default_value = "table")]
+    #[arg(value_parser = CheckOutput::parse)]
+    format: CheckOutput,
+
+    #[arg(help = "Url(s) to Crunchyroll series, seasons or episodes")]
+    #[arg(required = true)]
+    urls: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct CheckedEpisode {
+    series: String,
+    season_number: u32,
+    episode_number: String,
+    title: String,
+    id: String,
+    audio: Locale,
+    downloadable: bool,
+    reasons: Vec<String>,
+}
+
+impl Execute for Check {
+    async fn execute(self, ctx: Context) -> Result<()> {
+        let premium = ctx.crunchy.premium().await;
+        let mut checked = vec![];
+
+        for (i, url) in self.urls.iter().enumerate() {
+            let progress_handler = progress!("Parsing url {}", i + 1);
+            let (media_collection, url_filter) =
+                match parse_url(&ctx.crunchy, url.clone(), true).await {
+                    Ok(ok) => ok,
+                    Err(e) => bail!("url {} could not be parsed: {}", url, e),
+                };
+            progress_handler.stop(format!("Parsed url {}", i + 1));
+
+            let progress_handler = progress!("Checking episodes of url {}", i + 1);
+            for episode in url_filtered_episodes(media_collection, &url_filter, i + 1).await? {
+                for audio in &self.audio {
+                    checked.push(self.check_episode(&episode, audio, premium).await?)
+                }
+            }
+            progress_handler.stop(format!("Checked episodes of url {}", i + 1));
+        }
+
+        match self.format {
+            CheckOutput::Table => {
+                for episode in &checked {
+                    println!(
+                        "{:<16}  S{:02}E{:<5}  {:<5}  {}{}",
+                        if episode.downloadable {
+                            "downloadable"
+                        } else {
+                            "not downloadable"
+                        },
+                        episode.season_number,
+                        episode.episode_number,
+                        episode.audio,
+                        episode.title,
+                        if episode.reasons.is_empty() {
+                            "".to_string()
+                        } else {
+                            format!(" ({})", episode.reasons.join(", "))
+                        }
+                    )
+                }
+                println!(
+                    "{} of {} episode(s) are downloadable",
+                    checked.iter().filter(|e| e.downloadable).count(),
+                    checked.len()
+                )
+            }
+            CheckOutput::Json => println!("{}", serde_json::to_string_pretty(&checked)?),
+        }
+
+        Ok(())
+    }
+}
+
+impl Check {
+    /// Checks if `episode` can be downloaded with the given audio. All reasons why it can't be
+    /// downloaded are collected, the stream is only requested if no other reason was found, as it
+    /// would fail anyway.
+    async fn check_episode(
+        &self,
+        episode: &Episode,
+        audio: &Locale,
+        premium: bool,
+    ) -> Result<CheckedEpisode> {
+        let version = if &episode.audio_locale == audio {
+            Some(episode.clone())
+        } else if let Some(version) = episode
+            .versions
+            .iter()
+            .find(|v| &v.audio_locale == audio && v.id != episode.id)
+        {
+            Some(version.episode().await?)
+        } else {
+            None
+        };
+
+        let mut reasons = vec![];
+        if let Some(version) = &version {
+            if version.is_premium_only && !premium {
+                reasons.push("premium only".to_string())
+            }
+            for subtitle in &self.subtitle {
+                if !version.subtitle_locales.contains(subtitle) {
+                    reasons.push(format!("no {} subtitles", subtitle))
+                }
+            }
+            if reasons.is_empty() && !self.skip_stream_check {
+                match version.stream_maybe_without_drm().await {
+                    Ok(stream) => stream.invalidate().await?,
+                    Err(crunchyroll_rs::error::Error::Request { message, .. })
+                        if message.starts_with("TOO_MANY_ACTIVE_STREAMS") =>
+                    {
+                        bail!("Too many active/parallel streams. Please close at least one stream you're watching and try again")
+                    }
+                    // geo restrictions or missing licenses are reported as request errors too
+                    Err(e) => reasons.push(format!("stream not available: {}", e)),
+                }
+            }
+        } else {
+            reasons.push(format!("not available with {} audio", audio))
+        }
+
+        let episode = version.as_ref().unwrap_or(episode);
+        Ok(CheckedEpisode {
+            series: episode.series_title.clone(),
+            season_number: episode.season_number,
+            episode_number: if episode.episode.is_empty() {
+                episode.sequence_number.to_string()
+            } else {
+                episode.episode.clone()
+            },
+            title: episode.title.clone(),
+            id: episode.id.clone(),
+            audio: audio.clone(),
+            downloadable: reasons.is_empty(),
+            reasons,
+        })
+    }
+}
@@ -1,21 +1,60 @@
+use crate::utils::artwork::ArtworkWriter;
+use crate::utils::checkpoint::MuxCheckpoint;
+use crate::utils::checksum::{verify_existing_checksum, Checksum};
+use crate::utils::clap::is_default_value;
 use crate::utils::context::Context;
-use crate::utils::download::{DownloadBuilder, DownloadFormat, DownloadFormatMetadata};
-use crate::utils::ffmpeg::{FFmpegPreset, SOFTSUB_CONTAINERS};
-use crate::utils::filter::{Filter, FilterMediaScope};
-use crate::utils::format::{Format, SingleFormat};
-use crate::utils::locale::{resolve_locales, LanguageTagging};
-use crate::utils::log::progress;
-use crate::utils::os::{free_file, has_ffmpeg, is_special_file};
-use crate::utils::parse::parse_url;
-use crate::utils::video::stream_data_from_stream;
+use crate::utils::download::{
+    DownloadBuilder, DownloadFormat, DownloadFormatMetadata, PipeMode, StreamOptions,
+};
+use crate::utils::event::{CliEventSink, EventSink};
+use crate::utils::ffmpeg::{
+    is_stream_copy, AudioCodec, ExternalStream, FFmpegLog, FFmpegPreset, Mp4Layout,
+    SOFTSUB_CONTAINERS,
+};
+use crate::utils::filter::{Filter, FilterMediaScope, WatchFilter};
+use crate::utils::format::{
+    is_single_episode, Format, GroupBy, NamingScheme, SingleFormat, ANIME_TEMPLATE,
+    JELLYFIN_TEMPLATE, KODI_TEMPLATE, PLEX_TEMPLATE,
+};
+use crate::utils::hook::PostHook;
+use crate::utils::locale::{resolve_locales, LanguageTagging, LocaleMismatch};
+use crate::utils::log::{progress, LogFile};
+use crate::utils::nfo::NfoWriter;
+use crate::utils::os::{
+    check_dir_writable, free_file, has_ffmpeg, is_special_file, set_mtime, temp_directory, tempfile,
+};
+use crate::utils::parse::parse_url_or_crunchylist;
+use crate::utils::pause::listen_for_pause_keys;
+use crate::utils::preset_map::PresetMap;
+use crate::utils::state::{record_failure, BatchState, BatchUrlStatus};
+use crate::utils::subtitle::{
+    parse_subtitle_encoding, SubtitleFormat, SubtitleOffset, SubtitleStyles, DEFAULT_SIGN_STYLES,
+};
+use crate::utils::summary::Summary;
+use crate::utils::test_run::{self, TestRunEventSink, TEST_RUN_SEGMENTS};
+use crate::utils::timing::Phase;
+use crate::utils::video::{list_variants, max_resolution, stream_data_from_stream};
+use crate::utils::webhook::{self, WebhookOn};
 use crate::Execute;
+use anyhow::anyhow;
 use anyhow::bail;
 use anyhow::Result;
+use chrono::NaiveDate;
+use clap::{CommandFactory, FromArgMatches};
 use crunchyroll_rs::media::Resolution;
 use crunchyroll_rs::Locale;
-use log::{debug, error, warn};
+use dialoguer::console::Term;
+use encoding_rs::{Encoding, UTF_8};
+use futures_util::stream::FuturesUnordered;
+use futures_util::StreamExt;
+use log::{debug, error, info, warn};
+use regex::Regex;
 use std::collections::HashMap;
-use std::path::Path;
+use std::iter;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::select;
+use tokio::sync::Semaphore;
 
 #[derive(Clone, Debug, clap::Parser)]
 #[clap(about = "Download a video")]
@@ -36,14 +75,39 @@ pub struct Download {
     pub(crate) subtitle: Option<Locale>,
     #[arg(skip)]
     output_subtitle_locale: String,
+    #[arg(
+        help = "Merge the subtitles of two languages into a single subtitle track, e.g. 'ja-JP,en-US'"
+    )]
+    #[arg(
+        long_help = "Merge the subtitles of two languages into a single subtitle track, e.g. 'ja-JP,en-US'. \
+    The first language is shown at the top of the video, the second one at the usual position at the bottom. \
+    Lines of both languages which start or end at almost the same time are aligned. \
+    If the second language isn't available, only the first one is added"
+    )]
+    #[arg(long, conflicts_with = "subtitle", value_delimiter = ',', num_args = 1)]
+    pub(crate) dual_subtitle: Vec<Locale>,
+    #[arg(help = "Add the sign and song lines of the dub language as forced subtitles")]
+    #[arg(
+        long_help = "Add the sign and song lines of the dub language as forced subtitles. \
+    If a dub is downloaded without `-s` / `--subtitle`, the lines of the dub language subtitles whose style is a sign or song style (see `--sign-styles`) are added as forced and default subtitle, so signs and songs which aren't dubbed are still translated. \
+    Crunchyroll doesn't mark which subtitles or lines are forced, so the lines are only detected by their style name. \
+    Subtitles with other style names may end up with no or too many lines, which is why this has to be enabled explicitly. \
+    Forced subtitles are only added to formats which support soft-subs"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) forced_subs: bool,
 
     #[arg(help = "Name of the output file")]
     #[arg(long_help = "Name of the output file. \
     If you use one of the following pattern they will get replaced:\n  \
       {title}                    → Title of the video\n  \
+      {episode_description}      → Description of the video\n  \
       {series_name}              → Name of the series\n  \
+      {series_description}       → Description of the series\n  \
       {season_name}              → Name of the season\n  \
       {audio}                    → Audio language of the video\n  \
+      {audio_locale}             → Alias for '{audio}'\n  \
+      {subtitle}                 → Subtitle language(s) of the video\n  \
       {width}                    → Width of the video\n  \
       {height}                   → Height of the video\n  \
       {season_number}            → Number of the season\n  \
@@ -56,19 +120,76 @@ pub struct Download {
       {release_day}              → Release day of the video\n  \
       {series_id}                → ID of the series\n  \
       {season_id}                → ID of the season\n  \
-      {episode_id}               → ID of the episode")]
+      {episode_id}               → ID of the episode\n  \
+      {artist}                   → Artist of the music video or concert\n  \
+      {track}                    → Title of the music video or concert\n  \
+      {type}                     → Type of the video (episode, movie, music video or concert)\n\
+    Number placeholders are padded with zeros to a length of 2. \
+    A custom length can be set by appending it to the placeholder name, e.g. '{episode_number:03}'. \
+    With ':auto' (e.g. '{episode_number:auto}') the length is the digit count of the largest episode number of the season, but at least 2")]
     #[arg(short, long, default_value = "{title}.mp4")]
     pub(crate) output: String,
+    /// If no output template is given, so `output` is the default template.
+    #[arg(skip)]
+    pub(crate) default_output: bool,
     #[arg(help = "Name of the output file if the episode is a special")]
     #[arg(long_help = "Name of the output file if the episode is a special. \
     If not set, the '-o'/'--output' flag will be used as name template")]
     #[arg(long)]
     pub(crate) output_specials: Option<String>,
+    #[arg(help = "Name of the output file of extras (see `--include-extras`)")]
+    #[arg(
+        long_help = "Name of the output file of extras (see `--include-extras`). \
+    If not set, extras are stored as '{title} [{type}]' in an 'extras' directory in the series directory of the '-o'/'--output' template"
+    )]
+    #[arg(long, requires = "include_extras")]
+    pub(crate) output_extras: Option<String>,
+    #[arg(help = "Name of an additional output file with burnt-in subtitles")]
+    #[arg(
+        long_help = "Name of an additional output file with burnt-in subtitles. \
+    The streams are only downloaded once and muxed into the '-o'/'--output' file as well as into this file, e.g. to get a softsub matroska and a hardsub mp4 file at once. \
+    Supports the same placeholders as '-o'/'--output' and requires '-s'/'--subtitle'. \
+    Burning in the subtitles re-encodes the video, which takes considerably longer than only muxing"
+    )]
+    #[arg(long, requires = "subtitle", conflicts_with = "raw")]
+    pub(crate) output_hard: Option<String>,
 
     #[arg(help = "Sanitize the output file for use with all operating systems. \
     This option only affects template options and not static characters.")]
     #[arg(long, default_value_t = false)]
     pub(crate) universal_output: bool,
+    #[arg(help = "Name season directories by the season title instead of the season number")]
+    #[arg(
+        long_help = "Name season directories by the season title instead of the season number. \
+    Replaces '{season_number}' in all directory components of the output template(s) with '{season_name}', the file name itself is not changed. \
+    Useful if the season numbers of a series are inconsistent (e.g. because a season is split into multiple cours)"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) season_folder_from_title: bool,
+    #[arg(help = "Directory structure of the output files. \
+    Supported are 'series', 'season' and 'flat'")]
+    #[arg(
+        long_help = "Directory structure of the output files, independent of the directories of the output template(s). Supported are: \n  \
+    series: '{series_name}/<file>'\n  \
+    season: '{series_name}/Season {season_number:02}/<file>'\n  \
+    flat: all files are stored in the same directory\n\
+    Only the leading directories of the output template which don't contain any placeholder are kept, the file name itself is not changed. \
+    E.g. with '--group-by season', '/media/anime/{series_name}/{title}.mkv' becomes '/media/anime/{series_name}/Season {season_number:02}/{title}.mkv'"
+    )]
+    #[arg(long)]
+    #[arg(value_parser = GroupBy::parse)]
+    pub(crate) group_by: Option<GroupBy>,
+    #[arg(help = "Use the output template of a media server naming scheme. \
+    Supported are 'plex', 'jellyfin', 'kodi' and 'anime'")]
+    #[arg(long_help = format!("Use a well-known output template instead of typing it via `-o`. Supported are: \n  \
+    plex: '{}'\n  \
+    jellyfin: '{}'\n  \
+    kodi: '{}'\n  \
+    anime: '{}'\n\
+    The file extension is '.mp4'. If an output template is given via `-o`, it's used instead of the naming scheme", PLEX_TEMPLATE, JELLYFIN_TEMPLATE, KODI_TEMPLATE, ANIME_TEMPLATE))]
+    #[arg(long)]
+    #[arg(value_parser = NamingScheme::parse)]
+    pub(crate) naming_scheme: Option<NamingScheme>,
 
     #[arg(help = "Video resolution")]
     #[arg(long_help = "The video resolution. \
@@ -79,6 +200,50 @@ pub struct Download {
     #[arg(short, long, default_value = "best")]
     #[arg(value_parser = crate::utils::clap::clap_parse_resolution)]
     pub(crate) resolution: Resolution,
+    #[arg(
+        help = "Download the lowest video variant which has at least the given resolution (e.g. 720p)"
+    )]
+    #[arg(
+        long_help = "Download the lowest video variant which has at least the given resolution. \
+    The resolution can be specified like with `-r`/`--resolution` (e.g. 720p or 1280x720), a plain number (e.g. 720) is interpreted as height. \
+    If no variant meets the minimum, the best available variant is used. \
+    Takes precedence over 'best' and 'worst' of `-r`/`--resolution` and can't be used together with a specific resolution"
+    )]
+    #[arg(long)]
+    #[arg(value_parser = crate::utils::clap::clap_parse_min_resolution)]
+    pub(crate) min_resolution: Option<Resolution>,
+    #[arg(help = "Only download video variants which are at most the given number of pixels high")]
+    #[arg(
+        long_help = "Only download video variants which are at most the given number of pixels high. \
+    Variants which are higher are ignored, `-r`/`--resolution` and `--min-resolution` choose among the remaining variants (e.g. 'best' chooses the largest variant within the bounds). \
+    Can be combined with `--max-width`. If no variant fits, the smallest available variant is used"
+    )]
+    #[arg(long)]
+    pub(crate) max_height: Option<u64>,
+    #[arg(help = "Only download video variants which are at most the given number of pixels wide")]
+    #[arg(
+        long_help = "Only download video variants which are at most the given number of pixels wide. \
+    Variants which are wider are ignored, `-r`/`--resolution` and `--min-resolution` choose among the remaining variants (e.g. 'best' chooses the largest variant within the bounds). \
+    Can be combined with `--max-height`. If no variant fits, the smallest available variant is used"
+    )]
+    #[arg(long)]
+    pub(crate) max_width: Option<u64>,
+    #[arg(
+        help = "Download the video variant whose bitrate is closest to the given value (in kbps)"
+    )]
+    #[arg(
+        long_help = "Download the video variant whose bitrate is closest to the given value (in kbps). \
+    Takes precedence over `-r`/`--resolution`. Use `--list` to see all available variants and `-v` to see which one was chosen"
+    )]
+    #[arg(long)]
+    pub(crate) bitrate: Option<u64>,
+    #[arg(help = "List the available video variants of every video instead of downloading it")]
+    #[arg(
+        long_help = "List the available video variants (resolution, bitrate and codecs) of every video instead of downloading it. \
+    The listed bitrate can be passed to `--bitrate` to download exactly this variant"
+    )]
+    #[arg(long, default_value_t = false, conflicts_with = "test_run")]
+    pub(crate) list: bool,
 
     #[arg(
         long,
@@ -110,6 +275,188 @@ pub struct Download {
     )]
     #[arg(long)]
     pub(crate) ffmpeg_threads: Option<usize>,
+    #[arg(
+        help = "Json or toml file with additional ffmpeg output arguments for specific episodes"
+    )]
+    #[arg(
+        long_help = "Json or toml file with additional ffmpeg output arguments for specific episodes, e.g. '{\"S01E05\": \"-c:v libx264 -vf yadif\"}'. \
+    Episodes are identified by their id (e.g. 'G6JQ1WJ2R'), by season and episode number (e.g. 'S01E05') or by episode number only (e.g. '5'). \
+    If multiple keys match an episode, the most specific one (in this order) is used. \
+    The arguments are appended to the ones of `--ffmpeg-preset`, options which are given in both take the value of the file. \
+    Episodes without a matching key only use `--ffmpeg-preset`"
+    )]
+    #[arg(long)]
+    pub(crate) preset_map: Option<PathBuf>,
+    #[arg(skip)]
+    loaded_preset_map: PresetMap,
+
+    #[arg(help = "Abort if a stream would be re-encoded instead of only being copied")]
+    #[arg(
+        long_help = "Abort if a stream would be re-encoded instead of only being copied. \
+    Some options (e.g. burning in subtitles because the output container doesn't support softsubs, `--loudnorm` or `--cut-intro`) require the streams to be re-encoded, which takes considerably longer. \
+    With this flag set, the command is aborted in this case, before anything is downloaded. \
+    Streams which are re-encoded because of the chosen `--ffmpeg-preset` are not affected"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) strict_copy: bool,
+    #[arg(
+        help = "When the output of ffmpeg is shown. Valid modes are 'hidden', 'on-error' (default) and 'always'"
+    )]
+    #[arg(
+        long_help = "When the output of ffmpeg is shown while generating the output file. \
+    'hidden' never shows it, if ffmpeg fails only its exit status is reported. \
+    'on-error' shows it if ffmpeg fails. \
+    'always' additionally logs every line while ffmpeg is running, which is visible with `--verbose`"
+    )]
+    #[arg(long, default_value = "on-error")]
+    #[arg(value_parser = FFmpegLog::parse)]
+    pub(crate) ffmpeg_log: FFmpegLog,
+    #[arg(help = "The maximum number of ffmpeg processes which are running at the same time")]
+    #[arg(
+        long_help = "The maximum number of ffmpeg processes which are generating output files at the same time. \
+    Only takes effect with `--pipeline`, where up to this many episodes are muxed while the next one is downloaded. \
+    Default is the number of cpu cores if the video gets re-encoded and 1 if the streams are only remuxed"
+    )]
+    #[arg(long, requires = "pipeline")]
+    pub(crate) ffmpeg_concurrency: Option<usize>,
+    #[arg(help = "Download the next episode while the previous one is muxed")]
+    #[arg(
+        long_help = "Download the streams of the next episode while the previous episode is muxed, instead of waiting until muxing is finished. \
+    This improves the throughput if muxing takes a while, e.g. when the video gets re-encoded. \
+    At most `--ffmpeg-concurrency` episodes are muxed at the same time, so the temporary files of that many episodes plus the currently downloaded one may exist at the same time. \
+    The steps after muxing (e.g. `--write-nfo` or `--post-hook`) run when the episode is muxed. \
+    Only the download command supports this, the archive command always muxes an episode before it downloads the next one"
+    )]
+    #[arg(long, default_value_t = false, conflicts_with_all = ["pipe", "test_run"])]
+    pub(crate) pipeline: bool,
+    #[arg(
+        help = "Normalize the audio loudness (EBU R128). Optionally takes the target loudness in LUFS, default is -23"
+    )]
+    #[arg(
+        long_help = "Normalize the audio loudness with ffmpeg's 'loudnorm' filter, following the EBU R128 recommendation. \
+    Optionally takes the target integrated loudness in LUFS (between -70 and -5), default is -23 LUFS. \
+    Because the audio has to be re-encoded for this, it only has an effect if the output gets re-encoded anyway (e.g. when using `--ffmpeg-preset` or burning in subtitles). \
+    If the streams are only remuxed, the normalization is skipped"
+    )]
+    #[arg(long, num_args = 0..=1, default_missing_value = "-23", allow_negative_numbers = true)]
+    #[arg(value_parser = crate::utils::clap::clap_parse_loudness_target)]
+    pub(crate) loudnorm: Option<f32>,
+    #[arg(help = "Codec of the output audio. Valid codecs are 'copy', 'aac' and 'opus'")]
+    #[arg(
+        long_help = "Codec of the output audio. Valid codecs are 'copy', 'aac' and 'opus'. \
+    By default, the audio is copied if possible and encoded with aac if it has to be re-encoded (e.g. because of `--loudnorm` or `--cut-intro`). \
+    'copy' fails if the audio has to be re-encoded and can't be combined with `--loudnorm`, `--cut-intro` or `--cut-outro`. 'aac' and 'opus' always re-encode the audio. \
+    'opus' can't be used with mov / mp4-audio outputs"
+    )]
+    #[arg(long)]
+    #[arg(value_parser = AudioCodec::parse)]
+    pub(crate) audio_codec: Option<AudioCodec>,
+
+    #[arg(help = "Move the index of mp4 / mov files to their beginning, so they can be streamed")]
+    #[arg(
+        long_help = "Move the index (moov atom) of mp4 / mov output files to their beginning, so they can be played while they're still being downloaded (e.g. when served over http). \
+    This requires an additional pass over the file after it was written, which takes a bit longer for large files. \
+    Has no effect on other output formats"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) faststart: bool,
+    #[arg(help = "Write mp4 / mov files as fragments, so they can be streamed")]
+    #[arg(
+        long_help = "Write mp4 / mov output files as fragments which each carry their own index, so they can be played while they're still being downloaded (e.g. when served over http). \
+    Unlike `--faststart`, no additional pass over the file is needed, but not every (older) player supports fragmented files and seeking may be slower. \
+    Has no effect on other output formats"
+    )]
+    #[arg(long, default_value_t = false, conflicts_with = "faststart")]
+    pub(crate) fragmented: bool,
+    #[arg(help = "Bitrate of the output audio in kbps if it's re-encoded")]
+    #[arg(long_help = "Bitrate of the output audio in kbps (e.g. 128). \
+    Only used if the audio is re-encoded, either because of `--audio-codec` or because an option like `--loudnorm` requires it. \
+    Default is the default of the ffmpeg encoder")]
+    #[arg(long, value_parser = clap::value_parser!(u32).range(8..=1024))]
+    pub(crate) audio_bitrate: Option<u32>,
+
+    #[arg(help = "Format of the subtitles. Valid formats are 'ass', 'srt' and 'vtt'")]
+    #[arg(
+        long_help = "Format in which the subtitles are stored in the output file. Valid formats are 'ass' (default, the format Crunchyroll provides), 'srt' and 'vtt'. \
+    When converting to 'srt' or 'vtt', all styling is stripped and overlapping dialogues are merged into a single cue. \
+    Only works with matroska / '.mkv' output files, burnt-in subtitles are always rendered from the original ass subtitles"
+    )]
+    #[arg(long, default_value_t = SubtitleFormat::default())]
+    #[arg(value_parser = SubtitleFormat::parse)]
+    pub(crate) subtitle_format: SubtitleFormat,
+    #[arg(
+        help = "Charset of the subtitles, e.g. 'shift_jis' or 'windows-1252'. Default is 'utf-8'"
+    )]
+    #[arg(
+        long_help = "Charset the subtitles are encoded with, e.g. 'shift_jis' or 'windows-1252'. Default is 'utf-8'. \
+    Characters which can't be represented in the charset are replaced with '?'. \
+    Containers like mkv and mp4 always store subtitles as utf-8, so this can only be used together with `--raw`, to encode the subtitle files stored by it"
+    )]
+    #[arg(long)]
+    #[arg(value_parser = parse_subtitle_encoding)]
+    pub(crate) subtitle_encoding: Option<&'static Encoding>,
+
+    #[arg(
+        help = "Shift the timings of the subtitles by the given milliseconds. \
+    Can be set for a specific subtitle language via '<language>=<milliseconds>'"
+    )]
+    #[arg(
+        long_help = "Shift the timings of the subtitles by the given milliseconds, e.g. '-250' to show them earlier or '500' to show them later. \
+    Some subtitles of Crunchyroll are offset by a fixed amount relative to the video. \
+    To only shift the subtitles of a specific language, use '<language>=<milliseconds>' (e.g. 'de-DE=500'). \
+    Can be used multiple times, an offset for a specific language takes precedence over an offset for all languages. \
+    Subtitles which would start before the video are shown from its beginning"
+    )]
+    #[arg(long, allow_negative_numbers = true)]
+    #[arg(value_parser = SubtitleOffset::parse)]
+    pub(crate) subtitle_offset: Vec<SubtitleOffset>,
+    #[arg(help = "Add an audio file to the output. Must be in format '<file>:<locale>'")]
+    #[arg(
+        long_help = "Add an audio file (e.g. a better dub) to the output, in addition to the audio from Crunchyroll. \
+    Must be in format '<file>:<locale>' (e.g. 'dub.m4a:de-DE'), the locale is used as language of the audio track. \
+    Can be used multiple times. If the file is a video, only its audio is used. \
+    The file is added as it is, so it must match the timings of the episode. \
+    Only works if the url(s) resolve to a single episode"
+    )]
+    #[arg(long, value_parser = ExternalStream::parse)]
+    pub(crate) external_audio: Vec<ExternalStream>,
+    #[arg(help = "Add a subtitle file to the output. Must be in format '<file>:<locale>'")]
+    #[arg(
+        long_help = "Add a subtitle file (e.g. a fansub) to the output, in addition to the subtitles from Crunchyroll. \
+    Must be in format '<file>:<locale>' (e.g. 'fansub.ass:en-US'), the locale is used as language of the subtitle track. \
+    Can be used multiple times. The file is added as it is, so it must match the timings of the episode. \
+    Only works if the url(s) resolve to a single episode and the output container supports soft subtitles (mkv, mov, mp4)"
+    )]
+    #[arg(long, value_parser = ExternalStream::parse)]
+    pub(crate) external_subtitle: Vec<ExternalStream>,
+    #[arg(
+        help = "Which lines of the subtitles are kept. Valid values are 'all' (default) and 'dialogue-only'"
+    )]
+    #[arg(
+        long_help = "Which lines of the subtitles are kept. Valid values are 'all' (default) and 'dialogue-only'. \
+    With 'dialogue-only', all lines whose style is a sign, typesetting or song style (e.g. 'Signs', 'OS', 'OP', 'Song') are removed, see `--sign-styles`. \
+    Timings and all other lines are unchanged"
+    )]
+    #[arg(long, default_value = "all")]
+    #[arg(value_parser = SubtitleStyles::parse)]
+    pub(crate) subtitle_styles: SubtitleStyles,
+    #[arg(help = format!("Style names which are removed with `--subtitle-styles dialogue-only`. \
+    Default is: {}", DEFAULT_SIGN_STYLES.join(", ")))]
+    #[arg(long_help = format!("Style names which are removed with `--subtitle-styles dialogue-only` and kept in forced subtitles, separated by commas. \
+    A line is removed if any word of its style name is one of the given names (case-insensitive), e.g. 'signs' matches 'Signs_Top'. \
+    Default is: {}", DEFAULT_SIGN_STYLES.join(", ")))]
+    #[arg(long, value_delimiter = ',')]
+    pub(crate) sign_styles: Vec<String>,
+
+    #[arg(
+        help = "Skip episodes which aren't available with exactly the requested audio and subtitle languages"
+    )]
+    #[arg(
+        long_help = "Skip episodes which aren't available with exactly the requested audio and subtitle languages, instead of downloading them with other or fewer languages. \
+    Skipped episodes are listed in the summary"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) strict_locale: bool,
 
     #[arg(help = "Skip files which are already existing by their name")]
     #[arg(long, default_value_t = false)]
@@ -117,6 +464,225 @@ pub struct Download {
     #[arg(help = "Skip special episodes")]
     #[arg(long, default_value_t = false)]
     pub(crate) skip_specials: bool,
+    #[arg(help = "Also download the extras (openings, endings, PVs, ...) of series")]
+    #[arg(
+        long_help = "Also download the extras (openings, endings, PVs, ...) of series. \
+    Only has an effect on series urls. \
+    Extras are stored in an 'extras' directory as used by Kodi and other media servers, see `--output-extras`"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) include_extras: bool,
+    #[arg(help = "Only download episodes which aired on or after the given date (YYYY-MM-DD)")]
+    #[arg(long)]
+    pub(crate) since: Option<NaiveDate>,
+    #[arg(help = "Only download episodes which aired on or before the given date (YYYY-MM-DD)")]
+    #[arg(long)]
+    pub(crate) until: Option<NaiveDate>,
+    #[arg(help = "Only download episodes whose title matches the given regex (case-insensitive)")]
+    #[arg(long, value_parser = crate::utils::clap::clap_parse_title_regex)]
+    pub(crate) episode_matches: Option<Regex>,
+    #[arg(help = "Only download episodes whose title contains the given text (case-insensitive)")]
+    #[arg(long, value_parser = crate::utils::clap::clap_parse_title_contains)]
+    pub(crate) title_contains: Option<Regex>,
+    #[arg(help = "Only download episodes which were already watched with the logged in account")]
+    #[arg(
+        long_help = "Only download episodes which were already watched with the logged in account. \
+    An episode counts as watched if it was fully watched or if at least `--watched-threshold` percent of it was watched. \
+    Requires to be logged in with an account"
+    )]
+    #[arg(long, default_value_t = false, conflicts_with = "unwatched_only")]
+    pub(crate) watched_only: bool,
+    #[arg(help = "Only download episodes which weren't watched yet with the logged in account")]
+    #[arg(
+        long_help = "Only download episodes which weren't watched yet with the logged in account. \
+    Episodes which were only partially watched, less than `--watched-threshold` percent, are considered unwatched. \
+    Requires to be logged in with an account"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) unwatched_only: bool,
+    #[arg(
+        help = "Percentage of an episode which must be watched until it counts as watched for `--watched-only` and `--unwatched-only`"
+    )]
+    #[arg(long, default_value_t = 90, value_parser = clap::value_parser!(u8).range(1..=100))]
+    pub(crate) watched_threshold: u8,
+
+    #[arg(help = "Record which of the given urls were already processed completely in a file")]
+    #[arg(
+        long_help = "Record which of the given urls were already processed completely in a file. \
+    The file is updated after every url, so if the batch gets aborted, it can be continued with `--resume-batch` afterwards. \
+    Without `--resume-batch`, the progress of an already existing file gets reset"
+    )]
+    #[arg(long)]
+    pub(crate) state_file: Option<PathBuf>,
+    #[arg(help = "Skip urls which are marked as completed in the file given via `--state-file`")]
+    #[arg(long, default_value_t = false, requires = "state_file")]
+    pub(crate) resume_batch: bool,
+    #[arg(
+        help = "Only retry the videos which failed in previous runs according to the file given via `--state-file`"
+    )]
+    #[arg(
+        long_help = "Only retry the videos which failed in previous runs according to the file given via `--state-file`. \
+    If a state file is used, a video which fails to download doesn't abort the run but is recorded in the state file and the remaining videos are downloaded. \
+    With this flag, only the recorded videos are downloaded again, no url has to be given. Videos which now succeed are removed from the state file"
+    )]
+    #[arg(
+        long,
+        default_value_t = false,
+        requires = "state_file",
+        conflicts_with = "resume_batch"
+    )]
+    pub(crate) retry_failed: bool,
+    #[arg(
+        help = "Only download seasons of series which weren't downloaded before according to the file given via `--state-file`"
+    )]
+    #[arg(
+        long_help = "Only download seasons of series which weren't downloaded before according to the file given via `--state-file`. \
+    All seasons of which episodes were downloaded are recorded in the state file, on the next run only seasons which aren't recorded yet are downloaded. \
+    Useful to regularly check a series for new seasons (cours). \
+    Only applies to series urls"
+    )]
+    #[arg(long, default_value_t = false, requires = "state_file")]
+    pub(crate) only_new_seasons: bool,
+    #[arg(
+        help = "Download already existing files again if Crunchyroll updated the video since it was downloaded"
+    )]
+    #[arg(
+        long_help = "Download already existing files again if Crunchyroll updated the video since it was downloaded. \
+    A fingerprint of the streams (their bitrate, codecs, resolution and duration) of every downloaded video is recorded in the file given via `--state-file`. \
+    When a video is skipped because its file already exists, the fingerprint of its current streams is compared against the recorded one and the file gets replaced if they differ. \
+    Only the stream metadata is requested for this, nothing is downloaded for unchanged videos. \
+    Files which were downloaded before a fingerprint was recorded are considered as unchanged"
+    )]
+    #[arg(
+        long,
+        default_value_t = false,
+        requires_all = ["state_file", "skip_existing"]
+    )]
+    pub(crate) check_updates: bool,
+
+    #[arg(help = "Write NFO files for media servers like Kodi or Jellyfin")]
+    #[arg(
+        long_help = "Write NFO files for media servers like Kodi or Jellyfin. \
+    Besides a NFO file next to every episode, a 'tvshow.nfo' with the series metadata is written to the series directory and a 'season.nfo' to the season directory. \
+    If the directory of the output file contains a season placeholder (e.g. '{series_name}/Season {season_number}/{title}.mkv'), its parent is used as series directory, otherwise the directory of the output file. \
+    Existing NFO files are only rewritten if their content has changed"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) write_nfo: bool,
+    #[arg(help = "Download the series poster and fanart for media servers like Kodi or Jellyfin")]
+    #[arg(
+        long_help = "Download the series artwork for media servers like Kodi or Jellyfin. \
+    The largest available tall poster is stored as 'poster.jpg' and the largest wide poster as 'fanart.jpg' in the series directory, which is determined like with `--write-nfo`. \
+    Crunchyroll provides no banners and no season posters, so neither 'banner.jpg' nor 'seasonNN-poster.jpg' are written. \
+    Already existing images are kept"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) write_artwork: bool,
+    #[arg(
+        help = "Embed metadata like title, series and release date as tags into the output file"
+    )]
+    #[arg(
+        long_help = "Embed metadata as tags into the output file, which can be read by most players and media servers. \
+    The title, series name, season number, episode number, release date and description are embedded. \
+    This complements `--write-nfo`, which writes the metadata into separate files"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) embed_metadata: bool,
+
+    #[arg(help = "Write a checksum file next to every downloaded file. \
+    Valid algorithms are 'sha256' and 'crc32'")]
+    #[arg(
+        long_help = "Write a checksum file next to every downloaded file (e.g. 'My Episode.mkv.sha256'), which can be verified with tools like `sha256sum -c`. \
+    Valid algorithms are 'sha256' and 'crc32'. \
+    The checksum is calculated by reading the finished file once more after ffmpeg has written it, which takes additional disk I/O for every file. \
+    If used together with `--skip-existing`, already existing files which have a checksum file are verified against it and downloaded again if the checksum doesn't match"
+    )]
+    #[arg(long)]
+    #[arg(value_parser = Checksum::parse)]
+    pub(crate) checksum: Option<Checksum>,
+
+    #[arg(
+        help = "Set the modification time of every downloaded file to the release date of the video"
+    )]
+    #[arg(
+        long_help = "Set the modification time of every downloaded file to the release date of the video (e.g. the air date of an episode). \
+    Useful for media servers and file browsers which sort by modification time. \
+    Files of videos without a known release date are not changed"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) set_mtime: bool,
+    #[arg(
+        help = "Also set the access time of every downloaded file to the release date of the video"
+    )]
+    #[arg(long, default_value_t = false, requires = "set_mtime")]
+    pub(crate) set_atime: bool,
+
+    #[arg(
+        help = "Store the downloaded streams together with a manifest instead of muxing them into the output file"
+    )]
+    #[arg(
+        long_help = "Store the downloaded streams together with a manifest instead of muxing them into the output file. \
+    The streams are written to a directory next to the output file (e.g. 'My Episode.mkv.raw'). \
+    The output file can be created from it later on with `crunchy-cli repair`, without downloading anything again"
+    )]
+    #[arg(long, default_value_t = false, conflicts_with = "checksum")]
+    pub(crate) raw: bool,
+
+    #[arg(help = "What to write to stdout if the output is '-'. \
+    Supported are 'muxed', 'video' and 'audio'")]
+    #[arg(
+        long_help = "What to write to stdout if the output is '-'. Supported are: \n  \
+    muxed: All streams are muxed into a single mpegts stream (default)\n  \
+    video: The raw video stream, as it's downloaded. No muxing is done and FFmpeg is not needed\n  \
+    audio: The raw audio stream, as it's downloaded. No muxing is done and FFmpeg is not needed\n\
+    'video' and 'audio' can only be used if the url(s) resolve to a single episode"
+    )]
+    #[arg(long, default_value = "muxed")]
+    #[arg(value_parser = PipeMode::parse)]
+    pub(crate) pipe: PipeMode,
+
+    #[arg(help = "Test if the url can be downloaded, without writing any file")]
+    #[arg(
+        long_help = "Test if the url can be downloaded, without writing any file. \
+    Every stage of a download is run once for the first video of the url: the login, the selection of the videos, fetching the streams, downloading the first segments of every stream and muxing them with ffmpeg into a temporary file, which is deleted afterwards. \
+    Whether each stage passed or failed is reported, which makes it useful as smoke test for scripts. \
+    Only the stream metadata and a few segments are requested"
+    )]
+    #[arg(long, default_value_t = false, conflicts_with_all = ["state_file", "pipe"])]
+    pub(crate) test_run: bool,
+
+    #[arg(help = "Run a command after every successfully downloaded file")]
+    #[arg(
+        long_help = "Run a command after every successfully downloaded file, e.g. to trigger a library scan of a media server. \
+    The command is executed by the system shell ('sh' or 'cmd' on Windows). \
+    Information about the downloaded file are passed as environment variables: \
+    CRUNCHY_OUTPUT, CRUNCHY_TITLE, CRUNCHY_SERIES_ID, CRUNCHY_SERIES_NAME, CRUNCHY_SERIES_DESCRIPTION, CRUNCHY_SEASON_ID, CRUNCHY_SEASON_NAME, CRUNCHY_SEASON_NUMBER, CRUNCHY_EPISODE_ID, CRUNCHY_EPISODE_NUMBER, CRUNCHY_EPISODE_DESCRIPTION and CRUNCHY_AUDIO. \
+    If the command fails, a warning is shown but the download continues"
+    )]
+    #[arg(long)]
+    pub(crate) post_hook: Option<String>,
+    #[arg(help = "Seconds after which the `--post-hook` command gets killed")]
+    #[arg(long, default_value_t = 300, requires = "post_hook")]
+    pub(crate) post_hook_timeout: u64,
+    #[arg(help = "Abort if the `--post-hook` command fails instead of only showing a warning")]
+    #[arg(long, default_value_t = false, requires = "post_hook")]
+    pub(crate) post_hook_fail: bool,
+    #[arg(help = "Send json events about completed and failed downloads to the given url")]
+    #[arg(
+        long_help = "Send json events about completed and failed downloads to the given url via POST requests, e.g. to get notified on a headless server. \
+    Events are sent for every completed or failed episode and when the run finishes. \
+    Failed requests are retried a few times, but never fail the download. \
+    Custom headers (`--header`) aren't sent to the webhook, only the download traffic proxy of `--proxy` is used. \
+    Pending events are sent for at most 30 seconds when the run finishes. \
+    See the README for the payload schema"
+    )]
+    #[arg(long)]
+    pub(crate) webhook: Option<String>,
+    #[arg(help = "Which events are sent to `--webhook`. \
+    Valid options: complete (completed episodes and runs), fail (failed episodes and runs), all")]
+    #[arg(long, default_value = "all", requires = "webhook")]
+    #[arg(value_parser = WebhookOn::parse)]
+    pub(crate) webhook_on: WebhookOn,
 
     #[arg(help = "Includes chapters (e.g. intro, credits, ...)")]
     #[arg(long_help = "Includes chapters (e.g. intro, credits, ...). \
@@ -126,6 +692,34 @@ pub struct Download {
     #[arg(long, default_value_t = false)]
     pub(crate) include_chapters: bool,
 
+    #[arg(help = "Cut the intro out of the video. \
+    This re-encodes the video and changes its timestamps")]
+    #[arg(
+        long_help = "Cut the intro out of the video, based on the same timing information which is used for chapters. \
+    This is destructive: the video and audio are re-encoded, the subtitles and chapters are shifted accordingly and the timestamps of the output don't match the original episode anymore. \
+    Episodes without intro timing are downloaded as they are"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) cut_intro: bool,
+    #[arg(help = "Cut the outro (credits) out of the video. \
+    This re-encodes the video and changes its timestamps")]
+    #[arg(
+        long_help = "Cut the outro (credits) out of the video, based on the same timing information which is used for chapters. \
+    This is destructive: the video and audio are re-encoded, the subtitles and chapters are shifted accordingly and the timestamps of the output don't match the original episode anymore. \
+    Episodes without outro timing are downloaded as they are"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) cut_outro: bool,
+
+    #[arg(help = "Interactively select the seasons and episodes to download")]
+    #[arg(
+        long_help = "Interactively select the seasons and episodes to download. \
+    After the url is resolved, a checklist of all seasons and afterwards of all episodes of the selected seasons is shown. \
+    Requires an interactive terminal and can't be used together with `-y`/`--yes` or `-q`/`--quiet`"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) interactive: bool,
+
     #[arg(help = "Skip any interactive input")]
     #[arg(short, long, default_value_t = false)]
     pub(crate) yes: bool,
@@ -138,14 +732,158 @@ pub struct Download {
     #[arg(short, long, default_value_t = num_cpus::get())]
     pub(crate) threads: usize,
 
-    #[arg(help = "Url(s) to Crunchyroll episodes or series")]
-    #[arg(required = true)]
+    #[arg(help = "Url(s) to Crunchyroll episodes, series or crunchylists")]
+    #[arg(required_unless_present = "retry_failed")]
     pub(crate) urls: Vec<String>,
 }
 
+impl Download {
+    /// Parses the command from `args` like [`clap::Parser::try_parse_from`], but additionally
+    /// records if the default output template is used.
+    pub(crate) fn try_parse_args(args: Vec<String>) -> Result<Self, clap::Error> {
+        let matches = Self::command().try_get_matches_from(args)?;
+        let mut download = Self::from_arg_matches(&matches)?;
+        download.default_output = is_default_value(&matches, "output");
+        Ok(download)
+    }
+}
+
 impl Execute for Download {
     fn pre_check(&mut self) -> Result<()> {
-        if !has_ffmpeg() {
+        if let Some(preset_map) = &self.preset_map {
+            self.loaded_preset_map = match PresetMap::load(preset_map) {
+                Ok(preset_map) => preset_map,
+                Err(e) => bail!(
+                    "Failed to load preset map {}: {}",
+                    preset_map.to_string_lossy(),
+                    e
+                ),
+            }
+        }
+
+        if !self.dual_subtitle.is_empty() {
+            if self.dual_subtitle.len() != 2 {
+                bail!("`--dual-subtitle` requires exactly two languages")
+            } else if self.dual_subtitle[0] == self.dual_subtitle[1] {
+                bail!("`--dual-subtitle` requires two different languages")
+            } else if self.subtitle_format != SubtitleFormat::Ass {
+                bail!("`--dual-subtitle` can only be used with ass subtitles (`--subtitle-format ass`)")
+            }
+            // the first language is handled like a regular subtitle, the second one is added to it
+            self.subtitle = Some(self.dual_subtitle[0].clone())
+        }
+
+        if self.audio_bitrate.is_some() && self.audio_codec == Some(AudioCodec::Copy) {
+            bail!("`--audio-bitrate` can't be used together with `--audio-codec copy`")
+        }
+        if self.audio_codec == Some(AudioCodec::Copy) {
+            // all of them filter the audio, which requires it to be re-encoded
+            if self.loudnorm.is_some() {
+                bail!("`--loudnorm` can't be used together with `--audio-codec copy`")
+            } else if self.cut_intro || self.cut_outro {
+                bail!("`--cut-intro` and `--cut-outro` can't be used together with `--audio-codec copy`")
+            }
+        }
+        if let Some(audio_codec) = &self.audio_codec {
+            for output in [Some(&self.output), self.output_specials.as_ref()]
+                .into_iter()
+                .flatten()
+            {
+                let extension = Path::new(output)
+                    .extension()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .to_lowercase();
+                if !audio_codec.supports_container(&extension) {
+                    bail!(
+                        "The audio codec '{}' isn't supported by '.{}' output files",
+                        audio_codec,
+                        extension
+                    )
+                }
+            }
+        }
+        if self.faststart || self.fragmented {
+            let has_mp4_output = [Some(&self.output), self.output_specials.as_ref()]
+                .into_iter()
+                .flatten()
+                .any(|o| {
+                    ["mov", "mp4"].contains(
+                        &Path::new(o)
+                            .extension()
+                            .unwrap_or_default()
+                            .to_string_lossy()
+                            .to_lowercase()
+                            .as_str(),
+                    )
+                });
+            if !has_mp4_output {
+                warn!("`--faststart` and `--fragmented` only have an effect on mp4 and mov output files")
+            }
+        }
+        if self.interactive {
+            if self.yes {
+                bail!("`--interactive` can't be used together with `-y`/`--yes` or `-q`/`--quiet`")
+            } else if !Term::stdout().is_term() {
+                bail!("`--interactive` requires an interactive terminal")
+            }
+        }
+
+        if let (Some(since), Some(until)) = (self.since, self.until) {
+            if since > until {
+                bail!("`--since` must not be after `--until`")
+            }
+        }
+
+        if self.min_resolution.is_some()
+            && self.resolution.height != u64::MAX
+            && self.resolution.height != u64::MIN
+        {
+            bail!("`--min-resolution` can't be used together with a specific resolution for `-r`/`--resolution`")
+        }
+        if let (Some(min_resolution), Some(max_height)) = (&self.min_resolution, self.max_height) {
+            if min_resolution.height > max_height {
+                bail!("`--min-resolution` must not be higher than `--max-height`")
+            }
+        }
+
+        if self.raw
+            && [Some(&self.output), self.output_specials.as_ref()]
+                .into_iter()
+                .flatten()
+                .any(|o| is_special_file(o) || o == "-")
+        {
+            bail!("`--raw` can only be used if the output is a regular file")
+        }
+
+        if self.test_run {
+            if self.urls.len() != 1 {
+                bail!("`--test-run` requires exactly one url")
+            } else if self.output == "-" || is_special_file(&self.output) {
+                bail!("`--test-run` requires the output to be a file")
+            }
+        }
+
+        if self.pipe != PipeMode::Muxed {
+            if self.output != "-" {
+                bail!("`--pipe` can only be used if the output is '-'")
+            }
+            if self.subtitle.is_some()
+                || self.output_specials.is_some()
+                || self.output_hard.is_some()
+                || !self.external_audio.is_empty()
+                || !self.external_subtitle.is_empty()
+            {
+                bail!("`--pipe {}` can't be used together with `-s`/`--subtitle`, `--output-specials`, `--output-hard`, `--external-audio` or `--external-subtitle`", if self.pipe == PipeMode::Video { "video" } else { "audio" })
+            }
+        }
+
+        if self.cut_intro || self.cut_outro {
+            warn!("`--cut-intro` and `--cut-outro` re-encode the video and remove parts of it. The timestamps of the output files won't match the original episodes")
+        }
+
+        // nothing gets muxed if a single stream is piped, so ffmpeg isn't required
+        if !has_ffmpeg() && self.pipe == PipeMode::Muxed {
             bail!("FFmpeg is needed to run this command")
         } else if Path::new(&self.output)
             .extension()
@@ -157,11 +895,63 @@ impl Execute for Download {
             bail!("No file extension found. Please specify a file extension (via `-o`) for the output file")
         }
 
+        for external in &self.external_audio {
+            external.check_stream_type("Audio")?
+        }
+        for external in &self.external_subtitle {
+            external.check_stream_type("Subtitle")?
+        }
+        if !self.external_subtitle.is_empty()
+            && !SOFTSUB_CONTAINERS.contains(
+                &Path::new(&self.output)
+                    .extension()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .as_ref(),
+            )
+        {
+            bail!(
+                "`--external-subtitle` only works with {} output files",
+                SOFTSUB_CONTAINERS.join(", ")
+            )
+        }
+
+        if self.checksum.is_some()
+            && [Some(&self.output), self.output_specials.as_ref()]
+                .into_iter()
+                .flatten()
+                .any(|o| is_special_file(o) || o == "-")
+        {
+            bail!("`--checksum` can only be used if the output is a regular file")
+        }
+
+        if self.subtitle_encoding.is_some_and(|e| e != UTF_8) && !self.raw {
+            bail!("`--subtitle-encoding` can only be used together with `--raw`, muxed subtitles are always stored as utf-8")
+        }
+        if self.subtitle_format == SubtitleFormat::Vtt
+            && self.subtitle_encoding.is_some_and(|e| e != UTF_8)
+        {
+            warn!("WebVTT subtitles must be utf-8 encoded, some players may not be able to read them with `--subtitle-encoding`")
+        }
+
+        if self.subtitle_format != SubtitleFormat::Ass {
+            for output in [Some(&self.output), self.output_specials.as_ref()]
+                .into_iter()
+                .flatten()
+            {
+                if Path::new(output).extension().unwrap_or_default() != "mkv" {
+                    bail!(
+                        "`--subtitle-format` can only be used with matroska / '.mkv' output files"
+                    )
+                }
+            }
+        }
+
         if self.subtitle.is_some() {
             if let Some(ext) = Path::new(&self.output).extension() {
                 if self.force_hardsub {
                     warn!("Hardsubs are forced. Adding subtitles may take a while")
-                } else if !["mkv", "mov", "mp4"].contains(&ext.to_string_lossy().as_ref()) {
+                } else if !SOFTSUB_CONTAINERS.contains(&ext.to_string_lossy().as_ref()) {
                     warn!("Detected a container which does not support softsubs. Adding subtitles may take a while")
                 }
             }
@@ -180,18 +970,121 @@ impl Execute for Download {
             if let Some(ext) = Path::new(special_output).extension() {
                 if self.force_hardsub {
                     warn!("Hardsubs are forced for special episodes. Adding subtitles may take a while")
-                } else if !["mkv", "mov", "mp4"].contains(&ext.to_string_lossy().as_ref()) {
+                } else if !SOFTSUB_CONTAINERS.contains(&ext.to_string_lossy().as_ref()) {
                     warn!("Detected a container which does not support softsubs. Adding subtitles for special episodes may take a while")
                 }
             }
         }
 
+        if let Some(output_hard) = &self.output_hard {
+            if self.force_hardsub {
+                bail!("`--output-hard` can't be used together with `--force-hardsub`")
+            } else if !SOFTSUB_CONTAINERS.contains(
+                &Path::new(&self.output)
+                    .extension()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .as_ref(),
+            ) {
+                bail!("`--output-hard` requires the '-o'/'--output' file to be a container which supports softsubs (mkv, mov or mp4)")
+            } else if Path::new(output_hard)
+                .extension()
+                .unwrap_or_default()
+                .is_empty()
+                || is_special_file(output_hard)
+                || output_hard == "-"
+            {
+                bail!("No file extension found. Please specify a file extension (via `--output-hard`) for the hardsub output file")
+            }
+            warn!("Creating an additional hardsub copy re-encodes the video, this may take a while")
+        }
+
+        if self.strict_copy {
+            // subtitles are burned in if they're requested but the container doesn't support
+            // softsubs, and always for the additional hardsub file
+            let burn_subtitles = self.output_hard.is_some()
+                || (self.subtitle.is_some()
+                    && (self.force_hardsub
+                        || [Some(&self.output), self.output_specials.as_ref()]
+                            .into_iter()
+                            .flatten()
+                            .any(|o| {
+                                !SOFTSUB_CONTAINERS.contains(
+                                    &Path::new(o)
+                                        .extension()
+                                        .unwrap_or_default()
+                                        .to_string_lossy()
+                                        .as_ref(),
+                                )
+                            })));
+            // the episodes without an entry in the preset map only use the preset
+            for extra_args in iter::once(&vec![]).chain(self.loaded_preset_map.all_args()) {
+                let stream_args = StreamOptions {
+                    ffmpeg_preset: self.ffmpeg_preset.clone().unwrap_or_default(),
+                    ffmpeg_extra_args: extra_args.clone(),
+                    // the subtitle and the cut ranges aren't known yet, but only if they're
+                    // applied at all decides if the streams are re-encoded
+                    burn_subtitle: burn_subtitles.then(String::new),
+                    audio_codec: self.audio_codec.clone(),
+                    loudnorm: self.loudnorm,
+                    cut: (self.cut_intro || self.cut_outro).then(String::new),
+                    ..Default::default()
+                }
+                .build()?;
+                if !stream_args.reencoded.is_empty() {
+                    bail!(
+                        "The streams would be re-encoded ({}), aborting as `--strict-copy` is set",
+                        stream_args.reasons.join(", ")
+                    )
+                }
+            }
+        }
+
+        if let Some(naming_scheme) = &self.naming_scheme {
+            // the naming scheme is only a shortcut for the output template, an explicitly given
+            // template has precedence
+            if self.default_output {
+                self.output = naming_scheme.template("mp4")
+            } else {
+                warn!("`--naming-scheme` is ignored as an output template is given via `-o`")
+            }
+        }
+
+        if let Some(group_by) = &self.group_by {
+            let group =
+                |o: &String| Format::group_by_template(o, group_by, self.season_folder_from_title);
+            self.output = group(&self.output);
+            self.output_specials = self.output_specials.as_ref().map(group);
+            self.output_hard = self.output_hard.as_ref().map(group);
+        }
+
+        if self.include_extras && self.output_extras.is_none() {
+            self.output_extras = Some(Format::extras_template(&self.output))
+        }
+
+        if self.season_folder_from_title {
+            let output = Format::season_folder_from_title(&self.output);
+            if output == self.output && self.group_by.is_none() {
+                warn!("`--season-folder-from-title` has no effect as the output template has no directory containing '{{season_number}}'")
+            }
+            self.output = output;
+            self.output_specials = self
+                .output_specials
+                .as_ref()
+                .map(Format::season_folder_from_title);
+            self.output_hard = self
+                .output_hard
+                .as_ref()
+                .map(Format::season_folder_from_title);
+        }
+
         if let Some(language_tagging) = &self.language_tagging {
             self.audio = resolve_locales(&[self.audio.clone()]).remove(0);
             self.subtitle = self
                 .subtitle
                 .as_ref()
                 .map(|s| resolve_locales(&[s.clone()]).remove(0));
+            self.dual_subtitle = resolve_locales(&self.dual_subtitle);
             self.output_audio_locale = language_tagging.for_locale(&self.audio);
             self.output_subtitle_locale = self
                 .subtitle
@@ -211,10 +1104,78 @@ impl Execute for Download {
     }
 
     async fn execute(self, ctx: Context) -> Result<()> {
-        if !ctx.crunchy.premium().await {
+        if let Some(url) = &self.webhook {
+            webhook::start(
+                ctx.webhook_client.clone(),
+                url.clone(),
+                self.webhook_on.clone(),
+                "download",
+            )
+        }
+
+        // all streams are downloaded into the temp directory first
+        check_dir_writable(&temp_directory()).map_err(|e| {
+            anyhow!(
+                "{} (the temp directory can be changed via the `CRUNCHY_CLI_TEMP_DIR` environment variable)",
+                e
+            )
+        })?;
+
+        let premium = ctx.crunchy.premium().await;
+        if self.test_run {
+            test_run::start();
+            test_run::pass(
+                "login",
+                if premium {
+                    "logged in with a premium account"
+                } else {
+                    "logged in without premium"
+                },
+            )
+        }
+        if !premium {
             warn!("You may not be able to download all requested videos when logging in anonymously or using a non-premium account")
         }
 
+        let mut post_steps = PostSteps {
+            nfo_writer: self.write_nfo.then(NfoWriter::default),
+            artwork_writer: self
+                .write_artwork
+                .then(|| ArtworkWriter::new(ctx.client.clone())),
+            post_hook: self.post_hook.clone().map(|command| PostHook {
+                command,
+                timeout: std::time::Duration::from_secs(self.post_hook_timeout),
+                fail_on_error: self.post_hook_fail,
+            }),
+        };
+        // the episodes which are muxed while the next one is downloaded, only used with
+        // `--pipeline`
+        let mut pending = FuturesUnordered::new();
+
+        let mut summary = Summary::default();
+        listen_for_pause_keys();
+
+        let ffmpeg_concurrency = self.ffmpeg_concurrency.unwrap_or_else(|| {
+            let (_, output_args) = self
+                .ffmpeg_preset
+                .clone()
+                .unwrap_or_default()
+                .into_input_output_args();
+            if is_stream_copy(&output_args, "v") {
+                1
+            } else {
+                num_cpus::get()
+            }
+        });
+        let ffmpeg_semaphore = Arc::new(Semaphore::new(ffmpeg_concurrency.max(1)));
+
+        let mut batch_state = match &self.state_file {
+            Some(path) if self.retry_failed => Some(BatchState::load(path.clone(), &[])?),
+            Some(path) if self.resume_batch => Some(BatchState::load(path.clone(), &self.urls)?),
+            Some(path) => Some(BatchState::new(path.clone(), &self.urls)?),
+            None => None,
+        };
+
         let mut parsed_urls = vec![];
 
         let output_supports_softsubs = SOFTSUB_CONTAINERS.contains(
@@ -236,20 +1197,54 @@ impl Execute for Download {
             output_supports_softsubs
         };
 
-        for (i, url) in self.urls.clone().into_iter().enumerate() {
+        let urls = if self.retry_failed {
+            let failed_urls = batch_state.as_ref().unwrap().failed_urls();
+            if failed_urls.is_empty() {
+                info!("No failed videos found in the state file");
+                return Ok(());
+            }
+            info!("Retrying {} failed video(s)", failed_urls.len());
+            failed_urls
+        } else {
+            self.urls.clone()
+        };
+
+        for (i, url) in urls.into_iter().enumerate() {
+            if batch_state
+                .as_ref()
+                .is_some_and(|state| state.is_completed(&url))
+            {
+                info!("Skipping url {} as it was already completed", i + 1);
+                continue;
+            }
+
             let progress_handler = progress!("Parsing url {}", i + 1);
-            match parse_url(&ctx.crunchy, url.clone(), true).await {
-                Ok((media_collection, url_filter)) => {
+            match parse_url_or_crunchylist(&ctx.crunchy, url.clone(), true).await {
+                Ok(parsed) => {
                     progress_handler.stop(format!("Parsed url {}", i + 1));
-                    parsed_urls.push((media_collection, url_filter))
+                    // the url is only completed if all items of a crunchylist are completed
+                    let len = parsed.len();
+                    for (j, (media_collection, url_filter)) in parsed.into_iter().enumerate() {
+                        // the urls of failed videos aren't tracked as batch urls
+                        let last_of_url = j + 1 == len && !self.retry_failed;
+                        parsed_urls.push((
+                            i,
+                            url.clone(),
+                            media_collection,
+                            url_filter,
+                            last_of_url,
+                        ))
+                    }
                 }
                 Err(e) => bail!("url {} could not be parsed: {}", url, e),
             };
         }
 
-        for (i, (media_collection, url_filter)) in parsed_urls.into_iter().enumerate() {
+        let parsed_urls_len = parsed_urls.len();
+        for (i, url, media_collection, url_filter, last_of_url) in parsed_urls {
             let progress_handler = progress!("Fetching series details");
-            let single_format_collection = Filter::new(
+            let metadata_timer = Phase::Metadata.start(format!("url {}", i + 1));
+            let mut single_format_collection = Filter::new(
                 url_filter,
                 vec![self.audio.clone()],
                 self.subtitle.as_ref().map_or(vec![], |s| vec![s.clone()]),
@@ -300,15 +1295,91 @@ impl Execute for Download {
                 self.skip_specials,
                 ctx.crunchy.premium().await,
             )
+            .air_date_range(self.since, self.until)
+            .title_patterns(
+                [self.episode_matches.clone(), self.title_contains.clone()]
+                    .into_iter()
+                    .flatten()
+                    .collect(),
+            )
+            .watch_status(
+                (self.watched_only || self.unwatched_only).then(|| WatchFilter {
+                    crunchy: ctx.crunchy.clone(),
+                    watched: self.watched_only,
+                    threshold: self.watched_threshold,
+                }),
+            )
+            .fetch_series_description(
+                self.post_hook.is_some()
+                    || Format::has_series_description_fmt(&self.output)
+                    || self
+                        .output_specials
+                        .as_ref()
+                        .is_some_and(Format::has_series_description_fmt),
+            )
+            .only_new_seasons(
+                self.only_new_seasons
+                    .then(|| batch_state.as_ref().map(|s| s.known_seasons()))
+                    .flatten(),
+            )
+            .include_extras(self.include_extras)
+            .auto_padding(
+                Format::has_auto_padding_fmt(&self.output)
+                    || self
+                        .output_specials
+                        .as_ref()
+                        .is_some_and(Format::has_auto_padding_fmt),
+            )
             .visit(media_collection)
             .await?;
 
             if single_format_collection.is_empty() {
                 progress_handler.stop(format!("Skipping url {} (no matching videos found)", i + 1));
+                if let Some(batch_state) = batch_state.as_mut().filter(|_| last_of_url) {
+                    batch_state.set_status(&url, BatchUrlStatus::Completed)?
+                }
                 continue;
             }
+            metadata_timer.stop();
             progress_handler.stop(format!("Loaded series information for url {}", i + 1));
 
+            if self.interactive {
+                single_format_collection.interactive_select();
+                if single_format_collection.is_empty() {
+                    info!("Nothing selected for url {}", i + 1);
+                    continue;
+                }
+            }
+
+            let single_episode = is_single_episode(parsed_urls_len, single_format_collection.len());
+            if self.pipe != PipeMode::Muxed && !single_episode {
+                bail!("`--pipe` can only be used if the url(s) resolve to a single episode")
+            }
+            if (!self.external_audio.is_empty() || !self.external_subtitle.is_empty())
+                && !single_episode
+            {
+                bail!("`--external-audio` and `--external-subtitle` can only be used if the url(s) resolve to a single episode")
+            }
+
+            if self.test_run {
+                if single_format_collection.is_empty() {
+                    bail!("No video found for the url")
+                }
+                test_run::pass(
+                    "selection",
+                    format!("{} video(s) found", single_format_collection.len()),
+                )
+            }
+
+            if self.list {
+                for single_formats in single_format_collection.into_iter() {
+                    for single_format in single_formats {
+                        list_variants(&single_format).await?
+                    }
+                }
+                continue;
+            }
+
             single_format_collection.full_visual_output();
 
             let download_builder =
@@ -322,6 +1393,48 @@ impl Execute for Download {
                     })
                     .ffmpeg_preset(self.ffmpeg_preset.clone().unwrap_or_default())
                     .ffmpeg_threads(self.ffmpeg_threads)
+                    .strict_copy(self.strict_copy)
+                    .ffmpeg_log(self.ffmpeg_log.clone())
+                    .external_audios(self.external_audio.clone())
+                    .external_subtitles(self.external_subtitle.clone())
+                    .loudnorm(self.loudnorm)
+                    .audio_codec(self.audio_codec.clone())
+                    .mp4_layout(if self.fragmented {
+                        Mp4Layout::Fragmented
+                    } else if self.faststart {
+                        Mp4Layout::Faststart
+                    } else {
+                        Mp4Layout::Default
+                    })
+                    .audio_bitrate(self.audio_bitrate)
+                    .ffmpeg_semaphore(Some(ffmpeg_semaphore.clone()))
+                    .subtitle_format(self.subtitle_format.clone())
+                    .dual_subtitles(!self.dual_subtitle.is_empty())
+                    .subtitle_encoding(self.subtitle_encoding.filter(|e| *e != UTF_8))
+                    .subtitle_offsets(self.subtitle_offset.clone())
+                    .forced_subtitle_styles(if self.sign_styles.is_empty() {
+                        DEFAULT_SIGN_STYLES.iter().map(|s| s.to_string()).collect()
+                    } else {
+                        self.sign_styles.clone()
+                    })
+                    .subtitle_drop_styles(match self.subtitle_styles {
+                        SubtitleStyles::All => vec![],
+                        SubtitleStyles::DialogueOnly if self.sign_styles.is_empty() => {
+                            DEFAULT_SIGN_STYLES.iter().map(|s| s.to_string()).collect()
+                        }
+                        SubtitleStyles::DialogueOnly => self.sign_styles.clone(),
+                    })
+                    .raw(self.raw)
+                    .pipe(self.pipe.clone())
+                    .include_chapters(self.include_chapters)
+                    .cut_intro(self.cut_intro)
+                    .cut_outro(self.cut_outro)
+                    .event_sink(Some(if self.test_run {
+                        Arc::new(TestRunEventSink::default()) as Arc<dyn EventSink>
+                    } else {
+                        Arc::new(CliEventSink::default())
+                    }))
+                    .max_segments(self.test_run.then_some(TEST_RUN_SEGMENTS))
                     .threads(self.threads)
                     .audio_locale_output_map(HashMap::from([(
                         self.audio.clone(),
@@ -333,11 +1446,14 @@ impl Execute for Download {
                         }),
                     );
 
+            let season_ids = single_format_collection.season_ids();
+
             for mut single_formats in single_format_collection.into_iter() {
                 // the vec contains always only one item
                 let single_format = single_formats.remove(0);
 
-                let (download_format, format) = get_format(
+                let metadata_timer = Phase::Metadata.start(&single_format.title);
+                let (download_format, format) = match get_format(
                     &self,
                     &single_format,
                     if self.force_hardsub {
@@ -348,42 +1464,280 @@ impl Execute for Download {
                         !output_supports_softsubs
                     },
                 )
-                .await?;
+                .await
+                {
+                    Ok(ok) => ok,
+                    Err(e) if self.test_run => return Err(e),
+                    Err(e) => {
+                        if let Some(mismatch) = e.downcast_ref::<LocaleMismatch>() {
+                            warn!("Skipping {}", mismatch);
+                            summary.locale_mismatch(mismatch.title.clone());
+                            continue;
+                        }
+                        record_failure(batch_state.as_mut(), &single_format, e)?;
+                        continue;
+                    }
+                };
+                metadata_timer.stop();
 
-                let mut downloader = download_builder.clone().build();
+                let mut downloader = download_builder
+                    .clone()
+                    .container_tags(if self.embed_metadata {
+                        format.container_tags()
+                    } else {
+                        vec![]
+                    })
+                    .ffmpeg_extra_args(
+                        self.loaded_preset_map
+                            .args_for(&single_format)
+                            .cloned()
+                            .unwrap_or_default(),
+                    )
+                    .build();
+                let fingerprint = self.check_updates.then(|| download_format.fingerprint());
                 downloader.add_format(download_format);
 
-                let formatted_path = if format.is_special() {
-                    format.format_path(
-                        self.output_specials
-                            .as_ref()
-                            .map_or((&self.output).into(), |so| so.into()),
+                if self.test_run {
+                    test_run::pass(
+                        "streams",
+                        format!("fetched the streams of {}", single_format.title),
+                    );
+                    // the output is only created to check that the streams can be muxed into a
+                    // file of this format
+                    let extension = Path::new(&self.output)
+                        .extension()
+                        .unwrap_or_default()
+                        .to_string_lossy()
+                        .to_string();
+                    let dst = tempfile(format!(".{}", extension))?.into_temp_path();
+                    downloader.download(&dst).await?;
+                    info!("Test run passed, no file was written");
+                    return Ok(());
+                }
+
+                let formatted_path =
+                    if let (true, Some(output_extras)) = (format.extra, &self.output_extras) {
+                        format.format_path(
+                            output_extras.into(),
+                            self.universal_output,
+                            self.language_tagging.as_ref(),
+                        )
+                    } else if format.is_special() {
+                        format.format_path(
+                            self.output_specials
+                                .as_ref()
+                                .map_or((&self.output).into(), |so| so.into()),
+                            self.universal_output,
+                            self.language_tagging.as_ref(),
+                        )
+                    } else {
+                        format.format_path(
+                            (&self.output).into(),
+                            self.universal_output,
+                            self.language_tagging.as_ref(),
+                        )
+                    };
+                let (mut path, changed) = free_file(formatted_path.clone());
+                // a previous run was interrupted after muxing the existing file, so it's finished
+                // instead of being downloaded again
+                let resumed = changed && MuxCheckpoint::exists(&formatted_path);
+                // the existing file is replaced if crunchyroll updated the video since it was
+                // downloaded
+                let updated = !resumed
+                    && changed
+                    && self.skip_existing
+                    && match (&mut batch_state, &fingerprint) {
+                        (Some(batch_state), Some(fingerprint)) => {
+                            batch_state.is_updated(&single_format.url(), fingerprint)?
+                        }
+                        _ => false,
+                    };
+
+                if resumed {
+                    debug!(
+                        "Resuming already muxed file '{}'",
+                        formatted_path.to_string_lossy()
+                    );
+                    path.clone_from(&formatted_path)
+                } else if updated {
+                    info!(
+                        "'{}' was updated on Crunchyroll, downloading it again",
+                        formatted_path.to_string_lossy()
+                    );
+                    path.clone_from(&formatted_path)
+                } else if changed && self.skip_existing {
+                    if verify_existing_checksum(self.checksum.as_ref(), &formatted_path)? {
+                        debug!(
+                            "Skipping already existing file '{}'",
+                            formatted_path.to_string_lossy()
+                        );
+                        summary.skipped();
+                        continue;
+                    }
+                    path.clone_from(&formatted_path)
+                }
+
+                // the output directory is checked before anything is downloaded, as writing the
+                // output file only happens after all streams were downloaded
+                if !is_special_file(&path) && path.to_string_lossy() != "-" {
+                    if let Err(e) = check_dir_writable(path.parent().unwrap_or(Path::new(""))) {
+                        record_failure(batch_state.as_mut(), &single_format, e.into())?;
+                        continue;
+                    }
+                }
+
+                let hardsub_path = if let Some(output_hard) = &self.output_hard {
+                    let formatted_hardsub_path = format.format_path(
+                        output_hard.into(),
                         self.universal_output,
                         self.language_tagging.as_ref(),
-                    )
+                    );
+                    let hardsub_path = if resumed || updated {
+                        formatted_hardsub_path
+                    } else {
+                        free_file(formatted_hardsub_path).0
+                    };
+                    if hardsub_path == path {
+                        bail!("The hardsub output file must not be the same as the output file")
+                    }
+                    if let Err(e) =
+                        check_dir_writable(hardsub_path.parent().unwrap_or(Path::new("")))
+                    {
+                        record_failure(batch_state.as_mut(), &single_format, e.into())?;
+                        continue;
+                    }
+                    downloader.add_hardsub_output(hardsub_path.clone());
+                    Some(hardsub_path)
                 } else {
-                    format.format_path(
-                        (&self.output).into(),
-                        self.universal_output,
-                        self.language_tagging.as_ref(),
-                    )
+                    None
                 };
-                let (path, changed) = free_file(formatted_path.clone());
 
-                if changed && self.skip_existing {
-                    debug!(
-                        "Skipping already existing file '{}'",
-                        formatted_path.to_string_lossy()
-                    );
-                    continue;
+                // every episode gets its own log file, the messages of an episode which is muxed
+                // while the next one is downloaded (`--pipeline`) still end up in the right one
+                let log_file = LogFile::open(&path);
+                log_file.clone().sync_scope(|| format.visual_output(&path));
+
+                let episode = DownloadedEpisode {
+                    single_format,
+                    format,
+                    path,
+                    hardsub_path,
+                    fingerprint,
+                    log_file,
+                };
+                if self.pipeline {
+                    // the streams of this episode are downloaded while the previous episodes are
+                    // muxed. at most `--ffmpeg-concurrency` episodes are muxed at the same time,
+                    // which also limits how many temporary files exist
+                    while pending.len() >= ffmpeg_concurrency.max(1) {
+                        let Some((muxed, pending_episode)) = pending.next().await else {
+                            break;
+                        };
+                        finish_episode(
+                            &self,
+                            pending_episode,
+                            muxed,
+                            &mut post_steps,
+                            &mut summary,
+                            &mut batch_state,
+                        )
+                        .await?;
+                    }
+                    let fetched = {
+                        let fetch = episode
+                            .log_file
+                            .clone()
+                            .scope(downloader.fetch(&episode.path));
+                        tokio::pin!(fetch);
+                        loop {
+                            select! {
+                                fetched = &mut fetch => break fetched,
+                                Some((muxed, pending_episode)) = pending.next() => {
+                                    finish_episode(
+                                        &self,
+                                        pending_episode,
+                                        muxed,
+                                        &mut post_steps,
+                                        &mut summary,
+                                        &mut batch_state,
+                                    )
+                                    .await?
+                                }
+                            }
+                        }
+                    };
+                    match fetched {
+                        Ok(pending_mux) => {
+                            let log_file = episode.log_file.clone();
+                            pending.push(async move {
+                                (log_file.scope(pending_mux.mux()).await, episode)
+                            })
+                        }
+                        Err(e) => record_failure(batch_state.as_mut(), &episode.single_format, e)?,
+                    }
+                } else {
+                    let result = episode
+                        .log_file
+                        .clone()
+                        .scope(downloader.download(&episode.path))
+                        .await;
+                    finish_episode(
+                        &self,
+                        episode,
+                        result,
+                        &mut post_steps,
+                        &mut summary,
+                        &mut batch_state,
+                    )
+                    .await?;
                 }
+            }
 
-                format.visual_output(&path);
+            if batch_state.is_some() {
+                // the url must not be marked as completed before all of its episodes are muxed
+                while let Some((result, episode)) = pending.next().await {
+                    finish_episode(
+                        &self,
+                        episode,
+                        result,
+                        &mut post_steps,
+                        &mut summary,
+                        &mut batch_state,
+                    )
+                    .await?;
+                }
+            }
 
-                downloader.download(&path).await?
+            if let Some(batch_state) = &mut batch_state {
+                batch_state.add_known_seasons(season_ids)?;
+                if last_of_url {
+                    batch_state.set_status(&url, BatchUrlStatus::Completed)?
+                }
             }
         }
 
+        while let Some((result, episode)) = pending.next().await {
+            finish_episode(
+                &self,
+                episode,
+                result,
+                &mut post_steps,
+                &mut summary,
+                &mut batch_state,
+            )
+            .await?;
+        }
+
+        if let Some(failed) = batch_state
+            .map(|s| s.failed_urls().len())
+            .filter(|failed| *failed > 0)
+        {
+            bail!(
+                "{} video(s) failed to download, retry them with `--retry-failed`",
+                failed
+            )
+        }
+
         Ok(())
     }
 }
@@ -397,7 +1751,11 @@ async fn get_format(
     let Some((video, audio, contains_hardsub)) = stream_data_from_stream(
         &stream,
         &download.resolution,
-        if try_peer_hardsubs {
+        download.min_resolution.as_ref(),
+        max_resolution(download.max_width, download.max_height).as_ref(),
+        download.bitrate,
+        // peer hardsubs only contain a single language
+        if try_peer_hardsubs && download.dual_subtitle.is_empty() {
             download.subtitle.clone()
         } else {
             None
@@ -444,34 +1802,90 @@ async fn get_format(
     } else {
         None
     };
+    let secondary_subtitle = match (&subtitle, download.dual_subtitle.get(1)) {
+        (Some(_), Some(secondary_locale)) => {
+            let secondary_subtitle = stream
+                .subtitles
+                .get(secondary_locale)
+                .or_else(|| stream.captions.get(secondary_locale))
+                .cloned();
+            if secondary_subtitle.is_none() {
+                warn!(
+                    "{} has no {} subtitles, only {} subtitles are added",
+                    single_format.title, secondary_locale, download.dual_subtitle[0]
+                )
+            }
+            secondary_subtitle
+        }
+        _ => None,
+    };
+
+    if download.strict_locale {
+        let mut reasons = vec![];
+        if single_format.audio != download.audio {
+            reasons.push(format!(
+                "{} audio instead of {}",
+                single_format.audio, download.audio
+            ))
+        }
+        if let (Some(subtitle_locale), None, false) =
+            (&download.subtitle, &subtitle, contains_hardsub)
+        {
+            reasons.push(format!("no {} subtitles", subtitle_locale))
+        }
+        if let (Some(_), Some(secondary_locale), None) = (
+            &subtitle,
+            download.dual_subtitle.get(1),
+            &secondary_subtitle,
+        ) {
+            reasons.push(format!("no {} subtitles", secondary_locale))
+        }
+        if !reasons.is_empty() {
+            stream.invalidate().await?;
+            bail!(LocaleMismatch {
+                title: single_format.title.clone(),
+                reasons,
+            })
+        }
+    }
+
+    // dubs contain signs and songs in the original language, which are only translated in the
+    // subtitles of the dub language. if enabled and without explicitly requested subtitles, these
+    // lines are added as forced subtitles
+    let forced_subtitle = if download.subtitle.is_none()
+        && download.forced_subs
+        && single_format.audio != Locale::ja_JP
+    {
+        stream.subtitles.get(&single_format.audio).cloned()
+    } else {
+        None
+    };
+
+    let mut subtitles = subtitle.map_or(vec![], |s| {
+        vec![(
+            s,
+            single_format.audio != Locale::ja_JP && stream.subtitles.len() == 1,
+        )]
+    });
+    if let Some(secondary_subtitle) = secondary_subtitle {
+        subtitles.push((secondary_subtitle, false))
+    }
 
     let download_format = DownloadFormat {
         video: (video.clone(), single_format.audio.clone()),
         audios: vec![(audio, single_format.audio.clone())],
-        subtitles: subtitle.clone().map_or(vec![], |s| {
-            vec![(
-                s,
-                single_format.audio != Locale::ja_JP && stream.subtitles.len() == 1,
-            )]
-        }),
+        subtitles: subtitles.clone(),
+        forced_subtitle,
         metadata: DownloadFormatMetadata {
-            skip_events: if download.include_chapters {
+            skip_events: if download.include_chapters || download.cut_intro || download.cut_outro {
                 single_format.skip_events().await?
             } else {
                 None
             },
+            lyrics: single_format.is_music(),
         },
     };
-    let mut format = Format::from_single_formats(vec![(
-        single_format.clone(),
-        video,
-        subtitle.map_or(vec![], |s| {
-            vec![(
-                s,
-                single_format.audio != Locale::ja_JP && stream.subtitles.len() == 1,
-            )]
-        }),
-    )]);
+    let mut format = Format::from_single_formats(vec![(single_format.clone(), video, subtitles)]);
     if contains_hardsub {
         let (_, subs) = format.locales.get_mut(0).unwrap();
         subs.push(download.subtitle.clone().unwrap())
@@ -481,3 +1895,97 @@ async fn get_format(
 
     Ok((download_format, format))
 }
+
+/// The steps which are run for every episode after it was muxed.
+struct PostSteps {
+    nfo_writer: Option<NfoWriter>,
+    artwork_writer: Option<ArtworkWriter>,
+    post_hook: Option<PostHook>,
+}
+
+/// An episode whose streams are downloaded (or are being downloaded) into `path`.
+struct DownloadedEpisode {
+    single_format: SingleFormat,
+    format: Format,
+    path: PathBuf,
+    hardsub_path: Option<PathBuf>,
+    fingerprint: Option<String>,
+    log_file: LogFile,
+}
+
+/// Runs all steps after `episode` was muxed with `result`. If muxing failed, the failure is
+/// recorded instead.
+async fn finish_episode(
+    download: &Download,
+    episode: DownloadedEpisode,
+    result: Result<()>,
+    post_steps: &mut PostSteps,
+    summary: &mut Summary,
+    batch_state: &mut Option<BatchState>,
+) -> Result<()> {
+    let DownloadedEpisode {
+        single_format,
+        format,
+        path,
+        hardsub_path,
+        fingerprint,
+        log_file,
+    } = episode;
+
+    // with `--pipeline`, the next episode is already downloaded while this one is finished, so
+    // the messages are written into the log file of this episode explicitly
+    log_file
+        .scope(async move {
+            if let Err(e) = result {
+                return record_failure(batch_state.as_mut(), &single_format, e);
+            }
+            summary.downloaded();
+
+            if let Some(checksum) = &download.checksum {
+                checksum.write_sidecar(&path)?;
+            }
+
+            if download.set_mtime {
+                for file in [Some(&path), hardsub_path.as_ref()].into_iter().flatten() {
+                    set_mtime(file, format.release_date(), download.set_atime)?
+                }
+            }
+
+            let template = if format.is_special() {
+                download
+                    .output_specials
+                    .as_ref()
+                    .unwrap_or(&download.output)
+            } else {
+                &download.output
+            };
+            if let Some(nfo_writer) = &mut post_steps.nfo_writer {
+                nfo_writer
+                    .write(template, &path, &single_format, &format)
+                    .await?
+            }
+            if let Some(artwork_writer) = &mut post_steps.artwork_writer {
+                artwork_writer
+                    .write(template, &path, &single_format)
+                    .await?
+            }
+
+            if let Some(post_hook) = &post_steps.post_hook {
+                post_hook.run(&path, &format).await?
+            }
+
+            MuxCheckpoint::remove(&path);
+            if let Some(batch_state) = batch_state {
+                if let Some(fingerprint) = fingerprint {
+                    batch_state.set_fingerprint(single_format.url(), fingerprint)?
+                }
+                batch_state.remove_failed(&single_format.url())?
+            }
+            webhook::episode_completed(
+                &single_format,
+                [Some(path), hardsub_path].into_iter().flatten().collect(),
+            );
+            Ok(())
+        })
+        .await
+}
@@ -0,0 +1,171 @@
+use crate::utils::context::Context;
+use crate::utils::filter::url_filtered_episodes;
+use crate::utils::parse::parse_url;
+use crate::Execute;
+use anyhow::{bail, Result};
+use crunchyroll_rs::{Episode, Locale};
+use log::info;
+use serde::Serialize;
+use std::fs;
+use std::path::PathBuf;
+
+#[derive(Clone, Debug, Default)]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    Json,
+}
+
+impl ExportFormat {
+    pub fn parse(s: &str) -> Result<ExportFormat, String> {
+        Ok(match s.to_lowercase().as_str() {
+            "csv" => ExportFormat::Csv,
+            "json" => ExportFormat::Json,
+            _ => return Err(format!("'{}' is not a valid export format", s)),
+        })
+    }
+}
+
+#[derive(Debug, clap::Parser)]
+#[clap(about = "Export the episode list of series, seasons or episodes")]
+#[command(arg_required_else_help(true))]
+pub struct Export {
+    #[arg(help = "Format of the export. Valid formats are 'csv' (default) and 'json'")]
+    #[arg(short, long, default_value = "csv")]
+    #[arg(value_parser = ExportFormat::parse)]
+    format: ExportFormat,
+
+    #[arg(help = "File to write the export to. If not set, it's printed to stdout")]
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    #[arg(help = "Url(s) to Crunchyroll series, seasons or episodes. \
+    Like for the download, a filter can be appended to select specific seasons or episodes (e.g. '[S1E4-E8]')")]
+    #[arg(required = true)]
+    urls: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct ExportRow {
+    series: String,
+    season_number: u32,
+    season_title: String,
+    episode_number: String,
+    title: String,
+    id: String,
+    air_date: String,
+    audio_locales: Vec<Locale>,
+    subtitle_locales: Vec<Locale>,
+}
+
+impl ExportRow {
+    fn from_episode(episode: &Episode) -> Self {
+        let mut audio_locales = vec![episode.audio_locale.clone()];
+        for version in &episode.versions {
+            if !audio_locales.contains(&version.audio_locale) {
+                audio_locales.push(version.audio_locale.clone())
+            }
+        }
+
+        Self {
+            series: episode.series_title.clone(),
+            season_number: episode.season_number,
+            season_title: episode.season_title.to_string(),
+            episode_number: if episode.episode.is_empty() {
+                episode.sequence_number.to_string()
+            } else {
+                episode.episode.clone()
+            },
+            title: episode.title.clone(),
+            id: episode.id.clone(),
+            air_date: episode.episode_air_date.format("%Y-%m-%d").to_string(),
+            audio_locales,
+            subtitle_locales: episode.subtitle_locales.clone(),
+        }
+    }
+}
+
+impl Execute for Export {
+    async fn execute(self, ctx: Context) -> Result<()> {
+        let mut rows = vec![];
+
+        for (i, url) in self.urls.iter().enumerate() {
+            let (media_collection, url_filter) =
+                match parse_url(&ctx.crunchy, url.clone(), true).await {
+                    Ok(ok) => ok,
+                    Err(e) => bail!("url {} could not be parsed: {}", url, e),
+                };
+
+            let episodes = url_filtered_episodes(media_collection, &url_filter, i + 1).await?;
+            rows.extend(episodes.iter().map(ExportRow::from_episode));
+        }
+
+        let content = match self.format {
+            ExportFormat::Csv => to_csv(&rows),
+            ExportFormat::Json => serde_json::to_string_pretty(&rows)? + "\n",
+        };
+        if let Some(output) = &self.output {
+            if let Some(parent) = output.parent() {
+                if !parent.as_os_str().is_empty() && !parent.exists() {
+                    fs::create_dir_all(parent)?
+                }
+            }
+            fs::write(output, content)?;
+            info!(
+                "Exported {} episodes to {}",
+                rows.len(),
+                output.to_string_lossy()
+            )
+        } else {
+            print!("{}", content)
+        }
+
+        Ok(())
+    }
+}
+
+fn to_csv(rows: &[ExportRow]) -> String {
+    let mut csv = "series,season_number,season_title,episode_number,title,id,air_date,audio_locales,subtitle_locales\n".to_string();
+    for row in rows {
+        let fields = [
+            row.series.clone(),
+            row.season_number.to_string(),
+            row.season_title.clone(),
+            row.episode_number.clone(),
+            row.title.clone(),
+            row.id.clone(),
+            row.air_date.clone(),
+            join_locales(&row.audio_locales),
+            join_locales(&row.subtitle_locales),
+        ];
+        csv.push_str(
+            &fields
+                .iter()
+                .map(|f| csv_escape(f))
+                .collect::<Vec<String>>()
+                .join(","),
+        );
+        csv.push('\n')
+    }
+    csv
+}
+
+/// Quotes a field as described in RFC 4180 if it contains a comma, quote or line break. Quotes
+/// inside the field are doubled. Leading and trailing whitespace is quoted too, as some
+/// spreadsheet programs strip it otherwise.
+fn csv_escape(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) || field.trim() != field {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Locales of a single field are separated by spaces, so that the field doesn't have to be quoted.
+fn join_locales(locales: &[Locale]) -> String {
+    locales
+        .iter()
+        .map(|l| l.to_string())
+        .collect::<Vec<String>>()
+        .join(" ")
+}
@@ -1,28 +1,59 @@
+use crate::utils::clap::{is_default_value, redacted_header_value};
+use crate::utils::config::dump_config;
 use crate::utils::context::Context;
-use crate::utils::locale::system_locale;
+use crate::utils::dns::DohResolver;
+use crate::utils::http_timing::{http_timing_enabled, HttpTimings, TimingResolver};
+use crate::utils::locale::{resolve_locales, system_locale};
 use crate::utils::log::{progress, CliLogger};
+use crate::utils::os::remove_partial_outputs;
+use crate::utils::reauth::spawn_reauth_task;
+use crate::utils::test_run;
+use crate::utils::timing::Phase;
+use crate::utils::webhook;
 use anyhow::bail;
 use anyhow::Result;
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
 use crunchyroll_rs::crunchyroll::CrunchyrollBuilder;
 use crunchyroll_rs::error::Error;
 use crunchyroll_rs::{Crunchyroll, Locale};
 use log::{debug, error, warn, LevelFilter};
-use reqwest::{Client, Proxy};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Client, ClientBuilder, Proxy, Url};
+use std::collections::BTreeMap;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
 use std::{env, fs};
 
 mod archive;
+mod check;
 mod download;
+mod export;
+mod list_downloaded;
 mod login;
+mod probe;
+mod queue;
+mod repair;
 mod search;
+mod template_test;
 mod utils;
+mod watch;
 
-use crate::utils::rate_limit::RateLimiterService;
+use crate::utils::rate_limit::{AdaptiveRequestRate, RateLimiterService};
 pub use archive::Archive;
+pub use check::Check;
 use dialoguer::console::Term;
 pub use download::Download;
+pub use export::Export;
+pub use list_downloaded::ListDownloaded;
 pub use login::Login;
+pub use probe::Probe;
+pub use queue::Queue;
+pub use repair::Repair;
 pub use search::Search;
+pub use template_test::TemplateTest;
+pub use watch::Watch;
 
 trait Execute {
     fn pre_check(&mut self) -> Result<()> {
@@ -41,7 +72,13 @@ pub struct Cli {
     #[arg(
         help = "Overwrite the language in which results are returned. Default is your system language"
     )]
-    #[arg(global = true, long)]
+    #[arg(
+        long_help = "Overwrite the language in which results (metadata like titles and descriptions, which are also used for output templates) are returned. \
+            This is independent of the audio and subtitle languages. \
+            IETF tagged language codes (e.g. 'de') can be used too. \
+            Default is your system language, or en-US if it isn't supported by Crunchyroll"
+    )]
+    #[arg(global = true, long, visible_alias = "locale")]
     lang: Option<Locale>,
 
     #[arg(
@@ -67,9 +104,40 @@ pub struct Cli {
     proxy: Option<(Option<Proxy>, Option<Proxy>)>,
 
     #[arg(help = "Use custom user agent")]
-    #[arg(global = true, long)]
+    #[arg(
+        long_help = "Use a custom user agent for all requests, to Crunchyroll as well as for downloads. \
+            By default, the user agent of the underlying library is used"
+    )]
+    #[arg(global = true, long, value_parser = crate::utils::clap::clap_parse_user_agent)]
     user_agent: Option<String>,
 
+    #[arg(help = "Add a custom header to all requests. Must be in format of 'Name: Value'")]
+    #[arg(
+        long_help = "Add a custom header to all requests, to Crunchyroll as well as for downloads (e.g. 'CF-Access-Client-Id: <id>' if you are behind a protected proxy). \
+            Must be in format of 'Name: Value'. Can be used multiple times. \
+            The 'Host', 'Content-Length' and 'Transfer-Encoding' headers cannot be set"
+    )]
+    #[arg(global = true, long = "header", value_parser = crate::utils::clap::clap_parse_header)]
+    headers: Vec<(HeaderName, HeaderValue)>,
+
+    #[arg(help = "Resolve a host to a specific ip address. Must be in format of 'host:ip'")]
+    #[arg(
+        long_help = "Resolve a host to a specific ip address instead of using dns, for requests to Crunchyroll as well as for downloads. \
+            Must be in format of 'host:ip' (IPv6 addresses may be wrapped in brackets, e.g. 'example.com:[::1]'). \
+            Can be used multiple times, also for the same host to provide multiple addresses"
+    )]
+    #[arg(global = true, long, value_parser = crate::utils::clap::clap_parse_resolve)]
+    resolve: Vec<(String, IpAddr)>,
+    #[arg(help = "Resolve hosts via DNS-over-HTTPS with the given server url")]
+    #[arg(
+        long_help = "Resolve hosts via DNS-over-HTTPS instead of the system dns, for requests to Crunchyroll as well as for downloads. \
+            The server must support the JSON api (e.g. 'https://cloudflare-dns.com/dns-query' or 'https://dns.google/resolve'). \
+            The host of the server itself is resolved via the system dns, use an ip address in the url or `--resolve` to avoid this. \
+            Hosts set with `--resolve` are not resolved via DNS-over-HTTPS"
+    )]
+    #[arg(global = true, long, value_parser = crate::utils::clap::clap_parse_doh)]
+    doh: Option<Url>,
+
     #[arg(
         help = "Maximal speed to download/request (may be a bit off here and there). Must be in format of <number>[B|KB|MB]"
     )]
@@ -79,10 +147,110 @@ pub struct Cli {
     #[arg(global = true, long, value_parser = crate::utils::clap::clap_parse_speed_limit)]
     speed_limit: Option<u32>,
 
+    #[arg(
+        help = "Maximal number of requests per second. The rate is lowered automatically if Crunchyroll responds with 'too many requests'"
+    )]
+    #[arg(
+        long_help = "Maximal number of requests per second, for requests to Crunchyroll as well as for downloads. \
+    If a request is answered with 'too many requests' (429), the rate is halved and afterwards slowly increased again, up to the given value, as long as requests are successful. \
+    Must be at least 0.5, the rate is never lowered below this value"
+    )]
+    #[arg(global = true, long, value_parser = crate::utils::clap::clap_parse_request_rate)]
+    adaptive_request_rate: Option<f64>,
+
+    #[arg(help = "Minutes to wait for the end of a Crunchyroll maintenance")]
+    #[arg(
+        long_help = "Minutes to wait for the end of a Crunchyroll maintenance. \
+    While Crunchyroll is under maintenance, requests are retried every 30 seconds until the given time has elapsed. \
+    Without this flag, the command is aborted with an error"
+    )]
+    #[arg(global = true, long)]
+    wait_on_maintenance: Option<u64>,
+
+    #[arg(help = "Refresh the login session in the background, checking it every given minutes")]
+    #[arg(
+        long_help = "Refresh the login session in the background as soon as it expires. \
+    Crunchyroll sessions expire after a few minutes and are renewed before the next request otherwise. \
+    On very long runs this renews them in between, so that requests of the actual command never have to wait for it. \
+    The expiry of the session is checked at least every given minutes, as the command may renew it itself in the meantime"
+    )]
+    #[arg(global = true, long, value_parser = clap::value_parser!(u64).range(1..))]
+    reauth_interval: Option<u64>,
+
+    #[arg(help = "Seconds after which connecting to a server is aborted")]
+    #[arg(
+        long_help = "Seconds after which connecting to a server is aborted, for requests to Crunchyroll as well as for downloads. \
+            By default, there is no connect timeout"
+    )]
+    #[arg(global = true, long)]
+    connect_timeout: Option<u64>,
+    #[arg(help = "Seconds after which a request is aborted if no data was received")]
+    #[arg(
+        long_help = "Seconds after which a request is aborted if no data was received, for requests to Crunchyroll as well as for downloads. \
+            The timeout is reset every time data is received, so it doesn't limit the total duration of a request. \
+            By default, there is no read timeout. Independent of this, a single video segment download is always aborted after 60 seconds and retried"
+    )]
+    #[arg(global = true, long)]
+    read_timeout: Option<u64>,
+
+    #[arg(help = "Disable spinners and progress bars")]
+    #[arg(
+        long_help = "Disable spinners and progress bars and print plain status lines instead. \
+            This is done automatically on terminals which are not able to display them (`TERM=dumb`)"
+    )]
+    #[arg(global = true, long, default_value_t = false)]
+    no_spinner: bool,
+    #[arg(help = "Maximum number of progress steps which are printed per second")]
+    #[arg(
+        long_help = "Maximum number of progress steps (the `:: → ...` lines) which are printed per second. \
+            Steps exceeding the limit are not printed, instead the number of omitted steps is appended to the next printed one (e.g. `(+3 more)`). \
+            Warnings, errors and other log messages are never omitted. \
+            All steps are still written to the log files (`--log-dir`). By default, the rate is unlimited"
+    )]
+    #[arg(global = true, long, value_parser = clap::value_parser!(u32).range(1..))]
+    progress_rate: Option<u32>,
+
+    #[arg(help = "Write the effective configuration of the run as toml to the given file")]
+    #[arg(
+        long_help = "Write the effective configuration of the run (all given arguments and the defaults of all others) as toml to the given file, or to stderr if it's '-' (so it doesn't mix with output written to stdout, e.g. with `-o -`). \
+            This helps to share a working setup or to check which options were actually in effect. \
+            Credentials, webhook urls, header values and proxy credentials are redacted"
+    )]
+    #[arg(global = true, long)]
+    dump_config: Option<PathBuf>,
+
+    #[arg(
+        help = "Write the log of every downloaded file into a separate file in the given directory"
+    )]
+    #[arg(
+        long_help = "Write the log of every downloaded file into a separate file in the given directory. \
+            The log files are named after the output file (e.g. 'My Episode.mkv.log') and contain all info messages in the extended format with timestamps, also if the terminal output is reduced with `-q`. \
+            Use `-v` to include debug information. The terminal still shows the usual output"
+    )]
+    #[arg(global = true, long)]
+    log_dir: Option<PathBuf>,
+
     #[clap(subcommand)]
     command: Command,
 }
 
+/// Writes the effective configuration of the run with `args` to `path` (or stderr if it's `-`).
+fn write_config(path: &Path, args: &[String]) -> Result<()> {
+    let command = Cli::command();
+    let matches = command.clone().get_matches_from(args);
+    let config = dump_config(
+        &command,
+        &matches,
+        &format!("crunchy-cli {} configuration", version()),
+    )?;
+    if path.to_string_lossy() == "-" {
+        eprint!("{}", config)
+    } else {
+        fs::write(path, config)?
+    }
+    Ok(())
+}
+
 fn version() -> String {
     let package_version = env!("CARGO_PKG_VERSION");
     let git_commit_hash = env!("GIT_HASH");
@@ -98,16 +266,28 @@ fn version() -> String {
 #[derive(Debug, Subcommand)]
 enum Command {
     Archive(Archive),
+    Check(Check),
     Download(Download),
+    Export(Export),
+    ListDownloaded(ListDownloaded),
     Login(Login),
+    Probe(Probe),
+    Queue(Queue),
+    Repair(Repair),
     Search(Search),
+    TemplateTest(TemplateTest),
+    Watch(Watch),
 }
 
 #[derive(Debug, Parser)]
 struct Verbosity {
-    #[arg(help = "Verbose output")]
-    #[arg(global = true, short, long)]
-    verbose: bool,
+    #[arg(help = "Verbose output. Use it twice (`-vv`) for trace output")]
+    #[arg(
+        long_help = "Verbose output. Use it twice (`-vv`) for trace output, which additionally shows the timing of every http request, also of failed ones. \
+            The time of the tcp connect and tls handshake isn't shown separately, for new connections it's part of the time to first byte (or of the time until the request failed)"
+    )]
+    #[arg(global = true, short, long, action = clap::ArgAction::Count)]
+    verbose: u8,
 
     #[arg(help = "Quiet output. Does not print anything unless it's a error")]
     #[arg(
@@ -115,22 +295,92 @@ struct Verbosity {
     )]
     #[arg(global = true, short, long)]
     quiet: bool,
+
+    #[arg(help = "Quiet output, but print a summary at the end. Errors are still printed")]
+    #[arg(
+        long_help = "Quiet output, but print a summary at the end. Errors are still printed. \
+    Unlike `-q`/`--quiet`, the summary of how many files were downloaded or skipped is shown. Can be helpful for cron jobs"
+    )]
+    #[arg(global = true, long)]
+    quiet_summary_only: bool,
 }
 
 pub async fn main(args: &[String]) {
-    let mut cli: Cli = Cli::parse_from(args);
+    let matches = Cli::command().get_matches_from(args);
+    let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let sub_matches = matches.subcommand().unwrap().1;
+
+    if let Some(dump_config) = &cli.dump_config {
+        if let Err(e) = write_config(dump_config, args) {
+            eprintln!(
+                "Failed to write configuration to {}: {}",
+                dump_config.to_string_lossy(),
+                e
+            );
+            std::process::exit(1)
+        }
+    }
 
-    if cli.verbosity.verbose || cli.verbosity.quiet {
-        if cli.verbosity.verbose && cli.verbosity.quiet {
+    if let Some(log_dir) = &cli.log_dir {
+        if let Err(e) = fs::create_dir_all(log_dir) {
+            eprintln!(
+                "Failed to create log directory {}: {}",
+                log_dir.to_string_lossy(),
+                e
+            );
+            std::process::exit(1)
+        }
+    }
+
+    let verbose = cli.verbosity.verbose > 0;
+    if verbose || cli.verbosity.quiet || cli.verbosity.quiet_summary_only {
+        if verbose && cli.verbosity.quiet {
             eprintln!("Output cannot be verbose ('-v') and quiet ('-q') at the same time");
             std::process::exit(1)
-        } else if cli.verbosity.verbose {
-            CliLogger::init(LevelFilter::Debug).unwrap()
+        } else if cli.verbosity.quiet_summary_only && (verbose || cli.verbosity.quiet) {
+            eprintln!("'--quiet-summary-only' cannot be used together with verbose ('-v') or quiet ('-q') output");
+            std::process::exit(1)
+        } else if verbose {
+            CliLogger::init(
+                if cli.verbosity.verbose > 1 {
+                    LevelFilter::Trace
+                } else {
+                    LevelFilter::Debug
+                },
+                cli.log_dir.clone(),
+                cli.no_spinner,
+                cli.progress_rate,
+                false,
+            )
+            .unwrap()
         } else if cli.verbosity.quiet {
-            CliLogger::init(LevelFilter::Error).unwrap()
+            CliLogger::init(
+                LevelFilter::Error,
+                cli.log_dir.clone(),
+                cli.no_spinner,
+                cli.progress_rate,
+                false,
+            )
+            .unwrap()
+        } else if cli.verbosity.quiet_summary_only {
+            CliLogger::init(
+                LevelFilter::Info,
+                cli.log_dir.clone(),
+                cli.no_spinner,
+                cli.progress_rate,
+                true,
+            )
+            .unwrap()
         }
     } else {
-        CliLogger::init(LevelFilter::Info).unwrap()
+        CliLogger::init(
+            LevelFilter::Info,
+            cli.log_dir.clone(),
+            cli.no_spinner,
+            cli.progress_rate,
+            false,
+        )
+        .unwrap()
     }
 
     debug!("cli input: {:?}", cli);
@@ -138,18 +388,26 @@ pub async fn main(args: &[String]) {
     match &mut cli.command {
         Command::Archive(archive) => {
             // prevent interactive select to be shown when output should be quiet
-            if cli.verbosity.quiet {
+            if cli.verbosity.quiet || cli.verbosity.quiet_summary_only {
                 archive.yes = true;
             }
+            archive.default_output = is_default_value(sub_matches, "output");
             pre_check_executor(archive).await
         }
         Command::Download(download) => {
             // prevent interactive select to be shown when output should be quiet
-            if cli.verbosity.quiet {
+            if cli.verbosity.quiet || cli.verbosity.quiet_summary_only {
                 download.yes = true;
             }
+            download.default_output = is_default_value(sub_matches, "output");
             pre_check_executor(download).await
         }
+        Command::Check(check) => pre_check_executor(check).await,
+        Command::Export(export) => pre_check_executor(export).await,
+        Command::ListDownloaded(list_downloaded) => {
+            list_downloaded.default_output = is_default_value(sub_matches, "output");
+            pre_check_executor(list_downloaded).await
+        }
         Command::Login(login) => {
             if login.remove {
                 if let Some(session_file) = login::session_file_path() {
@@ -160,20 +418,17 @@ pub async fn main(args: &[String]) {
                 pre_check_executor(login).await
             }
         }
+        Command::Probe(probe) => pre_check_executor(probe).await,
+        Command::Queue(queue) => pre_check_executor(queue).await,
+        Command::Repair(repair) => pre_check_executor(repair).await,
         Command::Search(search) => pre_check_executor(search).await,
+        Command::TemplateTest(template_test) => pre_check_executor(template_test).await,
+        Command::Watch(watch) => pre_check_executor(watch).await,
     };
 
-    let ctx = match create_ctx(&mut cli).await {
-        Ok(ctx) => ctx,
-        Err(e) => {
-            error!("{}", e);
-            std::process::exit(1)
-        }
-    };
-    debug!("Created context");
-
     ctrlc::set_handler(move || {
         debug!("Ctrl-c detected");
+        remove_partial_outputs();
         if let Ok(dir) = fs::read_dir(env::temp_dir()) {
             for file in dir.flatten() {
                 if file
@@ -218,11 +473,70 @@ pub async fn main(args: &[String]) {
     .unwrap();
     debug!("Created ctrl-c handler");
 
+    // repairing only processes local files, so no login is required
+    if let Command::Repair(repair) = &cli.command {
+        if let Err(e) = repair.repair().await {
+            error!("An error occurred: {}", e);
+            std::process::exit(1)
+        }
+        return;
+    }
+    // without an url, the template is resolved for a sample episode which doesn't require a login
+    // either
+    if let Command::TemplateTest(template_test) = &cli.command {
+        if template_test.url.is_none() {
+            if let Err(e) = template_test.test(template_test.sample_format()) {
+                error!("An error occurred: {}", e);
+                std::process::exit(1)
+            }
+            return;
+        }
+    }
+
+    // adding to and listing the queue only touches the queue file
+    if let Command::Queue(queue) = &cli.command {
+        if !matches!(queue.action, queue::QueueAction::Run { .. }) {
+            if let Err(e) = queue.manage() {
+                error!("An error occurred: {}", e);
+                std::process::exit(1)
+            }
+            return;
+        }
+    }
+
+    let ctx = match create_ctx(&mut cli).await {
+        Ok(ctx) => ctx,
+        Err(e) => {
+            error!("{}", e);
+            std::process::exit(1)
+        }
+    };
+    debug!("Created context");
+
+    if let Some(reauth_interval) = cli.reauth_interval {
+        debug!(
+            "Refreshing the session when it expires, checking at least every {} minute(s)",
+            reauth_interval
+        );
+        spawn_reauth_task(
+            ctx.crunchy.clone(),
+            Duration::from_secs(reauth_interval.saturating_mul(60)),
+        );
+    }
+
     match cli.command {
         Command::Archive(archive) => execute_executor(archive, ctx).await,
         Command::Download(download) => execute_executor(download, ctx).await,
+        Command::Check(check) => execute_executor(check, ctx).await,
+        Command::Export(export) => execute_executor(export, ctx).await,
+        Command::ListDownloaded(list_downloaded) => execute_executor(list_downloaded, ctx).await,
         Command::Login(login) => execute_executor(login, ctx).await,
+        Command::Probe(probe) => execute_executor(probe, ctx).await,
+        Command::Queue(queue) => execute_executor(queue, ctx).await,
+        Command::Repair(repair) => execute_executor(repair, ctx).await,
         Command::Search(search) => execute_executor(search, ctx).await,
+        Command::TemplateTest(template_test) => execute_executor(template_test, ctx).await,
+        Command::Watch(watch) => execute_executor(watch, ctx).await,
     };
 }
 
@@ -234,7 +548,10 @@ async fn pre_check_executor(executor: &mut impl Execute) {
 }
 
 async fn execute_executor(executor: impl Execute, ctx: Context) {
-    if let Err(mut err) = executor.execute(ctx).await {
+    let result = executor.execute(ctx).await;
+    webhook::finish(result.as_ref().err()).await;
+    if let Err(mut err) = result {
+        test_run::fail(&err);
         if let Some(crunchy_error) = err.downcast_mut::<Error>() {
             if let Error::Block { message, .. } = crunchy_error {
                 *message = "Triggered Cloudflare bot protection. Try again later or use a VPN or proxy to spoof your location".to_string()
@@ -250,29 +567,89 @@ async fn execute_executor(executor: impl Execute, ctx: Context) {
 }
 
 async fn create_ctx(cli: &mut Cli) -> Result<Context> {
+    if let Some(user_agent) = &cli.user_agent {
+        debug!("Using custom user agent: {}", user_agent)
+    } else {
+        debug!("Using default user agent")
+    }
+    for (name, value) in &cli.headers {
+        debug!(
+            "Using custom header: {}: {}",
+            name,
+            redacted_header_value(name, value)
+        )
+    }
+
+    for (host, ip) in &cli.resolve {
+        debug!("Resolving {} to {}", host, ip)
+    }
+    // the resolver is shared by both clients, so that resolved hosts are cached only once
+    let doh_resolver = cli.doh.as_ref().map(|url| {
+        debug!("Using DNS-over-HTTPS server {}", url);
+        let mut builder = resolve_overrides(Client::builder(), &cli.resolve);
+        if let Some(p) = cli.proxy.as_ref().and_then(|p| p.0.clone()) {
+            builder = builder.proxy(p)
+        }
+        DohResolver::new(url.clone(), builder.build().unwrap())
+    });
+    // the timings are shared by both clients, like the resolver
+    let http_timings = http_timing_enabled().then(HttpTimings::default);
+
     let crunchy_client = reqwest_client(
         cli.proxy.as_ref().and_then(|p| p.0.clone()),
         cli.user_agent.clone(),
+        &cli.headers,
+        cli.connect_timeout,
+        cli.read_timeout,
+        &cli.resolve,
+        doh_resolver.clone(),
+        http_timings.clone(),
     );
     let internal_client = reqwest_client(
         cli.proxy.as_ref().and_then(|p| p.1.clone()),
         cli.user_agent.clone(),
+        &cli.headers,
+        cli.connect_timeout,
+        cli.read_timeout,
+        &cli.resolve,
+        doh_resolver,
+        http_timings.clone(),
     );
 
-    let crunchy = crunchyroll_session(
-        cli,
-        crunchy_client.clone(),
-        cli.speed_limit
-            .map(|l| RateLimiterService::new(l, crunchy_client)),
-    )
-    .await?;
+    // the request rate is shared, so that requests to Crunchyroll and downloads are adjusted
+    // together
+    let request_rate = cli.adaptive_request_rate.map(AdaptiveRequestRate::new);
+    let speed_limit = cli.speed_limit;
+    // maintenance pages are only detected for requests to the crunchyroll api, so the service
+    // is always used for it
+    let crunchy_rate_limiter =
+        RateLimiterService::new(speed_limit, request_rate.clone(), crunchy_client.clone())
+            .with_maintenance_check(
+                cli.wait_on_maintenance
+                    .map(|m| Duration::from_secs(m.saturating_mul(60))),
+            )
+            .with_http_timings(http_timings.clone());
+    // requests are only timed if they're sent through the service
+    let internal_rate_limiter =
+        (speed_limit.is_some() || request_rate.is_some() || http_timings.is_some()).then(|| {
+            RateLimiterService::new(speed_limit, request_rate, internal_client.clone())
+                .with_http_timings(http_timings)
+        });
+
+    let auth_timer = Phase::Auth.start("login");
+    let crunchy = crunchyroll_session(cli, crunchy_client, Some(crunchy_rate_limiter)).await?;
+    auth_timer.stop();
+
+    let mut webhook_client = Client::builder();
+    if let Some(p) = cli.proxy.as_ref().and_then(|p| p.1.clone()) {
+        webhook_client = webhook_client.proxy(p)
+    }
 
     Ok(Context {
         crunchy,
-        client: internal_client.clone(),
-        rate_limiter: cli
-            .speed_limit
-            .map(|l| RateLimiterService::new(l, internal_client)),
+        client: internal_client,
+        rate_limiter: internal_rate_limiter,
+        webhook_client: webhook_client.build().unwrap(),
     })
 }
 
@@ -293,34 +670,47 @@ async fn crunchyroll_session(
         Locale::pt_PT,
         Locale::ru_RU,
     ];
+    let mut system_lang = system_locale();
+    if !supported_langs.contains(&system_lang) {
+        system_lang = Locale::en_US
+    }
     let locale = if let Some(lang) = &cli.lang {
-        if !supported_langs.contains(lang) {
-            bail!(
-                "Via `--lang` specified language is not supported. Supported languages: {}",
+        let lang = resolve_locales(&[lang.clone()]).remove(0);
+        if supported_langs.contains(&lang) {
+            lang
+        } else {
+            warn!(
+                "Via `--lang` specified language is not supported, using {} instead. Supported languages: {}",
+                system_lang,
                 supported_langs
                     .iter()
                     .map(|l| format!("`{}` ({})", l, l.to_human_readable()))
                     .collect::<Vec<String>>()
                     .join(", ")
-            )
+            );
+            system_lang
         }
-        lang.clone()
     } else {
-        let mut lang = system_locale();
-        if !supported_langs.contains(&lang) {
+        if system_lang != system_locale() {
             warn!("Recognized system locale is not supported. Using en-US as default. Use `--lang` to overwrite the used language");
-            lang = Locale::en_US
         }
-        lang
+        system_lang
     };
+    debug!("Using {} as metadata language", locale);
 
     let mut builder = Crunchyroll::builder()
         .locale(locale)
         .client(client.clone())
         .stabilization_locales(cli.experimental_fixes)
         .stabilization_season_number(cli.experimental_fixes);
-    if let Command::Download(download) = &cli.command {
-        builder = builder.preferred_audio_locale(download.audio.clone())
+    // the watch command runs the download command too
+    let preferred_audio = match &cli.command {
+        Command::Download(download) => Some(download.audio.clone()),
+        Command::Watch(watch) => watch.preferred_audio_locale(),
+        _ => None,
+    };
+    if let Some(preferred_audio) = preferred_audio {
+        builder = builder.preferred_audio_locale(preferred_audio)
     }
     if let Some(rate_limiter) = rate_limiter {
         builder = builder.middleware(rate_limiter)
@@ -378,7 +768,17 @@ async fn crunchyroll_session(
     Ok(crunchy)
 }
 
-fn reqwest_client(proxy: Option<Proxy>, user_agent: Option<String>) -> Client {
+#[allow(clippy::too_many_arguments)]
+fn reqwest_client(
+    proxy: Option<Proxy>,
+    user_agent: Option<String>,
+    headers: &[(HeaderName, HeaderValue)],
+    connect_timeout: Option<u64>,
+    read_timeout: Option<u64>,
+    resolve: &[(String, IpAddr)],
+    doh_resolver: Option<DohResolver>,
+    http_timings: Option<HttpTimings>,
+) -> Client {
     let mut builder = CrunchyrollBuilder::predefined_client_builder();
     if let Some(p) = proxy {
         builder = builder.proxy(p)
@@ -386,6 +786,25 @@ fn reqwest_client(proxy: Option<Proxy>, user_agent: Option<String>) -> Client {
     if let Some(ua) = user_agent {
         builder = builder.user_agent(ua)
     }
+    if !headers.is_empty() {
+        // the default headers are merged with the headers of the predefined client builder
+        builder = builder.default_headers(HeaderMap::from_iter(headers.iter().cloned()))
+    }
+    if let Some(connect_timeout) = connect_timeout {
+        builder = builder.connect_timeout(Duration::from_secs(connect_timeout))
+    }
+    if let Some(read_timeout) = read_timeout {
+        builder = builder.read_timeout(Duration::from_secs(read_timeout))
+    }
+    builder = resolve_overrides(builder, resolve);
+    match (http_timings, doh_resolver) {
+        (Some(http_timings), doh_resolver) => {
+            builder =
+                builder.dns_resolver(Arc::new(TimingResolver::new(doh_resolver, http_timings)))
+        }
+        (None, Some(doh_resolver)) => builder = builder.dns_resolver(Arc::new(doh_resolver)),
+        (None, None) => (),
+    }
 
     #[cfg(any(feature = "openssl-tls", feature = "openssl-tls-static"))]
     let client = {
@@ -403,3 +822,17 @@ fn reqwest_client(proxy: Option<Proxy>, user_agent: Option<String>) -> Client {
 
     client
 }
+
+/// Overrides the dns resolution of the given hosts. Multiple addresses of the same host are
+/// grouped, as every override replaces the previous one of the host.
+fn resolve_overrides(mut builder: ClientBuilder, resolve: &[(String, IpAddr)]) -> ClientBuilder {
+    let mut hosts: BTreeMap<&str, Vec<SocketAddr>> = BTreeMap::new();
+    for (host, ip) in resolve {
+        // port 0 makes reqwest use the port of the requested url
+        hosts.entry(host).or_default().push(SocketAddr::new(*ip, 0))
+    }
+    for (host, addrs) in hosts {
+        builder = builder.resolve_to_addrs(host, &addrs)
+    }
+    builder
+}
@@ -0,0 +1,401 @@
+use crate::utils::context::Context;
+use crate::utils::filter::{Filter, FilterMediaScope};
+use crate::utils::format::{Format, GroupBy, NamingScheme, SingleFormat};
+use crate::utils::locale::{resolve_locales, LanguageTagging};
+use crate::utils::log::progress;
+use crate::utils::parse::parse_url;
+use crate::utils::video::stream_data_from_stream;
+use crate::Execute;
+use anyhow::{bail, Result};
+use crunchyroll_rs::media::Resolution;
+use crunchyroll_rs::Locale;
+use log::warn;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+#[derive(Clone, Debug, Default)]
+pub enum ListDownloadedOutput {
+    #[default]
+    Table,
+    Json,
+}
+
+impl ListDownloadedOutput {
+    pub fn parse(s: &str) -> Result<ListDownloadedOutput, String> {
+        Ok(match s.to_lowercase().as_str() {
+            "table" => ListDownloadedOutput::Table,
+            "json" => ListDownloadedOutput::Json,
+            _ => return Err(format!("'{}' is not a valid output format", s)),
+        })
+    }
+}
+
+#[derive(Debug, clap::Parser)]
+#[clap(
+    about = "Compare downloaded files in a directory with the episodes available on Crunchyroll"
+)]
+#[command(arg_required_else_help(true))]
+pub struct ListDownloaded {
+    #[arg(help = "Audio language the files were downloaded with")]
+    #[arg(short, long, default_value_t = crate::utils::locale::system_locale())]
+    audio: Locale,
+    #[arg(help = "Subtitle language the files were downloaded with")]
+    #[arg(short, long)]
+    subtitle: Option<Locale>,
+
+    #[arg(help = "Output template the files were downloaded with")]
+    #[arg(long_help = "Output template the files were downloaded with. \
+    Supports the same placeholders as the output template of the download command")]
+    #[arg(short, long, default_value = "{title}.mp4")]
+    output: String,
+    /// If no output template is given, so `output` is the default template.
+    #[arg(skip)]
+    pub(crate) default_output: bool,
+    #[arg(help = "Output template special episodes were downloaded with")]
+    #[arg(long)]
+    output_specials: Option<String>,
+    #[arg(help = "Whether the files were downloaded with `--universal-output`")]
+    #[arg(long, default_value_t = false)]
+    universal_output: bool,
+    #[arg(help = "Whether the files were downloaded with `--season-folder-from-title`")]
+    #[arg(long, default_value_t = false)]
+    season_folder_from_title: bool,
+    #[arg(help = "Directory structure the files were downloaded with (via `--group-by`)")]
+    #[arg(long)]
+    #[arg(value_parser = GroupBy::parse)]
+    group_by: Option<GroupBy>,
+    #[arg(help = "Naming scheme the files were downloaded with (via `--naming-scheme`)")]
+    #[arg(long)]
+    #[arg(value_parser = NamingScheme::parse)]
+    naming_scheme: Option<NamingScheme>,
+    #[arg(help = "File extension of the files if they were downloaded with `--naming-scheme`")]
+    #[arg(
+        long_help = "File extension of the files if they were downloaded with `--naming-scheme`. \
+    The download command uses 'mp4', the archive command 'mkv'. Has no effect if an output template is given via `-o`"
+    )]
+    #[arg(long, default_value = "mp4")]
+    naming_scheme_extension: String,
+    #[arg(help = "Language tagging the files were downloaded with")]
+    #[arg(long)]
+    #[arg(value_parser = LanguageTagging::parse)]
+    language_tagging: Option<LanguageTagging>,
+    #[arg(help = "Video resolution the files were downloaded with")]
+    #[arg(long_help = "Video resolution the files were downloaded with. \
+    Only relevant if the output template contains '{width}' or '{height}'")]
+    #[arg(short, long, default_value = "best")]
+    #[arg(value_parser = crate::utils::clap::clap_parse_resolution)]
+    resolution: Resolution,
+    #[arg(help = "Ignore special episodes")]
+    #[arg(long, default_value_t = false)]
+    skip_specials: bool,
+
+    #[arg(
+        help = "Format in which the result is printed. Valid formats are 'table' (default) and 'json'"
+    )]
+    #[arg(short, long, default_value = "table")]
+    #[arg(value_parser = ListDownloadedOutput::parse)]
+    format: ListDownloadedOutput,
+
+    #[arg(help = "Directory which contains the downloaded files")]
+    #[arg(long_help = "Directory which contains the downloaded files. \
+    Relative output templates are resolved against this directory")]
+    #[arg(short, long, default_value = ".")]
+    dir: PathBuf,
+
+    #[arg(help = "Url(s) to Crunchyroll episodes or series")]
+    #[arg(required = true)]
+    urls: Vec<String>,
+}
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum FileStatus {
+    Present,
+    Missing,
+    Extra,
+}
+
+#[derive(Serialize)]
+struct ListedFile {
+    status: FileStatus,
+    path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    series: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    season_number: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    episode_number: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+}
+
+impl Execute for ListDownloaded {
+    fn pre_check(&mut self) -> Result<()> {
+        if !self.dir.is_dir() {
+            bail!("'{}' is not a directory", self.dir.to_string_lossy())
+        }
+
+        if let Some(naming_scheme) = &self.naming_scheme {
+            // the naming scheme is only a shortcut for the output template, an explicitly given
+            // template has precedence
+            if self.default_output {
+                self.output = naming_scheme.template(&self.naming_scheme_extension)
+            } else {
+                warn!("`--naming-scheme` is ignored as an output template is given via `-o`")
+            }
+        }
+
+        if let Some(group_by) = &self.group_by {
+            let group =
+                |o: &String| Format::group_by_template(o, group_by, self.season_folder_from_title);
+            self.output = group(&self.output);
+            self.output_specials = self.output_specials.as_ref().map(group);
+        }
+
+        if self.season_folder_from_title {
+            self.output = Format::season_folder_from_title(&self.output);
+            self.output_specials = self
+                .output_specials
+                .as_ref()
+                .map(Format::season_folder_from_title);
+        }
+
+        if self.language_tagging.is_some() {
+            self.audio = resolve_locales(&[self.audio.clone()]).remove(0);
+            self.subtitle = self
+                .subtitle
+                .as_ref()
+                .map(|s| resolve_locales(&[s.clone()]).remove(0));
+        }
+
+        Ok(())
+    }
+
+    async fn execute(self, ctx: Context) -> Result<()> {
+        let mut files = vec![];
+
+        for (i, url) in self.urls.iter().enumerate() {
+            let progress_handler = progress!("Parsing url {}", i + 1);
+            let (media_collection, url_filter) =
+                match parse_url(&ctx.crunchy, url.clone(), true).await {
+                    Ok(ok) => ok,
+                    Err(e) => bail!("url {} could not be parsed: {}", url, e),
+                };
+            progress_handler.stop(format!("Parsed url {}", i + 1));
+
+            let progress_handler = progress!("Fetching series details");
+            let single_format_collection = Filter::new(
+                url_filter,
+                vec![self.audio.clone()],
+                self.subtitle.as_ref().map_or(vec![], |s| vec![s.clone()]),
+                |scope, locales| {
+                    if let FilterMediaScope::Series(series) = scope {
+                        bail!(
+                            "Series {} is not available with {} audio",
+                            series.title,
+                            locales[0]
+                        )
+                    }
+                    Ok(false)
+                },
+                |scope, locales| {
+                    if let FilterMediaScope::Series(series) = scope {
+                        bail!(
+                            "Series {} is not available with {} subtitles",
+                            series.title,
+                            locales[0]
+                        )
+                    }
+                    Ok(false)
+                },
+                |season| {
+                    warn!("Skipping premium episodes in season {season}");
+                    Ok(())
+                },
+                Format::has_relative_fmt(&self.output),
+                false,
+                self.skip_specials,
+                ctx.crunchy.premium().await,
+            )
+            .fetch_series_description(
+                Format::has_series_description_fmt(&self.output)
+                    || self
+                        .output_specials
+                        .as_ref()
+                        .is_some_and(Format::has_series_description_fmt),
+            )
+            .auto_padding(
+                Format::has_auto_padding_fmt(&self.output)
+                    || self
+                        .output_specials
+                        .as_ref()
+                        .is_some_and(Format::has_auto_padding_fmt),
+            )
+            .visit(media_collection)
+            .await?;
+            progress_handler.stop(format!("Loaded series information for url {}", i + 1));
+
+            let progress_handler = progress!("Resolving file names for url {}", i + 1);
+            for mut single_formats in single_format_collection.into_iter() {
+                // the vec contains always only one item
+                let single_format = single_formats.remove(0);
+                let Some(format) = self.format(&single_format).await? else {
+                    warn!(
+                        "Skipping {} as the resolution {} isn't available",
+                        single_format.title, self.resolution
+                    );
+                    continue;
+                };
+                let template = if format.is_special() {
+                    self.output_specials.as_ref().unwrap_or(&self.output)
+                } else {
+                    &self.output
+                };
+                let path = normalize_path(&self.dir.join(format.format_path(
+                    template.into(),
+                    self.universal_output,
+                    self.language_tagging.as_ref(),
+                )));
+
+                files.push(ListedFile {
+                    status: if path.is_file() {
+                        FileStatus::Present
+                    } else {
+                        FileStatus::Missing
+                    },
+                    path: path.to_string_lossy().to_string(),
+                    series: Some(format.series_name),
+                    season_number: Some(format.season_number),
+                    episode_number: Some(format.episode_number),
+                    title: Some(format.title),
+                })
+            }
+            progress_handler.stop(format!("Resolved file names for url {}", i + 1));
+        }
+
+        // only files with an extension which is also used by the expected files are considered
+        // as extra, otherwise e.g. nfo files or artwork would be listed too
+        let expected: HashSet<String> = files.iter().map(|f| f.path.clone()).collect();
+        let extensions: HashSet<String> = files
+            .iter()
+            .filter_map(|f| {
+                Path::new(&f.path)
+                    .extension()
+                    .map(|e| e.to_string_lossy().to_lowercase())
+            })
+            .collect();
+        for path in walk_files(&self.dir)? {
+            let path = normalize_path(&path);
+            let is_media = path
+                .extension()
+                .is_some_and(|e| extensions.contains(&e.to_string_lossy().to_lowercase()));
+            let path = path.to_string_lossy().to_string();
+            if is_media && !expected.contains(&path) {
+                files.push(ListedFile {
+                    status: FileStatus::Extra,
+                    path,
+                    series: None,
+                    season_number: None,
+                    episode_number: None,
+                    title: None,
+                })
+            }
+        }
+
+        match self.format {
+            ListDownloadedOutput::Table => {
+                for file in &files {
+                    let status = match file.status {
+                        FileStatus::Present => "present",
+                        FileStatus::Missing => "missing",
+                        FileStatus::Extra => "extra",
+                    };
+                    if let (Some(season_number), Some(episode_number)) =
+                        (&file.season_number, &file.episode_number)
+                    {
+                        println!(
+                            "{:<7}  S{:02}E{:<5}  {}",
+                            status, season_number, episode_number, file.path
+                        )
+                    } else {
+                        println!("{:<7}  {:<9}  {}", status, "", file.path)
+                    }
+                }
+                let count =
+                    |status: FileStatus| files.iter().filter(|f| f.status == status).count();
+                println!(
+                    "{} present, {} missing, {} extra",
+                    count(FileStatus::Present),
+                    count(FileStatus::Missing),
+                    count(FileStatus::Extra)
+                )
+            }
+            ListDownloadedOutput::Json => println!("{}", serde_json::to_string_pretty(&files)?),
+        }
+
+        Ok(())
+    }
+}
+
+impl ListDownloaded {
+    /// Builds the format of `single_format` in the same way as the download command does, so that
+    /// the resolved file names match. Returns [`None`] if the resolution isn't available.
+    async fn format(&self, single_format: &SingleFormat) -> Result<Option<Format>> {
+        let stream = single_format.stream().await?;
+        let Some((video, _, _)) =
+            stream_data_from_stream(&stream, &self.resolution, None, None, None, None).await?
+        else {
+            stream.invalidate().await?;
+            return Ok(None);
+        };
+
+        let subtitle = self.subtitle.as_ref().and_then(|subtitle_locale| {
+            if self.audio == Locale::ja_JP {
+                stream
+                    .subtitles
+                    .get(subtitle_locale)
+                    .or_else(|| stream.captions.get(subtitle_locale))
+                    .cloned()
+            } else {
+                stream
+                    .captions
+                    .get(subtitle_locale)
+                    .or_else(|| stream.subtitles.get(subtitle_locale))
+                    .cloned()
+            }
+        });
+        let format = Format::from_single_formats(vec![(
+            single_format.clone(),
+            video,
+            subtitle.map_or(vec![], |s| vec![(s, false)]),
+        )]);
+
+        stream.invalidate().await?;
+
+        Ok(Some(format))
+    }
+}
+
+/// Removes `.` components, so that paths from the output template and from the directory listing
+/// can be compared.
+fn normalize_path(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|c| !matches!(c, Component::CurDir))
+        .collect()
+}
+
+fn walk_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    for entry in fs::read_dir(dir)?.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            files.extend(walk_files(&path)?)
+        } else if path.is_file() {
+            files.push(path)
+        }
+    }
+    files.sort();
+    Ok(files)
+}
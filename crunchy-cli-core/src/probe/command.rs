@@ -0,0 +1,308 @@
+use crate::utils::context::Context;
+use crate::utils::format::SingleFormat;
+use crate::utils::parse::parse_url;
+use crate::Execute;
+use anyhow::{bail, Result};
+use crunchyroll_rs::media::StreamData;
+use crunchyroll_rs::{Locale, MediaCollection};
+use serde::Serialize;
+
+#[derive(Clone, Debug, Default)]
+pub enum ProbeOutput {
+    #[default]
+    Table,
+    Json,
+}
+
+impl ProbeOutput {
+    pub fn parse(s: &str) -> Result<ProbeOutput, String> {
+        Ok(match s.to_lowercase().as_str() {
+            "table" => ProbeOutput::Table,
+            "json" => ProbeOutput::Json,
+            _ => return Err(format!("'{}' is not a valid probe output", s)),
+        })
+    }
+}
+
+#[derive(Debug, clap::Parser)]
+#[clap(about = "Show all available streams of a video without downloading it")]
+#[command(arg_required_else_help(true))]
+pub struct Probe {
+    #[arg(
+        help = "Format in which the available streams are printed. Valid formats are 'table' (default) and 'json'"
+    )]
+    #[arg(short, long, default_value = "table")]
+    #[arg(value_parser = ProbeOutput::parse)]
+    output: ProbeOutput,
+
+    #[arg(help = "Only list the available audio languages")]
+    #[arg(long_help = "Only list the available audio languages. \
+    Unlike the full probe, this also works with series and season urls and is faster, as only metadata is requested")]
+    #[arg(long, default_value_t = false)]
+    list_audio: bool,
+    #[arg(help = "Only list the available subtitle languages")]
+    #[arg(long_help = "Only list the available subtitle languages. \
+    Unlike the full probe, this also works with series and season urls and is faster, as only metadata is requested")]
+    #[arg(long, default_value_t = false)]
+    list_subtitles: bool,
+
+    #[arg(help = "Url of the episode, movie, music video or concert to probe")]
+    #[arg(
+        long_help = "Url of the episode, movie, music video or concert to probe. \
+    If `--list-audio` or `--list-subtitles` is used, series and season urls are supported too"
+    )]
+    url: String,
+}
+
+#[derive(Serialize)]
+struct ProbeVideo {
+    resolution: Option<String>,
+    fps: Option<f64>,
+    bandwidth: u64,
+    codecs: String,
+}
+
+#[derive(Serialize)]
+struct ProbeAudio {
+    bandwidth: u64,
+    sampling_rate: Option<u32>,
+    codecs: String,
+}
+
+#[derive(Serialize)]
+struct ProbeLocales {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    audio: Option<Vec<Locale>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    subtitles: Option<Vec<Locale>>,
+}
+
+#[derive(Serialize)]
+struct ProbeResult {
+    title: String,
+    audio_locale: Locale,
+    other_audio_locales: Vec<Locale>,
+    videos: Vec<ProbeVideo>,
+    audios: Vec<ProbeAudio>,
+    subtitles: Vec<Locale>,
+    closed_captions: Vec<Locale>,
+}
+
+impl Execute for Probe {
+    async fn execute(self, ctx: Context) -> Result<()> {
+        let (media_collection, _) = match parse_url(&ctx.crunchy, self.url.clone(), false).await {
+            Ok(ok) => ok,
+            Err(e) => bail!("url {} could not be parsed: {}", self.url, e),
+        };
+
+        if self.list_audio || self.list_subtitles {
+            let (mut audio, mut subtitles) = available_locales(media_collection).await?;
+            audio.sort_by_key(|l| l.to_string());
+            audio.dedup();
+            subtitles.sort_by_key(|l| l.to_string());
+            subtitles.dedup();
+
+            let locales = ProbeLocales {
+                audio: self.list_audio.then_some(audio),
+                subtitles: self.list_subtitles.then_some(subtitles),
+            };
+            match self.output {
+                ProbeOutput::Table => {
+                    if let Some(audio) = &locales.audio {
+                        println!("Audio: {}", join_locales(audio))
+                    }
+                    if let Some(subtitles) = &locales.subtitles {
+                        println!("Subtitles: {}", join_locales(subtitles))
+                    }
+                }
+                ProbeOutput::Json => println!("{}", serde_json::to_string_pretty(&locales)?),
+            }
+            return Ok(());
+        }
+
+        let (single_format, other_audio_locales) = match media_collection {
+            MediaCollection::Episode(episode) => {
+                let other_audio_locales = episode
+                    .versions
+                    .iter()
+                    .map(|v| v.audio_locale.clone())
+                    .filter(|l| l != &episode.audio_locale)
+                    .collect();
+                let subtitles = episode.subtitle_locales.clone();
+                (
+                    SingleFormat::new_from_episode(episode, subtitles, None, None),
+                    other_audio_locales,
+                )
+            }
+            MediaCollection::Movie(movie) => (SingleFormat::new_from_movie(movie, vec![]), vec![]),
+            MediaCollection::MusicVideo(music_video) => {
+                (SingleFormat::new_from_music_video(music_video), vec![])
+            }
+            MediaCollection::Concert(concert) => (SingleFormat::new_from_concert(concert), vec![]),
+            _ => {
+                bail!("Only urls of a single episode, movie, music video or concert can be probed")
+            }
+        };
+
+        let stream = single_format.stream().await?;
+        let (mut videos, mut audios) = stream.stream_data(None).await?.unwrap();
+        let mut subtitles: Vec<Locale> = stream.subtitles.keys().cloned().collect();
+        let mut closed_captions: Vec<Locale> = stream.captions.keys().cloned().collect();
+        stream.invalidate().await?;
+
+        videos.sort_by(|a, b| a.bandwidth.cmp(&b.bandwidth).reverse());
+        audios.sort_by(|a, b| a.bandwidth.cmp(&b.bandwidth).reverse());
+        subtitles.sort_by_key(|l| l.to_string());
+        closed_captions.sort_by_key(|l| l.to_string());
+
+        let result = ProbeResult {
+            title: single_format.title.clone(),
+            audio_locale: single_format.audio.clone(),
+            other_audio_locales,
+            videos: videos.iter().map(probe_video).collect(),
+            audios: audios.iter().map(probe_audio).collect(),
+            subtitles,
+            closed_captions,
+        };
+
+        match self.output {
+            ProbeOutput::Table => print_table(&result),
+            ProbeOutput::Json => println!("{}", serde_json::to_string_pretty(&result)?),
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the available audio and subtitle locales. Series, seasons and episodes contain them in
+/// their metadata, for all other media the stream has to be requested.
+async fn available_locales(
+    media_collection: MediaCollection,
+) -> Result<(Vec<Locale>, Vec<Locale>)> {
+    Ok(match media_collection {
+        MediaCollection::Series(series) => {
+            // the locale fields of a series aren't always populated
+            if !series.audio_locales.is_empty() {
+                (series.audio_locales, series.subtitle_locales)
+            } else {
+                let (mut audio, mut subtitles) = (vec![], vec![]);
+                for season in series.seasons().await? {
+                    audio.extend(season.audio_locales);
+                    subtitles.extend(season.subtitle_locales);
+                }
+                (audio, subtitles)
+            }
+        }
+        MediaCollection::Season(season) => (season.audio_locales, season.subtitle_locales),
+        MediaCollection::Episode(episode) => {
+            let mut audio = vec![episode.audio_locale.clone()];
+            audio.extend(episode.versions.iter().map(|v| v.audio_locale.clone()));
+            (audio, episode.subtitle_locales)
+        }
+        MediaCollection::MovieListing(_) => {
+            bail!("Movie listings aren't supported, use the url of a single movie instead")
+        }
+        media_collection => {
+            let single_format = match media_collection {
+                MediaCollection::Movie(movie) => SingleFormat::new_from_movie(movie, vec![]),
+                MediaCollection::MusicVideo(music_video) => {
+                    SingleFormat::new_from_music_video(music_video)
+                }
+                MediaCollection::Concert(concert) => SingleFormat::new_from_concert(concert),
+                _ => unreachable!(),
+            };
+            let stream = single_format.stream().await?;
+            let mut subtitles: Vec<Locale> = stream.subtitles.keys().cloned().collect();
+            subtitles.extend(stream.captions.keys().cloned());
+            let audio = vec![stream.audio_locale.clone()];
+            stream.invalidate().await?;
+            (audio, subtitles)
+        }
+    })
+}
+
+fn probe_video(stream_data: &StreamData) -> ProbeVideo {
+    ProbeVideo {
+        resolution: stream_data.resolution().map(|r| r.to_string()),
+        fps: stream_data.fps(),
+        bandwidth: stream_data.bandwidth,
+        codecs: stream_data.codecs.clone(),
+    }
+}
+
+fn probe_audio(stream_data: &StreamData) -> ProbeAudio {
+    ProbeAudio {
+        bandwidth: stream_data.bandwidth,
+        sampling_rate: stream_data.sampling_rate(),
+        codecs: stream_data.codecs.clone(),
+    }
+}
+
+fn print_table(result: &ProbeResult) {
+    println!("{} [{}]", result.title, result.audio_locale);
+    if !result.other_audio_locales.is_empty() {
+        println!(
+            "Other audio versions: {}",
+            join_locales(&result.other_audio_locales)
+        )
+    }
+
+    println!("\nVideo");
+    println!(
+        "  {:<12} {:>6} {:>12}  {}",
+        "RESOLUTION", "FPS", "BANDWIDTH", "CODECS"
+    );
+    for video in &result.videos {
+        println!(
+            "  {:<12} {:>6} {:>12}  {}",
+            video.resolution.as_deref().unwrap_or("unknown"),
+            video
+                .fps
+                .map_or("-".to_string(), |fps| format!("{:.2}", fps)),
+            format!("{} kbps", video.bandwidth / 1000),
+            video.codecs
+        )
+    }
+
+    println!("\nAudio");
+    println!(
+        "  {:<12} {:>6} {:>12}  {}",
+        "", "RATE", "BANDWIDTH", "CODECS"
+    );
+    for audio in &result.audios {
+        println!(
+            "  {:<12} {:>6} {:>12}  {}",
+            "",
+            audio
+                .sampling_rate
+                .map_or("-".to_string(), |r| r.to_string()),
+            format!("{} kbps", audio.bandwidth / 1000),
+            audio.codecs
+        )
+    }
+
+    println!(
+        "\nSubtitles: {}",
+        if result.subtitles.is_empty() {
+            "none".to_string()
+        } else {
+            join_locales(&result.subtitles)
+        }
+    );
+    println!(
+        "Closed captions: {}",
+        if result.closed_captions.is_empty() {
+            "none".to_string()
+        } else {
+            join_locales(&result.closed_captions)
+        }
+    )
+}
+
+fn join_locales(locales: &[Locale]) -> String {
+    locales
+        .iter()
+        .map(|l| l.to_string())
+        .collect::<Vec<String>>()
+        .join(", ")
+}
@@ -0,0 +1,238 @@
+use crate::utils::context::Context;
+use crate::utils::queue::{JobQueue, QueueCommand, QueueJob};
+use crate::utils::state::BatchState;
+use crate::{Archive, Download, Execute};
+use anyhow::{bail, Result};
+use clap::Parser;
+use log::{error, info, warn};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+#[derive(Debug, clap::Parser)]
+#[clap(about = "Add downloads to a persistent queue and process them later on")]
+#[command(arg_required_else_help(true))]
+pub struct Queue {
+    #[arg(help = "Path of the queue file")]
+    #[arg(long_help = "Path of the queue file. \
+    Jobs are only ever appended to it, so it stays valid even if the process gets killed while writing")]
+    #[arg(short, long, default_value = "crunchy-cli-queue.jsonl")]
+    file: PathBuf,
+
+    #[command(subcommand)]
+    pub(crate) action: QueueAction,
+}
+
+#[derive(Debug, clap::Subcommand)]
+pub enum QueueAction {
+    #[command(about = "Add a download to the queue")]
+    #[command(
+        long_about = "Add a download to the queue. All arguments are passed to the download \
+        (or archive) command when the queue is run, e.g. `queue add -a de-DE https://...`. \
+        The arguments are validated when they're added"
+    )]
+    Add {
+        #[arg(help = "Queue the job for the archive command instead of the download command")]
+        #[arg(long, default_value_t = false)]
+        archive: bool,
+
+        #[arg(help = "Arguments and url(s) of the download or archive command")]
+        #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    #[command(about = "Process all pending jobs of the queue")]
+    #[command(
+        long_about = "Process all pending jobs of the queue, in the order they were added. \
+        Every job keeps track of which urls it already processed and skips already existing files, \
+        so an interrupted job continues with the episode it was interrupted at on the next run. \
+        Failed jobs stay in the queue and are retried on the next run, starting with the videos which failed to download"
+    )]
+    Run {
+        #[arg(help = "Seconds to wait between two jobs")]
+        #[arg(long, default_value_t = 0)]
+        delay: u64,
+    },
+    #[command(about = "List all jobs of the queue and their status")]
+    List,
+}
+
+impl Execute for Queue {
+    fn pre_check(&mut self) -> Result<()> {
+        if let QueueAction::Add { archive, args } = &self.action {
+            let mut command_line = vec![if *archive { "archive" } else { "download" }.to_string()];
+            command_line.extend(args.iter().cloned());
+            let result = if *archive {
+                Archive::try_parse_from(command_line).map(|_| ())
+            } else {
+                Download::try_parse_from(command_line).map(|_| ())
+            };
+            if let Err(e) = result {
+                bail!("Invalid job arguments: {}", e)
+            }
+        }
+        Ok(())
+    }
+
+    async fn execute(self, ctx: Context) -> Result<()> {
+        let QueueAction::Run { delay } = self.action else {
+            return self.manage();
+        };
+
+        let mut queue = JobQueue::load(self.file.clone())?;
+        let pending = queue.pending();
+        if pending.is_empty() {
+            info!("No pending jobs in {}", queue.path().to_string_lossy());
+            return Ok(());
+        }
+        info!("Processing {} pending job(s)", pending.len());
+
+        let mut failed = 0;
+        for (i, job) in pending.iter().enumerate() {
+            if i > 0 && delay > 0 {
+                tokio::time::sleep(Duration::from_secs(delay)).await
+            }
+            info!(
+                "Running job {} ({} of {}): {}",
+                job.id,
+                i + 1,
+                pending.len(),
+                job.command_line().join(" ")
+            );
+
+            let state_file = queue.state_file(job.id);
+            match run_job(job, state_file.clone(), clone_context(&ctx)).await {
+                Ok(()) => {
+                    queue.set_completed(job.id)?;
+                    let _ = fs::remove_file(state_file);
+                    info!("Completed job {}", job.id)
+                }
+                Err(e) => {
+                    error!("Job {} failed: {}", job.id, e);
+                    queue.set_failed(job.id, &e)?;
+                    failed += 1
+                }
+            }
+        }
+
+        if failed > 0 {
+            bail!(
+                "{} of {} job(s) failed, they are retried on the next run",
+                failed,
+                pending.len()
+            )
+        }
+        Ok(())
+    }
+}
+
+impl Queue {
+    /// Adds a job to or lists the jobs of the queue. Both only operate on the queue file, so no
+    /// login is required.
+    pub(crate) fn manage(&self) -> Result<()> {
+        let mut queue = JobQueue::load(self.file.clone())?;
+        match &self.action {
+            QueueAction::Add { archive, args } => {
+                let command = if *archive {
+                    QueueCommand::Archive
+                } else {
+                    QueueCommand::Download
+                };
+                let job = queue.add(command, args.clone())?;
+                info!("Added job {} to {}", job.id, queue.path().to_string_lossy())
+            }
+            QueueAction::List => {
+                for job in queue.jobs() {
+                    let status = if queue.is_completed(job.id) {
+                        "completed"
+                    } else if queue.error(job.id).is_some() {
+                        "failed"
+                    } else {
+                        "pending"
+                    };
+                    println!(
+                        "{:<4}  {:<9}  {}",
+                        job.id,
+                        status,
+                        job.command_line().join(" ")
+                    );
+                    if let Some(error) = queue.error(job.id) {
+                        println!("{:<4}  {:<9}  {}", "", "", error)
+                    }
+                }
+            }
+            QueueAction::Run { .. } => unreachable!(),
+        }
+        Ok(())
+    }
+}
+
+/// Runs a single job. The job gets its own batch state file (unless it has one configured itself)
+/// and always skips existing files, which are only created after an episode is finished
+/// completely. Jobs are run unattended, so interactive prompts are disabled.
+///
+/// Videos which failed in an earlier run of the job are retried first. The urls they belong to are
+/// already completed, so they would never be downloaded again otherwise and the job could never
+/// finish.
+async fn run_job(job: &QueueJob, state_file: PathBuf, ctx: Context) -> Result<()> {
+    match job.command {
+        QueueCommand::Archive => {
+            let mut archive = Archive::try_parse_args(job.command_line())?;
+            archive.yes = true;
+            archive.skip_existing = true;
+            if archive.state_file.is_none() {
+                archive.state_file = Some(state_file);
+                archive.resume_batch = true;
+            }
+            if has_failed_videos(archive.state_file.as_ref().unwrap())? {
+                let mut retry = archive.clone();
+                retry.retry_failed = true;
+                retry.resume_batch = false;
+                retry.pre_check()?;
+                if let Err(e) = retry.execute(clone_context(&ctx)).await {
+                    warn!("Failed to retry the failed videos of job {}: {}", job.id, e)
+                }
+            }
+            archive.pre_check()?;
+            archive.execute(ctx).await
+        }
+        QueueCommand::Download => {
+            let mut download = Download::try_parse_args(job.command_line())?;
+            download.yes = true;
+            download.skip_existing = true;
+            if download.state_file.is_none() {
+                download.state_file = Some(state_file);
+                download.resume_batch = true;
+            }
+            if has_failed_videos(download.state_file.as_ref().unwrap())? {
+                let mut retry = download.clone();
+                retry.retry_failed = true;
+                retry.resume_batch = false;
+                retry.pre_check()?;
+                if let Err(e) = retry.execute(clone_context(&ctx)).await {
+                    warn!("Failed to retry the failed videos of job {}: {}", job.id, e)
+                }
+            }
+            download.pre_check()?;
+            download.execute(ctx).await
+        }
+    }
+}
+
+/// If the batch state file at `path` contains videos which failed to download.
+fn has_failed_videos(path: &Path) -> Result<bool> {
+    if !path.exists() {
+        return Ok(false);
+    }
+    Ok(!BatchState::load(path.to_path_buf(), &[])?
+        .failed_urls()
+        .is_empty())
+}
+
+fn clone_context(ctx: &Context) -> Context {
+    Context {
+        crunchy: ctx.crunchy.clone(),
+        client: ctx.client.clone(),
+        rate_limiter: ctx.rate_limiter.clone(),
+        webhook_client: ctx.webhook_client.clone(),
+    }
+}
@@ -0,0 +1,127 @@
+use crate::utils::context::Context;
+use crate::utils::download::run_ffmpeg;
+use crate::utils::ffmpeg::FFmpegLog;
+use crate::utils::os::has_ffmpeg;
+use crate::utils::raw::RawManifest;
+use crate::Execute;
+use anyhow::{bail, Result};
+use log::{debug, info};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, clap::Parser)]
+#[clap(about = "Create the output files of downloads which were stored with `--raw`")]
+#[command(arg_required_else_help(true))]
+pub struct Repair {
+    #[arg(help = "Keep the raw directories after the output files were created")]
+    #[arg(long, default_value_t = false)]
+    keep_raw: bool,
+
+    #[arg(
+        help = "When the output of ffmpeg is shown. Valid modes are 'hidden', 'on-error' (default) and 'always'"
+    )]
+    #[arg(long, default_value = "on-error")]
+    #[arg(value_parser = FFmpegLog::parse)]
+    ffmpeg_log: FFmpegLog,
+
+    #[arg(help = "Overwrite output files which are already existing")]
+    #[arg(long, default_value_t = false)]
+    overwrite: bool,
+
+    #[arg(help = "Raw directory or a directory which contains raw directories")]
+    #[arg(
+        long_help = "Raw directory (e.g. 'My Episode.mkv.raw') or a directory which contains raw directories. \
+    Directories are searched recursively"
+    )]
+    dir: PathBuf,
+}
+
+impl Execute for Repair {
+    fn pre_check(&mut self) -> Result<()> {
+        if !has_ffmpeg() {
+            bail!("FFmpeg is needed to run this command")
+        } else if !self.dir.is_dir() {
+            bail!("'{}' is not a directory", self.dir.to_string_lossy())
+        }
+
+        Ok(())
+    }
+
+    async fn execute(self, _ctx: Context) -> Result<()> {
+        self.repair().await
+    }
+}
+
+impl Repair {
+    /// Muxes all raw downloads. Unlike the other commands, this doesn't require a login as only
+    /// local files are processed.
+    pub async fn repair(&self) -> Result<()> {
+        let raw_dirs = find_raw_dirs(&self.dir)?;
+        if raw_dirs.is_empty() {
+            bail!("No raw downloads found in '{}'", self.dir.to_string_lossy())
+        }
+
+        for (i, raw_dir) in raw_dirs.iter().enumerate() {
+            let manifest = RawManifest::load(raw_dir)?;
+            let output = manifest.output_path(raw_dir);
+            if output.exists() && !self.overwrite {
+                info!(
+                    "Skipping '{}' as the output file is already existing",
+                    raw_dir.to_string_lossy()
+                );
+                continue;
+            }
+            for stream in &manifest.streams {
+                if !raw_dir.join(&stream.file).is_file() {
+                    bail!(
+                        "'{}' is missing in '{}'",
+                        stream.file,
+                        raw_dir.to_string_lossy()
+                    )
+                }
+            }
+
+            info!(
+                "Creating '{}' ({}/{})",
+                output.to_string_lossy(),
+                i + 1,
+                raw_dirs.len()
+            );
+            run_ffmpeg(
+                manifest.ffmpeg_args(raw_dir),
+                &output,
+                manifest.frames,
+                "Generating output file".to_string(),
+                None,
+                &self.ffmpeg_log,
+            )
+            .await?;
+
+            if !self.keep_raw {
+                fs::remove_dir_all(raw_dir)?;
+                debug!("Removed raw directory '{}'", raw_dir.to_string_lossy())
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn find_raw_dirs(dir: &Path) -> Result<Vec<PathBuf>> {
+    if dir.join(RawManifest::FILE_NAME).is_file() {
+        return Ok(vec![dir.to_path_buf()]);
+    }
+
+    let mut dirs: Vec<PathBuf> = fs::read_dir(dir)?
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.is_dir())
+        .collect();
+    dirs.sort();
+
+    let mut raw_dirs = vec![];
+    for dir in dirs {
+        raw_dirs.extend(find_raw_dirs(&dir)?)
+    }
+    Ok(raw_dirs)
+}
@@ -0,0 +1,195 @@
+use crate::utils::context::Context;
+use crate::utils::filter::{Filter, FilterMediaScope};
+use crate::utils::format::Format;
+use crate::utils::locale::LanguageTagging;
+use crate::utils::log::progress;
+use crate::utils::parse::{parse_resolution, parse_url};
+use crate::utils::video::stream_data_from_stream;
+use crate::Execute;
+use anyhow::{bail, Result};
+use crunchyroll_rs::Locale;
+use dialoguer::console::style;
+use log::warn;
+
+#[derive(Debug, clap::Parser)]
+#[clap(about = "Resolve an output template and print the resulting path")]
+#[command(arg_required_else_help(true))]
+pub struct TemplateTest {
+    #[arg(help = "Audio language of the video the template is resolved for")]
+    #[arg(short, long, default_value_t = Locale::ja_JP)]
+    audio: Locale,
+    #[arg(help = "Subtitle language of the video the template is resolved for")]
+    #[arg(short, long)]
+    subtitle: Option<Locale>,
+
+    #[arg(help = "Resolve the template like with `--universal-output`")]
+    #[arg(long, default_value_t = false)]
+    universal_output: bool,
+    #[arg(help = "Resolve the template like with `--language-tagging`")]
+    #[arg(long)]
+    #[arg(value_parser = LanguageTagging::parse)]
+    language_tagging: Option<LanguageTagging>,
+
+    #[arg(help = "Url of an episode to resolve the template for instead of the sample episode")]
+    #[arg(
+        long_help = "Url of an episode to resolve the template for instead of the sample episode. \
+    If it's a series or season url, the first episode is used. \
+    Without it, nothing is requested from Crunchyroll and no login is required"
+    )]
+    #[arg(long)]
+    pub(crate) url: Option<String>,
+
+    #[arg(help = "The output template to resolve")]
+    template: String,
+}
+
+impl Execute for TemplateTest {
+    async fn execute(self, ctx: Context) -> Result<()> {
+        let Some(url) = &self.url else {
+            return self.test(self.sample_format());
+        };
+
+        let progress_handler = progress!("Parsing url");
+        let (media_collection, url_filter) = match parse_url(&ctx.crunchy, url.clone(), true).await
+        {
+            Ok(ok) => ok,
+            Err(e) => bail!("url {} could not be parsed: {}", url, e),
+        };
+        progress_handler.stop("Parsed url");
+
+        let progress_handler = progress!("Fetching video details");
+        let single_format_collection = Filter::new(
+            url_filter,
+            vec![self.audio.clone()],
+            self.subtitle.as_ref().map_or(vec![], |s| vec![s.clone()]),
+            |scope, locales| {
+                if let FilterMediaScope::Series(series) = scope {
+                    bail!(
+                        "Series {} is not available with {} audio",
+                        series.title,
+                        locales[0]
+                    )
+                }
+                Ok(false)
+            },
+            |_, _| Ok(true),
+            |_| Ok(()),
+            Format::has_relative_fmt(&self.template),
+            false,
+            false,
+            ctx.crunchy.premium().await,
+        )
+        .fetch_series_description(Format::has_series_description_fmt(&self.template))
+        .auto_padding(Format::has_auto_padding_fmt(&self.template))
+        .visit(media_collection)
+        .await?;
+        let Some(single_format) = single_format_collection
+            .into_iter()
+            .next()
+            .and_then(|mut f| (!f.is_empty()).then(|| f.remove(0)))
+        else {
+            bail!("No video found for url {}", url)
+        };
+
+        let stream = single_format.stream().await?;
+        let Some((video, _, _)) = stream_data_from_stream(
+            &stream,
+            &parse_resolution("best".to_string())?,
+            None,
+            None,
+            None,
+            None,
+        )
+        .await?
+        else {
+            stream.invalidate().await?;
+            bail!("No video stream found for url {}", url)
+        };
+        let subtitle = self.subtitle.as_ref().and_then(|s| {
+            stream
+                .subtitles
+                .get(s)
+                .or_else(|| stream.captions.get(s))
+                .cloned()
+        });
+        stream.invalidate().await?;
+        progress_handler.stop("Fetched video details");
+
+        self.test(Format::from_single_formats(vec![(
+            single_format,
+            video,
+            subtitle.map_or(vec![], |s| vec![(s, false)]),
+        )]))
+    }
+}
+
+impl TemplateTest {
+    /// Prints the template resolved for `format`, unknown placeholders are highlighted. Fails if
+    /// the template contains unknown placeholders.
+    pub(crate) fn test(&self, format: Format) -> Result<()> {
+        let unknown = Format::unknown_placeholders(&self.template);
+        let mut path = format
+            .format_path(
+                (&self.template).into(),
+                self.universal_output,
+                self.language_tagging.as_ref(),
+            )
+            .to_string_lossy()
+            .to_string();
+        // unknown placeholders aren't replaced, so they're still part of the path
+        for placeholder in &unknown {
+            path = path.replace(placeholder, &style(placeholder).red().bold().to_string())
+        }
+        println!("{}", path);
+
+        if !unknown.is_empty() {
+            for placeholder in &unknown {
+                warn!("Unknown placeholder {}", placeholder)
+            }
+            bail!(
+                "The template contains {} unknown placeholder(s). Supported are: {}",
+                unknown.len(),
+                Format::PLACEHOLDERS
+                    .iter()
+                    .map(|(p, _)| format!("{{{}}}", p))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            )
+        }
+        Ok(())
+    }
+
+    /// A sample episode, used if no url is given.
+    pub(crate) fn sample_format(&self) -> Format {
+        Format {
+            title: "Alone and Lonesome".to_string(),
+            description: "In the distant future, humanity has abandoned the earth's surface and lives in mobile fortress cities called Plantations.".to_string(),
+            locales: vec![(
+                self.audio.clone(),
+                self.subtitle.iter().cloned().collect(),
+            )],
+            width: 1920,
+            height: 1080,
+            fps: 23.976,
+            release_year: 2018,
+            release_month: 1,
+            release_day: 13,
+            series_id: "GY8VEQ95Y".to_string(),
+            series_name: "DARLING in the FRANXX".to_string(),
+            series_description: "The story is set in the distant future. The land is ruined, and humanity establishes the mobile fort city Plantation.".to_string(),
+            season_id: "GR49C7EPY".to_string(),
+            season_title: "DARLING in the FRANXX".to_string(),
+            season_number: 1,
+            episode_id: "G6JQ1WJ2R".to_string(),
+            episode_number: "1".to_string(),
+            relative_episode_number: Some(1),
+            sequence_number: 1.0,
+            relative_sequence_number: Some(1.0),
+            episode_number_width: None,
+            artist: "".to_string(),
+            track: "".to_string(),
+            source_type: "episode".to_string(),
+            extra: false,
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use crate::utils::format::SingleFormat;
+use crate::utils::nfo::media_dirs;
+use crate::utils::os::is_special_file;
+use anyhow::Result;
+use log::{debug, warn};
+use reqwest::Client;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+/// Downloads the artwork of series into the series directory, using the file names Kodi, Jellyfin
+/// and similar media servers expect: the tall series poster as `poster.jpg` and the wide poster as
+/// `fanart.jpg`. Every image is only downloaded once per run, already existing images are kept.
+/// Crunchyroll has no banners and no images of seasons, so `banner.jpg` and season posters
+/// (`seasonNN-poster.jpg`) can't be written.
+pub struct ArtworkWriter {
+    client: Client,
+    series: HashSet<String>,
+}
+
+impl ArtworkWriter {
+    pub fn new(client: Client) -> Self {
+        Self {
+            client,
+            series: HashSet::new(),
+        }
+    }
+
+    /// `template` is the (unformatted) output template which was used to create `dst`.
+    pub async fn write(
+        &mut self,
+        template: &str,
+        dst: &Path,
+        single_format: &SingleFormat,
+    ) -> Result<()> {
+        if is_special_file(dst) || dst.to_string_lossy() == "-" || !single_format.is_episode() {
+            return Ok(());
+        }
+        if !self.series.insert(single_format.series_id.clone()) {
+            return Ok(());
+        }
+        let Some(series) = single_format.series().await? else {
+            return Ok(());
+        };
+
+        let (_, series_dir) = media_dirs(template, dst);
+        // the images are available in multiple resolutions, the largest one is used
+        let poster = series
+            .images
+            .poster_tall
+            .iter()
+            .max_by_key(|i| i.width * i.height);
+        let fanart = series
+            .images
+            .poster_wide
+            .iter()
+            .max_by_key(|i| i.width * i.height);
+        for (file_name, image) in [("poster.jpg", poster), ("fanart.jpg", fanart)] {
+            let Some(image) = image else {
+                debug!("Series {} has no image for {}", series.title, file_name);
+                continue;
+            };
+            let path = series_dir.join(file_name);
+            if path.exists() {
+                debug!("Artwork {} already exists", path.to_string_lossy());
+                continue;
+            }
+            // missing artwork shouldn't fail the whole download
+            if let Err(e) = self.download(&image.source, &path).await {
+                warn!(
+                    "Failed to download artwork {}: {}",
+                    path.to_string_lossy(),
+                    e
+                )
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn download(&self, url: &str, path: &Path) -> Result<()> {
+        let data = self
+            .client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+        if let Some(parent) = path.parent() {
+            if !parent.as_os_str().is_empty() && !parent.exists() {
+                fs::create_dir_all(parent)?
+            }
+        }
+        fs::write(path, data)?;
+        debug!("Wrote artwork {}", path.to_string_lossy());
+        Ok(())
+    }
+}
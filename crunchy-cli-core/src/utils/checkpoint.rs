@@ -0,0 +1,141 @@
+use anyhow::Result;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Default, Deserialize, Serialize)]
+struct CheckpointFile {
+    /// Outputs which were muxed completely.
+    muxed: Vec<PathBuf>,
+}
+
+/// Records which outputs of an episode were already muxed, so a restarted run doesn't download
+/// and mux them again if it was interrupted in a later step (e.g. while writing the nfo file or
+/// running the post hook). The checkpoint is stored next to the main output as
+/// `.{file name}.state` and removed when all steps of the episode are finished.
+pub struct MuxCheckpoint {
+    path: PathBuf,
+    state: CheckpointFile,
+}
+
+impl MuxCheckpoint {
+    fn path_for(dst: &Path) -> PathBuf {
+        dst.with_file_name(format!(
+            ".{}.state",
+            dst.file_name().unwrap_or_default().to_string_lossy()
+        ))
+    }
+
+    /// Loads the checkpoint of `dst`. Outputs which don't exist anymore (e.g. because they got
+    /// deleted manually) are not considered as muxed. If no checkpoint exists, an empty one is
+    /// returned.
+    pub fn load(dst: &Path) -> Self {
+        let path = Self::path_for(dst);
+        let mut state = match fs::read(&path) {
+            Ok(data) => serde_json::from_slice::<CheckpointFile>(&data).unwrap_or_else(|e| {
+                warn!(
+                    "Ignoring invalid checkpoint {}: {}",
+                    path.to_string_lossy(),
+                    e
+                );
+                CheckpointFile::default()
+            }),
+            Err(_) => CheckpointFile::default(),
+        };
+        state.muxed.retain(|output| output.exists());
+        Self { path, state }
+    }
+
+    /// If a checkpoint with at least one muxed output exists for `dst`.
+    pub fn exists(dst: &Path) -> bool {
+        !Self::load(dst).state.muxed.is_empty()
+    }
+
+    pub fn is_muxed(&self, output: &Path) -> bool {
+        self.state.muxed.iter().any(|o| o == output)
+    }
+
+    /// Marks `output` as muxed and directly writes the checkpoint.
+    pub fn set_muxed(&mut self, output: &Path) -> Result<()> {
+        if !self.is_muxed(output) {
+            self.state.muxed.push(output.to_path_buf())
+        }
+        // written to a temporary file first, so a crash while writing doesn't leave an invalid
+        // checkpoint behind
+        let tmp = self.path.with_extension("state.tmp");
+        fs::write(&tmp, serde_json::to_vec(&self.state)?)?;
+        fs::rename(&tmp, &self.path)?;
+        debug!("Checkpointed mux of {}", output.to_string_lossy());
+        Ok(())
+    }
+
+    /// Removes the checkpoint of `dst`, if any.
+    pub fn remove(dst: &Path) {
+        let path = Self::path_for(dst);
+        if path.exists() {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checkpoint_restart() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let dst = dir.path().join("video.mkv");
+        let hardsub = dir.path().join("video.hardsub.mkv");
+
+        let mut checkpoint = MuxCheckpoint::load(&dst);
+        assert!(!checkpoint.is_muxed(&dst));
+        assert!(!MuxCheckpoint::exists(&dst));
+
+        fs::write(&dst, b"muxed")?;
+        checkpoint.set_muxed(&dst)?;
+        assert!(dir.path().join(".video.mkv.state").exists());
+        // the run gets interrupted before the second output is muxed
+        drop(checkpoint);
+
+        let checkpoint = MuxCheckpoint::load(&dst);
+        assert!(MuxCheckpoint::exists(&dst));
+        assert!(checkpoint.is_muxed(&dst));
+        assert!(!checkpoint.is_muxed(&hardsub));
+
+        MuxCheckpoint::remove(&dst);
+        assert!(!MuxCheckpoint::exists(&dst));
+        assert!(!dir.path().join(".video.mkv.state").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_checkpoint_deleted_output() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let dst = dir.path().join("video.mkv");
+
+        fs::write(&dst, b"muxed")?;
+        MuxCheckpoint::load(&dst).set_muxed(&dst)?;
+        // an output which got deleted after it was muxed has to be muxed again
+        fs::remove_file(&dst)?;
+        assert!(!MuxCheckpoint::load(&dst).is_muxed(&dst));
+        assert!(!MuxCheckpoint::exists(&dst));
+        Ok(())
+    }
+
+    #[test]
+    fn test_checkpoint_invalid() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let dst = dir.path().join("video.mkv");
+
+        fs::write(&dst, b"muxed")?;
+        fs::write(dir.path().join(".video.mkv.state"), b"{\"muxed\": [")?;
+        let mut checkpoint = MuxCheckpoint::load(&dst);
+        assert!(!checkpoint.is_muxed(&dst));
+        // the invalid checkpoint gets replaced
+        checkpoint.set_muxed(&dst)?;
+        assert!(MuxCheckpoint::load(&dst).is_muxed(&dst));
+        Ok(())
+    }
+}
@@ -0,0 +1,167 @@
+use anyhow::{bail, Result};
+use crc32fast::Hasher;
+use log::{debug, warn};
+use sha2::{Digest, Sha256};
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Checksum {
+    Sha256,
+    Crc32,
+}
+
+impl Display for Checksum {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.extension())
+    }
+}
+
+impl Checksum {
+    pub fn parse(s: &str) -> Result<Checksum, String> {
+        Ok(match s.to_lowercase().as_str() {
+            "sha256" => Checksum::Sha256,
+            "crc32" => Checksum::Crc32,
+            _ => return Err(format!("'{}' is not a valid checksum algorithm", s)),
+        })
+    }
+
+    /// File extension of the sidecar file, without leading dot.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Checksum::Sha256 => "sha256",
+            Checksum::Crc32 => "crc32",
+        }
+    }
+
+    /// Path of the sidecar file which belongs to `path`, e.g. `video.mkv.sha256`.
+    pub fn sidecar_path(&self, path: &Path) -> PathBuf {
+        let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+        file_name.push(format!(".{}", self.extension()));
+        path.with_file_name(file_name)
+    }
+
+    /// Hashes the file at `path` and writes the checksum next to it. The sidecar has the same
+    /// format as the output of `sha256sum`, so it can be verified with `sha256sum -c`.
+    ///
+    /// This reads the whole file again. It can't be hashed while it's written because ffmpeg seeks
+    /// back in the output file, e.g. to write mkv cues or to move the mp4 index to the front.
+    pub fn write_sidecar(&self, path: &Path) -> Result<PathBuf> {
+        let hash = self.hash_file(path)?;
+        let sidecar_path = self.sidecar_path(path);
+        fs::write(
+            &sidecar_path,
+            format!(
+                "{}  {}\n",
+                hash,
+                path.file_name().unwrap_or_default().to_string_lossy()
+            ),
+        )?;
+        debug!(
+            "Wrote {} checksum of {} to {}",
+            self,
+            path.to_string_lossy(),
+            sidecar_path.to_string_lossy()
+        );
+        Ok(sidecar_path)
+    }
+
+    /// Verifies the file at `path` against its sidecar. Returns [`None`] if no sidecar exists.
+    pub fn verify_sidecar(&self, path: &Path) -> Result<Option<bool>> {
+        let sidecar_path = self.sidecar_path(path);
+        if !sidecar_path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(&sidecar_path)?;
+        let Some(expected) = content.split_whitespace().next() else {
+            bail!("Checksum file {} is empty", sidecar_path.to_string_lossy())
+        };
+        Ok(Some(expected.eq_ignore_ascii_case(&self.hash_file(path)?)))
+    }
+
+    /// Reads the file in chunks, so that the file never has to be loaded into memory completely.
+    fn hash_file(&self, path: &Path) -> Result<String> {
+        let mut file = File::open(path)?;
+        let mut buf = vec![0; 1024 * 1024];
+
+        let mut sha256 = Sha256::new();
+        let mut crc32 = Hasher::new();
+        loop {
+            let n = file.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            match self {
+                Checksum::Sha256 => sha256.update(&buf[..n]),
+                Checksum::Crc32 => crc32.update(&buf[..n]),
+            }
+        }
+
+        Ok(match self {
+            Checksum::Sha256 => format!("{:x}", sha256.finalize()),
+            Checksum::Crc32 => format!("{:08x}", crc32.finalize()),
+        })
+    }
+}
+
+/// Checks if an already existing output file is still intact. Returns `true` if no checksum is
+/// used or the file has no sidecar, as there is nothing to verify against in this case.
+pub fn verify_existing_checksum(checksum: Option<&Checksum>, path: &Path) -> Result<bool> {
+    let Some(checksum) = checksum else {
+        return Ok(true);
+    };
+    match checksum.verify_sidecar(path)? {
+        Some(true) => {
+            debug!(
+                "{} checksum of {} matches",
+                checksum,
+                path.to_string_lossy()
+            );
+            Ok(true)
+        }
+        Some(false) => {
+            warn!(
+                "{} checksum of {} doesn't match, downloading it again",
+                checksum,
+                path.to_string_lossy()
+            );
+            Ok(false)
+        }
+        None => Ok(true),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sidecar_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("video.mkv");
+        fs::write(&path, b"123456789").unwrap();
+
+        for (checksum, expected) in [
+            (
+                Checksum::Sha256,
+                "15e2b0d3c33891ebb0f1ef609ec419420c20e320ce94c65fbc8c3312448eb225",
+            ),
+            (Checksum::Crc32, "cbf43926"),
+        ] {
+            assert_eq!(checksum.verify_sidecar(&path).unwrap(), None);
+            let sidecar_path = checksum.write_sidecar(&path).unwrap();
+            assert_eq!(
+                fs::read_to_string(&sidecar_path).unwrap(),
+                format!("{}  video.mkv\n", expected)
+            );
+            assert_eq!(checksum.verify_sidecar(&path).unwrap(), Some(true));
+
+            fs::write(&path, b"12345678").unwrap();
+            assert_eq!(checksum.verify_sidecar(&path).unwrap(), Some(false));
+            fs::write(&path, b"123456789").unwrap();
+        }
+    }
+}
@@ -1,12 +1,52 @@
 use crate::utils::parse::parse_resolution;
+use crate::utils::rate_limit::MIN_REQUEST_RATE;
+use clap::parser::ValueSource;
+use clap::ArgMatches;
 use crunchyroll_rs::media::Resolution;
 use regex::Regex;
 use reqwest::Proxy;
+use std::str::FromStr;
+
+/// If the argument `id` has its default value because it wasn't given. Comparing the parsed value
+/// with the default can't tell if the same value was given explicitly.
+pub fn is_default_value(matches: &ArgMatches, id: &str) -> bool {
+    matches.value_source(id) == Some(ValueSource::DefaultValue)
+}
 
 pub fn clap_parse_resolution(s: &str) -> Result<Resolution, String> {
     parse_resolution(s.to_string()).map_err(|e| e.to_string())
 }
 
+pub fn clap_parse_min_resolution(s: &str) -> Result<Resolution, String> {
+    // a plain number is interpreted as height, like '720p'
+    let resolution = if s.chars().all(|c| c.is_ascii_digit()) {
+        parse_resolution(format!("{}p", s))
+    } else {
+        parse_resolution(s.to_string())
+    }
+    .map_err(|e| e.to_string())?;
+    if resolution.height == u64::MAX || resolution.height == u64::MIN {
+        return Err("Minimum resolution must be a specific resolution (e.g. 720p)".to_string());
+    }
+    Ok(resolution)
+}
+
+/// Parses a case-insensitive regex which is matched against episode titles.
+pub fn clap_parse_title_regex(s: &str) -> Result<Regex, String> {
+    regex::RegexBuilder::new(s)
+        .case_insensitive(true)
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+/// Builds a case-insensitive regex which matches titles containing `s`.
+pub fn clap_parse_title_contains(s: &str) -> Result<Regex, String> {
+    if s.is_empty() {
+        return Err("The title must not be empty".to_string());
+    }
+    clap_parse_title_regex(&regex::escape(s))
+}
+
 pub fn clap_parse_proxies(s: &str) -> Result<(Option<Proxy>, Option<Proxy>), String> {
     let double_proxy_regex =
         Regex::new(r"^(?P<first>(https?|socks5h?)://.+):(?P<second>(https?|socks5h?)://.+)$")
@@ -59,3 +99,157 @@ pub fn clap_parse_speed_limit(s: &str) -> Result<u32, String> {
     };
     Ok(bytes)
 }
+
+pub fn clap_parse_request_rate(s: &str) -> Result<f64, String> {
+    let rate: f64 = s
+        .parse()
+        .map_err(|_| format!("'{}' is not a valid request rate", s))?;
+    if !rate.is_finite() || rate < MIN_REQUEST_RATE {
+        return Err(format!(
+            "Request rate must be a number of at least {} requests per second",
+            MIN_REQUEST_RATE
+        ));
+    }
+    Ok(rate)
+}
+
+pub fn clap_parse_loudness_target(s: &str) -> Result<f32, String> {
+    let lufs: f32 = s
+        .trim_end_matches("LUFS")
+        .trim()
+        .parse()
+        .map_err(|_| format!("'{}' is not a valid loudness target", s))?;
+    // these are the limits of ffmpeg's 'loudnorm' filter
+    if !(-70.0..=-5.0).contains(&lufs) {
+        return Err("Loudness target must be between -70 and -5 LUFS".to_string());
+    }
+    Ok(lufs)
+}
+
+pub fn clap_parse_header(s: &str) -> Result<(http::HeaderName, http::HeaderValue), String> {
+    let Some((name, value)) = s.split_once(':') else {
+        return Err(format!(
+            "'{}' is not a valid header, must be in format 'Name: Value'",
+            s
+        ));
+    };
+    let name = http::HeaderName::from_str(name.trim())
+        .map_err(|_| format!("'{}' is not a valid header name", name.trim()))?;
+    // these headers are calculated per request, overwriting them would break every request
+    for forbidden in [
+        http::header::HOST,
+        http::header::CONTENT_LENGTH,
+        http::header::TRANSFER_ENCODING,
+    ] {
+        if name == forbidden {
+            return Err(format!("The '{}' header cannot be set", name));
+        }
+    }
+    let mut value = http::HeaderValue::from_str(value.trim())
+        .map_err(|_| format!("Value of header '{}' contains invalid characters", name))?;
+    // custom headers often carry credentials. sensitive values are shown as 'Sensitive' in debug
+    // output (e.g. the debug print of the cli arguments) and aren't written to http logs
+    value.set_sensitive(true);
+    Ok((name, value))
+}
+
+/// Parses a dns override in the format 'host:ip'. IPv6 addresses may be wrapped in brackets.
+pub fn clap_parse_resolve(s: &str) -> Result<(String, std::net::IpAddr), String> {
+    let Some((host, ip)) = s.split_once(':') else {
+        return Err(format!(
+            "'{}' is not a valid dns override, must be in format 'host:ip'",
+            s
+        ));
+    };
+    if host.is_empty()
+        || !host
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+    {
+        return Err(format!("'{}' is not a valid host name", host));
+    }
+    let ip = ip
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse::<std::net::IpAddr>()
+        .map_err(|_| format!("'{}' is not a valid ip address", ip))?;
+    Ok((host.to_lowercase(), ip))
+}
+
+pub fn clap_parse_doh(s: &str) -> Result<reqwest::Url, String> {
+    let url = reqwest::Url::parse(s).map_err(|e| e.to_string())?;
+    if url.scheme() != "https" {
+        return Err("DNS-over-HTTPS url must start with 'https://'".to_string());
+    }
+    Ok(url)
+}
+
+/// Returns the header value, or a placeholder if the header name looks like the value is a secret
+/// which shouldn't show up in logs.
+pub fn redacted_header_value(name: &http::HeaderName, value: &http::HeaderValue) -> String {
+    let name = name.as_str();
+    if [
+        "auth",
+        "token",
+        "key",
+        "secret",
+        "cookie",
+        "password",
+        "session",
+        "client-id",
+    ]
+    .iter()
+    .any(|s| name.contains(s))
+    {
+        "<redacted>".to_string()
+    } else {
+        value.to_str().unwrap_or("<binary>").to_string()
+    }
+}
+
+pub fn clap_parse_user_agent(s: &str) -> Result<String, String> {
+    if s.trim().is_empty() {
+        return Err("User agent must not be empty".to_string());
+    }
+    // the user agent is sent as header, so it must only contain valid header characters
+    http::HeaderValue::from_str(s).map_err(|_| format!("'{}' is not a valid user agent", s))?;
+    Ok(s.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::{Arg, Command};
+
+    #[test]
+    fn test_is_default_value() {
+        let command =
+            Command::new("test").arg(Arg::new("output").short('o').default_value("{title}.mp4"));
+        let matches = command.clone().get_matches_from(["test"]);
+        assert!(is_default_value(&matches, "output"));
+        // the default template given explicitly isn't the default value
+        let matches = command.get_matches_from(["test", "-o", "{title}.mp4"]);
+        assert!(!is_default_value(&matches, "output"));
+    }
+
+    #[test]
+    fn test_parse_title_patterns() {
+        assert!(clap_parse_title_regex("Fr(an)+xx")
+            .unwrap()
+            .is_match("FRANXX"));
+        assert!(clap_parse_title_regex("(unclosed").is_err());
+        assert!(clap_parse_title_contains("(unclosed")
+            .unwrap()
+            .is_match("An (Unclosed) Title"));
+        assert!(clap_parse_title_contains("").is_err())
+    }
+
+    #[test]
+    fn test_parse_request_rate() {
+        assert_eq!(clap_parse_request_rate("2.5"), Ok(2.5));
+        assert_eq!(clap_parse_request_rate("0.5"), Ok(0.5));
+        for invalid in ["0", "-1", "0.1", "NaN", "inf", "fast"] {
+            assert!(clap_parse_request_rate(invalid).is_err(), "{}", invalid)
+        }
+    }
+}
@@ -6,4 +6,7 @@ pub struct Context {
     pub crunchy: Crunchyroll,
     pub client: Client,
     pub rate_limiter: Option<RateLimiterService>,
+    /// Client for the `--webhook`. Only the proxy is set, the custom headers (which may contain
+    /// secrets) are never sent to it.
+    pub webhook_client: Client,
 }
@@ -0,0 +1,128 @@
+use log::debug;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::{Client, Url};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Record types as defined in RFC 1035 and RFC 3596.
+const RECORD_TYPE_A: u16 = 1;
+const RECORD_TYPE_AAAA: u16 = 28;
+
+#[derive(Deserialize)]
+struct DohResponse {
+    #[serde(rename = "Status")]
+    status: u32,
+    #[serde(rename = "Answer", default)]
+    answer: Vec<DohAnswer>,
+}
+
+#[derive(Deserialize)]
+struct DohAnswer {
+    #[serde(rename = "type")]
+    record_type: u16,
+    #[serde(rename = "TTL", default)]
+    ttl: u64,
+    data: String,
+}
+
+/// Resolves host names via DNS-over-HTTPS, using the JSON api which is supported by most public
+/// resolvers (e.g. `https://cloudflare-dns.com/dns-query` or `https://dns.google/resolve`).
+/// Resolved addresses are cached for the ttl of their records, as every new connection resolves
+/// the host again.
+#[derive(Clone)]
+pub struct DohResolver {
+    url: Url,
+    client: Client,
+    cache: Arc<Mutex<HashMap<String, (Vec<IpAddr>, Instant)>>>,
+}
+
+impl DohResolver {
+    pub fn new(url: Url, client: Client) -> Self {
+        Self {
+            url,
+            client,
+            cache: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    async fn lookup(&self, host: &str) -> anyhow::Result<Vec<IpAddr>> {
+        if let Some((addrs, valid_until)) = self.cache.lock().unwrap().get(host) {
+            if *valid_until > Instant::now() {
+                return Ok(addrs.clone());
+            }
+        }
+
+        let mut addrs = vec![];
+        let mut ttl = u64::MAX;
+        for record_type in [RECORD_TYPE_A, RECORD_TYPE_AAAA] {
+            let response: DohResponse = self
+                .client
+                .get(self.url.clone())
+                .query(&[("name", host), ("type", &record_type.to_string())])
+                .header(reqwest::header::ACCEPT, "application/dns-json")
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+            // 3 is NXDOMAIN, which isn't an error if the other record type exists
+            if response.status != 0 && response.status != 3 {
+                anyhow::bail!(
+                    "DNS-over-HTTPS server responded with status {} for {}",
+                    response.status,
+                    host
+                )
+            }
+            for answer in response.answer {
+                // cname records are resolved by the server, only the resulting addresses are used
+                if answer.record_type != record_type {
+                    continue;
+                }
+                if let Ok(addr) = answer.data.parse::<IpAddr>() {
+                    addrs.push(addr);
+                    ttl = ttl.min(answer.ttl)
+                }
+            }
+        }
+        if addrs.is_empty() {
+            anyhow::bail!("DNS-over-HTTPS server returned no addresses for {}", host)
+        }
+        debug!(
+            "Resolved {} via DNS-over-HTTPS to {}",
+            host,
+            addrs
+                .iter()
+                .map(|a| a.to_string())
+                .collect::<Vec<String>>()
+                .join(", ")
+        );
+
+        self.cache.lock().unwrap().insert(
+            host.to_string(),
+            (addrs.clone(), Instant::now() + Duration::from_secs(ttl)),
+        );
+        Ok(addrs)
+    }
+}
+
+impl Resolve for DohResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        // the cache is shared between all clones, so this doesn't lose any resolved addresses
+        let resolver = self.clone();
+        Box::pin(async move {
+            let addrs = resolver.lookup(name.as_str()).await?;
+            // the port is replaced by reqwest with the port of the requested url
+            let addrs: Addrs = Box::new(
+                addrs
+                    .into_iter()
+                    .map(|addr| SocketAddr::new(addr, 0))
+                    .collect::<Vec<SocketAddr>>()
+                    .into_iter(),
+            );
+            Ok(addrs)
+        })
+    }
+}
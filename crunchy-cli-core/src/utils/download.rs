@@ -1,23 +1,43 @@
-use crate::utils::ffmpeg::FFmpegPreset;
+use crate::utils::checkpoint::MuxCheckpoint;
+use crate::utils::event::EventSink;
+use crate::utils::ffmpeg::{
+    is_stream_copy, remove_stream_codec, remove_stream_copy, AudioCodec, ExternalStream, FFmpegLog,
+    FFmpegPreset, SOFTSUB_CONTAINERS,
+};
 use crate::utils::filter::real_dedup_vec;
 use crate::utils::fmt::format_time_delta;
-use crate::utils::log::progress;
-use crate::utils::os::{cache_dir, is_special_file, temp_directory, temp_named_pipe, tempfile};
+use crate::utils::log::{add_progress_bar, draw_progress, progress, LogFile};
+use crate::utils::os::{
+    cache_dir, is_special_file, temp_directory, temp_named_pipe, tempfile, PartialOutput,
+};
+use crate::utils::pause::wait_while_paused;
 use crate::utils::rate_limit::RateLimiterService;
+use crate::utils::raw::{RawManifest, RawStream, RawStreamKind};
+use crate::utils::retry::{retry_after, RetryThrottle};
+use crate::utils::subtitle::{
+    ass_to_srt, ass_to_vtt, drop_ass_styles, encode_subtitle, keep_ass_styles, merge_dual_ass,
+    shift_ass, SubtitleFormat, SubtitleOffset, DEFAULT_SIGN_STYLES,
+};
 use crate::utils::sync::{sync_audios, SyncAudio};
-use anyhow::{bail, Result};
+use crate::utils::timing::Phase;
+use anyhow::{anyhow, bail, Result};
 use chrono::{NaiveTime, TimeDelta};
 use crunchyroll_rs::media::{SkipEvents, SkipEventsEvent, StreamData, StreamSegment, Subtitle};
 use crunchyroll_rs::Locale;
-use indicatif::{ProgressBar, ProgressDrawTarget, ProgressFinish, ProgressStyle};
-use log::{debug, warn, LevelFilter};
+use encoding_rs::Encoding;
+use indicatif::{ProgressBar, ProgressFinish, ProgressStyle};
+use log::{debug, info, warn, LevelFilter};
 use regex::Regex;
-use reqwest::Client;
+use reqwest::{Client, StatusCode};
+use rsubs_lib::ssa::SSAEventLineType;
 use rsubs_lib::{SSA, VTT};
+use sha2::{Digest, Sha256};
 use std::borrow::Borrow;
 use std::cmp::Ordering;
 use std::collections::{BTreeMap, HashMap};
-use std::io::Write;
+use std::future::Future;
+use std::hash::Hash;
+use std::io::{BufRead, BufReader, Write};
 use std::ops::Add;
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
@@ -29,7 +49,7 @@ use time::Time;
 use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
 use tokio::select;
 use tokio::sync::mpsc::unbounded_channel;
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, Semaphore};
 use tokio::task::JoinSet;
 use tokio_util::sync::CancellationToken;
 use tower_service::Service;
@@ -54,6 +74,29 @@ impl MergeBehavior {
     }
 }
 
+/// What gets written if the output is stdout.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub enum PipeMode {
+    /// All streams are muxed into a single mpegts stream.
+    #[default]
+    Muxed,
+    /// The bytes of the video stream, as they're downloaded. Nothing is muxed.
+    Video,
+    /// The bytes of the audio stream, as they're downloaded. Nothing is muxed.
+    Audio,
+}
+
+impl PipeMode {
+    pub fn parse(s: &str) -> Result<PipeMode, String> {
+        Ok(match s.to_lowercase().as_str() {
+            "muxed" => PipeMode::Muxed,
+            "video" => PipeMode::Video,
+            "audio" => PipeMode::Audio,
+            _ => return Err(format!("'{}' is not a valid pipe mode", s)),
+        })
+    }
+}
+
 #[derive(Clone, derive_setters::Setters)]
 pub struct DownloadBuilder {
     client: Client,
@@ -66,10 +109,35 @@ pub struct DownloadBuilder {
     force_hardsub: bool,
     download_fonts: bool,
     no_closed_caption: bool,
+    include_chapters: bool,
+    cut_intro: bool,
+    cut_outro: bool,
     merge_sync_tolerance: Option<u32>,
     merge_sync_precision: Option<u32>,
     threads: usize,
     ffmpeg_threads: Option<usize>,
+    ffmpeg_extra_args: Vec<String>,
+    loudnorm: Option<f32>,
+    audio_codec: Option<AudioCodec>,
+    mp4_layout: Mp4Layout,
+    audio_bitrate: Option<u32>,
+    ffmpeg_semaphore: Option<Arc<Semaphore>>,
+    mux_into: Option<PathBuf>,
+    subtitle_format: SubtitleFormat,
+    subtitle_offsets: Vec<SubtitleOffset>,
+    subtitle_drop_styles: Vec<String>,
+    forced_subtitle_styles: Vec<String>,
+    subtitle_encoding: Option<&'static Encoding>,
+    dual_subtitles: bool,
+    raw: bool,
+    pipe: PipeMode,
+    max_segments: Option<usize>,
+    strict_copy: bool,
+    ffmpeg_log: FFmpegLog,
+    container_tags: Vec<(String, String)>,
+    external_audios: Vec<ExternalStream>,
+    external_subtitles: Vec<ExternalStream>,
+    event_sink: Option<Arc<dyn EventSink>>,
     audio_locale_output_map: HashMap<Locale, String>,
     subtitle_locale_output_map: HashMap<Locale, String>,
 }
@@ -87,10 +155,35 @@ impl DownloadBuilder {
             force_hardsub: false,
             download_fonts: false,
             no_closed_caption: false,
+            include_chapters: false,
+            cut_intro: false,
+            cut_outro: false,
             merge_sync_tolerance: None,
             merge_sync_precision: None,
             threads: num_cpus::get(),
             ffmpeg_threads: None,
+            ffmpeg_extra_args: vec![],
+            loudnorm: None,
+            audio_codec: None,
+            mp4_layout: Mp4Layout::default(),
+            audio_bitrate: None,
+            ffmpeg_semaphore: None,
+            mux_into: None,
+            subtitle_format: SubtitleFormat::default(),
+            subtitle_offsets: vec![],
+            subtitle_drop_styles: vec![],
+            forced_subtitle_styles: DEFAULT_SIGN_STYLES.iter().map(|s| s.to_string()).collect(),
+            subtitle_encoding: None,
+            dual_subtitles: false,
+            raw: false,
+            pipe: PipeMode::default(),
+            max_segments: None,
+            strict_copy: false,
+            ffmpeg_log: FFmpegLog::default(),
+            container_tags: vec![],
+            external_audios: vec![],
+            external_subtitles: vec![],
+            event_sink: None,
             audio_locale_output_map: HashMap::new(),
             subtitle_locale_output_map: HashMap::new(),
         }
@@ -110,13 +203,48 @@ impl DownloadBuilder {
             download_fonts: self.download_fonts,
             no_closed_caption: self.no_closed_caption,
 
+            include_chapters: self.include_chapters,
+            cut_intro: self.cut_intro,
+            cut_outro: self.cut_outro,
+
             merge_sync_tolerance: self.merge_sync_tolerance,
             merge_sync_precision: self.merge_sync_precision,
 
             download_threads: self.threads,
             ffmpeg_threads: self.ffmpeg_threads,
+            ffmpeg_extra_args: self.ffmpeg_extra_args,
+
+            loudnorm: self.loudnorm,
+            audio_codec: self.audio_codec,
+            mp4_layout: self.mp4_layout,
+            audio_bitrate: self.audio_bitrate,
+
+            ffmpeg_semaphore: self.ffmpeg_semaphore,
+
+            mux_into: self.mux_into,
+
+            subtitle_format: self.subtitle_format,
+            subtitle_offsets: self.subtitle_offsets,
+            subtitle_drop_styles: self.subtitle_drop_styles,
+            forced_subtitle_styles: self.forced_subtitle_styles,
+            subtitle_encoding: self.subtitle_encoding,
+            dual_subtitles: self.dual_subtitles,
+
+            raw: self.raw,
+            pipe: self.pipe,
+            max_segments: self.max_segments,
+            strict_copy: self.strict_copy,
+            ffmpeg_log: self.ffmpeg_log,
+
+            container_tags: self.container_tags,
+            external_audios: self.external_audios,
+            external_subtitles: self.external_subtitles,
+
+            event_sink: self.event_sink,
 
             formats: vec![],
+            hardsub_outputs: vec![],
+            audio_outputs: vec![],
 
             audio_locale_output_map: self.audio_locale_output_map,
             subtitle_locale_output_map: self.subtitle_locale_output_map,
@@ -130,6 +258,15 @@ struct FFmpegVideoMeta {
     start_time: Option<TimeDelta>,
 }
 
+struct FFmpegMuxIntoMeta {
+    path: PathBuf,
+    length: TimeDelta,
+    fps: f64,
+    audios: usize,
+    subtitles: usize,
+    attachments: usize,
+}
+
 struct FFmpegAudioMeta {
     path: TempPath,
     locale: Locale,
@@ -140,20 +277,249 @@ struct FFmpegAudioMeta {
 struct FFmpegSubtitleMeta {
     path: TempPath,
     locale: Locale,
+    /// Locale of the bottom language if the subtitle is a dual subtitle.
+    secondary_locale: Option<Locale>,
     cc: bool,
+    lyrics: bool,
+    /// If the subtitle only contains the sign and song lines of the audio language.
+    forced: bool,
     start_time: Option<TimeDelta>,
     video_idx: usize,
 }
 
+/// The streams which were fetched by [`Downloader::fetch_streams`] and are muxed into the output
+/// file(s).
+struct FetchedStreams {
+    videos: Vec<FFmpegVideoMeta>,
+    audios: Vec<FFmpegAudioMeta>,
+    mux_into: Option<FFmpegMuxIntoMeta>,
+    subtitle_offsets: HashMap<usize, TimeDelta>,
+    /// Subtitle data by their url.
+    prefetched_subtitles: HashMap<String, Vec<u8>>,
+    cut_ranges: CutRanges,
+    max_len: TimeDelta,
+    max_frames: u64,
+    fmt_space: usize,
+}
+
+/// Time ranges, in seconds, which are cut out of the output.
+#[derive(Default)]
+struct CutRanges(Vec<(f32, f32)>);
+
+impl CutRanges {
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Total duration of all cut ranges.
+    fn duration(&self) -> f32 {
+        self.0.iter().map(|(start, end)| end - start).sum()
+    }
+
+    /// Maps a timestamp of the original video to the timestamp in the cut video. Timestamps
+    /// inside a cut range are mapped to the start of it.
+    fn map(&self, time: f32) -> f32 {
+        let cut: f32 = self
+            .0
+            .iter()
+            .map(|(start, end)| (time.min(*end) - start).max(0.0))
+            .sum();
+        time - cut
+    }
+
+    /// A ffmpeg expression which is true if the frame is inside a cut range.
+    fn between_expr(&self) -> String {
+        self.0
+            .iter()
+            .map(|(start, end)| format!("between(t,{},{})", start, end))
+            .collect::<Vec<String>>()
+            .join("+")
+    }
+}
+
+/// The options which decide if and how the streams of an output file are re-encoded. Used to
+/// build the arguments when muxing and, by `--strict-copy`, to check them before anything is
+/// downloaded.
+#[derive(Clone, Debug, Default)]
+pub struct StreamOptions {
+    pub ffmpeg_preset: FFmpegPreset,
+    /// Output arguments which are appended to the ones of the preset, e.g. from `--preset-map`.
+    pub ffmpeg_extra_args: Vec<String>,
+    /// If the streams are muxed into an existing file. Re-encoding would also affect its streams,
+    /// so the preset and the extra arguments are ignored and everything is only copied.
+    pub mux_into: bool,
+    /// Path of the subtitle which is burned into the video, already escaped for the ass filter.
+    pub burn_subtitle: Option<String>,
+    /// If [`None`], the audio is copied if possible and encoded with aac otherwise.
+    pub audio_codec: Option<AudioCodec>,
+    pub loudnorm: Option<f32>,
+    /// ffmpeg expression which is true for the frames which are cut out, see
+    /// [`CutRanges::between_expr`].
+    pub cut: Option<String>,
+}
+
+/// The ffmpeg arguments built from [`StreamOptions`].
+pub struct StreamArgs {
+    pub input: Vec<String>,
+    pub output: Vec<String>,
+    /// Names of the streams (`video`, `audio`) which the preset copies but which get re-encoded.
+    pub reencoded: Vec<&'static str>,
+    /// Why the streams get re-encoded.
+    pub reasons: Vec<&'static str>,
+}
+
+impl StreamOptions {
+    pub fn build(self) -> Result<StreamArgs> {
+        let (input, mut output) = if self.mux_into {
+            (vec![], vec!["-c".to_string(), "copy".to_string()])
+        } else {
+            let (input, mut output) = self.ffmpeg_preset.into_input_output_args();
+            // ffmpeg uses the last value of options which are given multiple times, so the extra
+            // arguments take precedence over the preset
+            output.extend(self.ffmpeg_extra_args);
+            (input, output)
+        };
+        // streams which are copied by the preset but may get re-encoded below
+        let copy_expected = [
+            ("video", "v", is_stream_copy(&output, "v")),
+            ("audio", "a", is_stream_copy(&output, "a")),
+        ];
+        let mut reasons = vec![];
+
+        if let Some(subtitle) = self.burn_subtitle {
+            // remove '-c:v copy' and '-c:a copy' from output presets as its causes issues with
+            // burning subs into the video
+            remove_stream_copy(&mut output, "v");
+            remove_stream_copy(&mut output, "a");
+            reasons.push("burning in subtitles");
+            output.extend(["-vf".to_string(), format!("ass='{}'", subtitle)])
+        }
+
+        // the audio codec is applied before the filters, so that they use it instead of falling
+        // back to aac and, if the audio is re-encoded anyway, loudness normalization isn't skipped
+        if let Some(codec) = self
+            .audio_codec
+            .as_ref()
+            .filter(|c| !matches!(c, AudioCodec::Copy))
+        {
+            if is_stream_copy(&output, "a") {
+                reasons.push("`--audio-codec`")
+            }
+            remove_stream_codec(&mut output, "a");
+            output.extend(["-c:a".to_string(), codec.encoder().to_string()])
+        }
+
+        if let Some(loudness) = self.loudnorm {
+            if is_stream_copy(&output, "v") && is_stream_copy(&output, "a") {
+                warn!("Audio loudness normalization requires the output to be re-encoded, skipping it as the streams are only remuxed")
+            } else {
+                // the audio can't be filtered if it's only copied, so it gets re-encoded with aac
+                if remove_stream_copy(&mut output, "a") {
+                    output.extend(["-c:a".to_string(), "aac".to_string()])
+                }
+                reasons.push("loudness normalization");
+                // 'loudnorm' upsamples the audio to 192kHz, 'aresample' brings it back to a sane
+                // sample rate. it's the last filter, so the loudness of the already filtered
+                // audio is normalized
+                append_filter(
+                    &mut output,
+                    "-af",
+                    format!("loudnorm=I={}:TP=-1.5:LRA=11,aresample=48000", loudness),
+                )
+            }
+        }
+
+        if let Some(between) = self.cut {
+            // the cut parts are dropped by filters, which require the streams to be re-encoded
+            remove_stream_copy(&mut output, "v");
+            if remove_stream_copy(&mut output, "a") {
+                output.extend(["-c:a".to_string(), "aac".to_string()])
+            }
+            reasons.push("cutting intro / outro");
+            // the cut is done before any other filter, as e.g. burnt-in subtitles are already
+            // shifted to the timestamps of the cut video
+            prepend_filter(
+                &mut output,
+                "-vf",
+                format!("select='not({})',setpts=N/FRAME_RATE/TB", between),
+            );
+            prepend_filter(
+                &mut output,
+                "-af",
+                format!("aselect='not({})',asetpts=N/SR/TB", between),
+            );
+        }
+
+        // copying the audio together with audio filters is rejected when checking the arguments,
+        // this only catches filters of custom presets
+        if let Some(AudioCodec::Copy) = &self.audio_codec {
+            if output.iter().any(|p| p == "-af") {
+                bail!(
+                    "The audio can't be copied as it has to be re-encoded ({}), use another `--audio-codec`",
+                    reasons.join(", ")
+                )
+            }
+            remove_stream_codec(&mut output, "a");
+            output.extend(["-c:a".to_string(), "copy".to_string()])
+        }
+
+        let reencoded = copy_expected
+            .into_iter()
+            .filter(|(_, stream_type, expected)| *expected && !is_stream_copy(&output, stream_type))
+            .map(|(name, _, _)| name)
+            .collect();
+        Ok(StreamArgs {
+            input,
+            output,
+            reencoded,
+            reasons,
+        })
+    }
+}
+
 pub struct DownloadFormat {
     pub video: (StreamData, Locale),
     pub audios: Vec<(StreamData, Locale)>,
     pub subtitles: Vec<(Subtitle, bool)>,
+    /// Subtitle of the audio language from which only the sign and song lines are muxed, as
+    /// forced subtitle.
+    pub forced_subtitle: Option<Subtitle>,
     pub metadata: DownloadFormatMetadata,
 }
 
+impl DownloadFormat {
+    /// A fingerprint of the streams, to detect if Crunchyroll replaced them with another version.
+    /// Only properties which don't change between two requests of the same version are included,
+    /// the segment urls for example contain expiring tokens.
+    pub fn fingerprint(&self) -> String {
+        let mut hasher = Sha256::new();
+        for (stream_data, locale) in [&self.video].into_iter().chain(self.audios.iter()) {
+            let segments = stream_data.segments();
+            hasher.update(
+                format!(
+                    "{}|{}|{}|{}|{}|{};",
+                    locale,
+                    stream_data.bandwidth,
+                    stream_data.codecs,
+                    stream_data
+                        .resolution()
+                        .map(|r| r.to_string())
+                        .unwrap_or_default(),
+                    segments.len(),
+                    len_from_segments(&segments).num_milliseconds()
+                )
+                .as_bytes(),
+            )
+        }
+        format!("{:x}", hasher.finalize())
+    }
+}
+
 pub struct DownloadFormatMetadata {
     pub skip_events: Option<SkipEvents>,
+    /// If the format is a music video or concert. Their subtitles are lyrics and are labeled as
+    /// such.
+    pub lyrics: bool,
 }
 
 pub struct Downloader {
@@ -170,24 +536,171 @@ pub struct Downloader {
     download_fonts: bool,
     no_closed_caption: bool,
 
+    include_chapters: bool,
+    cut_intro: bool,
+    cut_outro: bool,
+
     merge_sync_tolerance: Option<u32>,
     merge_sync_precision: Option<u32>,
 
     download_threads: usize,
     ffmpeg_threads: Option<usize>,
+    /// Output arguments which are appended to the ones of the preset, e.g. from `--preset-map`.
+    ffmpeg_extra_args: Vec<String>,
+
+    loudnorm: Option<f32>,
+    /// If [`None`], the audio is copied if possible and encoded with aac otherwise.
+    audio_codec: Option<AudioCodec>,
+    mp4_layout: Mp4Layout,
+    /// In kbps, only used if the audio is re-encoded.
+    audio_bitrate: Option<u32>,
+
+    ffmpeg_semaphore: Option<Arc<Semaphore>>,
+
+    mux_into: Option<PathBuf>,
+
+    subtitle_format: SubtitleFormat,
+    subtitle_offsets: Vec<SubtitleOffset>,
+    /// Dialogue lines with one of these styles are removed from ass subtitles.
+    subtitle_drop_styles: Vec<String>,
+    /// Only dialogue lines with one of these styles are kept in forced subtitles.
+    forced_subtitle_styles: Vec<String>,
+    /// Charset of softsubs, [`None`] is UTF-8. Burnt-in subtitles are always UTF-8 encoded.
+    subtitle_encoding: Option<&'static Encoding>,
+    /// If set, the two subtitles of a format are merged into a single dual subtitle.
+    dual_subtitles: bool,
+
+    raw: bool,
+    pipe: PipeMode,
+    max_segments: Option<usize>,
+    strict_copy: bool,
+    ffmpeg_log: FFmpegLog,
+
+    container_tags: Vec<(String, String)>,
+    external_audios: Vec<ExternalStream>,
+    external_subtitles: Vec<ExternalStream>,
+
+    event_sink: Option<Arc<dyn EventSink>>,
 
     formats: Vec<DownloadFormat>,
+    hardsub_outputs: Vec<PathBuf>,
+    /// Outputs which only contain the audio of their locale, used instead of `dst` if not empty.
+    audio_outputs: Vec<(PathBuf, Locale)>,
 
     audio_locale_output_map: HashMap<Locale, String>,
     subtitle_locale_output_map: HashMap<Locale, String>,
 }
 
+/// The downloaded streams of an episode which are not muxed yet, see [`Downloader::fetch`].
+pub struct PendingMux {
+    downloader: Downloader,
+    dst: PathBuf,
+    fetched: Option<(FetchedStreams, Option<MuxCheckpoint>)>,
+}
+
+impl PendingMux {
+    /// Muxes the downloaded streams into the output file(s). The temporary files of the streams
+    /// are removed afterwards.
+    pub async fn mux(self) -> Result<()> {
+        let PendingMux {
+            downloader,
+            dst,
+            fetched,
+        } = self;
+
+        let result = match fetched {
+            Some((fetched, checkpoint)) => downloader.mux_outputs(&dst, &fetched, checkpoint).await,
+            None => Ok(()),
+        };
+
+        if let Some(event_sink) = &downloader.event_sink {
+            match &result {
+                Ok(_) => event_sink.episode_finished(&dst),
+                Err(e) => event_sink.error(&dst, e),
+            }
+        }
+        result
+    }
+}
+
 impl Downloader {
     pub fn add_format(&mut self, format: DownloadFormat) {
         self.formats.push(format);
     }
 
-    pub async fn download(mut self, dst: &Path) -> Result<()> {
+    /// Creates an additional copy with burnt-in subtitles at `dst`. The streams are only
+    /// downloaded once and muxed for every output.
+    pub fn add_hardsub_output(&mut self, dst: PathBuf) {
+        self.hardsub_outputs.push(dst);
+    }
+
+    /// Creates an output at `dst` which only contains the audio of `locale`, besides the video and
+    /// all subtitles. If audio outputs are added, they're created instead of the output which
+    /// contains all audios, so the output of the first audio should be the `dst` which is given to
+    /// [`Downloader::download`]. The streams are only downloaded once and muxed for every output.
+    pub fn add_audio_output(&mut self, dst: PathBuf, locale: Locale) {
+        self.audio_outputs.push((dst, locale));
+    }
+
+    pub async fn download(self, dst: &Path) -> Result<()> {
+        self.fetch(dst).await?.mux().await
+    }
+
+    /// Downloads all streams of `dst` without muxing them. Muxing is done by the returned
+    /// [`PendingMux`], so the streams of the next episode can already be downloaded while the
+    /// streams of this one are muxed.
+    pub async fn fetch(mut self, dst: &Path) -> Result<PendingMux> {
+        if let Some(event_sink) = &self.event_sink {
+            event_sink.episode_started(dst)
+        }
+
+        match self.fetch_streams(dst).await {
+            Ok(fetched) => Ok(PendingMux {
+                downloader: self,
+                dst: dst.to_path_buf(),
+                fetched,
+            }),
+            Err(e) => {
+                if let Some(event_sink) = &self.event_sink {
+                    event_sink.error(dst, &e)
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Downloads all streams. Returns [`None`] if nothing has to be muxed, e.g. because the
+    /// streams were piped to stdout or all outputs were already muxed by a previous run.
+    async fn fetch_streams(
+        &mut self,
+        dst: &Path,
+    ) -> Result<Option<(FetchedStreams, Option<MuxCheckpoint>)>> {
+        if self.pipe != PipeMode::Muxed {
+            self.pipe_stream().await?;
+            return Ok(None);
+        }
+
+        // outputs which were already muxed by a previous, interrupted run are not created again.
+        // outputs of only some segments are never complete, so they're not checkpointed
+        let checkpoint =
+            (!is_special_file(dst) && dst.to_string_lossy() != "-" && self.max_segments.is_none())
+                .then(|| MuxCheckpoint::load(dst));
+        if let Some(checkpoint) = &checkpoint {
+            if checkpoint.is_muxed(dst)
+                && self.hardsub_outputs.iter().all(|o| checkpoint.is_muxed(o))
+                && self
+                    .audio_outputs
+                    .iter()
+                    .all(|(o, _)| checkpoint.is_muxed(o))
+            {
+                info!(
+                    "{} was already muxed by a previous run, skipping download and mux",
+                    dst.to_string_lossy()
+                );
+                return Ok(None);
+            }
+        }
+
         // `.unwrap_or_default()` here unless https://doc.rust-lang.org/stable/std/path/fn.absolute.html
         // gets stabilized as the function might throw error on weird file paths
         let required = self.check_free_space(dst).await.unwrap_or_default();
@@ -248,15 +761,51 @@ impl Downloader {
             }
         }
 
+        let mux_into = if let Some(mux_into) = self.mux_into.clone() {
+            let Some(mux_into_meta) = self.prepare_mux_into(mux_into)? else {
+                return Ok(None);
+            };
+            Some(mux_into_meta)
+        } else {
+            None
+        };
+
+        let download_timer = Phase::Download.start(dst.to_string_lossy());
+
+        // subtitles are small and don't depend on the audio and video downloads, so they're fetched
+        // in the background while the segments are downloaded. the prefetch task gets aborted if
+        // the join set is dropped, e.g. because a segment download failed
+        let prefetch_subtitles: Vec<Subtitle> = self
+            .formats
+            .iter()
+            .flat_map(|f| f.subtitles.iter())
+            .filter(|(_, cc)| !(*cc && self.no_closed_caption))
+            .map(|(subtitle, _)| subtitle.clone())
+            .chain(
+                self.formats
+                    .iter()
+                    .filter_map(|f| f.forced_subtitle.clone()),
+            )
+            .collect();
+        let mut subtitle_prefetch = spawn_prefetch(
+            prefetch_subtitles
+                .into_iter()
+                .map(|subtitle| {
+                    (subtitle.url.clone(), async move {
+                        let data = subtitle.data().await?;
+                        debug!("Prefetched {} subtitles", subtitle.locale);
+                        anyhow::Ok(data)
+                    })
+                })
+                .collect(),
+        );
+
         let mut video_offset = None;
         let mut audio_offsets = HashMap::new();
         let mut subtitle_offsets = HashMap::new();
         let mut raw_audios = vec![];
         let mut videos = vec![];
         let mut audios = vec![];
-        let mut subtitles = vec![];
-        let mut fonts = vec![];
-        let mut chapters = None;
         let mut max_len = TimeDelta::min_value();
         let mut max_frames = 0;
         let fmt_space = self
@@ -268,7 +817,8 @@ impl Downloader {
                     .map(|(_, locale)| format!("Downloading {} audio", locale).len())
             })
             .max()
-            .unwrap();
+            // there might be no audio to download when only subtitles are added via `--mux-into`
+            .unwrap_or("Downloading subtitles".len());
 
         // downloads all audios
         for (i, format) in self.formats.iter().enumerate() {
@@ -277,6 +827,7 @@ impl Downloader {
                     .download_audio(
                         stream_data,
                         format!("{:<1$}", format!("Downloading {} audio", locale), fmt_space),
+                        self.max_segments,
                     )
                     .await?;
                 raw_audios.push(SyncAudio {
@@ -350,7 +901,7 @@ impl Downloader {
                 let mut subtitle_prepend = vec![];
                 let mut audio_append = vec![];
                 let mut subtitle_append = vec![];
-                for (i, format) in self.formats.into_iter().enumerate() {
+                for (i, format) in std::mem::take(&mut self.formats).into_iter().enumerate() {
                     if i < root_format_idx {
                         audio_prepend.extend(format.audios);
                         subtitle_prepend.extend(format.subtitles);
@@ -389,13 +940,26 @@ impl Downloader {
             })
         }
 
-        // downloads all videos
-        for (i, format) in self.formats.iter().enumerate() {
+        if let Some(mux_into) = &mux_into {
+            max_len = mux_into.length;
+            max_frames = (mux_into.length.num_milliseconds() as f64 / 1000.0 * mux_into.fps) as u64
+        }
+
+        let cut_ranges = self.cut_ranges();
+
+        // downloads all videos. if the streams are muxed into an existing file, its video is used
+        // instead
+        for (i, format) in self
+            .formats
+            .iter()
+            .enumerate()
+            .filter(|_| mux_into.is_none())
+        {
             let path = self
                 .download_video(
                     &format.video.0,
                     format!("{:<1$}", format!("Downloading video #{}", i + 1), fmt_space),
-                    None,
+                    self.max_segments,
                 )
                 .await?;
 
@@ -418,31 +982,196 @@ impl Downloader {
             })
         }
 
+        let prefetched_subtitles = match subtitle_prefetch.join_next().await {
+            Some(prefetched) => prefetched??,
+            None => HashMap::new(),
+        };
+        download_timer.stop();
+
+        let fetched = FetchedStreams {
+            videos,
+            audios,
+            mux_into,
+            subtitle_offsets,
+            prefetched_subtitles,
+            cut_ranges,
+            max_len,
+            max_frames,
+            fmt_space,
+        };
+        Ok(Some((fetched, checkpoint)))
+    }
+
+    /// Muxes the fetched streams into `dst` and all additional hardsub outputs.
+    async fn mux_outputs(
+        &self,
+        dst: &Path,
+        fetched: &FetchedStreams,
+        mut checkpoint: Option<MuxCheckpoint>,
+    ) -> Result<()> {
+        let main_outputs = if self.audio_outputs.is_empty() {
+            vec![(dst, self.force_hardsub, None)]
+        } else {
+            self.audio_outputs
+                .iter()
+                .map(|(o, locale)| (o.as_path(), self.force_hardsub, Some(locale)))
+                .collect()
+        };
+        // the additional hardsub and audio copies are created from the same streams, so nothing
+        // has to be downloaded again
+        let outputs = main_outputs.into_iter().chain(
+            self.hardsub_outputs
+                .iter()
+                .map(|o| (o.as_path(), true, None)),
+        );
+        for (output, force_hardsub, audio_locale) in outputs {
+            if checkpoint.as_ref().is_some_and(|c| c.is_muxed(output)) {
+                debug!(
+                    "{} was already muxed by a previous run",
+                    output.to_string_lossy()
+                );
+                continue;
+            }
+            if let Some(event_sink) = &self.event_sink {
+                event_sink.mux_started(output)
+            }
+            let mux_timer = Phase::Mux.start(output.to_string_lossy());
+            self.mux(output, force_hardsub, audio_locale, fetched)
+                .await?;
+            mux_timer.stop();
+            if let Some(checkpoint) = &mut checkpoint {
+                checkpoint.set_muxed(output)?
+            }
+        }
+        Ok(())
+    }
+
+    /// Muxes the fetched streams into `dst`. Can be called multiple times with the same streams
+    /// to create multiple output files. If `audio_locale` is set, only the audio of this locale is
+    /// muxed.
+    async fn mux(
+        &self,
+        dst: &Path,
+        force_hardsub: bool,
+        audio_locale: Option<&Locale>,
+        fetched: &FetchedStreams,
+    ) -> Result<()> {
+        let FetchedStreams {
+            videos,
+            audios,
+            mux_into,
+            subtitle_offsets,
+            prefetched_subtitles,
+            cut_ranges,
+            max_len,
+            max_frames,
+            fmt_space,
+        } = fetched;
+        let (max_len, mut max_frames, fmt_space) = (*max_len, *max_frames, *fmt_space);
+        let audios: Vec<&FFmpegAudioMeta> = audios
+            .iter()
+            .filter(|a| audio_locale.map_or(true, |locale| &a.locale == locale))
+            .collect();
+        let mut subtitles = vec![];
+        let mut fonts = vec![];
+        let mut chapters = None;
+
+        // this formats are supporting embedding subtitles into the video container instead of
+        // burning it into the video stream directly
+        let container_supports_softsubs = !force_hardsub
+            && SOFTSUB_CONTAINERS.contains(&dst.extension().unwrap_or_default().to_str().unwrap());
+
+        // burnt-in subtitles are always rendered from ass subtitles
+        let subtitle_format = if container_supports_softsubs {
+            self.subtitle_format.clone()
+        } else {
+            SubtitleFormat::Ass
+        };
+
         for (i, format) in self.formats.iter().enumerate() {
-            if format.subtitles.is_empty() {
+            // forced subtitles are only added as softsubs, burning them into the video would make
+            // them indistinguishable from the video itself
+            if format.subtitles.is_empty()
+                && (format.forced_subtitle.is_none() || !container_supports_softsubs)
+            {
                 continue;
             }
 
-            let progress_spinner = if log::max_level() == LevelFilter::Info {
-                let progress_spinner = ProgressBar::new_spinner()
-                    .with_style(
-                        ProgressStyle::with_template(
-                            format!(
-                                ":: {:<1$}  {{msg}} {{spinner}}",
-                                "Downloading subtitles", fmt_space
+            let progress_spinner = if draw_progress() {
+                let progress_spinner = add_progress_bar(
+                    ProgressBar::new_spinner()
+                        .with_style(
+                            ProgressStyle::with_template(
+                                format!(
+                                    ":: {:<1$}  {{msg}} {{spinner}}",
+                                    "Downloading subtitles", fmt_space
+                                )
+                                .as_str(),
                             )
-                            .as_str(),
+                            .unwrap()
+                            .tick_strings(&["—", "\\", "|", "/", ""]),
                         )
-                        .unwrap()
-                        .tick_strings(&["—", "\\", "|", "/", ""]),
-                    )
-                    .with_finish(ProgressFinish::Abandon);
+                        .with_finish(ProgressFinish::Abandon),
+                );
                 progress_spinner.enable_steady_tick(Duration::from_millis(100));
                 Some(progress_spinner)
             } else {
+                if log::max_level() == LevelFilter::Info {
+                    info!("Downloading subtitles")
+                }
                 None
             };
 
+            let video_length = match &mux_into {
+                Some(mux_into) => mux_into.length,
+                None => videos[i.min(videos.len() - 1)].length,
+            };
+
+            if self.dual_subtitles && format.subtitles.len() == 2 {
+                let (top, _) = &format.subtitles[0];
+                let (bottom, _) = &format.subtitles[1];
+                if let Some(pb) = &progress_spinner {
+                    pb.set_message(format!("{} + {}", top.locale, bottom.locale))
+                }
+                let mut merged = vec![];
+                for subtitle in [top, bottom] {
+                    merged.push(
+                        self.load_subtitle(
+                            subtitle.clone(),
+                            prefetched_subtitles.get(&subtitle.url).cloned(),
+                            video_length,
+                            cut_ranges,
+                            false,
+                        )
+                        .await?,
+                    )
+                }
+                let bottom_ass = merged.pop().unwrap();
+                let top_ass = merged.pop().unwrap();
+                let path = self.write_subtitle(
+                    &merge_dual_ass(top_ass, bottom_ass),
+                    &top.locale,
+                    &subtitle_format,
+                    self.subtitle_encoding
+                        .filter(|_| container_supports_softsubs),
+                )?;
+                debug!(
+                    "Downloaded {} and {} subtitles as dual subtitle",
+                    top.locale, bottom.locale
+                );
+                subtitles.push(FFmpegSubtitleMeta {
+                    path,
+                    locale: top.locale.clone(),
+                    secondary_locale: Some(bottom.locale.clone()),
+                    cc: false,
+                    lyrics: format.metadata.lyrics,
+                    forced: false,
+                    start_time: subtitle_offsets.get(&i).cloned(),
+                    video_idx: i,
+                });
+                continue;
+            }
+
             for (j, (subtitle, cc)) in format.subtitles.iter().enumerate() {
                 if *cc && self.no_closed_caption {
                     continue;
@@ -457,14 +1186,22 @@ impl Downloader {
                     if *cc {
                         progress_message += " (CC)";
                     }
-                    if i.min(videos.len() - 1) != 0 {
+                    if i.min(videos.len().saturating_sub(1)) != 0 {
                         progress_message += &format!(" [Video: #{}]", i + 1);
                     }
                     pb.set_message(progress_message)
                 }
 
                 let path = self
-                    .download_subtitle(subtitle.clone(), videos[i.min(videos.len() - 1)].length)
+                    .download_subtitle(
+                        subtitle.clone(),
+                        prefetched_subtitles.get(&subtitle.url).cloned(),
+                        video_length,
+                        &subtitle_format,
+                        self.subtitle_encoding
+                            .filter(|_| container_supports_softsubs),
+                        cut_ranges,
+                    )
                     .await?;
                 debug!(
                     "Downloaded {} subtitles{}",
@@ -474,14 +1211,61 @@ impl Downloader {
                 subtitles.push(FFmpegSubtitleMeta {
                     path,
                     locale: subtitle.locale.clone(),
+                    secondary_locale: None,
                     cc: *cc,
+                    lyrics: format.metadata.lyrics,
+                    forced: false,
                     start_time: subtitle_offsets.get(&j).cloned(),
                     video_idx: i,
                 })
             }
+
+            if let Some(forced_subtitle) = format
+                .forced_subtitle
+                .as_ref()
+                .filter(|_| container_supports_softsubs)
+            {
+                let ass = self
+                    .load_subtitle(
+                        forced_subtitle.clone(),
+                        prefetched_subtitles.get(&forced_subtitle.url).cloned(),
+                        video_length,
+                        cut_ranges,
+                        true,
+                    )
+                    .await?;
+                if ass
+                    .events
+                    .iter()
+                    .any(|e| matches!(e.line_type, SSAEventLineType::Dialogue))
+                {
+                    let path = self.write_subtitle(
+                        &ass,
+                        &forced_subtitle.locale,
+                        &subtitle_format,
+                        self.subtitle_encoding,
+                    )?;
+                    debug!("Downloaded {} forced subtitles", forced_subtitle.locale);
+                    subtitles.push(FFmpegSubtitleMeta {
+                        path,
+                        locale: forced_subtitle.locale.clone(),
+                        secondary_locale: None,
+                        cc: false,
+                        lyrics: false,
+                        forced: true,
+                        start_time: subtitle_offsets.get(&i).cloned(),
+                        video_idx: i,
+                    })
+                } else {
+                    debug!(
+                        "{} subtitles have no sign or song lines, no forced subtitles are added",
+                        forced_subtitle.locale
+                    )
+                }
+            }
         }
 
-        for format in self.formats.iter() {
+        for format in self.formats.iter().filter(|_| self.include_chapters) {
             if let Some(skip_events) = &format.metadata.skip_events {
                 let (file, path) = tempfile(".chapter")?.into_parts();
                 chapters = Some((
@@ -500,7 +1284,8 @@ impl Downloader {
         }
 
         if self.download_fonts
-            && !self.force_hardsub
+            && !force_hardsub
+            && subtitle_format == SubtitleFormat::Ass
             && dst.extension().unwrap_or_default().to_str().unwrap() == "mkv"
         {
             let mut font_names = vec![];
@@ -509,23 +1294,28 @@ impl Downloader {
             }
             real_dedup_vec(&mut font_names);
 
-            let progress_spinner = if log::max_level() == LevelFilter::Info {
-                let progress_spinner = ProgressBar::new_spinner()
-                    .with_style(
-                        ProgressStyle::with_template(
-                            format!(
-                                ":: {:<1$}  {{msg}} {{spinner}}",
-                                "Downloading fonts", fmt_space
+            let progress_spinner = if draw_progress() {
+                let progress_spinner = add_progress_bar(
+                    ProgressBar::new_spinner()
+                        .with_style(
+                            ProgressStyle::with_template(
+                                format!(
+                                    ":: {:<1$}  {{msg}} {{spinner}}",
+                                    "Downloading fonts", fmt_space
+                                )
+                                .as_str(),
                             )
-                            .as_str(),
+                            .unwrap()
+                            .tick_strings(&["—", "\\", "|", "/", ""]),
                         )
-                        .unwrap()
-                        .tick_strings(&["—", "\\", "|", "/", ""]),
-                    )
-                    .with_finish(ProgressFinish::Abandon);
+                        .with_finish(ProgressFinish::Abandon),
+                );
                 progress_spinner.enable_steady_tick(Duration::from_millis(100));
                 Some(progress_spinner)
             } else {
+                if log::max_level() == LevelFilter::Info {
+                    info!("Downloading fonts")
+                }
                 None
             };
             for font_name in font_names {
@@ -559,6 +1349,23 @@ impl Downloader {
         let mut attachments = vec![];
         let mut metadata = vec![];
 
+        // the number of video inputs and the number of audio, subtitle and attachment streams which
+        // are already existing in the output. only differs from the default if muxing into an
+        // existing file
+        let mut video_inputs = videos.len();
+        let (mut audio_offset, mut subtitle_offset, mut attachment_offset) = (0, 0, 0);
+        if let Some(mux_into) = &mux_into {
+            input.extend([
+                "-i".to_string(),
+                mux_into.path.to_string_lossy().to_string(),
+            ]);
+            maps.extend(["-map".to_string(), "0".to_string()]);
+            video_inputs = 1;
+            audio_offset = mux_into.audios;
+            subtitle_offset = mux_into.subtitles;
+            attachment_offset = mux_into.attachments;
+        }
+
         for (i, meta) in videos.iter().enumerate() {
             if let Some(start_time) = meta.start_time {
                 input.extend(["-itsoffset".to_string(), format_time_delta(&start_time)])
@@ -585,9 +1392,9 @@ impl Downloader {
                 input.extend(["-itsoffset".to_string(), format_time_delta(&start_time)])
             }
             input.extend(["-i".to_string(), meta.path.to_string_lossy().to_string()]);
-            maps.extend(["-map".to_string(), (i + videos.len()).to_string()]);
+            maps.extend(["-map".to_string(), (i + video_inputs).to_string()]);
             metadata.extend([
-                format!("-metadata:s:a:{}", i),
+                format!("-metadata:s:a:{}", i + audio_offset),
                 format!(
                     "language={}",
                     self.audio_locale_output_map
@@ -596,7 +1403,7 @@ impl Downloader {
                 ),
             ]);
             metadata.extend([
-                format!("-metadata:s:a:{}", i),
+                format!("-metadata:s:a:{}", i + audio_offset),
                 format!(
                     "title={}",
                     if videos.len() == 1 {
@@ -615,29 +1422,27 @@ impl Downloader {
         for (i, font) in fonts.iter().enumerate() {
             attachments.extend(["-attach".to_string(), font.to_string_lossy().to_string()]);
             metadata.extend([
-                format!("-metadata:s:t:{}", i),
+                format!("-metadata:s:t:{}", i + attachment_offset),
                 "mimetype=font/woff2".to_string(),
             ])
         }
 
-        // this formats are supporting embedding subtitles into the video container instead of
-        // burning it into the video stream directly
-        let container_supports_softsubs = !self.force_hardsub
-            && ["mkv", "mov", "mp4"]
-                .contains(&dst.extension().unwrap_or_default().to_str().unwrap());
-
         if container_supports_softsubs {
             for (i, meta) in subtitles.iter().enumerate() {
                 if let Some(start_time) = meta.start_time {
                     input.extend(["-itsoffset".to_string(), format_time_delta(&start_time)])
                 }
+                // the subtitle file isn't utf-8 encoded, which ffmpeg would assume otherwise
+                if let Some(encoding) = self.subtitle_encoding {
+                    input.extend(["-sub_charenc".to_string(), encoding.name().to_string()])
+                }
                 input.extend(["-i".to_string(), meta.path.to_string_lossy().to_string()]);
                 maps.extend([
                     "-map".to_string(),
-                    (i + videos.len() + audios.len()).to_string(),
+                    (i + video_inputs + audios.len()).to_string(),
                 ]);
                 metadata.extend([
-                    format!("-metadata:s:s:{}", i),
+                    format!("-metadata:s:s:{}", i + subtitle_offset),
                     format!(
                         "language={}",
                         self.subtitle_locale_output_map
@@ -646,12 +1451,21 @@ impl Downloader {
                     ),
                 ]);
                 metadata.extend([
-                    format!("-metadata:s:s:{}", i),
+                    format!("-metadata:s:s:{}", i + subtitle_offset),
                     format!("title={}", {
                         let mut title = meta.locale.to_human_readable();
+                        if let Some(secondary_locale) = &meta.secondary_locale {
+                            title += &format!(" / {}", secondary_locale.to_human_readable())
+                        }
                         if meta.cc {
                             title += " (CC)"
                         }
+                        if meta.lyrics {
+                            title += " (Lyrics)"
+                        }
+                        if meta.forced {
+                            title += " (Forced)"
+                        }
                         if videos.len() > 1 {
                             title += &format!(" [Video: #{}]", meta.video_idx + 1)
                         }
@@ -661,30 +1475,111 @@ impl Downloader {
             }
         }
 
+        // the user provided files are added after all streams from crunchyroll
+        let mut next_input = video_inputs
+            + audios.len()
+            + container_supports_softsubs
+                .then_some(subtitles.len())
+                .unwrap_or_default();
+        for (i, external) in self.external_audios.iter().enumerate() {
+            input.extend([
+                "-i".to_string(),
+                external.path.to_string_lossy().to_string(),
+            ]);
+            // only the audio is used, the file might be a video with the better dub
+            maps.extend(["-map".to_string(), format!("{}:a", next_input)]);
+            next_input += 1;
+            let stream = i + audios.len() + audio_offset;
+            metadata.extend([
+                format!("-metadata:s:a:{}", stream),
+                format!(
+                    "language={}",
+                    self.audio_locale_output_map
+                        .get(&external.locale)
+                        .unwrap_or(&external.locale.to_string())
+                ),
+            ]);
+            metadata.extend([
+                format!("-metadata:s:a:{}", stream),
+                format!("title={}", external.locale.to_human_readable()),
+            ]);
+        }
+        if container_supports_softsubs {
+            for (i, external) in self.external_subtitles.iter().enumerate() {
+                input.extend([
+                    "-i".to_string(),
+                    external.path.to_string_lossy().to_string(),
+                ]);
+                maps.extend(["-map".to_string(), format!("{}:s", next_input)]);
+                next_input += 1;
+                let stream = i + subtitles.len() + subtitle_offset;
+                metadata.extend([
+                    format!("-metadata:s:s:{}", stream),
+                    format!(
+                        "language={}",
+                        self.subtitle_locale_output_map
+                            .get(&external.locale)
+                            .unwrap_or(&external.locale.to_string())
+                    ),
+                ]);
+                metadata.extend([
+                    format!("-metadata:s:s:{}", stream),
+                    format!("title={}", external.locale.to_human_readable()),
+                ]);
+            }
+        } else if !self.external_subtitles.is_empty() {
+            debug!(
+                "Not adding the external subtitles to {} as it has burnt-in subtitles",
+                dst.to_string_lossy()
+            )
+        }
+
+        for (key, value) in &self.container_tags {
+            metadata.extend(["-metadata".to_string(), format!("{}={}", key, value)])
+        }
+
         if let Some(((file, path), chapters)) = chapters.as_mut() {
-            write_ffmpeg_chapters(file, max_len, chapters)?;
+            write_ffmpeg_chapters(file, max_len, chapters, cut_ranges)?;
             input.extend(["-i".to_string(), path.to_string_lossy().to_string()]);
-            maps.extend([
-                "-map_metadata".to_string(),
-                (videos.len()
-                    + audios.len()
-                    + container_supports_softsubs
-                        .then_some(subtitles.len())
-                        .unwrap_or_default())
-                .to_string(),
-            ])
+            maps.extend(["-map_metadata".to_string(), next_input.to_string()])
         }
 
+        let default_subtitle_position =
+            self.default_subtitle.as_ref().and_then(|default_subtitle| {
+                subtitles.iter().position(|m| &m.locale == default_subtitle)
+            });
         let preset_custom = matches!(self.ffmpeg_preset, FFmpegPreset::Custom(_));
-        let (input_presets, mut output_presets) = self.ffmpeg_preset.into_input_output_args();
-        let fifo = temp_named_pipe()?;
-
-        let mut command_args = vec![
-            "-y".to_string(),
-            "-hide_banner".to_string(),
-            "-vstats_file".to_string(),
-            fifo.path().to_string_lossy().to_string(),
-        ];
+        let StreamArgs {
+            input: input_presets,
+            output: mut output_presets,
+            reencoded,
+            reasons: reencode_reasons,
+        } = StreamOptions {
+            ffmpeg_preset: self.ffmpeg_preset.clone(),
+            ffmpeg_extra_args: self.ffmpeg_extra_args.clone(),
+            mux_into: mux_into.is_some(),
+            burn_subtitle: default_subtitle_position
+                .filter(|_| !container_supports_softsubs)
+                .map(|position| {
+                    let path = &subtitles.get(position).unwrap().path;
+                    // ffmpeg doesn't removes all ':' and '\' from the filename when using the ass
+                    // filter. well, on windows these characters are used in absolute paths, so
+                    // they have to be correctly escaped here
+                    if cfg!(windows) {
+                        path.to_str()
+                            .unwrap()
+                            .replace('\\', "\\\\")
+                            .replace(':', "\\:")
+                    } else {
+                        path.to_string_lossy().to_string()
+                    }
+                }),
+            audio_codec: self.audio_codec.clone(),
+            loudnorm: self.loudnorm,
+            cut: (!cut_ranges.is_empty()).then(|| cut_ranges.between_expr()),
+        }
+        .build()?;
+        let mut command_args = vec![];
         command_args.extend(input_presets);
         command_args.extend(input);
         command_args.extend(maps);
@@ -697,72 +1592,57 @@ impl Downloader {
         }
 
         // set default subtitle
-        if let Some(default_subtitle) = self.default_subtitle {
-            if let Some(position) = subtitles.iter().position(|m| m.locale == default_subtitle) {
-                if container_supports_softsubs {
-                    match dst.extension().unwrap_or_default().to_str().unwrap() {
-                        "mov" | "mp4" => output_presets.extend([
-                            "-movflags".to_string(),
-                            "faststart".to_string(),
-                            "-c:s".to_string(),
-                            "mov_text".to_string(),
-                        ]),
-                        _ => (),
-                    }
-                } else {
-                    // remove '-c:v copy' and '-c:a copy' from output presets as its causes issues with
-                    // burning subs into the video
-                    let mut last = String::new();
-                    let mut remove_count = 0;
-                    for (i, s) in output_presets.clone().iter().enumerate() {
-                        if (last == "-c:v" || last == "-c:a") && s == "copy" {
-                            // remove last
-                            output_presets.remove(i - remove_count - 1);
-                            remove_count += 1;
-                            output_presets.remove(i - remove_count);
-                            remove_count += 1;
-                        }
-                        last.clone_from(s);
-                    }
+        if let Some(position) = default_subtitle_position.filter(|_| container_supports_softsubs) {
+            match dst.extension().unwrap_or_default().to_str().unwrap() {
+                "mov" | "mp4" => output_presets.extend([
+                    "-movflags".to_string(),
+                    "faststart".to_string(),
+                    "-c:s".to_string(),
+                    "mov_text".to_string(),
+                ]),
+                _ => (),
+            }
+            command_args.extend([
+                format!("-disposition:s:s:{}", position + subtitle_offset),
+                "default".to_string(),
+            ])
+        }
 
-                    output_presets.extend([
-                        "-vf".to_string(),
-                        format!(
-                            "ass='{}'",
-                            // ffmpeg doesn't removes all ':' and '\' from the filename when using
-                            // the ass filter. well, on windows these characters are used in
-                            // absolute paths, so they have to be correctly escaped here
-                            if cfg!(windows) {
-                                subtitles
-                                    .get(position)
-                                    .unwrap()
-                                    .path
-                                    .to_str()
-                                    .unwrap()
-                                    .replace('\\', "\\\\")
-                                    .replace(':', "\\:")
-                            } else {
-                                subtitles
-                                    .get(position)
-                                    .unwrap()
-                                    .path
-                                    .to_string_lossy()
-                                    .to_string()
-                            }
-                        ),
-                    ])
-                }
+        if let Some(movflags) = self.mp4_layout.movflags() {
+            if ["mov", "mp4"].contains(&dst.extension().unwrap_or_default().to_str().unwrap()) {
+                // ffmpeg uses the last given movflags, so this replaces the faststart flag which
+                // might be set for the default subtitle above
+                output_presets.extend(["-movflags".to_string(), movflags.to_string()])
             }
+        }
 
-            if container_supports_softsubs {
-                if let Some(position) = subtitles
-                    .iter()
-                    .position(|meta| meta.locale == default_subtitle)
-                {
-                    command_args.extend([
-                        format!("-disposition:s:s:{}", position),
-                        "default".to_string(),
-                    ])
+        // the cut parts aren't processed, so they don't count to the progress
+        if !cut_ranges.is_empty() {
+            let max_len_secs = max_len.num_milliseconds() as f64 / 1000.0;
+            if max_len_secs > 0.0 {
+                max_frames =
+                    (max_frames as f64 * (1.0 - cut_ranges.duration() as f64 / max_len_secs)) as u64
+            }
+        }
+
+        if let Some(audio_bitrate) = self.audio_bitrate {
+            if is_stream_copy(&output_presets, "a") {
+                debug!("Ignoring the audio bitrate as the audio is copied")
+            } else {
+                output_presets.extend(["-b:a".to_string(), format!("{}k", audio_bitrate)])
+            }
+        }
+
+        // without an explicit codec, ffmpeg would convert the subtitles back to ass as it's the
+        // default subtitle codec of matroska
+        if container_supports_softsubs {
+            match subtitle_format {
+                SubtitleFormat::Ass => (),
+                SubtitleFormat::Srt => {
+                    output_presets.extend(["-c:s".to_string(), "srt".to_string()])
+                }
+                SubtitleFormat::Vtt => {
+                    output_presets.extend(["-c:s".to_string(), "webvtt".to_string()])
                 }
             }
         }
@@ -773,7 +1653,48 @@ impl Downloader {
                 continue;
             }
 
-            command_args.extend([format!("-disposition:s:s:{}", i), "forced".to_string()])
+            command_args.extend([
+                format!("-disposition:s:s:{}", i + subtitle_offset),
+                "forced".to_string(),
+            ])
+        }
+        // forced subtitles are only added if no subtitle was requested, so they are also the
+        // default subtitle
+        for (i, subtitle) in subtitles.iter().enumerate() {
+            if !subtitle.forced {
+                continue;
+            }
+
+            command_args.extend([
+                format!("-disposition:s:s:{}", i + subtitle_offset),
+                "forced+default".to_string(),
+            ])
+        }
+
+        for (name, stream_type) in [("video", "v"), ("audio", "a")] {
+            debug!(
+                "The {} stream is {}",
+                name,
+                if is_stream_copy(&output_presets, stream_type) {
+                    "copied"
+                } else {
+                    "re-encoded"
+                }
+            );
+            if reencoded.contains(&name) {
+                if self.strict_copy {
+                    bail!(
+                        "The {} stream would be re-encoded ({}), aborting as `--strict-copy` is set",
+                        name,
+                        reencode_reasons.join(", ")
+                    )
+                }
+                info!(
+                    "The {} stream gets re-encoded ({}), this may take a while",
+                    name,
+                    reencode_reasons.join(", ")
+                )
+            }
         }
 
         command_args.extend(output_presets);
@@ -781,56 +1702,156 @@ impl Downloader {
             command_args.extend(["-f".to_string(), output_format]);
         }
 
-        // prepend './' to the path on linux since ffmpeg may interpret the path incorrectly if it's just the filename.
-        // see https://github.com/crunchy-labs/crunchy-cli/issues/303 for example
-        if !cfg!(windows)
-            && dst
-                .parent()
-                .map_or(true, |p| p.to_string_lossy().is_empty())
-        {
-            command_args.push(Path::new("./").join(dst).to_string_lossy().to_string());
-        } else {
-            command_args.push(dst.to_string_lossy().to_string())
+        if self.raw {
+            return write_raw(
+                dst,
+                command_args,
+                videos,
+                &audios,
+                &subtitles,
+                chapters.as_ref().map(|((_, path), _)| path),
+                &fonts,
+                max_frames,
+            );
         }
 
-        debug!("ffmpeg {}", command_args.join(" "));
+        run_ffmpeg(
+            command_args,
+            dst,
+            max_frames,
+            format!("{:<1$}", "Generating output file", fmt_space + 1),
+            self.ffmpeg_semaphore.as_deref(),
+            &self.ffmpeg_log,
+        )
+        .await
+    }
 
-        // create parent directory if it does not exist
-        if let Some(parent) = dst.parent() {
-            if !parent.exists() {
-                fs::create_dir_all(parent)?
+    /// The parts which should be cut out of the output, based on the skip events of the first
+    /// format which has some.
+    fn cut_ranges(&self) -> CutRanges {
+        if !self.cut_intro && !self.cut_outro {
+            return CutRanges::default();
+        }
+        let Some(skip_events) = self
+            .formats
+            .iter()
+            .find_map(|f| f.metadata.skip_events.as_ref())
+        else {
+            debug!("No skip events available, nothing gets cut");
+            return CutRanges::default();
+        };
+
+        let mut ranges = vec![];
+        for (cut, name, event) in [
+            (self.cut_intro, "intro", &skip_events.intro),
+            (self.cut_outro, "outro", &skip_events.credits),
+        ] {
+            if !cut {
+                continue;
+            }
+            match event {
+                Some(event) if event.end > event.start => {
+                    debug!("Cutting {} ({}s - {}s)", name, event.start, event.end);
+                    ranges.push((event.start, event.end))
+                }
+                _ => debug!("No {} timing available, nothing gets cut", name),
             }
         }
+        ranges.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+        CutRanges(ranges)
+    }
 
-        let ffmpeg = Command::new("ffmpeg")
-            // pass ffmpeg stdout to real stdout only if output file is stdout
-            .stdout(if dst.to_str().unwrap() == "-" {
-                Stdio::inherit()
-            } else {
-                Stdio::null()
-            })
-            .stderr(Stdio::piped())
-            .args(command_args)
-            .spawn()?;
-        let ffmpeg_progress_cancel = CancellationToken::new();
-        let ffmpeg_progress_cancellation_token = ffmpeg_progress_cancel.clone();
-        let ffmpeg_progress = tokio::spawn(async move {
-            ffmpeg_progress(
-                max_frames,
-                fifo,
-                format!("{:<1$}", "Generating output file", fmt_space + 1),
-                ffmpeg_progress_cancellation_token,
+    /// Probes the file given via `mux_into` and removes all audios and subtitles which are already
+    /// existing in it from the formats. Returns [`None`] if there is nothing left to add.
+    fn prepare_mux_into(&mut self, path: PathBuf) -> Result<Option<FFmpegMuxIntoMeta>> {
+        let streams = probe_streams(&path)?;
+        if !streams.iter().any(|s| s.codec_type == "video") {
+            bail!(
+                "'{}' does not contain a video stream",
+                path.to_string_lossy()
             )
-            .await
-        });
+        }
+
+        for format in self.formats.iter_mut() {
+            format.audios.retain(|(_, locale)| {
+                let language = self
+                    .audio_locale_output_map
+                    .get(locale)
+                    .cloned()
+                    .unwrap_or(locale.to_string());
+                let existing = streams
+                    .iter()
+                    .any(|s| s.codec_type == "audio" && s.language == language);
+                if existing {
+                    debug!("Skipping {} audio as it already exists in the file", locale)
+                }
+                !existing
+            });
+            format.subtitles.retain(|(subtitle, cc)| {
+                let language = self
+                    .subtitle_locale_output_map
+                    .get(&subtitle.locale)
+                    .cloned()
+                    .unwrap_or(subtitle.locale.to_string());
+                // cc subtitles are always marked as forced
+                let existing = streams.iter().any(|s| {
+                    s.codec_type == "subtitle" && s.language == language && s.forced == *cc
+                });
+                if existing {
+                    debug!(
+                        "Skipping {} subtitles{} as they already exist in the file",
+                        subtitle.locale,
+                        cc.then_some(" (cc)").unwrap_or_default()
+                    )
+                }
+                !existing
+            });
+            if let Some(forced_subtitle) = &format.forced_subtitle {
+                let language = self
+                    .subtitle_locale_output_map
+                    .get(&forced_subtitle.locale)
+                    .cloned()
+                    .unwrap_or(forced_subtitle.locale.to_string());
+                if streams
+                    .iter()
+                    .any(|s| s.codec_type == "subtitle" && s.language == language && s.forced)
+                {
+                    debug!(
+                        "Skipping {} forced subtitles as they already exist in the file",
+                        forced_subtitle.locale
+                    );
+                    format.forced_subtitle = None
+                }
+            }
+        }
 
-        let result = ffmpeg.wait_with_output()?;
-        if !result.status.success() {
-            ffmpeg_progress.abort();
-            bail!("{}", String::from_utf8_lossy(result.stderr.as_slice()))
+        if self
+            .formats
+            .iter()
+            .all(|f| f.audios.is_empty() && f.subtitles.is_empty() && f.forced_subtitle.is_none())
+        {
+            info!(
+                "'{}' already contains all requested audios and subtitles",
+                path.to_string_lossy()
+            );
+            return Ok(None);
         }
-        ffmpeg_progress_cancel.cancel();
-        ffmpeg_progress.await?
+
+        let (length, fps) = get_video_stats(&path)?;
+        Ok(Some(FFmpegMuxIntoMeta {
+            length,
+            fps,
+            audios: streams.iter().filter(|s| s.codec_type == "audio").count(),
+            subtitles: streams
+                .iter()
+                .filter(|s| s.codec_type == "subtitle")
+                .count(),
+            attachments: streams
+                .iter()
+                .filter(|s| s.codec_type == "attachment")
+                .count(),
+            path,
+        }))
     }
 
     async fn check_free_space(
@@ -893,6 +1914,33 @@ impl Downloader {
         Ok((tmp_required, dst_required))
     }
 
+    /// Writes the segments of the single video or audio stream in order to stdout, without
+    /// muxing them. Subtitles, chapters and all other streams are ignored.
+    async fn pipe_stream(&self) -> Result<()> {
+        if self.formats.len() != 1 {
+            bail!("Only a single stream can be piped to stdout")
+        }
+        let format = &self.formats[0];
+        let (stream_data, message) = match self.pipe {
+            PipeMode::Video => (&format.video.0, "Downloading video".to_string()),
+            PipeMode::Audio => {
+                if format.audios.len() != 1 {
+                    bail!("Only a single audio stream can be piped to stdout")
+                }
+                let (stream_data, locale) = &format.audios[0];
+                (stream_data, format!("Downloading {} audio", locale))
+            }
+            PipeMode::Muxed => unreachable!(),
+        };
+
+        let _download_timer = Phase::Download.start("stdout");
+        let mut stdout = std::io::stdout();
+        self.download_segments(&mut stdout, message, stream_data, None)
+            .await?;
+        stdout.flush()?;
+        Ok(())
+    }
+
     async fn download_video(
         &self,
         stream_data: &StreamData,
@@ -908,11 +1956,16 @@ impl Downloader {
         Ok(path)
     }
 
-    async fn download_audio(&self, stream_data: &StreamData, message: String) -> Result<TempPath> {
+    async fn download_audio(
+        &self,
+        stream_data: &StreamData,
+        message: String,
+        max_segments: Option<usize>,
+    ) -> Result<TempPath> {
         let tempfile = tempfile(".m4a")?;
         let (mut file, path) = tempfile.into_parts();
 
-        self.download_segments(&mut file, message, stream_data, None)
+        self.download_segments(&mut file, message, stream_data, max_segments)
             .await?;
 
         Ok(path)
@@ -921,14 +1974,56 @@ impl Downloader {
     async fn download_subtitle(
         &self,
         subtitle: Subtitle,
+        prefetched: Option<Vec<u8>>,
         max_length: TimeDelta,
+        format: &SubtitleFormat,
+        encoding: Option<&'static Encoding>,
+        cut_ranges: &CutRanges,
     ) -> Result<TempPath> {
-        let buf = subtitle.data().await?;
+        let locale = subtitle.locale.clone();
+        let ass = self
+            .load_subtitle(subtitle, prefetched, max_length, cut_ranges, false)
+            .await?;
+        self.write_subtitle(&ass, &locale, format, encoding)
+    }
+
+    /// Downloads `subtitle` and converts it to ass. All timing adjustments and style changes are
+    /// already applied. If `forced` is set, only the sign and song lines are kept.
+    async fn load_subtitle(
+        &self,
+        subtitle: Subtitle,
+        prefetched: Option<Vec<u8>>,
+        max_length: TimeDelta,
+        cut_ranges: &CutRanges,
+        forced: bool,
+    ) -> Result<SSA> {
+        let buf = match prefetched {
+            Some(buf) => buf,
+            None => subtitle.data().await?,
+        };
         let mut ass = match subtitle.format.as_str() {
             "ass" => SSA::parse(String::from_utf8_lossy(&buf))?,
             "vtt" => VTT::parse(String::from_utf8_lossy(&buf))?.to_ssa(),
             _ => bail!("unknown subtitle format: {}", subtitle.format),
         };
+        shift_ass(
+            &mut ass,
+            SubtitleOffset::for_locale(&self.subtitle_offsets, &subtitle.locale),
+        );
+        if forced {
+            let kept = keep_ass_styles(&mut ass, &self.forced_subtitle_styles);
+            debug!(
+                "Kept {} sign / song line(s) of {} subtitles as forced subtitles",
+                kept, subtitle.locale
+            )
+        } else if !self.subtitle_drop_styles.is_empty() {
+            let dropped = drop_ass_styles(&mut ass, &self.subtitle_drop_styles);
+            debug!(
+                "Removed {} sign / song line(s) from {} subtitles",
+                dropped, subtitle.locale
+            )
+        }
+
         // subtitles aren't always correct sorted and video players may have issues with that. to
         // prevent issues, the subtitles are sorted
         // (https://github.com/crunchy-labs/crunchy-cli/issues/208)
@@ -952,16 +2047,60 @@ impl Downloader {
             }
         }
 
+        // shifts the subtitles to the timestamps of the cut video. subtitles which are only shown
+        // in the cut parts are removed
+        if !cut_ranges.is_empty() {
+            let to_time = |secs: f32| {
+                Time::from_hms(0, 0, 0)
+                    .unwrap()
+                    .add(Duration::from_millis((secs * 1000.0) as u64))
+            };
+            for event in ass.events.iter_mut() {
+                event.start = to_time(cut_ranges.map(time_to_secs(event.start)));
+                event.end = to_time(cut_ranges.map(time_to_secs(event.end)));
+            }
+            ass.events.retain(|e| e.start < e.end)
+        }
+
         // without this additional info, subtitle look very messy in some video player
         // (https://github.com/crunchy-labs/crunchy-cli/issues/66)
         ass.info
             .additional_fields
             .insert("ScaledBorderAndShadow".to_string(), "yes".to_string());
 
-        let tempfile = tempfile(".ass")?;
+        Ok(ass)
+    }
+
+    fn write_subtitle(
+        &self,
+        ass: &SSA,
+        locale: &Locale,
+        format: &SubtitleFormat,
+        encoding: Option<&'static Encoding>,
+    ) -> Result<TempPath> {
+        let tempfile = tempfile(format!(".{}", format.extension()))?;
         let path = tempfile.into_temp_path();
 
-        fs::write(&path, ass.to_string())?;
+        let content = match format {
+            SubtitleFormat::Ass => ass.to_string(),
+            SubtitleFormat::Srt => ass_to_srt(ass),
+            SubtitleFormat::Vtt => ass_to_vtt(ass),
+        };
+        if let Some(encoding) = encoding {
+            let (encoded, unmappable) = encode_subtitle(&content, encoding);
+            if !unmappable.is_empty() {
+                warn!(
+                    "{} subtitles contain {} character(s) which can't be represented in {} and were replaced with '?': {}",
+                    locale,
+                    unmappable.len(),
+                    encoding.name(),
+                    unmappable.iter().take(10).collect::<String>()
+                )
+            }
+            fs::write(&path, encoded)?;
+        } else {
+            fs::write(&path, content)?;
+        }
 
         Ok(path)
     }
@@ -1010,23 +2149,11 @@ impl Downloader {
 
         let count = Arc::new(Mutex::new(0));
 
-        let progress = if log::max_level() == LevelFilter::Info {
-            let estimated_file_size = estimate_stream_data_file_size(stream_data, &segments);
-
-            let progress = ProgressBar::new(estimated_file_size)
-                .with_style(
-                    ProgressStyle::with_template(
-                        ":: {msg} {bytes:>10} {bytes_per_sec:>12} [{wide_bar}] {percent:>3}%",
-                    )
-                    .unwrap()
-                    .progress_chars("##-"),
-                )
-                .with_message(message)
-                .with_finish(ProgressFinish::Abandon);
-            Some(progress)
-        } else {
-            None
-        };
+        let mut estimated_file_size = estimate_stream_data_file_size(stream_data, &segments);
+        let mut downloaded = 0;
+        if let Some(event_sink) = &self.event_sink {
+            event_sink.segments_started(&message, estimated_file_size)
+        }
 
         let cpus = self.download_threads.min(segments.len());
         let mut segs: Vec<Vec<StreamSegment>> = Vec::with_capacity(cpus);
@@ -1038,6 +2165,7 @@ impl Downloader {
         }
 
         let (sender, mut receiver) = unbounded_channel();
+        let retry_throttle = RetryThrottle::default();
 
         let mut join_set: JoinSet<Result<()>> = JoinSet::new();
         for num in 0..cpus {
@@ -1046,7 +2174,10 @@ impl Downloader {
             let thread_client = self.client.clone();
             let mut thread_rate_limiter = self.rate_limiter.clone();
             let thread_count = count.clone();
-            join_set.spawn(async move {
+            let thread_retry_throttle = retry_throttle.clone();
+            // the download threads are separate tasks, they don't inherit the log file
+            let thread_log_file = LogFile::current();
+            join_set.spawn(thread_log_file.scope(async move {
                 let after_download_sender = thread_sender.clone();
 
                 // the download process is encapsulated in its own function. this is done to easily
@@ -1054,6 +2185,7 @@ impl Downloader {
                 // itself can report that an error has occurred
                 let download = || async move {
                     for (i, segment) in thread_segments.into_iter().enumerate() {
+                        wait_while_paused().await;
                         let mut retry_count = 0;
                         let buf = loop {
                             let request = thread_client
@@ -1062,13 +2194,20 @@ impl Downloader {
                             let response = if let Some(rate_limiter) = &mut thread_rate_limiter {
                                 rate_limiter.call(request.build()?).await.map_err(anyhow::Error::new)
                             } else {
-                                request.send().await.map_err(anyhow::Error::new)
+                                request.send().await.map_err(|e| describe_request_error(e, "waiting for the response"))
                             };
 
+                            let mut server_retry_after = None;
                             let err = match response {
+                                Ok(r) if !r.status().is_success() => {
+                                    if r.status() == StatusCode::TOO_MANY_REQUESTS {
+                                        server_retry_after = retry_after(&r)
+                                    }
+                                    anyhow!("Server responded with status {}", r.status())
+                                }
                                 Ok(r) => match r.bytes().await {
                                     Ok(b) => break b.to_vec(),
-                                    Err(e) => anyhow::Error::new(e)
+                                    Err(e) => describe_request_error(e, "reading the response body")
                                 }
                                 Err(e) => e,
                             };
@@ -1076,7 +2215,8 @@ impl Downloader {
                             if retry_count == 5 {
                                 bail!("Max retry count reached ({}), multiple errors occurred while receiving segment {}: {}", retry_count, num + (i * cpus), err)
                             }
-                            debug!("Failed to download segment {} ({}). Retrying, {} out of 5 retries left", num + (i * cpus), err, 5 - retry_count);
+                            let waited = thread_retry_throttle.wait(retry_count, server_retry_after).await;
+                            debug!("Failed to download segment {} ({}). Retrying after {}ms, {} out of 5 retries left", num + (i * cpus), err, waited.as_millis(), 5 - retry_count);
 
                             retry_count += 1;
                         };
@@ -1104,7 +2244,7 @@ impl Downloader {
                 }
 
                 result
-            });
+            }));
         }
         // drop the sender already here so it does not outlive all download threads which are the only
         // real consumers of it
@@ -1121,14 +2261,13 @@ impl Downloader {
                 break;
             }
 
-            if let Some(p) = &progress {
-                let progress_len = p.length().unwrap();
-                let estimated_segment_len = (stream_data.bandwidth / 8)
-                    * segments.get(pos as usize).unwrap().length.as_secs();
-                let bytes_len = bytes.len() as u64;
-
-                p.set_length(progress_len - estimated_segment_len + bytes_len);
-                p.inc(bytes_len)
+            let estimated_segment_len =
+                (stream_data.bandwidth / 8) * segments.get(pos as usize).unwrap().length.as_secs();
+            let bytes_len = bytes.len() as u64;
+            estimated_file_size = estimated_file_size - estimated_segment_len + bytes_len;
+            downloaded += bytes_len;
+            if let Some(event_sink) = &self.event_sink {
+                event_sink.segment_progress(downloaded, estimated_file_size)
             }
 
             // check if the currently sent bytes are the next in the buffer. if so, write them directly
@@ -1168,10 +2307,45 @@ impl Downloader {
             )
         }
 
+        if let Some(event_sink) = &self.event_sink {
+            event_sink.segments_finished()
+        }
+
         Ok(())
     }
 }
 
+/// reqwest only reports that an operation timed out, this adds in which phase of the request it
+/// happened. `phase` describes what was done when the error occurred, connect timeouts are
+/// detected automatically.
+pub(crate) fn describe_request_error(error: reqwest::Error, phase: &str) -> anyhow::Error {
+    if !error.is_timeout() {
+        anyhow::Error::new(error)
+    } else if error.is_connect() {
+        anyhow!("Connect timeout: {}", error)
+    } else {
+        anyhow!("Read timeout while {}: {}", phase, error)
+    }
+}
+
+/// Runs `fetches` one after another in a background task and collects their results by key. The
+/// task gets aborted if the returned join set is dropped.
+fn spawn_prefetch<K, F>(fetches: Vec<(K, F)>) -> JoinSet<Result<HashMap<K, Vec<u8>>>>
+where
+    K: Eq + Hash + Send + 'static,
+    F: Future<Output = Result<Vec<u8>>> + Send + 'static,
+{
+    let mut join_set = JoinSet::new();
+    join_set.spawn(async move {
+        let mut prefetched = HashMap::new();
+        for (key, fetch) in fetches {
+            prefetched.insert(key, fetch.await?);
+        }
+        Ok(prefetched)
+    });
+    join_set
+}
+
 fn estimate_stream_data_file_size(stream_data: &StreamData, segments: &[StreamSegment]) -> u64 {
     (stream_data.bandwidth / 8) * segments.iter().map(|s| s.length.as_secs()).sum::<u64>()
 }
@@ -1210,6 +2384,73 @@ fn get_video_stats(path: &Path) -> Result<(TimeDelta, f64)> {
     ))
 }
 
+/// A stream of the existing file which the download is muxed into (`--mux-into`).
+struct ProbedStream {
+    codec_type: String,
+    language: String,
+    forced: bool,
+}
+
+/// Get all streams of a matroska file via ffprobe.
+fn probe_streams(path: &Path) -> Result<Vec<ProbedStream>> {
+    let ffprobe = match Command::new("ffprobe")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .args([
+            "-v",
+            "error",
+            "-show_entries",
+            "format=format_name:stream=codec_type:stream_tags=language:stream_disposition=forced",
+            "-of",
+            "json",
+        ])
+        .arg(path)
+        .output()
+    {
+        Ok(ffprobe) => ffprobe,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            bail!("ffprobe is needed to mux into an existing file")
+        }
+        Err(e) => return Err(e.into()),
+    };
+    if !ffprobe.status.success() {
+        bail!(
+            "'{}' is not a valid video file: {}",
+            path.to_string_lossy(),
+            String::from_utf8_lossy(ffprobe.stderr.as_slice()).trim()
+        )
+    }
+
+    let output: serde_json::Value = serde_json::from_slice(&ffprobe.stdout)?;
+    if !output["format"]["format_name"]
+        .as_str()
+        .unwrap_or_default()
+        .contains("matroska")
+    {
+        bail!("'{}' is not a matroska file", path.to_string_lossy())
+    }
+
+    Ok(output["streams"]
+        .as_array()
+        .map(|streams| {
+            streams
+                .iter()
+                .map(|stream| ProbedStream {
+                    codec_type: stream["codec_type"]
+                        .as_str()
+                        .unwrap_or_default()
+                        .to_string(),
+                    language: stream["tags"]["language"]
+                        .as_str()
+                        .unwrap_or_default()
+                        .to_string(),
+                    forced: stream["disposition"]["forced"].as_i64().unwrap_or_default() == 1,
+                })
+                .collect()
+        })
+        .unwrap_or_default())
+}
+
 // all subtitle fonts (extracted from javascript)
 const FONTS: [(&str, &str); 68] = [
     ("Adobe Arabic", "AdobeArabic-Bold.woff2"),
@@ -1325,18 +2566,56 @@ fn get_subtitle_stats(path: &Path) -> Result<Vec<String>> {
     Ok(fonts)
 }
 
+/// Adds `filter` in front of the filters of `option` (e.g. '-vf') or adds the option if it does
+/// not exist yet.
+fn prepend_filter(presets: &mut Vec<String>, option: &str, filter: String) {
+    if let Some(position) = presets.iter().position(|p| p == option) {
+        if let Some(filters) = presets.get_mut(position + 1) {
+            *filters = format!("{},{}", filter, filters);
+            return;
+        }
+    }
+    presets.extend([option.to_string(), filter])
+}
+
+/// Adds `filter` behind the filters of `option` (e.g. '-af') or adds the option if it does not
+/// exist yet. ffmpeg only uses the last value of an option, so the filter is added to it.
+fn append_filter(presets: &mut Vec<String>, option: &str, filter: String) {
+    if let Some(position) = presets.iter().rposition(|p| p == option) {
+        if let Some(filters) = presets.get_mut(position + 1) {
+            *filters = format!("{},{}", filters, filter);
+            return;
+        }
+    }
+    presets.extend([option.to_string(), filter])
+}
+
+fn time_to_secs(time: Time) -> f32 {
+    time.hour() as f32 * 3600.0
+        + time.minute() as f32 * 60.0
+        + time.second() as f32
+        + time.millisecond() as f32 / 1000.0
+}
+
 fn write_ffmpeg_chapters(
     file: &mut fs::File,
     video_len: TimeDelta,
     events: &mut Vec<(&str, &SkipEventsEvent)>,
+    cut_ranges: &CutRanges,
 ) -> Result<()> {
-    let video_len = video_len.num_milliseconds() as f32 / 1000.0;
+    let video_len = cut_ranges.map(video_len.num_milliseconds() as f32 / 1000.0);
     events.sort_by(|(_, event_a), (_, event_b)| event_a.start.total_cmp(&event_b.start));
 
     writeln!(file, ";FFMETADATA1")?;
 
     let mut last_end_time = 0.0;
     for (name, event) in events {
+        let (start, end) = (cut_ranges.map(event.start), cut_ranges.map(event.end));
+        // chapters which were cut out completely
+        if start >= end {
+            continue;
+        }
+
         /*
             - Convert from seconds to milliseconds for the correct timescale
             - Include an extra 'Episode' chapter if the start of the current chapter is more than 10
@@ -1344,21 +2623,21 @@ fn write_ffmpeg_chapters(
               This is done before writing the actual chapter of this loop to keep the chapter
               chronologically in order
         */
-        if event.start - last_end_time > 10.0 {
+        if start - last_end_time > 10.0 {
             writeln!(file, "[CHAPTER]")?;
             writeln!(file, "TIMEBASE=1/1000")?;
             writeln!(file, "START={}", (last_end_time * 1000.0) as u32)?;
-            writeln!(file, "END={}", (event.start * 1000.0) as u32)?;
+            writeln!(file, "END={}", (start * 1000.0) as u32)?;
             writeln!(file, "title=Episode")?;
         }
 
         writeln!(file, "[CHAPTER]")?;
         writeln!(file, "TIMEBASE=1/1000")?;
-        writeln!(file, "START={}", (event.start * 1000.0) as u32)?;
-        writeln!(file, "END={}", (event.end * 1000.0) as u32)?;
+        writeln!(file, "START={}", (start * 1000.0) as u32)?;
+        writeln!(file, "END={}", (end * 1000.0) as u32)?;
         writeln!(file, "title={}", name)?;
 
-        last_end_time = event.end;
+        last_end_time = end;
     }
 
     // only add a trailing chapter if the gap between the end of the last chapter and the total video
@@ -1374,6 +2653,252 @@ fn write_ffmpeg_chapters(
     Ok(())
 }
 
+/// Runs ffmpeg with `args` and writes the result to `dst`. `max_frames` is the number of frames of
+/// the output and is used to display the progress. `ffmpeg_log` controls when the output of ffmpeg
+/// is shown.
+pub async fn run_ffmpeg(
+    args: Vec<String>,
+    dst: &Path,
+    max_frames: u64,
+    message: String,
+    semaphore: Option<&Semaphore>,
+    ffmpeg_log: &FFmpegLog,
+) -> Result<()> {
+    let fifo = temp_named_pipe()?;
+
+    let mut command_args = vec![
+        "-y".to_string(),
+        "-hide_banner".to_string(),
+        "-vstats_file".to_string(),
+        fifo.path().to_string_lossy().to_string(),
+    ];
+    command_args.extend(args);
+
+    // ffmpeg writes to a partial file first which is renamed to the actual output file after
+    // ffmpeg has finished successfully. this way the output file never ends up half-written
+    let partial_output =
+        (!is_special_file(dst) && dst.to_str().unwrap() != "-").then(|| PartialOutput::new(dst));
+    let output = partial_output
+        .as_ref()
+        .map_or(dst.to_path_buf(), |p| p.path().to_path_buf());
+
+    // prepend './' to the path on linux since ffmpeg may interpret the path incorrectly if it's just the filename.
+    // see https://github.com/crunchy-labs/crunchy-cli/issues/303 for example
+    if !cfg!(windows)
+        && output
+            .parent()
+            .map_or(true, |p| p.to_string_lossy().is_empty())
+    {
+        command_args.push(Path::new("./").join(&output).to_string_lossy().to_string());
+    } else {
+        command_args.push(output.to_string_lossy().to_string())
+    }
+
+    debug!("ffmpeg {}", command_args.join(" "));
+
+    // create parent directory if it does not exist
+    if let Some(parent) = dst.parent() {
+        if !parent.exists() {
+            fs::create_dir_all(parent)?
+        }
+    }
+
+    // limits how many ffmpeg processes are running at the same time
+    let _ffmpeg_permit = if let Some(semaphore) = semaphore {
+        if semaphore.available_permits() == 0 {
+            debug!("Waiting for a free ffmpeg slot")
+        }
+        Some(semaphore.acquire().await?)
+    } else {
+        None
+    };
+
+    let mut ffmpeg = Command::new("ffmpeg")
+        // pass ffmpeg stdout to real stdout only if output file is stdout
+        .stdout(if dst.to_str().unwrap() == "-" {
+            Stdio::inherit()
+        } else {
+            Stdio::null()
+        })
+        .stderr(Stdio::piped())
+        .args(command_args)
+        .spawn()?;
+    let ffmpeg_progress_cancel = CancellationToken::new();
+    let ffmpeg_progress_cancellation_token = ffmpeg_progress_cancel.clone();
+    let ffmpeg_progress = tokio::spawn(LogFile::current().scope(async move {
+        ffmpeg_progress(
+            max_frames,
+            fifo,
+            message,
+            ffmpeg_progress_cancellation_token,
+        )
+        .await
+    }));
+
+    // stderr is read in a separate thread, so that the lines can be logged while ffmpeg is still
+    // running
+    let stderr = ffmpeg.stderr.take().unwrap();
+    let log_live = ffmpeg_log == &FFmpegLog::Always;
+    let log_file = LogFile::current();
+    let stderr_reader = std::thread::spawn(move || {
+        log_file.sync_scope(|| {
+            let mut lines = vec![];
+            for line in BufReader::new(stderr).lines().map_while(|l| l.ok()) {
+                if log_live {
+                    debug!("ffmpeg: {}", line)
+                }
+                lines.push(line)
+            }
+            lines
+        })
+    });
+    let status = ffmpeg.wait()?;
+    let stderr = stderr_reader.join().unwrap_or_default().join("\n");
+    if !status.success() {
+        ffmpeg_progress.abort();
+        if ffmpeg_log == &FFmpegLog::Hidden || stderr.trim().is_empty() {
+            bail!("ffmpeg exited with {}", status)
+        }
+        bail!("ffmpeg exited with {}:\n{}", status, stderr.trim_end())
+    }
+    ffmpeg_progress_cancel.cancel();
+    ffmpeg_progress.await??;
+
+    if let Some(partial_output) = partial_output {
+        partial_output.persist()?
+    }
+    Ok(())
+}
+
+/// Stores the downloaded streams in a directory next to `dst` instead of muxing them. A
+/// [`RawManifest`] is written alongside, which can be used by the `repair` command to create the
+/// output file later on.
+#[allow(clippy::too_many_arguments)]
+fn write_raw(
+    dst: &Path,
+    mut ffmpeg_args: Vec<String>,
+    videos: &[FFmpegVideoMeta],
+    audios: &[&FFmpegAudioMeta],
+    subtitles: &[FFmpegSubtitleMeta],
+    chapters: Option<&TempPath>,
+    fonts: &[PathBuf],
+    frames: u64,
+) -> Result<()> {
+    let raw_dir = RawManifest::directory(dst);
+    fs::create_dir_all(&raw_dir)?;
+
+    let mut streams = vec![];
+    let mut store = |src: &Path,
+                     file: String,
+                     kind: RawStreamKind,
+                     locale: Option<&Locale>,
+                     keep_src: bool|
+     -> Result<()> {
+        let target = raw_dir.join(&file);
+        // temporary files are moved if possible, copying is only the fallback if the temp
+        // directory is on another filesystem
+        if keep_src || fs::rename(src, &target).is_err() {
+            fs::copy(src, &target)?;
+        }
+
+        let src = src.to_string_lossy().to_string();
+        let escaped_src = src.replace('\\', "\\\\").replace(':', "\\:");
+        let placeholder = format!("{}/{}", RawManifest::DIR_PLACEHOLDER, file);
+        for arg in ffmpeg_args.iter_mut() {
+            *arg = arg
+                .replace(&src, &placeholder)
+                .replace(&escaped_src, &placeholder)
+        }
+        streams.push(RawStream {
+            kind,
+            file,
+            locale: locale.map(|l| l.to_string()),
+        });
+        Ok(())
+    };
+
+    let extension = |path: &Path| -> String {
+        path.extension()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string()
+    };
+    for (i, video) in videos.iter().enumerate() {
+        store(
+            &video.path,
+            format!("video-{}.{}", i, extension(&video.path)),
+            RawStreamKind::Video,
+            None,
+            false,
+        )?
+    }
+    for (i, audio) in audios.iter().enumerate() {
+        store(
+            &audio.path,
+            format!("audio-{}-{}.{}", i, audio.locale, extension(&audio.path)),
+            RawStreamKind::Audio,
+            Some(&audio.locale),
+            false,
+        )?
+    }
+    for (i, subtitle) in subtitles.iter().enumerate() {
+        store(
+            &subtitle.path,
+            format!(
+                "subtitle-{}-{}{}.{}",
+                i,
+                subtitle.locale,
+                if subtitle.cc { "-cc" } else { "" },
+                extension(&subtitle.path)
+            ),
+            RawStreamKind::Subtitle,
+            Some(&subtitle.locale),
+            false,
+        )?
+    }
+    if let Some(chapters) = chapters {
+        store(
+            chapters,
+            "chapters.txt".to_string(),
+            RawStreamKind::Chapters,
+            None,
+            false,
+        )?
+    }
+    // fonts are stored in the cache directory and are re-used by other downloads, so they're
+    // only copied
+    for font in fonts {
+        store(
+            font,
+            format!(
+                "font-{}",
+                font.file_name().unwrap_or_default().to_string_lossy()
+            ),
+            RawStreamKind::Font,
+            None,
+            true,
+        )?
+    }
+
+    let manifest = RawManifest {
+        output: dst
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string(),
+        frames,
+        streams,
+        ffmpeg_args,
+    };
+    manifest.save(&raw_dir)?;
+    info!(
+        "Stored raw streams in {}. Use `crunchy-cli repair` to create the output file",
+        raw_dir.to_string_lossy()
+    );
+
+    Ok(())
+}
+
 async fn ffmpeg_progress<R: AsyncReadExt + Unpin>(
     total_frames: u64,
     stats: R,
@@ -1382,19 +2907,23 @@ async fn ffmpeg_progress<R: AsyncReadExt + Unpin>(
 ) -> Result<()> {
     let current_frame = Regex::new(r"frame=\s+(?P<frame>\d+)")?;
 
-    let progress = if log::max_level() == LevelFilter::Info {
-        let progress = ProgressBar::new(total_frames)
-            .with_style(
-                ProgressStyle::with_template(":: {msg} [{wide_bar}] {percent:>3}%")
-                    .unwrap()
-                    .progress_chars("##-"),
-            )
-            .with_message(message)
-            .with_finish(ProgressFinish::Abandon);
-        progress.set_draw_target(ProgressDrawTarget::stdout());
+    let progress = if draw_progress() {
+        let progress = add_progress_bar(
+            ProgressBar::new(total_frames)
+                .with_style(
+                    ProgressStyle::with_template(":: {msg} [{wide_bar}] {percent:>3}%")
+                        .unwrap()
+                        .progress_chars("##-"),
+                )
+                .with_message(message)
+                .with_finish(ProgressFinish::Abandon),
+        );
         progress.enable_steady_tick(Duration::from_millis(200));
         Some(progress)
     } else {
+        if log::max_level() == LevelFilter::Info {
+            info!("{}", message.trim_end())
+        }
         None
     };
 
@@ -1451,3 +2980,165 @@ async fn ffmpeg_progress<R: AsyncReadExt + Unpin>(
 fn len_from_segments(segments: &[StreamSegment]) -> TimeDelta {
     TimeDelta::milliseconds(segments.iter().map(|s| s.length.as_millis()).sum::<u128>() as i64)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::FutureExt;
+
+    #[test]
+    fn test_append_filter() {
+        let mut presets = vec!["-c:v".to_string(), "copy".to_string()];
+        append_filter(&mut presets, "-af", "loudnorm".to_string());
+        assert_eq!(presets, ["-c:v", "copy", "-af", "loudnorm"]);
+
+        // the filters of a custom preset and of the extra arguments are kept, ffmpeg only uses
+        // the last '-af'
+        let mut presets = vec![
+            "-af".to_string(),
+            "volume=2".to_string(),
+            "-af".to_string(),
+            "highpass=f=200".to_string(),
+        ];
+        append_filter(&mut presets, "-af", "loudnorm".to_string());
+        assert_eq!(
+            presets,
+            ["-af", "volume=2", "-af", "highpass=f=200,loudnorm"]
+        );
+    }
+
+    #[test]
+    fn test_stream_options() -> Result<()> {
+        let reencoded = |options: StreamOptions| -> Result<Vec<&'static str>> {
+            Ok(options.build()?.reencoded)
+        };
+
+        assert!(reencoded(StreamOptions::default())?.is_empty());
+        // loudnorm is skipped if nothing gets re-encoded
+        assert!(reencoded(StreamOptions {
+            loudnorm: Some(-16.0),
+            ..Default::default()
+        })?
+        .is_empty());
+        let burn = StreamOptions {
+            burn_subtitle: Some("sub.ass".to_string()),
+            ..Default::default()
+        }
+        .build()?;
+        assert_eq!(burn.reencoded, ["video", "audio"]);
+        assert_eq!(burn.reasons, ["burning in subtitles"]);
+        assert_eq!(
+            reencoded(StreamOptions {
+                audio_codec: Some(AudioCodec::Opus),
+                ..Default::default()
+            })?,
+            ["audio"]
+        );
+
+        // the extra arguments are part of the arguments which are checked
+        let extra_args = vec!["-c:a".to_string(), "aac".to_string()];
+        let extended = StreamOptions {
+            ffmpeg_preset: FFmpegPreset::Custom(Some("-c:v copy".to_string())),
+            ffmpeg_extra_args: extra_args.clone(),
+            cut: Some("between(t,0,90)".to_string()),
+            ..Default::default()
+        }
+        .build()?;
+        assert!(extended
+            .output
+            .starts_with(&["-c:a".to_string(), "aac".to_string()]));
+        assert_eq!(extended.reencoded, ["video"]);
+        // but are ignored if the streams are muxed into an existing file
+        let mux_into = StreamOptions {
+            ffmpeg_preset: FFmpegPreset::Custom(Some("-c:v libx264".to_string())),
+            ffmpeg_extra_args: extra_args,
+            mux_into: true,
+            ..Default::default()
+        }
+        .build()?;
+        assert_eq!(mux_into.output, ["-c", "copy"]);
+
+        let reencode_video = FFmpegPreset::Custom(Some("-c:v libx264 -c:a copy".to_string()));
+        let normalized = StreamOptions {
+            ffmpeg_preset: reencode_video.clone(),
+            loudnorm: Some(-16.0),
+            ..Default::default()
+        }
+        .build()?;
+        assert_eq!(normalized.reencoded, ["audio"]);
+        assert_eq!(normalized.reasons, ["loudness normalization"]);
+        // the audio is copied again explicitly
+        assert!(reencoded(StreamOptions {
+            ffmpeg_preset: reencode_video,
+            audio_codec: Some(AudioCodec::Copy),
+            ..Default::default()
+        })?
+        .is_empty());
+        // audio filters can't be applied to copied audio
+        assert!(StreamOptions {
+            ffmpeg_preset: FFmpegPreset::Custom(Some("-c:v libx264".to_string())),
+            loudnorm: Some(-16.0),
+            audio_codec: Some(AudioCodec::Copy),
+            ..Default::default()
+        }
+        .build()
+        .is_err());
+
+        let reencode = FFmpegPreset::Custom(Some("-c:v libx264 -c:a aac".to_string()));
+        assert!(reencoded(StreamOptions {
+            ffmpeg_preset: reencode,
+            burn_subtitle: Some("sub.ass".to_string()),
+            audio_codec: Some(AudioCodec::Opus),
+            loudnorm: Some(-16.0),
+            cut: Some("between(t,0,90)".to_string()),
+            ..Default::default()
+        })?
+        .is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_subtitle_prefetch_overlaps_download() -> Result<()> {
+        let events = Arc::new(std::sync::Mutex::new(vec![]));
+
+        let fetch_events = events.clone();
+        let mut prefetch = spawn_prefetch(vec![("en-US", async move {
+            fetch_events.lock().unwrap().push("subtitle start");
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            fetch_events.lock().unwrap().push("subtitle end");
+            anyhow::Ok(b"[Script Info]".to_vec())
+        })]);
+
+        // stands in for the segment download, the subtitle has to be fetched while it's running
+        events.lock().unwrap().push("segments start");
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        events.lock().unwrap().push("segments end");
+
+        let prefetched = prefetch.join_next().await.unwrap()??;
+        assert_eq!(prefetched["en-US"], b"[Script Info]");
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                "segments start",
+                "subtitle start",
+                "subtitle end",
+                "segments end"
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_subtitle_prefetch_error() {
+        let mut prefetch = spawn_prefetch(vec![
+            ("de-DE", async { anyhow::Ok(vec![]) }.boxed()),
+            (
+                "en-US",
+                async { Err(anyhow!("subtitle not found")) }.boxed(),
+            ),
+        ]);
+        let result = prefetch.join_next().await.unwrap().unwrap();
+        assert_eq!(result.unwrap_err().to_string(), "subtitle not found");
+    }
+}
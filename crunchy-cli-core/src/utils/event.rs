@@ -0,0 +1,69 @@
+use crate::utils::log::{add_progress_bar, draw_progress};
+use indicatif::{ProgressBar, ProgressFinish, ProgressStyle};
+use log::{debug, info, LevelFilter};
+use std::path::Path;
+use std::sync::Mutex;
+
+/// Receives events from a [`crate::utils::download::Downloader`]. Every method has an empty
+/// default implementation, so only the events which are of interest must be implemented.
+#[allow(unused_variables)]
+pub trait EventSink: Send + Sync {
+    /// The download of the file at `dst` has started.
+    fn episode_started(&self, dst: &Path) {}
+    /// The download of stream segments (a video or audio) has started. `estimated_size` is the
+    /// estimated size of the whole stream in bytes.
+    fn segments_started(&self, message: &str, estimated_size: u64) {}
+    /// A segment was downloaded. `estimated_size` gets more accurate with every downloaded
+    /// segment.
+    fn segment_progress(&self, downloaded: u64, estimated_size: u64) {}
+    /// All segments of the stream were downloaded.
+    fn segments_finished(&self) {}
+    /// The downloaded streams are muxed into the file at `dst`.
+    fn mux_started(&self, dst: &Path) {}
+    /// The file at `dst` was successfully created.
+    fn episode_finished(&self, dst: &Path) {}
+    /// An error occurred while downloading the file at `dst`.
+    fn error(&self, dst: &Path, error: &anyhow::Error) {}
+}
+
+/// The default [`EventSink`] of the cli. Draws a progress bar for segment downloads.
+#[derive(Default)]
+pub struct CliEventSink {
+    progress: Mutex<Option<ProgressBar>>,
+}
+
+impl EventSink for CliEventSink {
+    fn segments_started(&self, message: &str, estimated_size: u64) {
+        if draw_progress() {
+            let progress = ProgressBar::new(estimated_size)
+                .with_style(
+                    ProgressStyle::with_template(
+                        ":: {msg} {bytes:>10} {bytes_per_sec:>12} [{wide_bar}] {percent:>3}%",
+                    )
+                    .unwrap()
+                    .progress_chars("##-"),
+                )
+                .with_message(message.to_string())
+                .with_finish(ProgressFinish::Abandon);
+            *self.progress.lock().unwrap() = Some(add_progress_bar(progress))
+        } else if log::max_level() == LevelFilter::Info {
+            info!("{}", message.trim_end())
+        }
+    }
+
+    fn segment_progress(&self, downloaded: u64, estimated_size: u64) {
+        if let Some(progress) = self.progress.lock().unwrap().as_ref() {
+            progress.set_length(estimated_size);
+            progress.set_position(downloaded)
+        }
+    }
+
+    fn segments_finished(&self) {
+        // dropping the progress bar leaves it as it is on the terminal
+        self.progress.lock().unwrap().take();
+    }
+
+    fn episode_finished(&self, dst: &Path) {
+        debug!("Finished download of {}", dst.to_string_lossy())
+    }
+}
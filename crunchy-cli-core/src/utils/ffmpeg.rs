@@ -1,7 +1,11 @@
+use anyhow::bail;
+use crunchyroll_rs::Locale;
 use lazy_static::lazy_static;
 use regex::Regex;
 use std::fmt;
 use std::fmt::Formatter;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
 use std::str::FromStr;
 
 pub const SOFTSUB_CONTAINERS: [&str; 3] = ["mkv", "mov", "mp4"];
@@ -379,3 +383,261 @@ impl FFmpegPreset {
         }
     }
 }
+
+/// Check if the ffmpeg output arguments are copying the stream of the given type (`v` for video,
+/// `a` for audio) instead of re-encoding it.
+pub(crate) fn is_stream_copy(args: &[String], stream_type: &str) -> bool {
+    args.windows(2)
+        .any(|w| w[1] == "copy" && (is_codec_arg(&w[0], stream_type) || is_codec_arg(&w[0], "")))
+}
+
+/// Check if `arg` sets the codec of the given stream type, e.g. `-c:a`, `-codec:a` or `-acodec`.
+/// An empty `stream_type` matches the arguments which set the codec of all streams (`-c` and
+/// `-codec`).
+fn is_codec_arg(arg: &str, stream_type: &str) -> bool {
+    if stream_type.is_empty() {
+        arg == "-c" || arg == "-codec"
+    } else {
+        arg == format!("-c:{}", stream_type)
+            || arg == format!("-codec:{}", stream_type)
+            || arg == format!("-{}codec", stream_type)
+    }
+}
+
+/// Remove all arguments which copy the stream of the given type from the ffmpeg output arguments.
+/// All forms matched by [`is_stream_copy`] are removed. As `-c copy` also copies all other
+/// streams, it's replaced with explicit copy arguments for the other stream types. Returns `true`
+/// if something was removed.
+pub(crate) fn remove_stream_copy(args: &mut Vec<String>, stream_type: &str) -> bool {
+    let mut removed = false;
+    let mut i = 0;
+    while i + 1 < args.len() {
+        if args[i + 1] != "copy" {
+            i += 1
+        } else if is_codec_arg(&args[i], stream_type) {
+            args.drain(i..i + 2);
+            removed = true
+        } else if is_codec_arg(&args[i], "") {
+            let others: Vec<String> = ["v", "a", "s"]
+                .into_iter()
+                .filter(|t| *t != stream_type)
+                .flat_map(|t| [format!("-c:{}", t), "copy".to_string()])
+                .collect();
+            let len = others.len();
+            args.splice(i..i + 2, others);
+            i += len;
+            removed = true
+        } else {
+            i += 1
+        }
+    }
+    removed
+}
+
+/// Removes all arguments which set the codec of the given stream type, e.g. `-c:a aac`.
+pub(crate) fn remove_stream_codec(args: &mut Vec<String>, stream_type: &str) {
+    let codec_args = [
+        format!("-c:{}", stream_type),
+        format!("-codec:{}", stream_type),
+        format!("-{}codec", stream_type),
+    ];
+    let mut i = 0;
+    while i + 1 < args.len() {
+        if codec_args.contains(&args[i]) {
+            args.drain(i..i + 2);
+        } else {
+            i += 1
+        }
+    }
+}
+
+/// The codec of the output audio.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum AudioCodec {
+    Copy,
+    Aac,
+    Opus,
+}
+
+impl fmt::Display for AudioCodec {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let value = match self {
+            AudioCodec::Copy => "copy",
+            AudioCodec::Aac => "aac",
+            AudioCodec::Opus => "opus",
+        };
+        write!(f, "{}", value)
+    }
+}
+
+impl AudioCodec {
+    pub fn parse(s: &str) -> Result<AudioCodec, String> {
+        Ok(match s.to_lowercase().as_str() {
+            "copy" => AudioCodec::Copy,
+            "aac" => AudioCodec::Aac,
+            "opus" => AudioCodec::Opus,
+            _ => return Err(format!("'{}' is not a valid audio codec", s)),
+        })
+    }
+
+    /// Name of the ffmpeg encoder.
+    pub fn encoder(&self) -> &'static str {
+        match self {
+            AudioCodec::Copy => "copy",
+            AudioCodec::Aac => "aac",
+            AudioCodec::Opus => "libopus",
+        }
+    }
+
+    /// Checks if the codec can be stored in a container with the given file extension. Copied
+    /// audio is aac, which is supported by all containers.
+    pub fn supports_container(&self, extension: &str) -> bool {
+        match self {
+            AudioCodec::Copy | AudioCodec::Aac => true,
+            AudioCodec::Opus => !["mov", "m4a", "flv", "avi"].contains(&extension),
+        }
+    }
+}
+
+/// How mp4 / mov output files are laid out, which decides if they can be played while they're
+/// still being downloaded (e.g. when served over http).
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub enum Mp4Layout {
+    /// The index (moov atom) is written at the end of the file, players have to load the whole
+    /// file (or seek to its end) before they can start playing.
+    #[default]
+    Default,
+    /// The index is moved to the beginning of the file. This needs an additional pass over the
+    /// file after it was written.
+    Faststart,
+    /// The file is split into fragments which each carry their own index, so no index has to be
+    /// moved and playback can start after the first fragment. Not every (older) player supports
+    /// this.
+    Fragmented,
+}
+
+impl Mp4Layout {
+    pub fn movflags(&self) -> Option<&'static str> {
+        match self {
+            Mp4Layout::Default => None,
+            Mp4Layout::Faststart => Some("+faststart"),
+            Mp4Layout::Fragmented => Some("+frag_keyframe+empty_moov"),
+        }
+    }
+}
+
+/// When the output of ffmpeg (its stderr) is shown.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub enum FFmpegLog {
+    /// Never shown. If ffmpeg fails, only its exit status is reported.
+    Hidden,
+    /// Shown if ffmpeg fails.
+    #[default]
+    OnError,
+    /// Every line is logged live (at debug level) and the output is also shown if ffmpeg fails.
+    Always,
+}
+
+impl FFmpegLog {
+    pub fn parse(s: &str) -> Result<FFmpegLog, String> {
+        Ok(match s.to_lowercase().as_str() {
+            "hidden" => FFmpegLog::Hidden,
+            "on-error" => FFmpegLog::OnError,
+            "always" => FFmpegLog::Always,
+            _ => return Err(format!("'{}' is not a valid ffmpeg log mode", s)),
+        })
+    }
+}
+
+/// An audio or subtitle file provided by the user, which is muxed into the output in addition to
+/// the streams from Crunchyroll.
+#[derive(Clone, Debug)]
+pub struct ExternalStream {
+    pub path: PathBuf,
+    pub locale: Locale,
+}
+
+impl ExternalStream {
+    /// Parses `<file>:<locale>`. The locale is separated by the last colon, so paths which contain
+    /// colons (e.g. windows drive letters) are possible too.
+    pub fn parse(s: &str) -> Result<ExternalStream, String> {
+        let Some((path, locale)) = s
+            .rsplit_once(':')
+            .filter(|(p, l)| !p.is_empty() && !l.is_empty())
+        else {
+            return Err(format!("'{}' is not in format '<file>:<locale>'", s));
+        };
+        let path = PathBuf::from(path);
+        if !path.is_file() {
+            return Err(format!(
+                "'{}' does not exist or is not a file",
+                path.to_string_lossy()
+            ));
+        }
+        let locale = Locale::from(locale.to_string());
+        if let Locale::Custom(custom) = &locale {
+            return Err(format!("'{}' is not a valid locale", custom));
+        }
+        Ok(ExternalStream { path, locale })
+    }
+
+    /// Checks with ffmpeg that the file contains at least one stream of the given type (`Audio`
+    /// or `Subtitle`), so an incompatible file is detected before anything is downloaded.
+    pub fn check_stream_type(&self, stream_type: &str) -> anyhow::Result<()> {
+        let output = Command::new("ffmpeg")
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .arg("-hide_banner")
+            .args(["-i", &self.path.to_string_lossy()])
+            .output()?;
+        let stream_marker = format!(": {}: ", stream_type);
+        if !String::from_utf8_lossy(&output.stderr)
+            .lines()
+            .any(|l| l.trim_start().starts_with("Stream #") && l.contains(&stream_marker))
+        {
+            bail!(
+                "'{}' contains no {} stream",
+                self.path.to_string_lossy(),
+                stream_type.to_lowercase()
+            )
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &str) -> Vec<String> {
+        s.split(' ').map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_stream_copy_forms() {
+        for copy in [
+            "-c:a copy",
+            "-codec:a copy",
+            "-acodec copy",
+            "-c copy",
+            "-codec copy",
+        ] {
+            let mut output = args(copy);
+            assert!(is_stream_copy(&output, "a"), "{}", copy);
+            assert!(remove_stream_copy(&mut output, "a"), "{}", copy);
+            assert!(!is_stream_copy(&output, "a"), "{}", copy);
+        }
+    }
+
+    #[test]
+    fn test_remove_stream_copy_keeps_other_streams() {
+        let mut output = args("-c copy -f matroska");
+        assert!(remove_stream_copy(&mut output, "a"));
+        assert_eq!(output, args("-c:v copy -c:s copy -f matroska"));
+        assert!(is_stream_copy(&output, "v"));
+
+        let mut output = args("-c:v copy -c:a aac");
+        assert!(!remove_stream_copy(&mut output, "a"));
+        assert_eq!(output, args("-c:v copy -c:a aac"));
+    }
+}
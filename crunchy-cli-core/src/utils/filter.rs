@@ -1,12 +1,16 @@
-use crate::utils::format::{SingleFormat, SingleFormatCollection};
+use crate::utils::format::{auto_padding_width, SingleFormat, SingleFormatCollection};
 use crate::utils::interactive_select::{check_for_duplicated_seasons, get_duplicated_seasons};
 use crate::utils::parse::{fract, UrlFilter};
-use anyhow::Result;
+use anyhow::{anyhow, bail, Result};
+use chrono::{Duration, NaiveDate};
+use crunchyroll_rs::media::PlayheadInformation;
 use crunchyroll_rs::{
-    Concert, Episode, Locale, MediaCollection, Movie, MovieListing, MusicVideo, Season, Series,
+    Concert, Crunchyroll, Episode, Locale, MediaCollection, Movie, MovieListing, MusicVideo,
+    Season, Series,
 };
-use log::{info, warn};
-use std::collections::{BTreeMap, HashMap};
+use log::{debug, info, warn};
+use regex::Regex;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::ops::Not;
 
 pub(crate) enum FilterMediaScope<'a> {
@@ -18,13 +22,36 @@ pub(crate) enum FilterMediaScope<'a> {
     Episode(Vec<&'a Episode>),
 }
 
+/// Filters episodes and movies by the watch progress of the logged in account.
+pub(crate) struct WatchFilter {
+    pub(crate) crunchy: Crunchyroll,
+    /// If only watched (`true`) or only unwatched (`false`) episodes are kept.
+    pub(crate) watched: bool,
+    /// The percentage of an episode which must be watched until it counts as watched.
+    pub(crate) threshold: u8,
+}
+
 pub(crate) struct Filter {
     url_filter: UrlFilter,
 
     skip_specials: bool,
     interactive_input: bool,
 
+    aired_since: Option<NaiveDate>,
+    aired_until: Option<NaiveDate>,
+    title_patterns: Vec<Regex>,
+    title_matches: (usize, usize),
+    watch_filter: Option<WatchFilter>,
+    watch_matches: (usize, usize),
+    /// `None` if the media was never watched.
+    playheads: HashMap<String, Option<PlayheadInformation>>,
+
+    fetch_series_description: bool,
+    known_seasons: Option<HashSet<String>>,
+    include_extras: bool,
+
     relative_episode_number: bool,
+    auto_padding: bool,
 
     audio_locales: Vec<Locale>,
     subtitle_locales: Vec<Locale>,
@@ -37,6 +64,9 @@ pub(crate) struct Filter {
 
     series_visited: bool,
     season_episodes: HashMap<String, Vec<Episode>>,
+    season_titles: HashMap<String, String>,
+    series_descriptions: HashMap<String, String>,
+    extras: Vec<SingleFormat>,
     season_with_premium: Option<Vec<u32>>,
     season_sorting: Vec<String>,
 }
@@ -60,6 +90,7 @@ impl Filter {
             audio_locales,
             subtitle_locales,
             relative_episode_number,
+            auto_padding: false,
             interactive_input,
             audios_missing,
             subtitles_missing,
@@ -67,13 +98,136 @@ impl Filter {
             is_premium,
             series_visited: false,
             season_episodes: HashMap::new(),
+            season_titles: HashMap::new(),
+            series_descriptions: HashMap::new(),
             skip_specials,
+            aired_since: None,
+            aired_until: None,
+            title_patterns: vec![],
+            title_matches: (0, 0),
+            watch_filter: None,
+            watch_matches: (0, 0),
+            playheads: HashMap::new(),
+            fetch_series_description: false,
+            known_seasons: None,
+            include_extras: false,
+            extras: vec![],
             season_with_premium: is_premium.not().then_some(vec![]),
             season_sorting: vec![],
         }
     }
 
+    /// Only episodes which aired in the given date range (both inclusive) are kept.
+    pub(crate) fn air_date_range(
+        mut self,
+        since: Option<NaiveDate>,
+        until: Option<NaiveDate>,
+    ) -> Self {
+        self.aired_since = since;
+        self.aired_until = until;
+        self
+    }
+
+    /// Only episodes whose title matches all `patterns` are kept.
+    pub(crate) fn title_patterns(mut self, patterns: Vec<Regex>) -> Self {
+        self.title_patterns = patterns;
+        self
+    }
+
+    /// Only episodes and movies which match the watch progress of `watch_filter` are kept. An
+    /// episode counts as watched if any of its audio versions is.
+    pub(crate) fn watch_status(mut self, watch_filter: Option<WatchFilter>) -> Self {
+        self.watch_filter = watch_filter;
+        self
+    }
+
+    /// Episodes don't contain the description of their series, if set it gets fetched separately.
+    pub(crate) fn fetch_series_description(mut self, fetch_series_description: bool) -> Self {
+        self.fetch_series_description = fetch_series_description;
+        self
+    }
+
+    /// If set, all seasons of a series which (or any of their audio versions) are contained in
+    /// `known_seasons` are skipped.
+    pub(crate) fn only_new_seasons(mut self, known_seasons: Option<HashSet<String>>) -> Self {
+        self.known_seasons = known_seasons;
+        self
+    }
+
+    /// If set, the width of auto padded number placeholders (`{episode_number:auto}`) is
+    /// calculated from the largest episode number of each season.
+    pub(crate) fn auto_padding(mut self, auto_padding: bool) -> Self {
+        self.auto_padding = auto_padding;
+        self
+    }
+
+    /// If set, the extras of a series (openings, endings, PVs, ...) are included too. Crunchyroll
+    /// provides them as music videos which are featured by the series.
+    pub(crate) fn include_extras(mut self, include_extras: bool) -> Self {
+        self.include_extras = include_extras;
+        self
+    }
+
+    /// Fetches the playheads of all `ids` which weren't fetched before.
+    async fn fetch_playheads(&mut self, mut ids: Vec<String>) -> Result<()> {
+        let Some(watch_filter) = &self.watch_filter else {
+            return Ok(());
+        };
+        ids.retain(|id| !self.playheads.contains_key(id));
+        ids.sort();
+        ids.dedup();
+
+        // too many ids at once result in a too long request url
+        for chunk in ids.chunks(50) {
+            let mut playheads = watch_filter
+                .crunchy
+                .playheads(chunk.to_vec())
+                .await
+                .map_err(|e| {
+                    anyhow!(
+                        "Failed to fetch the watch progress, `--watched-only` and `--unwatched-only` require to be logged in with an account: {}",
+                        e
+                    )
+                })?;
+            for id in chunk {
+                self.playheads.insert(id.clone(), playheads.remove(id));
+            }
+        }
+        Ok(())
+    }
+
+    /// If the media with the given ids (all audio versions of it) matches the watch filter.
+    async fn matches_watch_status(&mut self, ids: Vec<String>, duration: Duration) -> Result<bool> {
+        self.fetch_playheads(ids.clone()).await?;
+        let Some(watch_filter) = &self.watch_filter else {
+            return Ok(true);
+        };
+
+        let threshold = duration.num_seconds() * watch_filter.threshold as i64 / 100;
+        let watched = ids.iter().any(|id| {
+            self.playheads
+                .get(id)
+                .and_then(|p| p.as_ref())
+                // episodes which were watched to the end are sometimes not marked as fully
+                // watched, e.g. if the credits were skipped, so the playhead is checked too
+                .is_some_and(|p| {
+                    p.fully_watched || (threshold > 0 && p.playhead as i64 >= threshold)
+                })
+        });
+
+        if watched == watch_filter.watched {
+            self.watch_matches.0 += 1;
+            Ok(true)
+        } else {
+            self.watch_matches.1 += 1;
+            Ok(false)
+        }
+    }
+
     async fn visit_series(&mut self, series: Series) -> Result<Vec<Season>> {
+        self.series_descriptions
+            .insert(series.id.clone(), series.description.clone());
+
         // the audio locales field isn't always populated
         if !series.audio_locales.is_empty() {
             let missing_audios = missing_locales(&series.audio_locales, &self.audio_locales);
@@ -112,6 +266,27 @@ impl Filter {
             seasons.push(season)
         }
 
+        if let Some(known_seasons) = &self.known_seasons {
+            seasons.retain(|s| {
+                !known_seasons.contains(&s.id)
+                    && !s.versions.iter().any(|v| known_seasons.contains(&v.id))
+            });
+            if seasons.is_empty() {
+                info!("No new seasons found for {}", series.title)
+            } else {
+                info!(
+                    "Found {} new season(s) for {}: {}",
+                    seasons.len(),
+                    series.title,
+                    seasons
+                        .iter()
+                        .map(|s| format!("{} ({})", s.season_number, s.title))
+                        .collect::<Vec<String>>()
+                        .join(", ")
+                )
+            }
+        }
+
         let duplicated_seasons = get_duplicated_seasons(&seasons);
         if !duplicated_seasons.is_empty() {
             if self.interactive_input {
@@ -128,6 +303,18 @@ impl Filter {
             }
         }
 
+        if self.include_extras {
+            let extras = series.featured_music().await?;
+            if extras.is_empty() {
+                warn!("No extras found for series {}", series.title)
+            }
+            for extra in extras {
+                self.season_sorting.push(extra.id.clone());
+                self.extras
+                    .push(SingleFormat::new_from_extra(extra, &series))
+            }
+        }
+
         self.series_visited = true;
 
         Ok(seasons)
@@ -160,16 +347,7 @@ impl Filter {
             self.season_sorting.push(season.id.clone());
             let mut eps = season.episodes().await?;
 
-            // removes any episode that does not have the audio locale of the season. yes, this is
-            // the case sometimes
-            if season.audio_locales.len() < 2 {
-                let season_locale = season
-                    .audio_locales
-                    .first()
-                    .cloned()
-                    .unwrap_or(Locale::ja_JP);
-                eps.retain(|e| e.audio_locale == season_locale)
-            }
+            retain_season_audio(&season, &mut eps);
 
             #[allow(clippy::if_same_then_else)]
             if eps.len() < season.number_of_episodes as usize {
@@ -193,7 +371,22 @@ impl Filter {
             episodes.extend(eps)
         }
 
-        if self.relative_episode_number {
+        if self.watch_filter.is_some() {
+            // fetching the playheads of the whole season at once is much faster than requesting
+            // them episode by episode
+            let ids = episodes
+                .iter()
+                .flat_map(|e| {
+                    e.versions
+                        .iter()
+                        .map(|v| v.id.clone())
+                        .chain([e.id.clone()])
+                })
+                .collect();
+            self.fetch_playheads(ids).await?
+        }
+
+        if self.relative_episode_number || self.auto_padding {
             for episode in &episodes {
                 self.season_episodes
                     .entry(episode.season_id.clone())
@@ -213,6 +406,51 @@ impl Filter {
             return Ok(vec![]);
         }
 
+        if self.aired_since.is_some() || self.aired_until.is_some() {
+            // episodes without air date have it set to the unix epoch
+            if episode.episode_air_date.timestamp() <= 0 {
+                debug!(
+                    "Skipping episode {} ({}) of season {} as it has no air date",
+                    episode.sequence_number, episode.title, episode.season_number
+                );
+                return Ok(vec![]);
+            }
+            let air_date = episode.episode_air_date.date_naive();
+            if self.aired_since.is_some_and(|since| air_date < since)
+                || self.aired_until.is_some_and(|until| air_date > until)
+            {
+                return Ok(vec![]);
+            }
+        }
+
+        if !self.title_patterns.is_empty() {
+            if !title_matches(&self.title_patterns, &episode.title) {
+                debug!(
+                    "Skipping episode {} ({}) of season {} as its title doesn't match",
+                    episode.sequence_number, episode.title, episode.season_number
+                );
+                self.title_matches.1 += 1;
+                return Ok(vec![]);
+            }
+            self.title_matches.0 += 1
+        }
+
+        if self.watch_filter.is_some() {
+            let ids = episode
+                .versions
+                .iter()
+                .map(|v| v.id.clone())
+                .chain([episode.id.clone()])
+                .collect();
+            if !self.matches_watch_status(ids, episode.duration).await? {
+                debug!(
+                    "Skipping episode {} ({}) of season {} as its watch progress doesn't match",
+                    episode.sequence_number, episode.title, episode.season_number
+                );
+                return Ok(vec![]);
+            }
+        }
+
         // skip the episode if it's a special
         if self.skip_specials
             && (episode.sequence_number == 0.0 || episode.sequence_number.fract() != 0.0)
@@ -285,7 +523,8 @@ impl Filter {
 
         let mut relative_episode_number = None;
         let mut relative_sequence_number = None;
-        if self.relative_episode_number {
+        let mut episode_number_width = None;
+        if self.relative_episode_number || self.auto_padding {
             let season_eps = match self.season_episodes.get(&episode.season_id) {
                 Some(eps) => eps,
                 None => {
@@ -296,6 +535,19 @@ impl Filter {
                     self.season_episodes.get(&episode.season_id).unwrap()
                 }
             };
+            if self.auto_padding {
+                // the relative numbers never exceed the episode count of the season
+                let max_episode_number = season_eps
+                    .iter()
+                    .map(|e| e.sequence_number as u64)
+                    .max()
+                    .unwrap_or_default()
+                    .max(season_eps.len() as u64);
+                episode_number_width = Some(auto_padding_width(max_episode_number))
+            }
+        }
+        if self.relative_episode_number {
+            let season_eps = self.season_episodes.get(&episode.season_id).unwrap();
             let mut non_integer_sequence_number_count = 0;
             for (i, ep) in season_eps.iter().enumerate() {
                 if ep.sequence_number != 0.0 || ep.sequence_number.fract() == 0.0 {
@@ -321,15 +573,48 @@ impl Filter {
             }
         }
 
+        // the season title isn't always populated, but it's required for the `{season_name}` output
+        // placeholder
+        for e in episodes.iter_mut() {
+            if !e.season_title.is_empty() {
+                continue;
+            }
+            if !self.season_titles.contains_key(&e.season_id) {
+                let season = e.season().await?;
+                self.season_titles
+                    .insert(e.season_id.clone(), season.title.clone());
+            }
+            e.season_title
+                .clone_from(self.season_titles.get(&e.season_id).unwrap())
+        }
+
+        let series_description = if self.fetch_series_description {
+            if !self.series_descriptions.contains_key(&episode.series_id) {
+                let series = episode.series().await?;
+                self.series_descriptions
+                    .insert(episode.series_id.clone(), series.description);
+            }
+            self.series_descriptions.get(&episode.series_id).cloned()
+        } else {
+            None
+        };
+
         Ok(episodes
             .into_iter()
             .map(|e| {
-                SingleFormat::new_from_episode(
+                let mut single_format = SingleFormat::new_from_episode(
                     e.clone(),
                     e.subtitle_locales,
                     relative_episode_number.map(|n| n as u32),
                     relative_sequence_number,
-                )
+                );
+                single_format.episode_number_width = episode_number_width;
+                if let Some(series_description) = &series_description {
+                    single_format
+                        .series_description
+                        .clone_from(series_description)
+                }
+                single_format
             })
             .collect())
     }
@@ -339,6 +624,17 @@ impl Filter {
     }
 
     async fn visit_movie(&mut self, movie: Movie) -> Result<Vec<SingleFormat>> {
+        if self.watch_filter.is_some()
+            && !self
+                .matches_watch_status(vec![movie.id.clone()], movie.duration)
+                .await?
+        {
+            debug!(
+                "Skipping movie {} as its watch progress doesn't match",
+                movie.title
+            );
+            return Ok(vec![]);
+        }
         Ok(vec![SingleFormat::new_from_movie(movie, vec![])])
     }
 
@@ -351,6 +647,29 @@ impl Filter {
     }
 
     async fn finish(self, input: Vec<Vec<SingleFormat>>) -> Result<SingleFormatCollection> {
+        if !self.title_patterns.is_empty() {
+            let (matched, skipped) = self.title_matches;
+            info!(
+                "{} of {} episode(s) matched the title filter",
+                matched,
+                matched + skipped
+            )
+        }
+
+        if let Some(watch_filter) = &self.watch_filter {
+            let (matched, skipped) = self.watch_matches;
+            info!(
+                "{} of {} episode(s) are {}",
+                matched,
+                matched + skipped,
+                if watch_filter.watched {
+                    "watched"
+                } else {
+                    "unwatched"
+                }
+            )
+        }
+
         let flatten_input: Vec<SingleFormat> = input.into_iter().flatten().collect();
 
         let mut single_format_collection = SingleFormatCollection::new();
@@ -446,6 +765,7 @@ impl Filter {
 
             items = new_items
         }
+        result.push(std::mem::take(&mut self.extras));
 
         self.finish(result).await
     }
@@ -455,6 +775,66 @@ fn missing_locales<'a>(available: &[Locale], searched: &'a [Locale]) -> Vec<&'a
     searched.iter().filter(|p| !available.contains(p)).collect()
 }
 
+/// If `title` matches all `patterns`.
+fn title_matches(patterns: &[Regex], title: &str) -> bool {
+    patterns.iter().all(|p| p.is_match(title))
+}
+
+/// Removes any episode that does not have the audio locale of `season`. Yes, this is the case
+/// sometimes. Seasons with multiple audio locales are kept as they are.
+fn retain_season_audio(season: &Season, episodes: &mut Vec<Episode>) {
+    if season.audio_locales.len() < 2 {
+        let season_locale = season
+            .audio_locales
+            .first()
+            .cloned()
+            .unwrap_or(Locale::ja_JP);
+        episodes.retain(|e| e.audio_locale == season_locale)
+    }
+}
+
+/// Collects all episodes of `media_collection` which are selected by `url_filter`. Unlike
+/// [`Filter`], episodes aren't selected by audio, subtitles or premium availability. Every episode
+/// is included once, audio versions of seasons are skipped.
+pub(crate) async fn url_filtered_episodes(
+    media_collection: MediaCollection,
+    url_filter: &UrlFilter,
+    url_number: usize,
+) -> Result<Vec<Episode>> {
+    let seasons = match media_collection {
+        MediaCollection::Series(series) => series.seasons().await?,
+        MediaCollection::Season(season) => vec![season],
+        MediaCollection::Episode(episode) => return Ok(vec![episode]),
+        _ => bail!(
+            "Url {} is not a series, season or episode url, only those are supported",
+            url_number
+        ),
+    };
+
+    let mut known_seasons = HashSet::new();
+    let mut episodes = vec![];
+    for season in seasons {
+        if known_seasons.contains(&season.id) || !url_filter.is_season_valid(season.season_number) {
+            continue;
+        }
+        known_seasons.extend(season.versions.iter().map(|v| v.id.clone()));
+
+        let season_episodes = season.episodes().await?;
+        episodes.extend(select_season_episodes(&season, season_episodes, url_filter))
+    }
+    Ok(episodes)
+}
+
+fn select_season_episodes(
+    season: &Season,
+    mut episodes: Vec<Episode>,
+    url_filter: &UrlFilter,
+) -> Vec<Episode> {
+    retain_season_audio(season, &mut episodes);
+    episodes.retain(|e| url_filter.is_episode_valid(e.sequence_number, e.season_number));
+    episodes
+}
+
 /// Remove all duplicates from a [`Vec`].
 pub fn real_dedup_vec<T: Clone + Eq>(input: &mut Vec<T>) {
     let mut dedup = vec![];
@@ -465,3 +845,131 @@ pub fn real_dedup_vec<T: Clone + Eq>(input: &mut Vec<T>) {
     }
     *input = dedup
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::parse::split_url_filter;
+
+    fn episode(id: &str, sequence_number: f32, audio_locale: Locale) -> Episode {
+        let mut episode = Episode::default();
+        episode.id = id.to_string();
+        episode.season_number = 1;
+        episode.sequence_number = sequence_number;
+        episode.audio_locale = audio_locale;
+        episode
+    }
+
+    fn season(audio_locales: Vec<Locale>) -> Season {
+        let mut season = Season::default();
+        season.season_number = 1;
+        season.audio_locales = audio_locales;
+        season
+    }
+
+    fn url_filter(filter: &str) -> UrlFilter {
+        split_url_filter(
+            format!("https://www.crunchyroll.com/series/GY8VEQ95Y/darling-in-the-franxx{filter}"),
+            true,
+        )
+        .unwrap()
+        .1
+    }
+
+    fn ids(episodes: &[Episode]) -> Vec<&str> {
+        episodes.iter().map(|e| e.id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_select_season_episodes() {
+        let season = season(vec![Locale::ja_JP]);
+        let episodes = vec![
+            episode("E1", 1.0, Locale::ja_JP),
+            episode("E2", 2.0, Locale::ja_JP),
+            // listed in the japanese season, but belongs to the english version of it
+            episode("E2-dub", 2.0, Locale::en_US),
+            episode("E3", 3.0, Locale::ja_JP),
+            episode("E4", 4.0, Locale::ja_JP),
+        ];
+
+        assert_eq!(
+            ids(&select_season_episodes(
+                &season,
+                episodes.clone(),
+                &url_filter("")
+            )),
+            ["E1", "E2", "E3", "E4"]
+        );
+        assert_eq!(
+            ids(&select_season_episodes(
+                &season,
+                episodes.clone(),
+                &url_filter("[E2-E3]")
+            )),
+            ["E2", "E3"]
+        );
+        assert_eq!(
+            ids(&select_season_episodes(
+                &season,
+                episodes.clone(),
+                &url_filter("[S1E4,E1]")
+            )),
+            ["E1", "E4"]
+        );
+        assert!(select_season_episodes(&season, episodes, &url_filter("[S2]")).is_empty())
+    }
+
+    #[test]
+    fn test_select_season_episodes_multiple_audios() {
+        // seasons with multiple audios contain all of their episodes
+        let season = season(vec![Locale::ja_JP, Locale::en_US]);
+        let episodes = vec![
+            episode("E1", 1.0, Locale::ja_JP),
+            episode("E1-dub", 1.0, Locale::en_US),
+        ];
+        assert_eq!(
+            ids(&select_season_episodes(&season, episodes, &url_filter(""))),
+            ["E1", "E1-dub"]
+        )
+    }
+
+    #[test]
+    fn test_title_matches() {
+        use crate::utils::clap::{clap_parse_title_contains, clap_parse_title_regex};
+
+        let titles = [
+            "Alone and Lonesome",
+            "What It Means to Connect",
+            "Fighting Puppets",
+            "The Garden Where It All Began",
+            "Triumphant Return",
+        ];
+        let matching = |patterns: &[Regex]| {
+            titles
+                .iter()
+                .filter(|t| title_matches(patterns, t))
+                .copied()
+                .collect::<Vec<&str>>()
+        };
+
+        assert_eq!(matching(&[]), titles);
+        assert_eq!(
+            matching(&[clap_parse_title_contains("re").unwrap()]),
+            ["The Garden Where It All Began", "Triumphant Return"]
+        );
+        assert_eq!(
+            matching(&[clap_parse_title_regex(r"^(what|the)\b").unwrap()]),
+            ["What It Means to Connect", "The Garden Where It All Began"]
+        );
+        // all patterns must match
+        assert_eq!(
+            matching(&[
+                clap_parse_title_regex(r"^the").unwrap(),
+                clap_parse_title_contains("began").unwrap()
+            ]),
+            ["The Garden Where It All Began"]
+        );
+        // regex characters are matched literally
+        assert!(matching(&[clap_parse_title_contains("a.d").unwrap()]).is_empty());
+    }
+}
@@ -1,17 +1,110 @@
 use crate::utils::filter::real_dedup_vec;
+use crate::utils::interactive_select::select;
 use crate::utils::locale::LanguageTagging;
 use crate::utils::log::tab_info;
 use crate::utils::os::{is_special_file, sanitize};
 use anyhow::{bail, Result};
-use chrono::{Datelike, Duration};
+use chrono::{Datelike, Duration, NaiveDate};
 use crunchyroll_rs::media::{SkipEvents, Stream, StreamData, Subtitle};
-use crunchyroll_rs::{Concert, Episode, Locale, MediaCollection, Movie, MusicVideo};
+use crunchyroll_rs::{Concert, Episode, Locale, MediaCollection, Movie, MusicVideo, Series};
 use log::{debug, info};
+use regex::{Captures, Regex};
 use std::cmp::Ordering;
 use std::collections::BTreeMap;
 use std::env;
 use std::path::{Path, PathBuf};
 
+lazy_static::lazy_static! {
+    static ref SEASON_NUMBER_PLACEHOLDER_REGEX: Regex = Regex::new(r"\{season_number(:(\d+|auto))?}").unwrap();
+    static ref PLACEHOLDER_REGEX: Regex = Regex::new(r"\{[^{}]*}").unwrap();
+    static ref NUMBER_PLACEHOLDER_REGEX: Regex = Regex::new(&format!(
+        r"\{{(?P<name>{}):(?P<width>\d+|auto)}}",
+        Format::PLACEHOLDERS
+            .iter()
+            .filter(|(_, kind)| matches!(kind, PlaceholderKind::Number(_)))
+            .map(|(name, _)| *name)
+            .collect::<Vec<&str>>()
+            .join("|")
+    )).unwrap();
+}
+
+/// How the value of a placeholder is inserted by [`Format::format_path`].
+#[derive(Clone, Copy, Debug)]
+pub enum PlaceholderKind {
+    Text,
+    /// A number which supports an explicit width (e.g. `{episode_number:03}`) and auto padding
+    /// (`{episode_number:auto}`). Without them, it's padded with zeros to the given width.
+    Number(usize),
+}
+
+/// Directory structure which is applied to the output files, independent of the directories of
+/// the output template.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum GroupBy {
+    /// `{series_name}/<file>`.
+    Series,
+    /// `{series_name}/Season {season_number:02}/<file>`.
+    Season,
+    /// All files are stored in the same directory.
+    Flat,
+}
+
+impl GroupBy {
+    pub fn parse(s: &str) -> Result<GroupBy, String> {
+        Ok(match s.to_lowercase().as_str() {
+            "series" => GroupBy::Series,
+            "season" => GroupBy::Season,
+            "flat" => GroupBy::Flat,
+            _ => return Err(format!("'{}' is not a valid grouping", s)),
+        })
+    }
+}
+
+/// Output template of the `plex` naming scheme, without file extension.
+pub const PLEX_TEMPLATE: &str =
+    "{series_name}/Season {season_number:02}/{series_name} - S{season_number:02}E{episode_number:02} - {title}";
+/// Output template of the `jellyfin` naming scheme, without file extension.
+pub const JELLYFIN_TEMPLATE: &str =
+    "{series_name}/Season {season_number:02}/{series_name} S{season_number:02}E{episode_number:02} - {title}";
+/// Output template of the `kodi` naming scheme, without file extension.
+pub const KODI_TEMPLATE: &str =
+    "{series_name}/Season {season_number:02}/{series_name} - {season_number}x{episode_number:02} - {title}";
+/// Output template of the `anime` naming scheme, without file extension. Anime are usually
+/// numbered by episode only, so no season directory is used.
+pub const ANIME_TEMPLATE: &str = "{series_name}/{series_name} - {episode_number:02} - {title}";
+
+/// Well-known output templates which are used instead of an output template given via `-o`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum NamingScheme {
+    Plex,
+    Jellyfin,
+    Kodi,
+    Anime,
+}
+
+impl NamingScheme {
+    pub fn parse(s: &str) -> Result<NamingScheme, String> {
+        Ok(match s.to_lowercase().as_str() {
+            "plex" => NamingScheme::Plex,
+            "jellyfin" => NamingScheme::Jellyfin,
+            "kodi" => NamingScheme::Kodi,
+            "anime" => NamingScheme::Anime,
+            _ => return Err(format!("'{}' is not a valid naming scheme", s)),
+        })
+    }
+
+    /// The output template of the naming scheme with `extension` as file extension.
+    pub fn template(&self, extension: &str) -> String {
+        let template = match self {
+            NamingScheme::Plex => PLEX_TEMPLATE,
+            NamingScheme::Jellyfin => JELLYFIN_TEMPLATE,
+            NamingScheme::Kodi => KODI_TEMPLATE,
+            NamingScheme::Anime => ANIME_TEMPLATE,
+        };
+        format!("{}.{}", template, extension)
+    }
+}
+
 #[allow(dead_code)]
 #[derive(Clone)]
 pub struct SingleFormat {
@@ -29,6 +122,7 @@ pub struct SingleFormat {
 
     pub series_id: String,
     pub series_name: String,
+    pub series_description: String,
 
     pub season_id: String,
     pub season_title: String,
@@ -39,9 +133,20 @@ pub struct SingleFormat {
     pub relative_episode_number: Option<u32>,
     pub sequence_number: f32,
     pub relative_sequence_number: Option<f32>,
+    /// The width `{<number>:auto}` placeholders are padded to, based on the largest episode number
+    /// of the season. Only set if the output template uses auto padding.
+    pub episode_number_width: Option<usize>,
+
+    /// Artist and track name, only set for music videos and concerts.
+    pub artist: String,
+    pub track: String,
 
     pub duration: Duration,
 
+    /// If the video is an extra of a series (e.g. an opening or PV) and not part of the series
+    /// itself.
+    pub extra: bool,
+
     source: MediaCollection,
 }
 
@@ -74,6 +179,8 @@ impl SingleFormat {
             subtitles,
             series_id: episode.series_id.clone(),
             series_name: episode.series_title.clone(),
+            // episodes don't contain the series description, it must be set afterwards
+            series_description: String::new(),
             season_id: episode.season_id.clone(),
             season_title: episode.season_title.to_string(),
             season_number: episode.season_number,
@@ -86,7 +193,11 @@ impl SingleFormat {
             sequence_number: episode.sequence_number,
             relative_episode_number,
             relative_sequence_number,
+            episode_number_width: None,
+            artist: String::new(),
+            track: String::new(),
             duration: episode.duration,
+            extra: false,
             source: episode.into(),
         }
     }
@@ -103,6 +214,7 @@ impl SingleFormat {
             subtitles,
             series_id: movie.movie_listing_id.clone(),
             series_name: movie.movie_listing_title.clone(),
+            series_description: movie.description.clone(),
             season_id: movie.movie_listing_id.clone(),
             season_title: movie.movie_listing_title.to_string(),
             season_number: 1,
@@ -111,7 +223,11 @@ impl SingleFormat {
             relative_episode_number: Some(1),
             sequence_number: 1.0,
             relative_sequence_number: Some(1.0),
+            episode_number_width: None,
+            artist: String::new(),
+            track: String::new(),
             duration: movie.duration,
+            extra: false,
             source: movie.into(),
         }
     }
@@ -128,6 +244,7 @@ impl SingleFormat {
             subtitles: vec![],
             series_id: music_video.id.clone(),
             series_name: music_video.title.clone(),
+            series_description: music_video.description.clone(),
             season_id: music_video.id.clone(),
             season_title: music_video.title.clone(),
             season_number: 1,
@@ -136,11 +253,27 @@ impl SingleFormat {
             relative_episode_number: Some(1),
             sequence_number: 1.0,
             relative_sequence_number: Some(1.0),
+            episode_number_width: None,
+            artist: music_video.display_artist_name.clone(),
+            track: music_video.title.clone(),
             duration: music_video.duration,
+            extra: false,
             source: music_video.into(),
         }
     }
 
+    /// Creates an extra of `series`. Other than a standalone music video, the series information
+    /// are taken from the series the extra belongs to.
+    pub fn new_from_extra(music_video: MusicVideo, series: &Series) -> Self {
+        Self {
+            series_id: series.id.clone(),
+            series_name: series.title.clone(),
+            series_description: series.description.clone(),
+            extra: true,
+            ..Self::new_from_music_video(music_video)
+        }
+    }
+
     pub fn new_from_concert(concert: Concert) -> Self {
         Self {
             identifier: concert.id.clone(),
@@ -153,6 +286,7 @@ impl SingleFormat {
             subtitles: vec![],
             series_id: concert.id.clone(),
             series_name: concert.title.clone(),
+            series_description: concert.description.clone(),
             season_id: concert.id.clone(),
             season_title: concert.title.clone(),
             season_number: 1,
@@ -161,7 +295,11 @@ impl SingleFormat {
             relative_episode_number: Some(1),
             sequence_number: 1.0,
             relative_sequence_number: Some(1.0),
+            episode_number_width: None,
+            artist: concert.display_artist_name.clone(),
+            track: concert.title.clone(),
             duration: concert.duration,
+            extra: false,
             source: concert.into(),
         }
     }
@@ -191,6 +329,13 @@ impl SingleFormat {
         }
     }
 
+    pub async fn series(&self) -> Result<Option<Series>> {
+        match &self.source {
+            MediaCollection::Episode(e) => Ok(Some(e.series().await?)),
+            _ => Ok(None),
+        }
+    }
+
     pub fn source_type(&self) -> String {
         match &self.source {
             MediaCollection::Episode(_) => "episode",
@@ -202,10 +347,33 @@ impl SingleFormat {
         .to_string()
     }
 
+    /// The url of the video on Crunchyroll.
+    pub fn url(&self) -> String {
+        match &self.source {
+            MediaCollection::MusicVideo(_) => format!(
+                "https://www.crunchyroll.com/watch/musicvideo/{}",
+                self.episode_id
+            ),
+            MediaCollection::Concert(_) => format!(
+                "https://www.crunchyroll.com/watch/concert/{}",
+                self.episode_id
+            ),
+            // episodes and movies
+            _ => format!("https://www.crunchyroll.com/watch/{}", self.episode_id),
+        }
+    }
+
     pub fn is_episode(&self) -> bool {
         matches!(self.source, MediaCollection::Episode(_))
     }
 
+    pub fn is_music(&self) -> bool {
+        matches!(
+            self.source,
+            MediaCollection::MusicVideo(_) | MediaCollection::Concert(_)
+        )
+    }
+
     pub fn is_special(&self) -> bool {
         self.sequence_number == 0.0 || self.sequence_number.fract() != 0.0
     }
@@ -278,6 +446,23 @@ impl SingleFormatCollection {
         self.0.is_empty()
     }
 
+    pub fn len(&self) -> usize {
+        self.0.values().map(|episodes| episodes.len()).sum()
+    }
+
+    /// Ids of all seasons the contained formats belong to. Every audio version of a season has its
+    /// own id.
+    pub fn season_ids(&self) -> Vec<String> {
+        let mut season_ids: Vec<String> = self
+            .0
+            .values()
+            .flat_map(|episodes| episodes.values().flatten())
+            .map(|format| format.season_id.clone())
+            .collect();
+        real_dedup_vec(&mut season_ids);
+        season_ids
+    }
+
     pub fn add_single_formats(&mut self, single_formats: Vec<SingleFormat>) {
         let format = single_formats.first().unwrap();
         self.0
@@ -292,6 +477,62 @@ impl SingleFormatCollection {
             );
     }
 
+    /// Lets the user choose which seasons and episodes should be kept. The seasons are only asked
+    /// for if there are multiple, episodes only for seasons with more than one episode.
+    pub fn interactive_select(&mut self) {
+        if self.0.len() > 1 {
+            let seasons = self
+                .0
+                .iter()
+                .map(|(season_key, episodes)| {
+                    let first_episode = episodes.first_key_value().unwrap().1.first().unwrap();
+                    format!(
+                        "Season {} ({}, {} episodes)",
+                        season_key.0 .0,
+                        first_episode.season_title,
+                        episodes.len()
+                    )
+                })
+                .collect();
+            let keep = select(
+                "Select the seasons you want to download (space to select/deselect; enter to continue)",
+                seasons,
+            );
+            let mut i = 0;
+            self.0.retain(|_, _| {
+                i += 1;
+                keep.contains(&(i - 1))
+            });
+        }
+
+        for (season_key, episodes) in self.0.iter_mut() {
+            if episodes.len() < 2 {
+                continue;
+            }
+            let items = episodes
+                .values()
+                .map(|formats| {
+                    let format = formats.first().unwrap();
+                    format!(
+                        "S{:02}E{:0>2} » {}",
+                        format.season_number, format.episode_number, format.title
+                    )
+                })
+                .collect();
+            let keep = select(
+                &format!("Select the episodes of season {} you want to download (space to select/deselect; enter to continue)", season_key.0 .0),
+                items,
+            );
+            let mut i = 0;
+            episodes.retain(|_, _| {
+                i += 1;
+                keep.contains(&(i - 1))
+            });
+        }
+
+        self.0.retain(|_, episodes| !episodes.is_empty())
+    }
+
     pub fn full_visual_output(&self) {
         debug!("Series has {} seasons", self.0.len());
         for (season_key, episodes) in &self.0 {
@@ -304,7 +545,7 @@ impl SingleFormatCollection {
             );
             for (i, (_, formats)) in episodes.iter().enumerate() {
                 let format = formats.first().unwrap();
-                if log::max_level() == log::Level::Debug {
+                if log::max_level() >= log::Level::Debug {
                     info!(
                         "{} S{:02}E{:0>2}",
                         format.title, format.season_number, format.episode_number
@@ -366,6 +607,7 @@ pub struct Format {
 
     pub series_id: String,
     pub series_name: String,
+    pub series_description: String,
 
     pub season_id: String,
     pub season_title: String,
@@ -376,9 +618,45 @@ pub struct Format {
     pub relative_episode_number: Option<u32>,
     pub sequence_number: f32,
     pub relative_sequence_number: Option<f32>,
+    pub episode_number_width: Option<usize>,
+
+    pub artist: String,
+    pub track: String,
+
+    pub source_type: String,
+    pub extra: bool,
 }
 
 impl Format {
+    /// All placeholders which are supported by [`Format::format_path`]. This is the only list of
+    /// them, their values are resolved by [`Format::placeholder_value`].
+    pub const PLACEHOLDERS: [(&'static str, PlaceholderKind); 24] = [
+        ("title", PlaceholderKind::Text),
+        ("episode_description", PlaceholderKind::Text),
+        ("series_name", PlaceholderKind::Text),
+        ("series_description", PlaceholderKind::Text),
+        ("season_name", PlaceholderKind::Text),
+        ("audio", PlaceholderKind::Text),
+        ("audio_locale", PlaceholderKind::Text),
+        ("subtitle", PlaceholderKind::Text),
+        ("width", PlaceholderKind::Text),
+        ("height", PlaceholderKind::Text),
+        ("season_number", PlaceholderKind::Number(2)),
+        ("episode_number", PlaceholderKind::Number(2)),
+        ("relative_episode_number", PlaceholderKind::Number(2)),
+        ("sequence_number", PlaceholderKind::Number(2)),
+        ("relative_sequence_number", PlaceholderKind::Number(2)),
+        ("release_year", PlaceholderKind::Number(0)),
+        ("release_month", PlaceholderKind::Number(2)),
+        ("release_day", PlaceholderKind::Number(2)),
+        ("series_id", PlaceholderKind::Text),
+        ("season_id", PlaceholderKind::Text),
+        ("episode_id", PlaceholderKind::Text),
+        ("artist", PlaceholderKind::Text),
+        ("track", PlaceholderKind::Text),
+        ("type", PlaceholderKind::Text),
+    ];
+
     #[allow(clippy::type_complexity)]
     pub fn from_single_formats(
         mut single_formats: Vec<(SingleFormat, StreamData, Vec<(Subtitle, bool)>)>,
@@ -396,6 +674,7 @@ impl Format {
             })
             .collect();
         let (first_format, first_stream, _) = single_formats.remove(0);
+        let source_type = first_format.source_type();
 
         Self {
             title: first_format.title,
@@ -409,6 +688,9 @@ impl Format {
             release_day: first_format.release_day,
             series_id: first_format.series_id,
             series_name: first_format.series_name,
+            series_description: first_format.series_description,
+            artist: first_format.artist,
+            track: first_format.track,
             season_id: first_format.season_id,
             season_title: first_format.season_title,
             season_number: first_format.season_number,
@@ -417,6 +699,9 @@ impl Format {
             relative_episode_number: first_format.relative_episode_number,
             sequence_number: first_format.sequence_number,
             relative_sequence_number: first_format.relative_sequence_number,
+            episode_number_width: first_format.episode_number_width,
+            source_type,
+            extra: first_format.extra,
         }
     }
 
@@ -427,104 +712,50 @@ impl Format {
         universal: bool,
         language_tagging: Option<&LanguageTagging>,
     ) -> PathBuf {
-        let path = path
-            .to_string_lossy()
-            .to_string()
-            .replace("{title}", &sanitize(&self.title, true, universal))
-            .replace(
-                "{audio}",
-                &sanitize(
-                    self.locales
-                        .iter()
-                        .map(|(a, _)| language_tagging.map_or(a.to_string(), |t| t.for_locale(a)))
-                        .collect::<Vec<String>>()
-                        .join(
-                            &env::var("CRUNCHY_CLI_FORMAT_DELIMITER")
-                                .map_or("_".to_string(), |e| e),
-                        ),
-                    true,
-                    universal,
-                ),
-            )
-            .replace(
-                "{width}",
-                &sanitize(self.width.to_string(), true, universal),
-            )
-            .replace(
-                "{height}",
-                &sanitize(self.height.to_string(), true, universal),
-            )
-            .replace("{series_id}", &sanitize(&self.series_id, true, universal))
-            .replace(
-                "{series_name}",
-                &sanitize(&self.series_name, true, universal),
-            )
-            .replace("{season_id}", &sanitize(&self.season_id, true, universal))
-            .replace(
-                "{season_name}",
-                &sanitize(&self.season_title, true, universal),
-            )
-            .replace(
-                "{season_number}",
-                &format!(
-                    "{:0>2}",
-                    sanitize(self.season_number.to_string(), true, universal)
-                ),
-            )
-            .replace("{episode_id}", &sanitize(&self.episode_id, true, universal))
-            .replace(
-                "{episode_number}",
-                &format!("{:0>2}", sanitize(&self.episode_number, true, universal)),
-            )
-            .replace(
-                "{relative_episode_number}",
-                &format!(
-                    "{:0>2}",
-                    sanitize(
-                        self.relative_episode_number.unwrap_or_default().to_string(),
-                        true,
-                        universal,
-                    )
-                ),
-            )
-            .replace(
-                "{sequence_number}",
-                &format!(
-                    "{:0>2}",
-                    sanitize(self.sequence_number.to_string(), true, universal)
-                ),
-            )
-            .replace(
-                "{relative_sequence_number}",
-                &format!(
-                    "{:0>2}",
-                    sanitize(
-                        self.relative_sequence_number
-                            .unwrap_or_default()
-                            .to_string(),
-                        true,
-                        universal,
-                    )
-                ),
-            )
-            .replace(
-                "{release_year}",
-                &sanitize(self.release_year.to_string(), true, universal),
-            )
-            .replace(
-                "{release_month}",
-                &format!(
-                    "{:0>2}",
-                    sanitize(self.release_month.to_string(), true, universal)
-                ),
-            )
-            .replace(
-                "{release_day}",
-                &format!(
-                    "{:0>2}",
-                    sanitize(self.release_day.to_string(), true, universal)
-                ),
+        let delimiter = env::var("CRUNCHY_CLI_FORMAT_DELIMITER").map_or("_".to_string(), |e| e);
+        let audio = self
+            .locales
+            .iter()
+            .map(|(a, _)| language_tagging.map_or(a.to_string(), |t| t.for_locale(a)))
+            .collect::<Vec<String>>()
+            .join(&delimiter);
+        let mut subtitle_locales: Vec<&Locale> = self.locales.iter().flat_map(|(_, s)| s).collect();
+        real_dedup_vec(&mut subtitle_locales);
+        let subtitle = subtitle_locales
+            .into_iter()
+            .map(|s| language_tagging.map_or(s.to_string(), |t| t.for_locale(s)))
+            .collect::<Vec<String>>()
+            .join(&delimiter);
+
+        // placeholders with an explicit width (e.g. `{episode_number:03}`) or auto padding (e.g.
+        // `{episode_number:auto}`) are resolved first, the plain ones below keep their default
+        // padding
+        let path = NUMBER_PLACEHOLDER_REGEX
+            .replace_all(&path.to_string_lossy(), |caps: &Captures| {
+                let value = self.placeholder_value(&caps["name"], &audio, &subtitle);
+                let width = match &caps["width"] {
+                    "auto" => self.episode_number_width.unwrap_or(2),
+                    width => width.parse().unwrap_or_default(),
+                };
+                pad_number(&sanitize(value, true, universal), width)
+            })
+            .to_string();
+        let path = Self::PLACEHOLDERS.iter().fold(path, |path, (name, kind)| {
+            let placeholder = format!("{{{}}}", name);
+            if !path.contains(&placeholder) {
+                return path;
+            }
+            let value = sanitize(
+                self.placeholder_value(name, &audio, &subtitle),
+                true,
+                universal,
             );
+            let value = match *kind {
+                PlaceholderKind::Text => value,
+                PlaceholderKind::Number(width) => format!("{:0>width$}", value),
+            };
+            path.replace(&placeholder, &value)
+        });
 
         let mut path = PathBuf::from(path);
 
@@ -541,20 +772,55 @@ impl Format {
                 .to_string_lossy()
                 .to_string();
             if ext != name {
-                path.set_file_name(format!("{}.{}", &name[..(255 - ext.len() - 1)], ext))
+                path.set_file_name(format!(
+                    "{}.{}",
+                    truncate_str(&name, 255 - ext.len() - 1),
+                    ext
+                ))
             }
         }
         path.iter()
-            .map(|s| {
-                if s.len() > 255 {
-                    s.to_string_lossy()[..255].to_string()
-                } else {
-                    s.to_string_lossy().to_string()
-                }
-            })
+            .map(|s| truncate_str(&s.to_string_lossy(), 255).to_string())
             .collect()
     }
 
+    /// The unpadded and unsanitized value of the placeholder `name`, which must be one of
+    /// [`Format::PLACEHOLDERS`]. `audio` and `subtitle` are the (language tagged) locales, joined
+    /// by the delimiter.
+    fn placeholder_value(&self, name: &str, audio: &str, subtitle: &str) -> String {
+        match name {
+            "title" => self.title.clone(),
+            "episode_description" => self.description.clone(),
+            "series_name" => self.series_name.clone(),
+            "series_description" => self.series_description.clone(),
+            "season_name" => self.season_title.clone(),
+            "audio" | "audio_locale" => audio.to_string(),
+            "subtitle" => subtitle.to_string(),
+            "width" => self.width.to_string(),
+            "height" => self.height.to_string(),
+            "season_number" => self.season_number.to_string(),
+            "episode_number" => self.episode_number.clone(),
+            "relative_episode_number" => {
+                self.relative_episode_number.unwrap_or_default().to_string()
+            }
+            "sequence_number" => self.sequence_number.to_string(),
+            "relative_sequence_number" => self
+                .relative_sequence_number
+                .unwrap_or_default()
+                .to_string(),
+            "release_year" => self.release_year.to_string(),
+            "release_month" => self.release_month.to_string(),
+            "release_day" => self.release_day.to_string(),
+            "series_id" => self.series_id.clone(),
+            "season_id" => self.season_id.clone(),
+            "episode_id" => self.episode_id.clone(),
+            "artist" => self.artist.clone(),
+            "track" => self.track.clone(),
+            "type" => self.source_type.clone(),
+            _ => unreachable!("unknown placeholder {}", name),
+        }
+    }
+
     pub fn visual_output(&self, dst: &Path) {
         info!(
             "Downloading {} to {}",
@@ -596,8 +862,448 @@ impl Format {
         self.sequence_number == 0.0 || self.sequence_number.fract() != 0.0
     }
 
+    /// The release date, or [`None`] if it's unknown. Crunchyroll uses dates around the unix epoch
+    /// (or even before it) as placeholder if a video has no release date.
+    pub fn release_date(&self) -> Option<NaiveDate> {
+        if self.release_year <= 1970 {
+            return None;
+        }
+        NaiveDate::from_ymd_opt(
+            self.release_year as i32,
+            self.release_month as u32,
+            self.release_day as u32,
+        )
+    }
+
+    /// Global tags which can be embedded into the output container. Values are passed to ffmpeg
+    /// as separate arguments and need no quoting, only nul characters (which can't be part of a
+    /// process argument) are removed. Empty values are skipped.
+    pub fn container_tags(&self) -> Vec<(String, String)> {
+        let tags = [
+            ("title", self.title.clone()),
+            ("show", self.series_name.clone()),
+            // only episodes have a season, movies, music videos and concerts are always in
+            // season 1 as the output templates need a season number
+            (
+                "season_number",
+                (self.source_type == "episode")
+                    .then(|| self.season_number.to_string())
+                    .unwrap_or_default(),
+            ),
+            ("episode_id", self.episode_number.clone()),
+            (
+                "date",
+                self.release_date()
+                    .map(|d| d.format("%Y-%m-%d").to_string())
+                    .unwrap_or_default(),
+            ),
+            ("description", self.description.clone()),
+        ];
+        tags.into_iter()
+            .map(|(key, value)| (key.to_string(), value.replace('\0', "").trim().to_string()))
+            .filter(|(_, value)| !value.is_empty())
+            .collect()
+    }
+
+    /// The default output template for extras if `template` is the output template of the
+    /// episodes. As used by Kodi, extras are stored in an `extras` directory in the series
+    /// directory. If the directory of the episodes is named after the season, its parent is assumed
+    /// to be the series directory.
+    pub fn extras_template<S: AsRef<str>>(template: S) -> String {
+        let path = Path::new(template.as_ref());
+        let mut series_dir = path.parent().unwrap_or(Path::new(""));
+        if series_dir
+            .file_name()
+            .is_some_and(|n| n.to_string_lossy().contains("{season"))
+        {
+            series_dir = series_dir.parent().unwrap_or(Path::new(""))
+        }
+        series_dir
+            .join("extras")
+            .join(format!(
+                "{{title}} [{{type}}].{}",
+                path.extension().unwrap_or_default().to_string_lossy()
+            ))
+            .to_string_lossy()
+            .to_string()
+    }
+
+    /// Replaces `{season_number}` in the directory components of the output template with
+    /// `{season_name}`. The file name is not touched.
+    pub fn season_folder_from_title<S: AsRef<str>>(s: S) -> String {
+        let path = Path::new(s.as_ref());
+        let Some(file_name) = path.file_name() else {
+            return s.as_ref().to_string();
+        };
+        let parent = SEASON_NUMBER_PLACEHOLDER_REGEX
+            .replace_all(
+                &path.parent().unwrap_or(Path::new("")).to_string_lossy(),
+                "{season_name}",
+            )
+            .to_string();
+        Path::new(&parent)
+            .join(file_name)
+            .to_string_lossy()
+            .to_string()
+    }
+
+    /// Replaces the directories of the output template with the ones of `group_by`. Only the
+    /// leading directories without any placeholder (e.g. `/media/anime` of
+    /// `/media/anime/{series_name}/{title}.mkv`) are kept, the file name is not touched. If
+    /// `season_folder_from_title` is set, season directories are named after the season title.
+    pub fn group_by_template<S: AsRef<str>>(
+        s: S,
+        group_by: &GroupBy,
+        season_folder_from_title: bool,
+    ) -> String {
+        if s.as_ref() == "-" || is_special_file(s.as_ref()) {
+            return s.as_ref().to_string();
+        }
+        let path = Path::new(s.as_ref());
+        let Some(file_name) = path.file_name() else {
+            return s.as_ref().to_string();
+        };
+        let mut dir: PathBuf = path
+            .parent()
+            .unwrap_or(Path::new(""))
+            .components()
+            .take_while(|c| !PLACEHOLDER_REGEX.is_match(&c.as_os_str().to_string_lossy()))
+            .collect();
+        match group_by {
+            GroupBy::Series => dir.push("{series_name}"),
+            GroupBy::Season => {
+                dir.push("{series_name}");
+                dir.push(if season_folder_from_title {
+                    "{season_name}"
+                } else {
+                    "Season {season_number:02}"
+                })
+            }
+            GroupBy::Flat => (),
+        }
+        dir.join(file_name).to_string_lossy().to_string()
+    }
+
+    /// Returns all placeholders of `s` which aren't supported (e.g. typos like `{seasonnumber}`).
+    /// Number placeholders with an explicit width (e.g. `{episode_number:03}`) or auto padding
+    /// (`{episode_number:auto}`) are supported.
+    pub fn unknown_placeholders<S: AsRef<str>>(s: S) -> Vec<String> {
+        PLACEHOLDER_REGEX
+            .find_iter(s.as_ref())
+            .map(|m| m.as_str().to_string())
+            .filter(|p| {
+                !NUMBER_PLACEHOLDER_REGEX.is_match(p)
+                    && !Self::PLACEHOLDERS
+                        .iter()
+                        .any(|(name, _)| *name == p.trim_start_matches('{').trim_end_matches('}'))
+            })
+            .collect()
+    }
+
+    pub fn has_series_description_fmt<S: AsRef<str>>(s: S) -> bool {
+        s.as_ref().contains("{series_description}")
+    }
+
     pub fn has_relative_fmt<S: AsRef<str>>(s: S) -> bool {
-        return s.as_ref().contains("{relative_episode_number}")
-            || s.as_ref().contains("{relative_sequence_number}");
+        return s.as_ref().contains("{relative_episode_number")
+            || s.as_ref().contains("{relative_sequence_number");
+    }
+
+    pub fn has_auto_padding_fmt<S: AsRef<str>>(s: S) -> bool {
+        NUMBER_PLACEHOLDER_REGEX
+            .captures_iter(s.as_ref())
+            .any(|caps| &caps["width"] == "auto")
+    }
+}
+
+/// If `url_count` parsed urls (every item of a crunchylist counts as one url), of which the current
+/// one resolved to `episode_count` episodes, result in a single episode. Options which refer to one
+/// specific episode (like external files) can only be used then.
+pub fn is_single_episode(url_count: usize, episode_count: usize) -> bool {
+    url_count <= 1 && episode_count <= 1
+}
+
+/// The width episode numbers of a season are padded to with auto padding: the digit count of the
+/// largest episode number, but at least 2 (the default padding).
+pub fn auto_padding_width(max_episode_number: u64) -> usize {
+    max_episode_number.to_string().len().max(2)
+}
+
+/// Left pads the integer part of `number` with zeros until it's `width` characters long. Decimal
+/// numbers like `5.5` are padded before the dot, so a width of 3 results in `005.5`.
+fn pad_number(number: &str, width: usize) -> String {
+    let integer_len = number.find('.').unwrap_or(number.len());
+    if integer_len >= width {
+        number.to_string()
+    } else {
+        format!("{}{}", "0".repeat(width - integer_len), number)
+    }
+}
+
+/// Truncates `s` to at most `max_len` bytes without splitting a multibyte character (descriptions
+/// for example easily exceed the maximum file name length and may contain non-ascii characters).
+fn truncate_str(s: &str, max_len: usize) -> &str {
+    if s.len() <= max_len {
+        return s;
+    }
+    let mut end = max_len;
+    while !s.is_char_boundary(end) {
+        end -= 1
+    }
+    &s[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(episode_number: &str, episode_number_width: Option<usize>) -> Format {
+        Format {
+            title: "Alone and Lonesome".to_string(),
+            description: String::new(),
+            locales: vec![(Locale::ja_JP, vec![])],
+            width: 1920,
+            height: 1080,
+            fps: 23.976,
+            release_year: 2018,
+            release_month: 1,
+            release_day: 13,
+            series_id: String::new(),
+            series_name: "DARLING in the FRANXX".to_string(),
+            series_description: String::new(),
+            season_id: String::new(),
+            season_title: String::new(),
+            season_number: 1,
+            episode_id: String::new(),
+            episode_number: episode_number.to_string(),
+            relative_episode_number: Some(1),
+            sequence_number: episode_number.parse().unwrap(),
+            relative_sequence_number: Some(1.0),
+            episode_number_width,
+            artist: String::new(),
+            track: String::new(),
+            source_type: "episode".to_string(),
+            extra: false,
+        }
+    }
+
+    fn format_path(format: &Format, template: &str) -> String {
+        format
+            .format_path(template.into(), false, None)
+            .to_string_lossy()
+            .to_string()
+    }
+
+    #[test]
+    fn test_naming_scheme_template() {
+        let format = format("5", None);
+        for (scheme, expected) in [
+            (
+                "plex",
+                "DARLING in the FRANXX/Season 01/DARLING in the FRANXX - S01E05 - Alone and Lonesome.mkv",
+            ),
+            (
+                "jellyfin",
+                "DARLING in the FRANXX/Season 01/DARLING in the FRANXX S01E05 - Alone and Lonesome.mkv",
+            ),
+            (
+                "kodi",
+                "DARLING in the FRANXX/Season 01/DARLING in the FRANXX - 01x05 - Alone and Lonesome.mkv",
+            ),
+            (
+                "anime",
+                "DARLING in the FRANXX/DARLING in the FRANXX - 05 - Alone and Lonesome.mkv",
+            ),
+        ] {
+            // plain number placeholders like `{season_number}` of kodi are padded to 2 digits too
+            let template = NamingScheme::parse(scheme).unwrap().template("mkv");
+            assert!(Format::unknown_placeholders(&template).is_empty());
+            assert_eq!(format_path(&format, &template), expected, "{}", scheme)
+        }
+        assert_eq!(
+            NamingScheme::Anime.template("mp4"),
+            format!("{}.mp4", ANIME_TEMPLATE)
+        );
+        assert!(NamingScheme::parse("Plex").is_ok());
+        assert!(NamingScheme::parse("emby").is_err());
+    }
+
+    #[test]
+    fn test_is_single_episode() {
+        assert!(is_single_episode(1, 1));
+        // a crunchylist or multiple urls which each resolve to one episode
+        assert!(!is_single_episode(2, 1));
+        assert!(!is_single_episode(1, 2));
+        assert!(!is_single_episode(3, 4));
+    }
+
+    #[test]
+    fn test_pad_number() {
+        assert_eq!(pad_number("5", 2), "05");
+        assert_eq!(pad_number("5", 3), "005");
+        assert_eq!(pad_number("100", 2), "100");
+        assert_eq!(pad_number("1000", 3), "1000");
+        assert_eq!(pad_number("5.5", 3), "005.5");
+        assert_eq!(pad_number("12.5", 2), "12.5");
+        assert_eq!(pad_number("5", 0), "5");
+    }
+
+    #[test]
+    fn test_format_path_width() {
+        let format = format("5", None);
+        assert_eq!(format_path(&format, "E{episode_number}"), "E05");
+        assert_eq!(format_path(&format, "E{episode_number:03}"), "E005");
+        assert_eq!(format_path(&format, "E{episode_number:1}"), "E5");
+        assert_eq!(
+            format_path(&format, "S{season_number:03}E{sequence_number:04}"),
+            "S001E0005"
+        );
+        assert_eq!(
+            format_path(&format("125", None), "E{episode_number:02}"),
+            "E125"
+        );
+        assert_eq!(
+            format_path(&format("5.5", None), "E{episode_number:03}"),
+            "E005.5"
+        );
+    }
+
+    #[test]
+    fn test_format_path_auto_padding() {
+        assert_eq!(auto_padding_width(5), 2);
+        assert_eq!(auto_padding_width(99), 2);
+        assert_eq!(auto_padding_width(100), 3);
+        assert_eq!(auto_padding_width(1100), 4);
+
+        assert_eq!(
+            format_path(&format("5", Some(3)), "E{episode_number:auto}"),
+            "E005"
+        );
+        assert_eq!(
+            format_path(&format("5.5", Some(3)), "E{episode_number:auto}"),
+            "E005.5"
+        );
+        // without a known season size it falls back to the default padding
+        assert_eq!(
+            format_path(&format("5", None), "E{episode_number:auto}"),
+            "E05"
+        );
+
+        assert!(Format::has_auto_padding_fmt("E{episode_number:auto}"));
+        assert!(!Format::has_auto_padding_fmt("E{episode_number:03}"));
+        assert!(Format::unknown_placeholders("{episode_number:auto}").is_empty());
+    }
+
+    #[test]
+    fn test_format_path_all_placeholders() {
+        let format = format("5", None);
+        for (name, kind) in Format::PLACEHOLDERS {
+            let path = format_path(&format, &format!("{{{}}}", name));
+            assert!(!path.contains('{'), "{{{}}} wasn't replaced", name);
+            if let PlaceholderKind::Number(_) = kind {
+                let path = format_path(&format, &format!("{{{}:03}}", name));
+                assert!(!path.contains('{'), "{{{}:03}} wasn't replaced", name);
+            }
+        }
+        assert!(Format::unknown_placeholders(
+            Format::PLACEHOLDERS
+                .map(|(name, _)| format!("{{{}}}", name))
+                .join("")
+        )
+        .is_empty());
+
+        assert_eq!(
+            format_path(
+                &format,
+                "{series_name} {release_year}-{release_month}-{release_day} {type}"
+            ),
+            "DARLING in the FRANXX 2018-01-13 episode"
+        );
+    }
+
+    #[test]
+    fn test_group_by_template() {
+        let template = "/media/anime/{series_name}/{title}.mkv";
+        assert_eq!(
+            Format::group_by_template(template, &GroupBy::Series, false),
+            "/media/anime/{series_name}/{title}.mkv"
+        );
+        assert_eq!(
+            Format::group_by_template(template, &GroupBy::Season, false),
+            "/media/anime/{series_name}/Season {season_number:02}/{title}.mkv"
+        );
+        assert_eq!(
+            Format::group_by_template(template, &GroupBy::Season, true),
+            "/media/anime/{series_name}/{season_name}/{title}.mkv"
+        );
+        assert_eq!(
+            Format::group_by_template(template, &GroupBy::Flat, false),
+            "/media/anime/{title}.mkv"
+        );
+        // directories with placeholders after the first one are dropped as well
+        assert_eq!(
+            Format::group_by_template(
+                "out/{series_name}/static/{title}.mkv",
+                &GroupBy::Flat,
+                false
+            ),
+            "out/{title}.mkv"
+        );
+        assert_eq!(
+            Format::group_by_template("{title}.mkv", &GroupBy::Series, false),
+            "{series_name}/{title}.mkv"
+        );
+        assert_eq!(Format::group_by_template("-", &GroupBy::Season, false), "-");
+
+        assert_eq!(GroupBy::parse("Season"), Ok(GroupBy::Season));
+        assert!(GroupBy::parse("episode").is_err());
+    }
+
+    #[test]
+    fn test_group_by_multiple_series() {
+        let first = format("1", None);
+        let mut second = format("3", None);
+        second.title = "Mission".to_string();
+        second.series_name = "SPY x FAMILY".to_string();
+        second.season_number = 2;
+
+        let paths = |group_by: GroupBy| {
+            let template = Format::group_by_template("anime/{title}.mkv", &group_by, false);
+            [&first, &second].map(|f| format_path(f, &template))
+        };
+        assert_eq!(
+            paths(GroupBy::Series),
+            [
+                "anime/DARLING in the FRANXX/Alone and Lonesome.mkv",
+                "anime/SPY x FAMILY/Mission.mkv"
+            ]
+        );
+        assert_eq!(
+            paths(GroupBy::Season),
+            [
+                "anime/DARLING in the FRANXX/Season 01/Alone and Lonesome.mkv",
+                "anime/SPY x FAMILY/Season 02/Mission.mkv"
+            ]
+        );
+        assert_eq!(
+            paths(GroupBy::Flat),
+            ["anime/Alone and Lonesome.mkv", "anime/Mission.mkv"]
+        );
+    }
+
+    #[test]
+    fn test_container_tags() {
+        let tags = format("5", None).container_tags();
+        assert!(tags.contains(&("season_number".to_string(), "1".to_string())));
+        assert!(tags.contains(&("date".to_string(), "2018-01-13".to_string())));
+        // the description is empty and skipped
+        assert!(!tags.iter().any(|(key, _)| key == "description"));
+
+        let mut movie = format("1", None);
+        movie.source_type = "movie".to_string();
+        movie.release_year = 1970;
+        let tags = movie.container_tags();
+        assert!(!tags.iter().any(|(key, _)| key == "season_number"));
+        assert!(!tags.iter().any(|(key, _)| key == "date"));
     }
 }
@@ -0,0 +1,97 @@
+use crate::utils::format::Format;
+use anyhow::{bail, Result};
+use log::{debug, warn};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// A command which is run after every successful download. The command is executed by the system
+/// shell and gets information about the downloaded file via environment variables.
+#[derive(Clone, Debug)]
+pub struct PostHook {
+    pub command: String,
+    pub timeout: Duration,
+    pub fail_on_error: bool,
+}
+
+impl PostHook {
+    pub async fn run(&self, dst: &Path, format: &Format) -> Result<()> {
+        let mut command = if cfg!(windows) {
+            let mut command = Command::new("cmd");
+            command.args(["/C", &self.command]);
+            command
+        } else {
+            let mut command = Command::new("sh");
+            command.args(["-c", &self.command]);
+            command
+        };
+        command
+            .stdin(Stdio::null())
+            // if the output is written to stdout, the hook output would be mixed into it
+            .stdout(if dst.to_string_lossy() == "-" {
+                Stdio::null()
+            } else {
+                Stdio::inherit()
+            })
+            .stderr(Stdio::inherit())
+            .env("CRUNCHY_OUTPUT", dst)
+            .env("CRUNCHY_TITLE", &format.title)
+            .env("CRUNCHY_SERIES_ID", &format.series_id)
+            .env("CRUNCHY_SERIES_NAME", &format.series_name)
+            .env("CRUNCHY_SERIES_DESCRIPTION", &format.series_description)
+            .env("CRUNCHY_SEASON_ID", &format.season_id)
+            .env("CRUNCHY_SEASON_NAME", &format.season_title)
+            .env("CRUNCHY_SEASON_NUMBER", format.season_number.to_string())
+            .env("CRUNCHY_EPISODE_ID", &format.episode_id)
+            .env("CRUNCHY_EPISODE_NUMBER", &format.episode_number)
+            .env("CRUNCHY_EPISODE_DESCRIPTION", &format.description)
+            .env(
+                "CRUNCHY_AUDIO",
+                format
+                    .locales
+                    .iter()
+                    .map(|(a, _)| a.to_string())
+                    .collect::<Vec<String>>()
+                    .join(","),
+            );
+
+        debug!("Running post hook: {}", self.command);
+        let mut child = command.spawn()?;
+        let start = Instant::now();
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            if start.elapsed() > self.timeout {
+                let _ = child.kill();
+                let _ = child.wait();
+                let msg = format!(
+                    "Post hook was killed as it took longer than {} seconds",
+                    self.timeout.as_secs()
+                );
+                return self.handle_error(msg);
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await
+        };
+
+        if status.success() {
+            debug!("Post hook exited successfully");
+            Ok(())
+        } else {
+            self.handle_error(format!(
+                "Post hook exited with {}",
+                status
+                    .code()
+                    .map_or("no exit code".to_string(), |c| format!("exit code {}", c))
+            ))
+        }
+    }
+
+    fn handle_error(&self, msg: String) -> Result<()> {
+        if self.fail_on_error {
+            bail!("{}", msg)
+        }
+        warn!("{}", msg);
+        Ok(())
+    }
+}
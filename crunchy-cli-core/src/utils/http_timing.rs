@@ -0,0 +1,102 @@
+use crate::utils::dns::DohResolver;
+use log::{trace, LevelFilter};
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::{Method, Url};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The timings of http requests are only logged with trace output (`-vv`), as they create a line
+/// for every request.
+pub fn http_timing_enabled() -> bool {
+    log::max_level() >= LevelFilter::Trace
+}
+
+/// Collects the timings of http requests. The dns resolution is measured by [`TimingResolver`],
+/// the time to first byte and the total time by the request middleware.
+#[derive(Clone, Default)]
+pub struct HttpTimings {
+    /// Dns resolution times by host which weren't assigned to a request yet.
+    dns: Arc<Mutex<HashMap<String, Duration>>>,
+}
+
+impl HttpTimings {
+    fn record_dns(&self, host: &str, duration: Duration) {
+        trace!("Resolved {} in {}ms", host, duration.as_millis());
+        self.dns.lock().unwrap().insert(host.to_string(), duration);
+    }
+
+    /// Logs the timing breakdown of a request. Hosts are only resolved when a new connection is
+    /// opened, so the dns time is only shown for requests which opened one. The http client
+    /// doesn't expose the time of the tcp connect and tls handshake, for new connections they're
+    /// part of the time to first byte.
+    pub fn log_request(&self, method: &Method, url: &Url, ttfb: Duration, total: Duration) {
+        let dns = url
+            .host_str()
+            .and_then(|host| self.dns.lock().unwrap().remove(host));
+        trace!(
+            "{} {}: dns {}, ttfb {}ms, total {}ms",
+            method,
+            url,
+            dns.map_or("-".to_string(), |d| format!("{}ms", d.as_millis())),
+            ttfb.as_millis(),
+            total.as_millis()
+        )
+    }
+
+    /// Logs the timing of a request which failed with `error` after `total`, e.g. because the
+    /// connection couldn't be established or the response body couldn't be read.
+    pub fn log_failed_request(&self, method: &Method, url: &Url, total: Duration, error: &str) {
+        let dns = url
+            .host_str()
+            .and_then(|host| self.dns.lock().unwrap().remove(host));
+        trace!(
+            "{} {}: dns {}, failed after {}ms: {}",
+            method,
+            url,
+            dns.map_or("-".to_string(), |d| format!("{}ms", d.as_millis())),
+            total.as_millis(),
+            error
+        )
+    }
+}
+
+/// Measures how long the dns resolution of new connections takes. Hosts are resolved by the
+/// DNS-over-HTTPS resolver if one is used, otherwise by the system resolver.
+pub struct TimingResolver {
+    doh_resolver: Option<DohResolver>,
+    timings: HttpTimings,
+}
+
+impl TimingResolver {
+    pub fn new(doh_resolver: Option<DohResolver>, timings: HttpTimings) -> Self {
+        Self {
+            doh_resolver,
+            timings,
+        }
+    }
+}
+
+impl Resolve for TimingResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let doh_resolver = self.doh_resolver.clone();
+        let timings = self.timings.clone();
+        Box::pin(async move {
+            let host = name.as_str().to_string();
+            let start = Instant::now();
+            let addrs: Addrs = match doh_resolver {
+                Some(doh_resolver) => doh_resolver.resolve(name).await?,
+                // the port is replaced by reqwest with the port of the requested url
+                None => Box::new(
+                    tokio::net::lookup_host((host.as_str(), 0))
+                        .await?
+                        .collect::<Vec<SocketAddr>>()
+                        .into_iter(),
+                ),
+            };
+            timings.record_dns(&host, start.elapsed());
+            Ok(addrs)
+        })
+    }
+}
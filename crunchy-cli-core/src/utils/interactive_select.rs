@@ -1,4 +1,5 @@
 use crate::utils::log::progress_pause;
+use crate::utils::pause::suspend_pause_keys;
 use crunchyroll_rs::Season;
 use dialoguer::console::Term;
 use dialoguer::MultiSelect;
@@ -60,6 +61,7 @@ pub fn select(prompt: &str, input: Vec<String>) -> Vec<usize> {
 
     let def: Vec<bool> = (0..input.len()).map(|_| true).collect();
 
+    suspend_pause_keys(true);
     let selection = MultiSelect::new()
         .with_prompt(prompt)
         .items(&input[..])
@@ -68,6 +70,7 @@ pub fn select(prompt: &str, input: Vec<String>) -> Vec<usize> {
         .report(false)
         .interact_on(&Term::stdout())
         .unwrap_or_default();
+    suspend_pause_keys(false);
 
     selection
 }
@@ -146,3 +146,24 @@ pub fn all_locale_in_locales(locales: Vec<Locale>) -> Vec<Locale> {
         locales
     }
 }
+
+/// Error if an episode isn't available with exactly the requested locales and `--strict-locale`
+/// is set. The episode is skipped instead of being downloaded with other or fewer locales.
+#[derive(Debug)]
+pub struct LocaleMismatch {
+    pub title: String,
+    pub reasons: Vec<String>,
+}
+
+impl std::fmt::Display for LocaleMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} isn't available with the requested locales ({})",
+            self.title,
+            self.reasons.join(", ")
+        )
+    }
+}
+
+impl std::error::Error for LocaleMismatch {}
@@ -1,12 +1,44 @@
-use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
-use log::{
-    info, set_boxed_logger, set_max_level, Level, LevelFilter, Log, Metadata, Record,
-    SetLoggerError,
-};
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use log::{set_logger, set_max_level, Level, LevelFilter, Log, Metadata, Record, SetLoggerError};
+use std::env;
+use std::fs::{File, OpenOptions};
+use std::future::Future;
 use std::io::{stdout, Write};
-use std::sync::Mutex;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+
+/// A change of the progress which is currently shown. Progress isn't a log message, so it's not
+/// passed through `log` but sent to the subscriber set via [`subscribe_progress`].
+#[derive(Clone, Debug)]
+pub(crate) enum ProgressEvent {
+    /// Starts a new progress with the given message. If a progress is already active, the message
+    /// is shown as a step of it.
+    Start(String),
+    /// Hides the active progress, or shows it again if it's hidden.
+    Pause,
+    /// Stops the active progress, optionally with a final message.
+    End(Option<String>),
+}
+
+type ProgressSubscriber = Box<dyn Fn(ProgressEvent) + Send + Sync>;
+
+static PROGRESS_SUBSCRIBER: OnceLock<ProgressSubscriber> = OnceLock::new();
+
+/// Sets the function which receives all progress events. Only one subscriber can be set, further
+/// calls are ignored.
+pub(crate) fn subscribe_progress(subscriber: ProgressSubscriber) {
+    let _ = PROGRESS_SUBSCRIBER.set(subscriber);
+}
+
+/// Sends `event` to the progress subscriber. Does nothing if no subscriber is set.
+pub(crate) fn emit_progress(event: ProgressEvent) {
+    if let Some(subscriber) = PROGRESS_SUBSCRIBER.get() {
+        subscriber(event)
+    }
+}
 
 pub struct ProgressHandler {
     pub(crate) stopped: bool,
@@ -15,7 +47,7 @@ pub struct ProgressHandler {
 impl Drop for ProgressHandler {
     fn drop(&mut self) {
         if !self.stopped {
-            info!(target: "progress_end", "")
+            emit_progress(ProgressEvent::End(None))
         }
     }
 }
@@ -23,14 +55,14 @@ impl Drop for ProgressHandler {
 impl ProgressHandler {
     pub(crate) fn stop<S: AsRef<str>>(mut self, msg: S) {
         self.stopped = true;
-        info!(target: "progress_end", "{}", msg.as_ref())
+        emit_progress(ProgressEvent::End(Some(msg.as_ref().to_string())))
     }
 }
 
 macro_rules! progress {
     ($($arg:tt)+) => {
         {
-            log::info!(target: "progress", $($arg)+);
+            $crate::utils::log::emit_progress($crate::utils::log::ProgressEvent::Start(format!($($arg)+)));
             $crate::utils::log::ProgressHandler{stopped: false}
         }
     }
@@ -38,17 +70,80 @@ macro_rules! progress {
 pub(crate) use progress;
 
 macro_rules! progress_pause {
-    () => {
-        {
-            log::info!(target: "progress_pause", "")
+    () => {{
+        $crate::utils::log::emit_progress($crate::utils::log::ProgressEvent::Pause)
+    }};
+}
+pub(crate) use progress_pause;
+
+/// The directory the log files are written to, set by [`CliLogger::init`].
+static LOG_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+tokio::task_local! {
+    static LOG_FILE: LogFile;
+}
+
+/// A separate log file which gets all log messages of the code it scopes (via [`LogFile::scope`]
+/// or [`LogFile::sync_scope`]). The file isn't global, so the messages of episodes which are
+/// processed at the same time (e.g. with `--pipeline`) are written into their own files.
+#[derive(Clone, Default)]
+pub struct LogFile(Option<Arc<Mutex<File>>>);
+
+impl LogFile {
+    /// Opens the log file named after the given output path if a log directory is set. Otherwise,
+    /// or if the file can't be opened, the returned log file discards all messages.
+    pub(crate) fn open(output: &Path) -> Self {
+        let Some(log_dir) = LOG_DIR.get() else {
+            return Self::default();
+        };
+
+        let name = output
+            .file_name()
+            .map_or("stdout".to_string(), |n| n.to_string_lossy().to_string());
+        let path = log_dir.join(format!("{}.log", name));
+        match OpenOptions::new().create(true).append(true).open(&path) {
+            Ok(file) => Self(Some(Arc::new(Mutex::new(file)))),
+            Err(e) => {
+                write_terminal(|| {
+                    eprintln!(
+                        ":: Failed to open log file {}: {}",
+                        path.to_string_lossy(),
+                        e
+                    )
+                });
+                Self::default()
+            }
+        }
+    }
+
+    /// The log file of the code which is currently running. Tasks and threads which are spawned
+    /// don't inherit it, it must be passed to them explicitly.
+    pub(crate) fn current() -> Self {
+        LOG_FILE
+            .try_with(|log_file| log_file.clone())
+            .unwrap_or_default()
+    }
+
+    /// Writes all log messages of `future` into this log file.
+    pub(crate) fn scope<F: Future>(self, future: F) -> impl Future<Output = F::Output> {
+        LOG_FILE.scope(self, future)
+    }
+
+    /// Writes all log messages of `f` into this log file.
+    pub(crate) fn sync_scope<R, F: FnOnce() -> R>(self, f: F) -> R {
+        LOG_FILE.sync_scope(self, f)
+    }
+
+    fn write(&self, line: String) {
+        if let Some(file) = &self.0 {
+            let _ = writeln!(file.lock().unwrap(), "{}", line);
         }
     }
 }
-pub(crate) use progress_pause;
 
 macro_rules! tab_info {
     ($($arg:tt)+) => {
-        if log::max_level() == log::LevelFilter::Debug {
+        if log::max_level() >= log::LevelFilter::Debug {
             info!($($arg)+)
         } else {
             info!("\t{}", format!($($arg)+))
@@ -57,9 +152,107 @@ macro_rules! tab_info {
 }
 pub(crate) use tab_info;
 
+/// Logs a line of the final summary of a command. Unlike all other info messages, summary lines
+/// are also shown if only the summary should be printed (`--quiet-summary-only`).
+macro_rules! summary {
+    ($($arg:tt)+) => {
+        log::info!(target: "summary", $($arg)+)
+    }
+}
+pub(crate) use summary;
+
+static DRAW_PROGRESS: AtomicBool = AtomicBool::new(true);
+
+/// If progress bars / spinners should be drawn. This is not the case if the output isn't `Info`
+/// level or the terminal can't display them.
+pub(crate) fn draw_progress() -> bool {
+    log::max_level() == LevelFilter::Info && DRAW_PROGRESS.load(Ordering::Relaxed)
+}
+
+/// The progress bars of downloads and muxes. Bars which are shown at the same time, like the
+/// segment download of the next episode and the mux of the previous one with `--pipeline`, are
+/// drawn below each other instead of overwriting each other.
+static PROGRESS_BARS: OnceLock<MultiProgress> = OnceLock::new();
+
+fn progress_bars() -> &'static MultiProgress {
+    PROGRESS_BARS.get_or_init(|| MultiProgress::with_draw_target(ProgressDrawTarget::stdout()))
+}
+
+/// Adds `progress_bar` to the progress bars drawn on stdout. Every progress bar must be added
+/// before it's drawn the first time (e.g. before enabling its steady tick).
+pub(crate) fn add_progress_bar(progress_bar: ProgressBar) -> ProgressBar {
+    progress_bars().add(progress_bar)
+}
+
+/// Runs `write`, which writes lines to the terminal, while the progress bars are cleared. The
+/// bars are drawn (also by their tick threads) while holding the same lock, so a written line never
+/// ends up in the middle of a redraw. The bars are drawn again below the written lines afterwards.
+fn write_terminal<F: FnOnce()>(write: F) {
+    progress_bars().suspend(write)
+}
+
+/// Check if the terminal, given by the value of `TERM`, is too limited to render progress bars and
+/// spinners (e.g. `TERM=dumb`). Those terminals would print the control sequences as garbage.
+fn is_dumb_terminal(term: Option<&str>) -> bool {
+    term == Some("dumb")
+}
+
+/// Token bucket which limits how many progress steps are printed per second. Steps exceeding the
+/// limit are only counted and the count is appended to the next printed step.
+struct StepThrottle {
+    rate: f64,
+    tokens: f64,
+    last: Instant,
+    omitted: usize,
+}
+
+impl StepThrottle {
+    fn new(rate: u32) -> Self {
+        Self {
+            rate: rate as f64,
+            // a full bucket, so a burst of up to `rate` steps is printed directly
+            tokens: rate as f64,
+            last: Instant::now(),
+            omitted: 0,
+        }
+    }
+
+    /// Returns the line which should be printed for the step `msg`, or `None` if it's omitted.
+    fn step(&mut self, msg: &str) -> Option<String> {
+        let now = Instant::now();
+        self.tokens =
+            (self.tokens + now.duration_since(self.last).as_secs_f64() * self.rate).min(self.rate);
+        self.last = now;
+
+        if self.tokens < 1.0 {
+            self.omitted += 1;
+            return None;
+        }
+        self.tokens -= 1.0;
+        Some(match self.take_omitted() {
+            Some(omitted) => format!("{} {}", msg, omitted),
+            None => msg.to_string(),
+        })
+    }
+
+    /// Returns (and resets) the number of omitted steps as `(+N more)`, if any were omitted.
+    fn take_omitted(&mut self) -> Option<String> {
+        let omitted = std::mem::take(&mut self.omitted);
+        (omitted > 0).then(|| format!("(+{} more)", omitted))
+    }
+}
+
 pub struct CliLogger {
     level: LevelFilter,
+    /// The active progress. It's locked until a message is written, so another thread can't stop
+    /// or start a progress in between. The writes themselves are serialized with the progress
+    /// redraws by [`write_terminal`].
     progress: Mutex<Option<ProgressBar>>,
+    /// If the active progress is hidden via [`ProgressEvent::Pause`].
+    progress_paused: AtomicBool,
+    step_throttle: Option<Mutex<StepThrottle>>,
+    draw_progress: bool,
+    summary_only: bool,
 }
 
 impl Log for CliLogger {
@@ -68,42 +261,42 @@ impl Log for CliLogger {
     }
 
     fn log(&self, record: &Record) {
-        if !self.enabled(record.metadata())
-            || (record.target() != "progress"
-                && record.target() != "progress_pause"
-                && record.target() != "progress_end"
-                && !record.target().starts_with("crunchy_cli"))
-        {
+        if record.target() != "summary" && !record.target().starts_with("crunchy_cli") {
+            return;
+        }
+
+        let msg = record.args().to_string();
+        let target = record
+            .target()
+            .replacen("crunchy_cli_core", "crunchy_cli", 1);
+
+        // the log files get at least all info messages, regardless of the terminal log level
+        LogFile::current().write(self.extended_line(record.level(), &target, &msg));
+
+        if !self.enabled(record.metadata()) {
             return;
         }
 
+        if self.summary_only && record.target() != "summary" && record.level() > Level::Error {
+            return;
+        }
+
+        // the lock must be held until the message is written. otherwise another thread could stop
+        // the progress in between and the message would start a new progress instead
+        let mut progress = self.progress.lock().unwrap();
+
         if self.level >= LevelFilter::Debug {
-            self.extended(record);
+            self.println(self.extended_line(record.level(), &target, &msg));
             return;
         }
 
-        match record.target() {
-            "progress" => self.progress(record, false),
-            "progress_pause" => {
-                let progress = self.progress.lock().unwrap();
-                if let Some(p) = &*progress {
-                    p.set_draw_target(if p.is_hidden() {
-                        ProgressDrawTarget::stdout()
-                    } else {
-                        ProgressDrawTarget::hidden()
-                    })
-                }
-            }
-            "progress_end" => self.progress(record, true),
-            _ => {
-                if self.progress.lock().unwrap().is_some() {
-                    self.progress(record, false)
-                } else if record.level() > Level::Warn {
-                    self.normal(record)
-                } else {
-                    self.error(record)
-                }
-            }
+        // log messages are never throttled, only the steps of a progress are
+        if progress.is_some() {
+            self.println(format!(":: → {}", msg))
+        } else if record.level() > Level::Warn {
+            self.println(format!(":: {}", msg))
+        } else {
+            write_terminal(|| eprintln!(":: {}", msg))
         }
     }
 
@@ -113,56 +306,165 @@ impl Log for CliLogger {
 }
 
 impl CliLogger {
-    pub fn new(level: LevelFilter) -> Self {
+    /// If `summary_only` is set, only errors and summary lines are shown (they're still written
+    /// to the log files).
+    /// `progress_rate` limits how many progress steps are printed per second.
+    /// `term` is the value of the `TERM` environment variable.
+    pub fn new(
+        level: LevelFilter,
+        no_spinner: bool,
+        progress_rate: Option<u32>,
+        summary_only: bool,
+        term: Option<&str>,
+    ) -> Self {
         Self {
             level,
             progress: Mutex::new(None),
+            progress_paused: AtomicBool::new(false),
+            step_throttle: progress_rate.map(|rate| Mutex::new(StepThrottle::new(rate))),
+            draw_progress: !no_spinner && !summary_only && !is_dumb_terminal(term),
+            summary_only,
         }
     }
 
-    pub fn init(level: LevelFilter) -> Result<(), SetLoggerError> {
-        set_max_level(level);
-        set_boxed_logger(Box::new(CliLogger::new(level)))
+    pub fn init(
+        level: LevelFilter,
+        log_dir: Option<PathBuf>,
+        no_spinner: bool,
+        progress_rate: Option<u32>,
+        summary_only: bool,
+    ) -> Result<(), SetLoggerError> {
+        // the log files always contain info messages, also if the terminal output is quieter (e.g.
+        // with `-q`)
+        let max_level = if log_dir.is_some() {
+            level.max(LevelFilter::Info)
+        } else {
+            level
+        };
+        if let Some(log_dir) = log_dir {
+            let _ = LOG_DIR.set(log_dir);
+        }
+        let logger: &'static CliLogger = Box::leak(Box::new(CliLogger::new(
+            level,
+            no_spinner,
+            progress_rate,
+            summary_only,
+            env::var("TERM").ok().as_deref(),
+        )));
+        DRAW_PROGRESS.store(logger.draw_progress, Ordering::Relaxed);
+        set_max_level(max_level);
+        set_logger(logger)?;
+        subscribe_progress(Box::new(|event| logger.on_progress(event)));
+        Ok(())
     }
 
-    fn extended(&self, record: &Record) {
-        println!(
+    fn on_progress(&self, event: ProgressEvent) {
+        // progress messages are logged like info messages, so they're part of the log files too
+        let msg = match &event {
+            ProgressEvent::Start(msg) | ProgressEvent::End(Some(msg)) => msg.as_str(),
+            ProgressEvent::Pause | ProgressEvent::End(None) => "",
+        };
+        if !msg.is_empty() {
+            LogFile::current().write(self.extended_line(Level::Info, "crunchy_cli", msg))
+        }
+
+        if Level::Info > self.level || self.summary_only {
+            return;
+        }
+
+        let mut progress = self.progress.lock().unwrap();
+
+        if self.level >= LevelFilter::Debug {
+            if !msg.is_empty() {
+                self.println(self.extended_line(Level::Info, "crunchy_cli", msg))
+            }
+            return;
+        }
+
+        match event {
+            ProgressEvent::Start(msg) => self.progress(&mut progress, &msg, false),
+            ProgressEvent::Pause => {
+                if !self.draw_progress {
+                    return;
+                }
+                if let Some(p) = &*progress {
+                    if self.progress_paused.fetch_xor(true, Ordering::Relaxed) {
+                        add_progress_bar(p.clone());
+                    } else {
+                        progress_bars().remove(p)
+                    }
+                }
+            }
+            ProgressEvent::End(msg) => self.progress(&mut progress, &msg.unwrap_or_default(), true),
+        }
+    }
+
+    fn extended_line(&self, level: Level, target: &str, msg: &str) -> String {
+        format!(
             "[{}] {}  {} ({}) {}",
             chrono::Utc::now().format("%Y-%m-%d %H:%M:%S"),
-            record.level(),
-            // replace the 'progress' prefix if this function is invoked via 'progress!'
-            record
-                .target()
-                .replacen("crunchy_cli_core", "crunchy_cli", 1)
-                .replacen("progress_end", "crunchy_cli", 1)
-                .replacen("progress", "crunchy_cli", 1),
+            level,
+            target,
             format!("{:?}", thread::current().id())
                 .replace("ThreadId(", "")
                 .replace(')', ""),
-            record.args()
+            msg
         )
     }
 
-    fn normal(&self, record: &Record) {
-        println!(":: {}", record.args())
+    /// Prints `line` to stdout, above all progress bars.
+    fn println(&self, line: String) {
+        write_terminal(|| println!("{}", line))
     }
 
-    fn error(&self, record: &Record) {
-        eprintln!(":: {}", record.args())
-    }
+    /// `progress` is the locked progress of the logger. Steps of an active progress are throttled
+    /// if a step rate is set.
+    fn progress(&self, progress: &mut Option<ProgressBar>, msg: &str, stop: bool) {
+        // only steps of an active progress are throttled, starting and stopping a progress is
+        // always shown
+        let mut step = None;
+        if let Some(throttle) = self.step_throttle.as_ref().filter(|_| progress.is_some()) {
+            let mut throttle = throttle.lock().unwrap();
+            if !stop {
+                match throttle.step(msg) {
+                    Some(line) => step = Some(line),
+                    None => return,
+                }
+            } else if let Some(omitted) = throttle.take_omitted() {
+                // the steps omitted at last are summarized before the progress ends
+                self.println(format!(":: → {}", omitted))
+            }
+        }
+        let step = step.as_deref().unwrap_or(msg);
 
-    fn progress(&self, record: &Record, stop: bool) {
-        let mut progress = self.progress.lock().unwrap();
+        if !self.draw_progress {
+            // without being able to draw a spinner, only plain lines are printed. the progress is
+            // still stored (as hidden progress bar) to keep track if one is currently active
+            if stop && progress.is_some() {
+                progress.take();
+                if !msg.is_empty() {
+                    self.println(format!(":: {}", msg))
+                }
+            } else if progress.is_some() {
+                self.println(format!(":: → {}", step))
+            } else {
+                self.println(format!(":: {}...", msg));
+                *progress = Some(ProgressBar::hidden())
+            }
+            return;
+        }
 
-        let msg = format!("{}", record.args());
         if stop && progress.is_some() {
             if msg.is_empty() {
                 progress.take().unwrap().finish()
             } else {
-                progress.take().unwrap().finish_with_message(msg)
+                progress
+                    .take()
+                    .unwrap()
+                    .finish_with_message(msg.to_string())
             }
-        } else if let Some(p) = &*progress {
-            p.println(format!(":: → {}", msg))
+        } else if progress.is_some() {
+            self.println(format!(":: → {}", step))
         } else {
             #[cfg(not(windows))]
             let finish_str = "✔";
@@ -171,16 +473,279 @@ impl CliLogger {
             // we're using this (square root) symbol instead. microsoft.
             let finish_str = "√";
 
-            let pb = ProgressBar::new_spinner();
-            pb.set_style(
-                ProgressStyle::with_template(":: {spinner} {msg}")
-                    .unwrap()
-                    .tick_strings(&["—", "\\", "|", "/", finish_str]),
+            let pb = add_progress_bar(
+                ProgressBar::new_spinner().with_style(
+                    ProgressStyle::with_template(":: {spinner} {msg}")
+                        .unwrap()
+                        .tick_strings(&["—", "\\", "|", "/", finish_str]),
+                ),
             );
-            pb.set_draw_target(ProgressDrawTarget::stdout());
+            self.progress_paused.store(false, Ordering::Relaxed);
             pb.enable_steady_tick(Duration::from_millis(200));
-            pb.set_message(msg);
+            pb.set_message(msg.to_string());
             *progress = Some(pb)
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indicatif::TermLike;
+    use std::collections::HashSet;
+    use std::io;
+
+    /// The tests which draw the progress bars to a [`MockTerm`] change the draw target of all
+    /// progress bars, so they must not run at the same time.
+    static TERM_LOCK: Mutex<()> = Mutex::new(());
+
+    /// A terminal which only keeps the lines and the cursor row, so it shows what's visible after
+    /// all (re)draws.
+    #[derive(Clone, Debug, Default)]
+    struct MockTerm(Arc<Mutex<(Vec<String>, usize)>>);
+
+    impl MockTerm {
+        fn lines(&self) -> Vec<String> {
+            self.0
+                .lock()
+                .unwrap()
+                .0
+                .iter()
+                .map(|line| line.trim_end().to_string())
+                .filter(|line| !line.is_empty())
+                .collect()
+        }
+
+        fn with_line<F: FnOnce(&mut String)>(&self, f: F) {
+            let mut term = self.0.lock().unwrap();
+            let row = term.1;
+            if term.0.len() <= row {
+                term.0.resize(row + 1, String::new())
+            }
+            f(&mut term.0[row])
+        }
+    }
+
+    impl TermLike for MockTerm {
+        fn width(&self) -> u16 {
+            80
+        }
+
+        fn move_cursor_up(&self, n: usize) -> io::Result<()> {
+            let mut term = self.0.lock().unwrap();
+            term.1 = term.1.saturating_sub(n);
+            Ok(())
+        }
+
+        fn move_cursor_down(&self, n: usize) -> io::Result<()> {
+            self.0.lock().unwrap().1 += n;
+            Ok(())
+        }
+
+        fn move_cursor_right(&self, _: usize) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn move_cursor_left(&self, _: usize) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn write_line(&self, s: &str) -> io::Result<()> {
+            self.write_str(s)?;
+            self.move_cursor_down(1)
+        }
+
+        fn write_str(&self, s: &str) -> io::Result<()> {
+            self.with_line(|line| line.push_str(s));
+            Ok(())
+        }
+
+        fn clear_line(&self) -> io::Result<()> {
+            self.with_line(|line| line.clear());
+            Ok(())
+        }
+
+        fn flush(&self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_progress_bars_dont_overlap() {
+        let _lock = TERM_LOCK.lock().unwrap();
+        let term = MockTerm::default();
+        progress_bars().set_draw_target(ProgressDrawTarget::term_like(Box::new(term.clone())));
+
+        // like the segment download of the next episode while the previous one is muxed
+        let bar = |message: &str| {
+            add_progress_bar(
+                ProgressBar::new(100)
+                    .with_style(ProgressStyle::with_template("{msg} {pos}").unwrap())
+                    .with_message(message.to_string()),
+            )
+        };
+        let download = bar("download");
+        let mux = bar("mux");
+        download.set_position(10);
+        mux.set_position(20);
+        download.set_position(30);
+        download.abandon();
+        mux.abandon();
+
+        assert_eq!(term.lines(), vec!["download 30", "mux 20"]);
+    }
+
+    #[test]
+    fn test_concurrent_lines_stay_whole() {
+        let _lock = TERM_LOCK.lock().unwrap();
+        let term = MockTerm::default();
+        progress_bars().set_draw_target(ProgressDrawTarget::term_like(Box::new(term.clone())));
+
+        let bars: Vec<ProgressBar> = (0..3)
+            .map(|i| {
+                let bar = add_progress_bar(
+                    ProgressBar::new(1000)
+                        .with_style(ProgressStyle::with_template("{msg} {pos}").unwrap())
+                        .with_message(format!("bar {}", i)),
+                );
+                bar.enable_steady_tick(Duration::from_millis(1));
+                bar
+            })
+            .collect();
+
+        // the bars are redrawn by their tick threads and by position updates while many threads
+        // write lines at the same time
+        thread::scope(|s| {
+            for bar in &bars {
+                s.spawn(move || {
+                    for pos in 0..1000 {
+                        bar.set_position(pos)
+                    }
+                });
+            }
+            for t in 0..8 {
+                let term = term.clone();
+                s.spawn(move || {
+                    for i in 0..50 {
+                        write_terminal(|| term.write_line(&format!("line {} {}", t, i)).unwrap())
+                    }
+                });
+            }
+        });
+        for bar in &bars {
+            bar.abandon()
+        }
+
+        let written: Vec<String> = term
+            .lines()
+            .into_iter()
+            .filter(|line| line.contains("line"))
+            .collect();
+        for line in &written {
+            let parts: Vec<&str> = line.split(' ').collect();
+            assert!(
+                parts.len() == 3
+                    && parts[0] == "line"
+                    && parts[1..].iter().all(|p| p.parse::<u32>().is_ok()),
+                "partial line '{}'",
+                line
+            )
+        }
+        let expected: HashSet<String> = (0..8)
+            .flat_map(|t| (0..50).map(move |i| format!("line {} {}", t, i)))
+            .collect();
+        assert_eq!(written.len(), expected.len());
+        assert_eq!(written.into_iter().collect::<HashSet<String>>(), expected);
+    }
+
+    #[test]
+    fn test_dumb_terminal_fallback() {
+        assert!(is_dumb_terminal(Some("dumb")));
+        let logger = CliLogger::new(LevelFilter::Info, false, None, false, Some("dumb"));
+        assert!(!logger.draw_progress);
+        // a plain line is printed instead of drawing a spinner, the progress is only tracked
+        let mut progress = None;
+        logger.progress(&mut progress, "working", false);
+        assert!(progress.as_ref().is_some_and(|p| p.is_hidden()));
+        logger.progress(&mut progress, "done", true);
+        assert!(progress.is_none());
+
+        assert!(!is_dumb_terminal(Some("xterm-256color")));
+        assert!(!is_dumb_terminal(None));
+        let term = Some("xterm-256color");
+        assert!(CliLogger::new(LevelFilter::Info, false, None, false, term).draw_progress);
+        assert!(!CliLogger::new(LevelFilter::Info, true, None, false, term).draw_progress);
+    }
+
+    #[test]
+    fn test_throttle_only_progress_steps() {
+        let mut logger = CliLogger::new(LevelFilter::Info, false, Some(1), false, None);
+        logger.draw_progress = false;
+        *logger.progress.lock().unwrap() = Some(ProgressBar::hidden());
+
+        for level in [Level::Error, Level::Warn, Level::Info, Level::Error] {
+            logger.log(
+                &Record::builder()
+                    .args(format_args!("message"))
+                    .level(level)
+                    .target("crunchy_cli_core::test")
+                    .build(),
+            )
+        }
+
+        // the log messages didn't use up the burst of the throttle, so the first step is still
+        // printed and only the second one is omitted
+        let mut throttle = logger.step_throttle.as_ref().unwrap().lock().unwrap();
+        assert_eq!(throttle.step("step"), Some("step".to_string()));
+        assert_eq!(throttle.step("step"), None);
+        assert_eq!(throttle.take_omitted(), Some("(+1 more)".to_string()));
+        assert_eq!(throttle.take_omitted(), None);
+    }
+
+    #[tokio::test]
+    async fn test_log_file_per_scope() {
+        // only the summary is printed, the messages are still written into the log files
+        let logger = CliLogger::new(LevelFilter::Info, true, None, true, None);
+        let log = |msg: &str| {
+            logger.log(
+                &Record::builder()
+                    .args(format_args!("{}", msg))
+                    .level(Level::Info)
+                    .target("crunchy_cli_core::test")
+                    .build(),
+            )
+        };
+
+        let dir = tempfile::tempdir().unwrap();
+        let paths = [dir.path().join("first.log"), dir.path().join("second.log")];
+        let [first, second] = paths
+            .clone()
+            .map(|path| LogFile(Some(Arc::new(Mutex::new(File::create(path).unwrap())))));
+
+        // like the mux of an episode while the next one is downloaded with `--pipeline`
+        let episode = |log_file: LogFile, name: &'static str| {
+            log_file.scope(async move {
+                log(&format!("{} start", name));
+                tokio::task::yield_now().await;
+                // spawned threads only log into the file if it's passed to them
+                let log_file = LogFile::current();
+                thread::scope(|s| {
+                    s.spawn(move || log_file.sync_scope(|| log(&format!("{} thread", name))));
+                });
+                tokio::task::yield_now().await;
+                log(&format!("{} end", name))
+            })
+        };
+        tokio::join!(episode(first, "first"), episode(second, "second"));
+        log("unscoped");
+
+        for (path, name) in paths.iter().zip(["first", "second"]) {
+            let content = std::fs::read_to_string(path).unwrap();
+            let lines: Vec<&str> = content.lines().collect();
+            assert_eq!(lines.len(), 3, "{}", content);
+            for (line, step) in lines.iter().zip(["start", "thread", "end"]) {
+                assert!(line.ends_with(&format!("{} {}", name, step)), "{}", line)
+            }
+        }
+    }
+}
@@ -1,15 +1,36 @@
+pub mod artwork;
+pub mod checkpoint;
+pub mod checksum;
 pub mod clap;
+pub mod config;
 pub mod context;
+pub mod dns;
 pub mod download;
+pub mod event;
 pub mod ffmpeg;
 pub mod filter;
 pub mod fmt;
 pub mod format;
+pub mod hook;
+pub mod http_timing;
 pub mod interactive_select;
 pub mod locale;
 pub mod log;
+pub mod nfo;
 pub mod os;
 pub mod parse;
+pub mod pause;
+pub mod preset_map;
+pub mod queue;
 pub mod rate_limit;
+pub mod raw;
+pub mod reauth;
+pub mod retry;
+pub mod state;
+pub mod subtitle;
+pub mod summary;
 pub mod sync;
+pub mod test_run;
+pub mod timing;
 pub mod video;
+pub mod webhook;
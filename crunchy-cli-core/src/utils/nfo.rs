@@ -0,0 +1,192 @@
+use crate::utils::format::{Format, SingleFormat};
+use crate::utils::os::is_special_file;
+use anyhow::Result;
+use crunchyroll_rs::Series;
+use log::debug;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Writes NFO files as used by Kodi, Jellyfin and similar media servers. Besides a NFO file for
+/// every episode, a `tvshow.nfo` is written to the series directory and a `season.nfo` to the
+/// season directory (if the output template has one).
+#[derive(Default)]
+pub struct NfoWriter {
+    series: HashMap<String, Series>,
+    written: HashSet<PathBuf>,
+}
+
+impl NfoWriter {
+    /// `template` is the (unformatted) output template which was used to create `dst`.
+    pub async fn write(
+        &mut self,
+        template: &str,
+        dst: &Path,
+        single_format: &SingleFormat,
+        format: &Format,
+    ) -> Result<()> {
+        if is_special_file(dst) || dst.to_string_lossy() == "-" {
+            return Ok(());
+        }
+        if !single_format.is_episode() {
+            debug!(
+                "Skipping NFO files as {} is not an episode",
+                single_format.title
+            );
+            return Ok(());
+        }
+
+        write_if_changed(&dst.with_extension("nfo"), &episode_nfo(format))?;
+
+        let (season_dir, series_dir) = media_dirs(template, dst);
+        if let Some(season_dir) = season_dir {
+            let season_nfo = season_dir.join("season.nfo");
+            if self.written.insert(season_nfo.clone()) {
+                write_if_changed(&season_nfo, &season_nfo_content(format))?
+            }
+        }
+
+        let tvshow_nfo = series_dir.join("tvshow.nfo");
+        if self.written.contains(&tvshow_nfo) {
+            return Ok(());
+        }
+        if !self.series.contains_key(&format.series_id) {
+            let Some(series) = single_format.series().await? else {
+                return Ok(());
+            };
+            self.series.insert(format.series_id.clone(), series);
+        }
+        write_if_changed(
+            &tvshow_nfo,
+            &tvshow_nfo_content(self.series.get(&format.series_id).unwrap()),
+        )?;
+        self.written.insert(tvshow_nfo);
+
+        Ok(())
+    }
+}
+
+/// The season and series directory of `dst`, which was created with the (unformatted) output
+/// template `template`. If the directory the file is stored in is named after the season, it's
+/// assumed that its parent is the series directory. Otherwise, a flat layout without season
+/// directories is assumed and the directory of the file is the series directory.
+pub fn media_dirs(template: &str, dst: &Path) -> (Option<PathBuf>, PathBuf) {
+    let file_dir = dst.parent().unwrap_or(Path::new("")).to_path_buf();
+    let has_season_dir = Path::new(template)
+        .parent()
+        .and_then(|p| p.file_name())
+        .is_some_and(|n| n.to_string_lossy().contains("{season"));
+    if has_season_dir {
+        let series_dir = file_dir.parent().unwrap_or(Path::new("")).to_path_buf();
+        (Some(file_dir), series_dir)
+    } else {
+        (None, file_dir)
+    }
+}
+
+fn tvshow_nfo_content(series: &Series) -> String {
+    let mut nfo = vec![
+        xml_tag("title", &series.title),
+        xml_tag("plot", &series.description),
+        xml_tag("studio", &series.content_provider),
+    ];
+    if let Some(year) = series.series_launch_year {
+        nfo.push(xml_tag("year", year.to_string()))
+    }
+    if let Some(rating) = series.maturity_ratings.first() {
+        nfo.push(xml_tag("mpaa", rating))
+    }
+    for keyword in &series.keywords {
+        nfo.push(xml_tag("tag", keyword))
+    }
+    if let Some(poster) = series.images.poster_tall.iter().max_by_key(|i| i.width) {
+        nfo.push(format!(
+            "<thumb aspect=\"poster\">{}</thumb>",
+            xml_escape(&poster.source)
+        ))
+    }
+    if let Some(landscape) = series.images.poster_wide.iter().max_by_key(|i| i.width) {
+        nfo.push(format!(
+            "<thumb aspect=\"landscape\">{}</thumb>",
+            xml_escape(&landscape.source)
+        ))
+    }
+    nfo.push(format!(
+        "<uniqueid type=\"crunchyroll\" default=\"true\">{}</uniqueid>",
+        xml_escape(&series.id)
+    ));
+    xml_document("tvshow", nfo)
+}
+
+fn season_nfo_content(format: &Format) -> String {
+    xml_document(
+        "season",
+        vec![
+            xml_tag("title", &format.season_title),
+            xml_tag("seasonnumber", format.season_number.to_string()),
+        ],
+    )
+}
+
+fn episode_nfo(format: &Format) -> String {
+    xml_document(
+        "episodedetails",
+        vec![
+            xml_tag("title", &format.title),
+            xml_tag("showtitle", &format.series_name),
+            xml_tag("season", format.season_number.to_string()),
+            xml_tag("episode", format.sequence_number.to_string()),
+            xml_tag("plot", &format.description),
+            xml_tag(
+                "aired",
+                format!(
+                    "{}-{:02}-{:02}",
+                    format.release_year, format.release_month, format.release_day
+                ),
+            ),
+            format!(
+                "<uniqueid type=\"crunchyroll\" default=\"true\">{}</uniqueid>",
+                xml_escape(&format.episode_id)
+            ),
+        ],
+    )
+}
+
+fn xml_document(root: &str, children: Vec<String>) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n<{root}>\n{}\n</{root}>\n",
+        children
+            .into_iter()
+            .map(|c| format!("  {}", c))
+            .collect::<Vec<String>>()
+            .join("\n")
+    )
+}
+
+fn xml_tag<S: AsRef<str>>(name: &str, value: S) -> String {
+    format!("<{name}>{}</{name}>", xml_escape(value.as_ref()))
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Only writes the file if its content differs, so that media servers don't detect a change
+/// which would cause unnecessary rescans.
+fn write_if_changed(path: &Path, content: &str) -> Result<()> {
+    if fs::read_to_string(path).is_ok_and(|c| c == content) {
+        debug!("NFO file {} is unchanged", path.to_string_lossy());
+        return Ok(());
+    }
+    if let Some(parent) = path.parent() {
+        if !parent.as_os_str().is_empty() && !parent.exists() {
+            fs::create_dir_all(parent)?
+        }
+    }
+    fs::write(path, content)?;
+    debug!("Wrote NFO file {}", path.to_string_lossy());
+    Ok(())
+}
@@ -1,11 +1,15 @@
+use chrono::NaiveDate;
 use log::debug;
 use regex::{Regex, RegexBuilder};
 use std::borrow::Cow;
+use std::fs::FileTimes;
 use std::io::ErrorKind;
 use std::path::{Path, PathBuf};
 use std::pin::Pin;
 use std::process::{Command, Stdio};
+use std::sync::Mutex;
 use std::task::{Context, Poll};
+use std::time::SystemTime;
 use std::{env, fs, io};
 use tempfile::{Builder, NamedTempFile, TempPath};
 use tokio::io::{AsyncRead, ReadBuf};
@@ -46,6 +50,33 @@ pub fn tempfile<S: AsRef<str>>(suffix: S) -> io::Result<NamedTempFile> {
     Ok(tempfile)
 }
 
+/// Checks if files can be created in `dir` by creating (and removing) a temporary file in it.
+/// Directories which don't exist yet are created when the first file is written, so the closest
+/// existing parent directory is checked instead.
+pub fn check_dir_writable(dir: &Path) -> io::Result<()> {
+    let mut existing = dir;
+    while !existing.as_os_str().is_empty() && !existing.exists() {
+        existing = existing.parent().unwrap_or(Path::new(""))
+    }
+    if existing.as_os_str().is_empty() {
+        existing = Path::new(".")
+    }
+    match Builder::default()
+        .prefix(".crunchy-cli_")
+        .tempfile_in(existing)
+    {
+        Ok(_) => Ok(()),
+        Err(e) => Err(io::Error::new(
+            e.kind(),
+            format!(
+                "Can't write to directory '{}': {}",
+                existing.to_string_lossy(),
+                e
+            ),
+        )),
+    }
+}
+
 pub fn cache_dir<S: AsRef<str>>(name: S) -> io::Result<PathBuf> {
     let cache_dir = temp_directory().join(format!(".crunchy-cli_{}_cache", name.as_ref()));
     fs::create_dir_all(&cache_dir)?;
@@ -138,6 +169,76 @@ pub fn temp_named_pipe() -> io::Result<TempNamedPipe> {
     }
 }
 
+lazy_static::lazy_static! {
+    static ref PARTIAL_OUTPUTS: Mutex<Vec<PathBuf>> = Mutex::new(vec![]);
+}
+
+/// A file which is written next to the actual output file and only renamed to it if it was
+/// written completely. This way the output path either doesn't exist or contains a complete file.
+/// The file is removed if it gets dropped without being persisted.
+pub struct PartialOutput {
+    path: PathBuf,
+    dst: PathBuf,
+    persisted: bool,
+}
+
+impl PartialOutput {
+    pub fn new(dst: &Path) -> Self {
+        // the extension is kept as ffmpeg detects the output format by it
+        let path = dst.with_file_name(format!(
+            ".{}.part.{}",
+            dst.file_stem().unwrap_or_default().to_string_lossy(),
+            dst.extension().unwrap_or_default().to_string_lossy()
+        ));
+        PARTIAL_OUTPUTS.lock().unwrap().push(path.clone());
+        Self {
+            path,
+            dst: dst.to_path_buf(),
+            persisted: false,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Moves the partial file to the actual output path.
+    pub fn persist(mut self) -> io::Result<()> {
+        fs::rename(&self.path, &self.dst)?;
+        self.persisted = true;
+        Ok(())
+    }
+}
+
+impl Drop for PartialOutput {
+    fn drop(&mut self) {
+        if !self.persisted && self.path.exists() {
+            let _ = fs::remove_file(&self.path);
+        }
+        PARTIAL_OUTPUTS.lock().unwrap().retain(|p| p != &self.path)
+    }
+}
+
+/// Removes all partial output files which are currently written. Used when the process gets
+/// interrupted, as destructors aren't run then.
+pub fn remove_partial_outputs() {
+    // `try_lock` as the lock might be held by the interrupted thread
+    if let Ok(partial_outputs) = PARTIAL_OUTPUTS.try_lock() {
+        for path in partial_outputs.iter() {
+            let result = fs::remove_file(path);
+            debug!(
+                "Ctrl-c removed partial output file {} {}",
+                path.to_string_lossy(),
+                if result.is_ok() {
+                    "successfully"
+                } else {
+                    "not successfully"
+                }
+            )
+        }
+    }
+}
+
 /// Check if the given path exists and rename it until the new (renamed) file does not exist.
 pub fn free_file(mut path: PathBuf) -> (PathBuf, bool) {
     // do not rename it if it exists but is a special file
@@ -170,6 +271,43 @@ pub fn free_file(mut path: PathBuf) -> (PathBuf, bool) {
     (path, i != 0)
 }
 
+/// Sets the modification time (and the access time if `access` is true) of `path` to the start of
+/// `date` (UTC).
+pub fn set_file_date(path: &Path, date: NaiveDate, access: bool) -> io::Result<()> {
+    let time = SystemTime::from(date.and_hms_opt(0, 0, 0).unwrap().and_utc());
+    let mut times = FileTimes::new().set_modified(time);
+    if access {
+        times = times.set_accessed(time)
+    }
+    // windows only allows to change the times of files which are opened for writing
+    fs::OpenOptions::new()
+        .write(true)
+        .open(path)?
+        .set_times(times)
+}
+
+/// Sets the file times of `path` to `release_date`, see [`set_file_date`]. Raw downloads and
+/// special output files have no regular file to change, they're skipped.
+pub fn set_mtime(path: &Path, release_date: Option<NaiveDate>, access: bool) -> io::Result<()> {
+    if !path.is_file() {
+        return Ok(());
+    }
+    if let Some(date) = release_date {
+        set_file_date(path, date, access)?;
+        debug!(
+            "Set modification time of '{}' to {}",
+            path.to_string_lossy(),
+            date
+        )
+    } else {
+        debug!(
+            "Not setting modification time of '{}' as the release date is unknown",
+            path.to_string_lossy()
+        )
+    }
+    Ok(())
+}
+
 /// Check if the given path is a special file. On Linux this is probably a pipe and on Windows
 /// ¯\_(ツ)_/¯
 pub fn is_special_file<P: AsRef<Path>>(path: P) -> bool {
@@ -223,3 +361,107 @@ pub fn sanitize<S: AsRef<str>>(path: S, include_path_separator: bool, universal:
         collect(path)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_dir_writable() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        check_dir_writable(dir.path())?;
+        // missing directories are checked via their closest existing parent, without creating
+        // them
+        let missing = dir.path().join("Series").join("Season 01");
+        check_dir_writable(&missing)?;
+        assert!(!missing.exists());
+        // the probe file is removed again
+        assert_eq!(fs::read_dir(dir.path())?.count(), 0);
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_check_dir_writable_read_only() -> io::Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir()?;
+        let read_only = dir.path().join("read-only");
+        fs::create_dir(&read_only)?;
+        fs::set_permissions(&read_only, fs::Permissions::from_mode(0o555))?;
+        // permissions don't apply to root, the check can't fail then
+        if fs::File::create(read_only.join("probe")).is_ok() {
+            return Ok(());
+        }
+
+        for dir in [
+            read_only.clone(),
+            read_only.join("Series").join("Season 01"),
+        ] {
+            let err = check_dir_writable(&dir).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+            // the directory which can't be written is named
+            assert!(
+                err.to_string().contains(&*read_only.to_string_lossy()),
+                "{}",
+                err
+            );
+        }
+
+        fs::set_permissions(&read_only, fs::Permissions::from_mode(0o755))
+    }
+
+    #[test]
+    fn test_partial_output_persist_and_interrupt() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let dst = dir.path().join("video.mkv");
+
+        let partial_output = PartialOutput::new(&dst);
+        assert_eq!(partial_output.path(), dir.path().join(".video.part.mkv"));
+        fs::write(partial_output.path(), b"complete")?;
+        assert!(!dst.exists());
+        partial_output.persist()?;
+        assert_eq!(fs::read(&dst)?, b"complete");
+        assert!(!dir.path().join(".video.part.mkv").exists());
+
+        // ctrl-c removes the partial file while it's still written
+        let dst = dir.path().join("interrupted.mkv");
+        let partial_output = PartialOutput::new(&dst);
+        fs::write(partial_output.path(), b"incomplete")?;
+        remove_partial_outputs();
+        assert!(!partial_output.path().exists());
+        drop(partial_output);
+        assert!(!dst.exists());
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_partial_output_killed_mux() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let dst = dir.path().join("video.mkv");
+        let partial_output = PartialOutput::new(&dst);
+
+        // stands in for an ffmpeg process which gets killed while it writes the output
+        let mut mux = Command::new("sh")
+            .arg("-c")
+            .arg("printf truncated > \"$0\"; sleep 30")
+            .arg(partial_output.path())
+            .spawn()?;
+        for _ in 0..100 {
+            if partial_output.path().exists() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(20))
+        }
+        mux.kill()?;
+        assert!(!mux.wait()?.success());
+
+        // the mux failed, so the partial output is dropped without being persisted
+        let path = partial_output.path().to_path_buf();
+        drop(partial_output);
+        assert!(!path.exists());
+        assert!(!dst.exists());
+        Ok(())
+    }
+}
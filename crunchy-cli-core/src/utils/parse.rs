@@ -8,7 +8,7 @@ use regex::Regex;
 /// If a struct instance equals the [`Default::default()`] it's considered that no find is applied.
 /// If `from_*` is [`None`] they're set to [`u32::MIN`].
 /// If `to_*` is [`None`] they're set to [`u32::MAX`].
-#[derive(Debug, Default)]
+#[derive(Clone, Debug, Default)]
 pub struct InnerUrlFilter {
     from_episode: Option<f32>,
     to_episode: Option<f32>,
@@ -16,7 +16,7 @@ pub struct InnerUrlFilter {
     to_season: Option<u32>,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct UrlFilter {
     inner: Vec<InnerUrlFilter>,
 }
@@ -76,9 +76,102 @@ impl UrlFilter {
 /// In practice, it would look like this: `https://crunchyroll.com/series/12345678/example[S1E5-S3E2]`.
 pub async fn parse_url(
     crunchy: &Crunchyroll,
-    mut url: String,
+    url: String,
     with_filter: bool,
 ) -> Result<(MediaCollection, UrlFilter)> {
+    let (mut url, url_filter) = split_url_filter(url, with_filter)?;
+
+    // check if the url is the old series/episode scheme which still occurs in some places (like the
+    // rss)
+    let old_url_regex = Regex::new(r"https?://(www\.)?crunchyroll\.com/.+").unwrap();
+    if old_url_regex.is_match(&url) {
+        debug!("Detected maybe old url");
+        // replace the 'http' prefix with 'https' as http is not supported by the reqwest client
+        if url.starts_with("http://") {
+            url.replace_range(0..4, "https")
+        }
+        // the old url redirects to the new url. request the old url, follow the redirects and
+        // extract the final url
+        url = crunchy.client().get(&url).send().await?.url().to_string()
+    }
+
+    let parsed_url = crunchyroll_rs::parse_url(url).ok_or(anyhow!("Invalid url"))?;
+    debug!("Url type: {:?}", parsed_url);
+    let media_collection = match parsed_url {
+        UrlType::Series(id)
+        | UrlType::MovieListing(id)
+        | UrlType::EpisodeOrMovie(id)
+        | UrlType::MusicVideo(id)
+        | UrlType::Concert(id) => crunchy.media_collection_from_id(id).await?,
+    };
+
+    Ok((media_collection, url_filter))
+}
+
+/// Like [`parse_url`], but also supports urls of crunchylists (e.g.
+/// `https://www.crunchyroll.com/crunchylists/<id>`), which are expanded into all series and movies
+/// they contain. The filter of the url is applied to every item of the list.
+///
+/// Curated collections and category pages (e.g. `https://www.crunchyroll.com/videos/new`) are not
+/// crunchylists; they're only generated by the Crunchyroll website and rejected with an error.
+pub async fn parse_url_or_crunchylist(
+    crunchy: &Crunchyroll,
+    url: String,
+    with_filter: bool,
+) -> Result<Vec<(MediaCollection, UrlFilter)>> {
+    let collection_regex = Regex::new(
+        r"^https?://(www\.)?crunchyroll\.com/(\w{2}(-\w{2})?/)?(curated|collections?|videos|simulcasts)(/|$|\[)",
+    )
+    .unwrap();
+    if collection_regex.is_match(&url) {
+        bail!("Curated collections and category pages are not supported, only urls of series, seasons, episodes, movies and crunchylists can be used")
+    }
+
+    let crunchylist_regex = Regex::new(
+        r"^https?://(www\.)?crunchyroll\.com/(\w{2}(-\w{2})?/)?crunchylists/(?P<id>[\w-]+)",
+    )
+    .unwrap();
+    let Some(list_id) = crunchylist_regex
+        .captures(&url)
+        .map(|c| c["id"].to_string())
+    else {
+        return Ok(vec![parse_url(crunchy, url, with_filter).await?]);
+    };
+    let (_, url_filter) = split_url_filter(url, with_filter)?;
+
+    // crunchylists are only accessible by the account which created them
+    let Some(preview) = crunchy
+        .crunchylists()
+        .await?
+        .items
+        .into_iter()
+        .find(|c| c.list_id == list_id)
+    else {
+        bail!(
+            "Crunchylist {} not found, only crunchylists of the logged in account can be used",
+            list_id
+        )
+    };
+    let crunchylist = preview.crunchylist().await?;
+    debug!(
+        "Crunchylist '{}' contains {} item(s)",
+        preview.title,
+        crunchylist.items.len()
+    );
+    if crunchylist.items.is_empty() {
+        bail!("Crunchylist '{}' is empty", preview.title)
+    }
+
+    Ok(crunchylist
+        .items
+        .into_iter()
+        .map(|item| (item.panel, url_filter.clone()))
+        .collect())
+}
+
+/// Removes the filter (e.g. `[S1E1-E4]`) from `url` and parses it. If `with_filter` is false, the
+/// url is returned unchanged with a filter which matches everything.
+pub(crate) fn split_url_filter(mut url: String, with_filter: bool) -> Result<(String, UrlFilter)> {
     let url_filter = if with_filter {
         debug!("Url may contain filters");
 
@@ -133,31 +226,7 @@ pub async fn parse_url(
         UrlFilter::default()
     };
 
-    // check if the url is the old series/episode scheme which still occurs in some places (like the
-    // rss)
-    let old_url_regex = Regex::new(r"https?://(www\.)?crunchyroll\.com/.+").unwrap();
-    if old_url_regex.is_match(&url) {
-        debug!("Detected maybe old url");
-        // replace the 'http' prefix with 'https' as http is not supported by the reqwest client
-        if url.starts_with("http://") {
-            url.replace_range(0..4, "https")
-        }
-        // the old url redirects to the new url. request the old url, follow the redirects and
-        // extract the final url
-        url = crunchy.client().get(&url).send().await?.url().to_string()
-    }
-
-    let parsed_url = crunchyroll_rs::parse_url(url).ok_or(anyhow!("Invalid url"))?;
-    debug!("Url type: {:?}", parsed_url);
-    let media_collection = match parsed_url {
-        UrlType::Series(id)
-        | UrlType::MovieListing(id)
-        | UrlType::EpisodeOrMovie(id)
-        | UrlType::MusicVideo(id)
-        | UrlType::Concert(id) => crunchy.media_collection_from_id(id).await?,
-    };
-
-    Ok((media_collection, url_filter))
+    Ok((url, url_filter))
 }
 
 /// Parse a resolution given as a [`String`] to a [`crunchyroll_rs::media::Resolution`].
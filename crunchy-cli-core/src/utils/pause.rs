@@ -0,0 +1,94 @@
+use crate::utils::log::progress_pause;
+use log::info;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+static PAUSED: AtomicBool = AtomicBool::new(false);
+/// If the listener may read from stdin. Disabled while interactive prompts are shown, as they read
+/// from the terminal too. The listener holds the lock while it reads, so once
+/// [`suspend_pause_keys`] returns, no input is taken away from a prompt.
+static LISTENING: Mutex<bool> = Mutex::new(true);
+
+/// Starts a background thread which pauses downloads if `p` and resumes them if `r` is entered
+/// (each followed by enter). Only active if stdin and stdout are terminals. Calling it multiple
+/// times starts only one thread.
+pub fn listen_for_pause_keys() {
+    #[cfg(not(target_os = "windows"))]
+    {
+        use std::io::{stdin, stdout, BufRead, IsTerminal};
+        use std::os::fd::AsFd;
+
+        static LISTENER_STARTED: AtomicBool = AtomicBool::new(false);
+
+        if !stdin().is_terminal()
+            || !stdout().is_terminal()
+            || LISTENER_STARTED.swap(true, Ordering::SeqCst)
+        {
+            return;
+        }
+
+        std::thread::spawn(|| loop {
+            if !*LISTENING.lock().unwrap() {
+                std::thread::sleep(Duration::from_millis(200));
+                continue;
+            }
+            // stdin is polled instead of read directly, so that the thread doesn't block in a read
+            // while a prompt is shown
+            let stdin = stdin();
+            let mut fds = [nix::poll::PollFd::new(
+                stdin.as_fd(),
+                nix::poll::PollFlags::POLLIN,
+            )];
+            match nix::poll::poll(&mut fds, nix::poll::PollTimeout::from(200u16)) {
+                Ok(0) => continue,
+                Ok(_) => (),
+                Err(_) => return,
+            }
+            // a prompt may have been opened while polling. the input then belongs to the prompt.
+            // as the terminal is line buffered, a full line is available if stdin is readable, so
+            // the read doesn't block while holding the lock
+            let listening = LISTENING.lock().unwrap();
+            if !*listening {
+                continue;
+            }
+            let mut line = String::new();
+            let read = stdin.lock().read_line(&mut line).unwrap_or_default();
+            drop(listening);
+            if read == 0 {
+                return;
+            }
+            match line.trim() {
+                "p" => set_paused(true),
+                "r" => set_paused(false),
+                _ => (),
+            }
+        });
+    }
+}
+
+/// Stops reading from stdin until it's enabled again. Must be used around interactive prompts.
+/// If the listener is currently reading a line, this waits until it's done.
+pub fn suspend_pause_keys(suspend: bool) {
+    *LISTENING.lock().unwrap() = !suspend
+}
+
+fn set_paused(paused: bool) {
+    if PAUSED.swap(paused, Ordering::SeqCst) == paused {
+        return;
+    }
+    if paused {
+        info!("Paused downloads after the current segments, enter 'r' to resume");
+        progress_pause!()
+    } else {
+        progress_pause!();
+        info!("Resumed downloads")
+    }
+}
+
+/// Waits until downloads are resumed. Returns immediately if they aren't paused.
+pub async fn wait_while_paused() {
+    while PAUSED.load(Ordering::SeqCst) {
+        tokio::time::sleep(Duration::from_millis(200)).await
+    }
+}
@@ -0,0 +1,112 @@
+use crate::utils::format::SingleFormat;
+use anyhow::{bail, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Additional ffmpeg output arguments for specific episodes. The map file is a json or toml
+/// object whose keys identify the episodes and whose values are the arguments, e.g.
+/// `{"G6JQ1WJ2R": "-vf yadif -c:v libx264"}`. Episodes can be identified by their id, by season and
+/// episode number (`S01E05`) or by episode number only (`5`). If multiple keys match an episode,
+/// the most specific one (in this order) is used.
+#[derive(Clone, Debug, Default)]
+pub struct PresetMap {
+    args: HashMap<String, Vec<String>>,
+}
+
+impl PresetMap {
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = fs::read_to_string(path)?;
+        let raw: HashMap<String, String> = if path.extension().is_some_and(|e| e == "toml") {
+            toml::from_str(&data)?
+        } else {
+            serde_json::from_str(&data)?
+        };
+
+        let mut args = HashMap::new();
+        for (key, value) in raw {
+            let Some(split) = shlex::split(&value) else {
+                bail!(
+                    "Invalid ffmpeg arguments for '{}' in {}: {}",
+                    key,
+                    path.to_string_lossy(),
+                    value
+                )
+            };
+            args.insert(normalize_key(&key), split);
+        }
+        Ok(Self { args })
+    }
+
+    /// The arguments configured for `single_format`, if any.
+    pub fn args_for(&self, single_format: &SingleFormat) -> Option<&Vec<String>> {
+        [
+            single_format.episode_id.clone(),
+            format!(
+                "S{}E{}",
+                single_format.season_number, single_format.episode_number
+            ),
+            single_format.episode_number.clone(),
+        ]
+        .iter()
+        .find_map(|key| self.args.get(&normalize_key(key)))
+    }
+
+    /// The arguments of all configured episodes.
+    pub fn all_args(&self) -> impl Iterator<Item = &Vec<String>> {
+        self.args.values()
+    }
+}
+
+/// Makes keys comparable independent of case and zero padding, so e.g. `s1e5` matches `S01E05`
+/// and `05` matches `5`.
+fn normalize_key(key: &str) -> String {
+    let key = key.trim().to_uppercase();
+    let trim_number = |n: &str| {
+        let trimmed = n.trim_start_matches('0');
+        if trimmed.is_empty() || trimmed.starts_with('.') {
+            format!("0{}", trimmed)
+        } else {
+            trimmed.to_string()
+        }
+    };
+    let is_number = |n: &str| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit() || c == '.');
+
+    if is_number(&key) {
+        return trim_number(&key);
+    }
+    if let Some((season, episode)) = key.strip_prefix('S').and_then(|k| k.split_once('E')) {
+        if is_number(season) && is_number(episode) {
+            return format!("S{}E{}", trim_number(season), trim_number(episode));
+        }
+    }
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_toml_and_json() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let toml_path = dir.path().join("presets.toml");
+        fs::write(
+            &toml_path,
+            "# per episode presets\nS01E05 = \"-vf yadif -c:v libx264\"\n",
+        )?;
+        let json_path = dir.path().join("presets.json");
+        fs::write(&json_path, r#"{"S01E05": "-vf yadif -c:v libx264"}"#)?;
+
+        let expected = ["-vf", "yadif", "-c:v", "libx264"];
+        for path in [toml_path, json_path] {
+            let preset_map = PresetMap::load(&path)?;
+            assert_eq!(preset_map.args[&normalize_key("s1e5")], expected)
+        }
+
+        fs::write(dir.path().join("invalid.toml"), "S01E05 = 5\n")?;
+        assert!(PresetMap::load(&dir.path().join("invalid.toml")).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,193 @@
+use anyhow::Result;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QueueCommand {
+    Archive,
+    Download,
+}
+
+impl QueueCommand {
+    pub fn name(&self) -> &'static str {
+        match self {
+            QueueCommand::Archive => "archive",
+            QueueCommand::Download => "download",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct QueueJob {
+    pub id: u64,
+    pub command: QueueCommand,
+    /// Command line arguments of the command, without the command name itself.
+    pub args: Vec<String>,
+}
+
+impl QueueJob {
+    /// The full command line of the job, as it can be passed to the clap parser of the command.
+    pub fn command_line(&self) -> Vec<String> {
+        let mut command_line = vec![self.command.name().to_string()];
+        command_line.extend(self.args.iter().cloned());
+        command_line
+    }
+}
+
+/// A single line of the queue file.
+#[derive(Deserialize, Serialize)]
+#[serde(tag = "event", rename_all = "lowercase")]
+enum QueueEvent {
+    Added(QueueJob),
+    Completed { id: u64 },
+    Failed { id: u64, error: String },
+}
+
+/// A persistent job queue. The queue file is a journal with one json event per line which is only
+/// ever appended to, every event is synced to disk before it's considered written. A line which
+/// was only partially written because the process got killed is ignored when loading the queue.
+pub struct JobQueue {
+    path: PathBuf,
+    jobs: Vec<QueueJob>,
+    completed: BTreeSet<u64>,
+    failed: BTreeMap<u64, String>,
+}
+
+impl JobQueue {
+    /// Loads the queue from `path`. If the file does not exist, the queue is empty.
+    pub fn load(path: PathBuf) -> Result<Self> {
+        let mut queue = Self {
+            path,
+            jobs: vec![],
+            completed: BTreeSet::new(),
+            failed: BTreeMap::new(),
+        };
+        if !queue.path.exists() {
+            return Ok(queue);
+        }
+
+        let data = fs::read_to_string(&queue.path)?;
+        for (i, line) in data.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str::<QueueEvent>(line) {
+                Ok(QueueEvent::Added(job)) => queue.jobs.push(job),
+                Ok(QueueEvent::Completed { id }) => {
+                    queue.completed.insert(id);
+                    queue.failed.remove(&id);
+                }
+                Ok(QueueEvent::Failed { id, error }) => {
+                    queue.failed.insert(id, error);
+                }
+                Err(e) => warn!(
+                    "Ignoring invalid line {} of queue file {}: {}",
+                    i + 1,
+                    queue.path.to_string_lossy(),
+                    e
+                ),
+            }
+        }
+        Ok(queue)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn jobs(&self) -> &[QueueJob] {
+        &self.jobs
+    }
+
+    /// All jobs which weren't completed yet, in the order they were added. Failed jobs are
+    /// pending too, as they are retried on the next run.
+    pub fn pending(&self) -> Vec<QueueJob> {
+        self.jobs
+            .iter()
+            .filter(|j| !self.completed.contains(&j.id))
+            .cloned()
+            .collect()
+    }
+
+    pub fn is_completed(&self, id: u64) -> bool {
+        self.completed.contains(&id)
+    }
+
+    /// The error of the last failed run of the job, if it failed and wasn't completed since then.
+    pub fn error(&self, id: u64) -> Option<&String> {
+        self.failed.get(&id)
+    }
+
+    pub fn add(&mut self, command: QueueCommand, args: Vec<String>) -> Result<QueueJob> {
+        let job = QueueJob {
+            id: self.jobs.iter().map(|j| j.id + 1).max().unwrap_or(1),
+            command,
+            args,
+        };
+        self.append(&QueueEvent::Added(job.clone()))?;
+        self.jobs.push(job.clone());
+        Ok(job)
+    }
+
+    pub fn set_completed(&mut self, id: u64) -> Result<()> {
+        self.append(&QueueEvent::Completed { id })?;
+        self.completed.insert(id);
+        self.failed.remove(&id);
+        Ok(())
+    }
+
+    pub fn set_failed(&mut self, id: u64, error: &anyhow::Error) -> Result<()> {
+        let error = error.to_string();
+        self.append(&QueueEvent::Failed {
+            id,
+            error: error.clone(),
+        })?;
+        self.failed.insert(id, error);
+        Ok(())
+    }
+
+    /// Path of the batch state file of the job with the given id, which is used to resume the job
+    /// if it was interrupted.
+    pub fn state_file(&self, id: u64) -> PathBuf {
+        let mut file_name = self.path.file_name().unwrap_or_default().to_os_string();
+        file_name.push(format!(".{}.state.json", id));
+        self.path.with_file_name(file_name)
+    }
+
+    fn append(&self, event: &QueueEvent) -> Result<()> {
+        let parent = self
+            .path
+            .parent()
+            .filter(|p| !p.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        if !parent.exists() {
+            fs::create_dir_all(parent)?
+        }
+
+        let mut file = fs::OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&self.path)?;
+        let mut line = serde_json::to_string(event)?;
+        line.push('\n');
+        // if the last line was only partially written, the new event must not be appended to it
+        if file.metadata()?.len() > 0 {
+            let mut last = [0u8];
+            file.seek(SeekFrom::End(-1))?;
+            file.read_exact(&mut last)?;
+            if last[0] != b'\n' {
+                line.insert(0, '\n')
+            }
+        }
+        // the line is written at once, so that concurrent writers don't interleave their events
+        file.write_all(line.as_bytes())?;
+        file.sync_all()?;
+        Ok(())
+    }
+}
@@ -1,27 +1,146 @@
+use crate::utils::download::describe_request_error;
+use crate::utils::http_timing::HttpTimings;
 use async_speed_limit::Limiter;
 use crunchyroll_rs::error::Error;
 use futures_util::TryStreamExt;
-use reqwest::{Client, Request, Response, ResponseBuilderExt};
+use log::{debug, warn};
+use reqwest::header::CONTENT_TYPE;
+use reqwest::{Client, Request, Response, ResponseBuilderExt, StatusCode};
 use std::future::Future;
 use std::io;
 use std::pin::Pin;
 use std::sync::Arc;
 use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
 use tower_service::Service;
 
+/// The request rate never drops below this value (requests per second).
+pub const MIN_REQUEST_RATE: f64 = 0.5;
+/// Number of consecutive successful requests after which the request rate is increased.
+const SUCCESS_WINDOW: u32 = 20;
+/// Interval in which requests are retried while Crunchyroll is under maintenance.
+const MAINTENANCE_RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+struct AdaptiveRequestRateState {
+    rate: f64,
+    max_rate: f64,
+    successes: u32,
+    next_request: Instant,
+}
+
+/// Limits the number of requests per second and adjusts the limit AIMD-style (additive increase,
+/// multiplicative decrease): the rate is halved on every 429 (too many requests) response and
+/// slowly increased again, up to the initial rate, after sustained success.
+#[derive(Clone)]
+pub struct AdaptiveRequestRate {
+    state: Arc<std::sync::Mutex<AdaptiveRequestRateState>>,
+}
+
+impl AdaptiveRequestRate {
+    pub fn new(max_rate: f64) -> Self {
+        let max_rate = if max_rate.is_finite() {
+            max_rate.max(MIN_REQUEST_RATE)
+        } else {
+            MIN_REQUEST_RATE
+        };
+        Self {
+            state: Arc::new(std::sync::Mutex::new(AdaptiveRequestRateState {
+                rate: max_rate,
+                max_rate,
+                successes: 0,
+                next_request: Instant::now(),
+            })),
+        }
+    }
+
+    /// Waits until the next request may be sent with the current rate.
+    pub async fn acquire(&self) {
+        let wait = {
+            let mut state = self.state.lock().unwrap();
+            let now = Instant::now();
+            let slot = state.next_request.max(now);
+            state.next_request =
+                slot + Duration::try_from_secs_f64(1.0 / state.rate).unwrap_or(Duration::ZERO);
+            slot - now
+        };
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await
+        }
+    }
+
+    pub fn on_success(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.successes += 1;
+        if state.successes >= SUCCESS_WINDOW && state.rate < state.max_rate {
+            state.successes = 0;
+            state.rate =
+                (state.rate + state.max_rate / 10.0).clamp(MIN_REQUEST_RATE, state.max_rate);
+            debug!("Increased request rate to {:.2} requests/s", state.rate)
+        }
+    }
+
+    pub fn on_throttled(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.successes = 0;
+        state.rate = (state.rate / 2.0).max(MIN_REQUEST_RATE);
+        debug!(
+            "Got rate limited, decreased request rate to {:.2} requests/s",
+            state.rate
+        )
+    }
+}
+
 #[derive(Clone)]
 pub struct RateLimiterService {
     client: Arc<Client>,
-    rate_limiter: Limiter,
+    rate_limiter: Option<Limiter>,
+    request_rate: Option<AdaptiveRequestRate>,
+    maintenance_check: bool,
+    maintenance_wait: Option<Duration>,
+    http_timings: Option<HttpTimings>,
 }
 
 impl RateLimiterService {
-    pub fn new(bytes: u32, client: Client) -> Self {
+    pub fn new(
+        bytes: Option<u32>,
+        request_rate: Option<AdaptiveRequestRate>,
+        client: Client,
+    ) -> Self {
         Self {
             client: Arc::new(client),
-            rate_limiter: Limiter::new(bytes as f64),
+            rate_limiter: bytes.map(|b| Limiter::new(b as f64)),
+            request_rate,
+            maintenance_check: false,
+            maintenance_wait: None,
+            http_timings: None,
         }
     }
+
+    /// Detects maintenance pages of Crunchyroll and returns a clear error instead of passing them
+    /// on, where they would cause confusing json errors. If `wait` is set, the request is retried
+    /// until the maintenance is over or `wait` has elapsed.
+    pub fn with_maintenance_check(mut self, wait: Option<Duration>) -> Self {
+        self.maintenance_check = true;
+        self.maintenance_wait = wait;
+        self
+    }
+
+    /// Logs the timing breakdown of every request, see [`HttpTimings::log_request`].
+    pub fn with_http_timings(mut self, http_timings: Option<HttpTimings>) -> Self {
+        self.http_timings = http_timings;
+        self
+    }
+}
+
+/// During maintenance, Crunchyroll responds with a server error and a html page instead of json.
+/// Server errors with a json body are real errors and not treated as maintenance.
+fn is_maintenance(res: &Response) -> bool {
+    res.status().is_server_error()
+        && res
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|c| c.to_str().ok())
+            .is_some_and(|c| c.starts_with("text/html"))
 }
 
 impl Service<Request> for RateLimiterService {
@@ -33,13 +152,83 @@ impl Service<Request> for RateLimiterService {
         Poll::Ready(Ok(()))
     }
 
-    fn call(&mut self, req: Request) -> Self::Future {
+    fn call(&mut self, mut req: Request) -> Self::Future {
         let client = self.client.clone();
         let rate_limiter = self.rate_limiter.clone();
+        let request_rate = self.request_rate.clone();
+        let maintenance_check = self.maintenance_check;
+        let maintenance_wait = self.maintenance_wait;
+        let http_timings = self.http_timings.clone();
+        let method = req.method().clone();
 
         Box::pin(async move {
             let mut body = vec![];
-            let res = client.execute(req).await?;
+            let start = Instant::now();
+            let mut request_start;
+            let res = loop {
+                let retry_req = if maintenance_check {
+                    req.try_clone()
+                } else {
+                    None
+                };
+
+                if let Some(request_rate) = &request_rate {
+                    request_rate.acquire().await
+                }
+                // the time waiting for the request rate isn't part of the request timing
+                request_start = Instant::now();
+                let url = req.url().clone();
+                let res = client.execute(req).await.map_err(|e| {
+                    let status = e.status();
+                    let message = describe_request_error(e, "waiting for the response").to_string();
+                    if let Some(http_timings) = &http_timings {
+                        http_timings.log_failed_request(
+                            &method,
+                            &url,
+                            request_start.elapsed(),
+                            &message,
+                        )
+                    }
+                    Error::Request {
+                        url: url.to_string(),
+                        status,
+                        message,
+                    }
+                })?;
+                if let Some(request_rate) = &request_rate {
+                    if res.status() == StatusCode::TOO_MANY_REQUESTS {
+                        request_rate.on_throttled()
+                    } else if res.status().is_success() {
+                        request_rate.on_success()
+                    }
+                }
+
+                if !maintenance_check || !is_maintenance(&res) {
+                    break res;
+                }
+                match (maintenance_wait, retry_req) {
+                    (Some(wait), Some(retry_req)) if start.elapsed() < wait => {
+                        warn!(
+                            "Crunchyroll appears to be under maintenance, retrying in {} seconds",
+                            MAINTENANCE_RETRY_INTERVAL.as_secs()
+                        );
+                        tokio::time::sleep(MAINTENANCE_RETRY_INTERVAL).await;
+                        req = retry_req
+                    }
+                    _ => {
+                        return Err(Error::Request {
+                            url: res.url().to_string(),
+                            status: Some(res.status()),
+                            message: format!(
+                                "Crunchyroll appears to be under maintenance ({}), try again later",
+                                res.status()
+                            ),
+                        })
+                    }
+                }
+            };
+            let ttfb = request_start.elapsed();
+            let res_url = res.url().clone();
             let _url = res.url().clone().to_string();
             let url = _url.as_str();
 
@@ -53,21 +242,103 @@ impl Service<Request> for RateLimiterService {
                 .unwrap()
                 .clone_from(&res.extensions());
 
-            let limiter = rate_limiter.limit(
-                res.bytes_stream()
-                    .map_err(io::Error::other)
-                    .into_async_read(),
-            );
-
-            futures_util::io::copy(limiter, &mut body)
-                .await
-                .map_err(|e| Error::Request {
+            let stream = res
+                .bytes_stream()
+                .map_err(|e| {
+                    io::Error::other(describe_request_error(e, "reading the response body"))
+                })
+                .into_async_read();
+            let copied = if let Some(rate_limiter) = rate_limiter {
+                futures_util::io::copy(rate_limiter.limit(stream), &mut body).await
+            } else {
+                futures_util::io::copy(stream, &mut body).await
+            };
+            copied.map_err(|e| {
+                if let Some(http_timings) = &http_timings {
+                    http_timings.log_failed_request(
+                        &method,
+                        &res_url,
+                        request_start.elapsed(),
+                        &e.to_string(),
+                    )
+                }
+                Error::Request {
                     url: url.to_string(),
                     status: None,
                     message: e.to_string(),
-                })?;
+                }
+            })?;
+            if let Some(http_timings) = &http_timings {
+                http_timings.log_request(&method, &res_url, ttfb, request_start.elapsed())
+            }
 
             Ok(Response::from(http_res.body(body).unwrap()))
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(status: u16, content_type: &str) -> Response {
+        Response::from(
+            http::Response::builder()
+                .status(status)
+                .header(CONTENT_TYPE, content_type)
+                .body("")
+                .unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_is_maintenance() {
+        assert!(is_maintenance(&response(503, "text/html")));
+        assert!(is_maintenance(&response(500, "text/html; charset=utf-8")));
+        // real server errors of the api
+        assert!(!is_maintenance(&response(503, "application/json")));
+        assert!(!is_maintenance(&response(200, "text/html")));
+        assert!(!is_maintenance(&response(404, "text/html")));
+    }
+
+    fn rate(request_rate: &AdaptiveRequestRate) -> f64 {
+        request_rate.state.lock().unwrap().rate
+    }
+
+    #[test]
+    fn test_adaptive_request_rate() {
+        let request_rate = AdaptiveRequestRate::new(4.0);
+        request_rate.on_throttled();
+        assert_eq!(rate(&request_rate), 2.0);
+        for _ in 0..10 {
+            request_rate.on_throttled()
+        }
+        assert_eq!(rate(&request_rate), MIN_REQUEST_RATE);
+
+        for _ in 0..SUCCESS_WINDOW - 1 {
+            request_rate.on_success()
+        }
+        assert_eq!(rate(&request_rate), MIN_REQUEST_RATE);
+        request_rate.on_success();
+        assert_eq!(rate(&request_rate), MIN_REQUEST_RATE + 0.4);
+        for _ in 0..SUCCESS_WINDOW * 100 {
+            request_rate.on_success()
+        }
+        assert_eq!(rate(&request_rate), 4.0)
+    }
+
+    #[tokio::test]
+    async fn test_adaptive_request_rate_invalid_rates() {
+        // none of these may panic when calculating the interval between two requests
+        for max_rate in [0.0, -1.0, 1e-300, f64::NAN, f64::INFINITY] {
+            let request_rate = AdaptiveRequestRate::new(max_rate);
+            assert_eq!(rate(&request_rate), MIN_REQUEST_RATE);
+            request_rate.on_throttled();
+            request_rate.on_success();
+            request_rate.acquire().await
+        }
+        let request_rate = AdaptiveRequestRate::new(f64::MAX);
+        request_rate.acquire().await;
+        request_rate.acquire().await
+    }
+}
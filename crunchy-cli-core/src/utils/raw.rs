@@ -0,0 +1,88 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RawStreamKind {
+    Video,
+    Audio,
+    Subtitle,
+    Chapters,
+    Font,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RawStream {
+    pub kind: RawStreamKind,
+    /// File name of the stream, relative to the raw directory.
+    pub file: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub locale: Option<String>,
+}
+
+/// Describes the streams which were stored by `--raw` instead of being muxed. It's written as
+/// `manifest.json` into the raw directory and contains everything which is needed to mux the
+/// streams later on.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RawManifest {
+    /// File name of the output file. The file is created next to the raw directory.
+    pub output: String,
+    /// Number of video frames of the output, used to show the muxing progress.
+    pub frames: u64,
+    pub streams: Vec<RawStream>,
+    /// The ffmpeg arguments without the output file. Paths to the streams are prefixed with
+    /// [`RawManifest::DIR_PLACEHOLDER`].
+    pub ffmpeg_args: Vec<String>,
+}
+
+impl RawManifest {
+    pub const FILE_NAME: &'static str = "manifest.json";
+    pub const DIR_PLACEHOLDER: &'static str = "{raw_dir}";
+
+    /// The directory in which the raw streams of `dst` are stored.
+    pub fn directory(dst: &Path) -> PathBuf {
+        dst.with_file_name(format!(
+            "{}.raw",
+            dst.file_name().unwrap_or_default().to_string_lossy()
+        ))
+    }
+
+    pub fn load(dir: &Path) -> Result<Self> {
+        Ok(serde_json::from_slice(&fs::read(
+            dir.join(Self::FILE_NAME),
+        )?)?)
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<()> {
+        fs::write(dir.join(Self::FILE_NAME), serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Path of the output file if the raw streams are stored in `dir`.
+    pub fn output_path(&self, dir: &Path) -> PathBuf {
+        dir.parent().unwrap_or(Path::new("")).join(&self.output)
+    }
+
+    /// The ffmpeg arguments with the actual paths of the streams in `dir`.
+    pub fn ffmpeg_args(&self, dir: &Path) -> Vec<String> {
+        let dir = dir.to_string_lossy().to_string();
+        // the ass filter requires ':' and '\' to be escaped, which are used in windows paths
+        let escaped_dir = if cfg!(windows) {
+            dir.replace('\\', "\\\\").replace(':', "\\:")
+        } else {
+            dir.clone()
+        };
+        self.ffmpeg_args
+            .iter()
+            .map(|arg| {
+                if arg.starts_with("ass=") {
+                    arg.replace(Self::DIR_PLACEHOLDER, &escaped_dir)
+                } else {
+                    arg.replace(Self::DIR_PLACEHOLDER, &dir)
+                }
+            })
+            .collect()
+    }
+}
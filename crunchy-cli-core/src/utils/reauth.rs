@@ -0,0 +1,41 @@
+use chrono::Utc;
+use crunchyroll_rs::Crunchyroll;
+use log::{debug, warn};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+/// Renews the session in the background as soon as it expires. Crunchyroll access tokens are only
+/// valid for a few minutes and are renewed by the first api request after they've expired, so the
+/// task sleeps until the current token expires and then requests the account details, which
+/// forces the renewal. This way it happens between the requests of the actual command instead of
+/// delaying them. The expiry is checked at least every `interval`, as a request of the command may
+/// have renewed the token in the meantime. The token is shared by all clones of `crunchy`, so
+/// in-flight requests wait for a running renewal instead of starting their own.
+pub fn spawn_reauth_task(crunchy: Crunchyroll, interval: Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            let expires_in = (crunchy.session_expire().await - Utc::now())
+                .to_std()
+                .unwrap_or_default();
+            if !expires_in.is_zero() {
+                // the token is only renewed if it's expired when the request is made, the extra
+                // second prevents a request right before it expires
+                tokio::time::sleep((expires_in + Duration::from_secs(1)).min(interval)).await;
+                continue;
+            }
+            match crunchy.account().await {
+                Ok(_) if crunchy.session_expire().await > Utc::now() => {
+                    debug!("Proactively refreshed the session")
+                }
+                // e.g. anonymous sessions aren't renewed. the task waits for the next check
+                // instead of requesting the account over and over again
+                Ok(_) => tokio::time::sleep(interval).await,
+                // the next request of the command renews the token anyway, so this isn't fatal
+                Err(e) => {
+                    warn!("Failed to proactively refresh the session: {}", e);
+                    tokio::time::sleep(interval).await
+                }
+            }
+        }
+    })
+}
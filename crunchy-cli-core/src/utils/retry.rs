@@ -0,0 +1,144 @@
+use chrono::{DateTime, Utc};
+use reqwest::header::RETRY_AFTER;
+use reqwest::Response;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+
+/// Minimal gap between two retries, regardless of which download thread they come from.
+const MIN_RETRY_GAP: Duration = Duration::from_millis(250);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Spaces out the retries of concurrent downloads. Without it, all download threads would retry
+/// at the same time if the server fails for a moment, and the burst of requests may trigger the
+/// rate limiting or bot protection of Crunchyroll.
+#[derive(Clone, Default)]
+pub struct RetryThrottle {
+    next_retry: Arc<Mutex<Option<Instant>>>,
+}
+
+impl RetryThrottle {
+    /// Waits before retry number `retry_count` (starting at 0). If the server requested a delay
+    /// via `Retry-After`, it is used instead of the exponential backoff. The time is reserved
+    /// while holding the lock, so concurrent retries are always at least [`MIN_RETRY_GAP`] apart.
+    pub async fn wait(&self, retry_count: u32, retry_after: Option<Duration>) -> Duration {
+        let delay = retry_after.unwrap_or_else(|| backoff(retry_count));
+
+        let retry_at = {
+            let mut next_retry = self.next_retry.lock().await;
+            let mut retry_at = Instant::now() + delay;
+            if let Some(next) = *next_retry {
+                retry_at = retry_at.max(next)
+            }
+            *next_retry = Some(retry_at + MIN_RETRY_GAP);
+            retry_at
+        };
+
+        let wait = retry_at.saturating_duration_since(Instant::now());
+        tokio::time::sleep_until(retry_at).await;
+        wait
+    }
+}
+
+/// Exponential backoff, starting at 500 milliseconds, with up to 250 milliseconds of jitter.
+fn backoff(retry_count: u32) -> Duration {
+    let backoff = Duration::from_millis(500)
+        .saturating_mul(2u32.saturating_pow(retry_count))
+        .min(MAX_BACKOFF);
+    // the std hasher is randomly seeded, which is enough randomness to not let multiple threads
+    // retry in lockstep
+    let jitter = RandomState::new().build_hasher().finish() % 250;
+    backoff + Duration::from_millis(jitter)
+}
+
+/// Returns the delay the server requested via the `Retry-After` header of a response. The header
+/// may either contain the seconds to wait or a http date.
+pub fn retry_after(response: &Response) -> Option<Duration> {
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds).min(Duration::from_secs(300)));
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    (date.with_timezone(&Utc) - Utc::now())
+        .to_std()
+        .ok()
+        .map(|d| d.min(Duration::from_secs(300)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(retry_after: Option<&str>) -> Response {
+        let mut builder = http::Response::builder().status(429);
+        if let Some(retry_after) = retry_after {
+            builder = builder.header(RETRY_AFTER, retry_after)
+        }
+        Response::from(builder.body("").unwrap())
+    }
+
+    #[test]
+    fn test_retry_after() {
+        assert_eq!(
+            retry_after(&response(Some("5"))),
+            Some(Duration::from_secs(5))
+        );
+        // unreasonably long delays are capped
+        assert_eq!(
+            retry_after(&response(Some("86400"))),
+            Some(Duration::from_secs(300))
+        );
+
+        let date = (Utc::now() + chrono::Duration::seconds(60)).to_rfc2822();
+        let delay = retry_after(&response(Some(&date))).unwrap();
+        assert!(delay > Duration::from_secs(55) && delay <= Duration::from_secs(60));
+        let date = (Utc::now() - chrono::Duration::seconds(60)).to_rfc2822();
+        assert_eq!(retry_after(&response(Some(&date))), None);
+
+        assert_eq!(retry_after(&response(Some("soon"))), None);
+        assert_eq!(retry_after(&response(None)), None);
+    }
+
+    #[test]
+    fn test_backoff() {
+        for (retry_count, min) in [(0, 500), (1, 1000), (3, 4000), (20, 30000)] {
+            let backoff = backoff(retry_count);
+            assert!(
+                backoff >= Duration::from_millis(min) && backoff < Duration::from_millis(min + 250),
+                "retry {}: {:?}",
+                retry_count,
+                backoff
+            )
+        }
+    }
+
+    #[tokio::test]
+    async fn test_throttle_respects_retry_after() {
+        let throttle = RetryThrottle::default();
+        let start = Instant::now();
+        throttle.wait(0, Some(Duration::from_millis(300))).await;
+        assert!(start.elapsed() >= Duration::from_millis(300))
+    }
+
+    #[tokio::test]
+    async fn test_throttle_spaces_out_concurrent_retries() {
+        let throttle = RetryThrottle::default();
+        let start = Instant::now();
+        // three download threads get a 429 without a delay at the same time
+        let (a, b, c) = tokio::join!(
+            throttle.wait(0, Some(Duration::ZERO)),
+            throttle.wait(0, Some(Duration::ZERO)),
+            throttle.wait(0, Some(Duration::ZERO))
+        );
+        let mut waited = [a, b, c];
+        waited.sort();
+
+        assert!(waited[0] < MIN_RETRY_GAP);
+        assert!(waited[1] >= MIN_RETRY_GAP - Duration::from_millis(10));
+        assert!(waited[2] >= MIN_RETRY_GAP * 2 - Duration::from_millis(10));
+        assert!(start.elapsed() >= MIN_RETRY_GAP * 2)
+    }
+}
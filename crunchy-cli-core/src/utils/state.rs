@@ -0,0 +1,247 @@
+use crate::utils::format::SingleFormat;
+use crate::utils::webhook;
+use anyhow::Result;
+use log::error;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BatchUrlStatus {
+    Pending,
+    Completed,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct FailedVideo {
+    pub title: String,
+    pub error: String,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+struct BatchStateFile {
+    urls: BTreeMap<String, BatchUrlStatus>,
+    /// Ids of all seasons which were already downloaded.
+    #[serde(default)]
+    known_seasons: BTreeSet<String>,
+    /// Videos which failed to download, by their url.
+    #[serde(default)]
+    failed: BTreeMap<String, FailedVideo>,
+    /// Fingerprints of the streams of all downloaded videos, by their url.
+    #[serde(default)]
+    fingerprints: BTreeMap<String, String>,
+}
+
+/// Tracks which urls of a batch were already processed completely. Every change is directly
+/// written to the state file so that an aborted batch can be continued later on.
+pub struct BatchState {
+    path: PathBuf,
+    state: BatchStateFile,
+}
+
+impl BatchState {
+    /// Creates a new state in which all `urls` are pending. Only the known seasons, failed videos
+    /// and fingerprints of an existing state at `path` are kept, everything else gets overwritten.
+    pub fn new(path: PathBuf, urls: &[String]) -> Result<Self> {
+        let (known_seasons, failed, fingerprints) = fs::read(&path)
+            .ok()
+            .and_then(|data| serde_json::from_slice::<BatchStateFile>(&data).ok())
+            .map(|state| (state.known_seasons, state.failed, state.fingerprints))
+            .unwrap_or_default();
+        let mut state = Self {
+            path,
+            state: BatchStateFile {
+                known_seasons,
+                failed,
+                fingerprints,
+                ..Default::default()
+            },
+        };
+        for url in urls {
+            state
+                .state
+                .urls
+                .insert(url.clone(), BatchUrlStatus::Pending);
+        }
+        state.save()?;
+        Ok(state)
+    }
+
+    /// Loads the state from `path` and adds all `urls` which aren't tracked yet. If the file does
+    /// not exist, a new state is created.
+    pub fn load(path: PathBuf, urls: &[String]) -> Result<Self> {
+        if !path.exists() {
+            return Self::new(path, urls);
+        }
+
+        let mut state = Self {
+            state: serde_json::from_slice(&fs::read(&path)?)?,
+            path,
+        };
+        for url in urls {
+            state
+                .state
+                .urls
+                .entry(url.clone())
+                .or_insert(BatchUrlStatus::Pending);
+        }
+        state.save()?;
+        Ok(state)
+    }
+
+    pub fn is_completed(&self, url: &str) -> bool {
+        self.state.urls.get(url) == Some(&BatchUrlStatus::Completed)
+    }
+
+    pub fn set_status(&mut self, url: &str, status: BatchUrlStatus) -> Result<()> {
+        self.state.urls.insert(url.to_string(), status);
+        self.save()
+    }
+
+    pub fn known_seasons(&self) -> HashSet<String> {
+        self.state.known_seasons.iter().cloned().collect()
+    }
+
+    pub fn add_known_seasons(&mut self, season_ids: Vec<String>) -> Result<()> {
+        self.state.known_seasons.extend(season_ids);
+        self.save()
+    }
+
+    /// Urls of all videos which failed to download.
+    pub fn failed_urls(&self) -> Vec<String> {
+        self.state.failed.keys().cloned().collect()
+    }
+
+    pub fn add_failed(&mut self, url: String, title: String, error: &anyhow::Error) -> Result<()> {
+        self.state.failed.insert(
+            url,
+            FailedVideo {
+                title,
+                error: error.to_string(),
+            },
+        );
+        self.save()
+    }
+
+    /// Removes the video with the given url from the failed videos, e.g. because a retry
+    /// succeeded.
+    pub fn remove_failed(&mut self, url: &str) -> Result<()> {
+        if self.state.failed.remove(url).is_some() {
+            self.save()?
+        }
+        Ok(())
+    }
+
+    /// If the streams of the video with the given url changed since it was downloaded, according
+    /// to their fingerprint. Videos without a recorded fingerprint (e.g. because they were
+    /// downloaded before fingerprints were recorded) are considered as unchanged and `fingerprint`
+    /// gets recorded for them.
+    pub fn is_updated(&mut self, url: &str, fingerprint: &str) -> Result<bool> {
+        match self.state.fingerprints.get(url) {
+            Some(recorded) => Ok(recorded != fingerprint),
+            None => {
+                self.set_fingerprint(url.to_string(), fingerprint.to_string())?;
+                Ok(false)
+            }
+        }
+    }
+
+    pub fn set_fingerprint(&mut self, url: String, fingerprint: String) -> Result<()> {
+        if self.state.fingerprints.get(&url) != Some(&fingerprint) {
+            self.state.fingerprints.insert(url, fingerprint);
+            self.save()?
+        }
+        Ok(())
+    }
+
+    /// Writes the state to a temporary file first and replaces the actual state file with it
+    /// afterwards. This way the state file never ends up half-written, even if the process gets
+    /// killed while writing.
+    fn save(&self) -> Result<()> {
+        let parent = self
+            .path
+            .parent()
+            .filter(|p| !p.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        if !parent.exists() {
+            fs::create_dir_all(parent)?
+        }
+
+        let mut file = tempfile::NamedTempFile::new_in(parent)?;
+        file.write_all(&serde_json::to_vec_pretty(&self.state)?)?;
+        file.as_file().sync_all()?;
+        file.persist(&self.path)?;
+        Ok(())
+    }
+}
+
+/// If a state file is used, the failed video is recorded in it so that the remaining videos can
+/// still be downloaded. Otherwise the error is returned as is.
+pub fn record_failure(
+    batch_state: Option<&mut BatchState>,
+    single_format: &SingleFormat,
+    error: anyhow::Error,
+) -> Result<()> {
+    webhook::episode_failed(single_format, &error);
+    let Some(batch_state) = batch_state else {
+        return Err(error);
+    };
+    error!("Failed to download {}: {}", single_format.title, error);
+    batch_state.add_failed(single_format.url(), single_format.title.clone(), &error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn urls() -> Vec<String> {
+        ["url-1", "url-2", "url-3"]
+            .into_iter()
+            .map(|s| s.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_resume_after_crash() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("state.json");
+
+        let mut state = BatchState::new(path.clone(), &urls())?;
+        state.set_status("url-1", BatchUrlStatus::Completed)?;
+        state.add_known_seasons(vec!["season-1".to_string()])?;
+        // the batch gets aborted while the second url is processed, so the state is never
+        // written again
+        drop(state);
+        // a partial write of an interrupted save only ever affects the temporary file
+        fs::write(dir.path().join(".tmpABCDEF"), b"{\"urls\": {")?;
+
+        let state = BatchState::load(path.clone(), &urls())?;
+        assert!(state.is_completed("url-1"));
+        assert!(!state.is_completed("url-2"));
+        assert!(!state.is_completed("url-3"));
+        assert!(state.known_seasons().contains("season-1"));
+
+        // without resuming, the progress is reset but the known seasons are kept
+        let state = BatchState::new(path, &urls())?;
+        assert!(!state.is_completed("url-1"));
+        assert!(state.known_seasons().contains("season-1"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_adds_new_urls() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("state.json");
+
+        let mut state = BatchState::new(path.clone(), &urls()[..1])?;
+        state.set_status("url-1", BatchUrlStatus::Completed)?;
+
+        let state = BatchState::load(path, &urls())?;
+        assert!(state.is_completed("url-1"));
+        assert!(!state.is_completed("url-2"));
+        Ok(())
+    }
+}
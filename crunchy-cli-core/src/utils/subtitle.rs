@@ -0,0 +1,662 @@
+use crunchyroll_rs::Locale;
+use encoding_rs::{EncoderResult, Encoding};
+use lazy_static::lazy_static;
+use regex::Regex;
+use rsubs_lib::ssa::SSAEventLineType;
+use rsubs_lib::SSA;
+use std::fmt::{Display, Formatter};
+use time::Time;
+
+lazy_static! {
+    static ref ASS_POSITION_REGEX: Regex = Regex::new(r"\{[^}]*\\(an?\d|pos|move)[^}]*}").unwrap();
+    static ref ASS_OVERRIDE_REGEX: Regex = Regex::new(r"\{[^}]*}").unwrap();
+    static ref ASS_DRAWING_REGEX: Regex = Regex::new(r"\{[^}]*\\p[1-9][^}]*}").unwrap();
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub enum SubtitleFormat {
+    #[default]
+    Ass,
+    Srt,
+    Vtt,
+}
+
+impl Display for SubtitleFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let value = match self {
+            SubtitleFormat::Ass => "ass",
+            SubtitleFormat::Srt => "srt",
+            SubtitleFormat::Vtt => "vtt",
+        };
+        write!(f, "{}", value)
+    }
+}
+
+impl SubtitleFormat {
+    pub fn parse(s: &str) -> Result<SubtitleFormat, String> {
+        Ok(match s.to_lowercase().as_str() {
+            "ass" | "ssa" => SubtitleFormat::Ass,
+            "srt" => SubtitleFormat::Srt,
+            "vtt" | "webvtt" => SubtitleFormat::Vtt,
+            _ => return Err(format!("'{}' is not a valid subtitle format", s)),
+        })
+    }
+
+    /// File extension of the format, without leading dot.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            SubtitleFormat::Ass => "ass",
+            SubtitleFormat::Srt => "srt",
+            SubtitleFormat::Vtt => "vtt",
+        }
+    }
+}
+
+/// Which events of ass subtitles are kept.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub enum SubtitleStyles {
+    #[default]
+    All,
+    /// Only dialogues, events with sign or song styles are removed.
+    DialogueOnly,
+}
+
+impl SubtitleStyles {
+    pub fn parse(s: &str) -> Result<SubtitleStyles, String> {
+        Ok(match s.to_lowercase().as_str() {
+            "all" => SubtitleStyles::All,
+            "dialogue-only" | "dialogue" => SubtitleStyles::DialogueOnly,
+            _ => return Err(format!("'{}' is not a valid subtitle style selection", s)),
+        })
+    }
+}
+
+/// Style names which are commonly used for signs, typesetting and songs (opening, ending, insert
+/// songs, karaoke).
+pub const DEFAULT_SIGN_STYLES: &[&str] = &[
+    "sign",
+    "signs",
+    "os",
+    "ts",
+    "typeset",
+    "typesetting",
+    "title",
+    "song",
+    "songs",
+    "op",
+    "ed",
+    "insert",
+    "lyrics",
+    "karaoke",
+    "kara",
+    "romaji",
+];
+
+/// Removes all dialogue lines of `ass` whose style matches one of `styles`. The header, styles
+/// and timings of the remaining events are unchanged. Returns the number of removed lines.
+pub fn drop_ass_styles(ass: &mut SSA, styles: &[String]) -> usize {
+    let len = ass.events.len();
+    ass.events.retain(|e| {
+        !matches!(e.line_type, SSAEventLineType::Dialogue) || !is_style_matching(&e.style, styles)
+    });
+    len - ass.events.len()
+}
+
+/// Removes all dialogue lines of `ass` whose style doesn't match one of `styles`, the inverse of
+/// [`drop_ass_styles`]. Returns the number of remaining lines.
+pub fn keep_ass_styles(ass: &mut SSA, styles: &[String]) -> usize {
+    ass.events.retain(|e| {
+        !matches!(e.line_type, SSAEventLineType::Dialogue) || is_style_matching(&e.style, styles)
+    });
+    ass.events
+        .iter()
+        .filter(|e| matches!(e.line_type, SSAEventLineType::Dialogue))
+        .count()
+}
+
+/// Checks if any word of the style name is one of `styles` (case-insensitive). Style names are
+/// split at non-alphanumeric characters, digits and camel case boundaries, so e.g. `Signs_Top`,
+/// `OP1` and `SongRomaji` match `signs`, `op` and `song`.
+fn is_style_matching(style: &str, styles: &[String]) -> bool {
+    let mut words = vec![];
+    let mut word = String::new();
+    let mut prev: Option<char> = None;
+    for c in style.chars() {
+        let boundary = match prev {
+            _ if !c.is_alphanumeric() => true,
+            Some(p) => (p.is_lowercase() && c.is_uppercase()) || (p.is_numeric() != c.is_numeric()),
+            None => false,
+        };
+        if boundary && !word.is_empty() {
+            words.push(word.to_lowercase());
+            word.clear()
+        }
+        if c.is_alphanumeric() {
+            word.push(c)
+        }
+        prev = Some(c)
+    }
+    if !word.is_empty() {
+        words.push(word.to_lowercase())
+    }
+
+    words
+        .iter()
+        .any(|w| styles.iter().any(|s| s.eq_ignore_ascii_case(w)))
+}
+
+/// Shifts the timings of subtitles by a fixed amount, either of all subtitles or only of the
+/// subtitles of a specific locale.
+#[derive(Clone, Debug)]
+pub struct SubtitleOffset {
+    pub locale: Option<Locale>,
+    pub millis: i64,
+}
+
+impl SubtitleOffset {
+    /// Parses an offset in milliseconds (e.g. `-250`), optionally prefixed with the locale it
+    /// applies to (e.g. `de-DE=500`).
+    pub fn parse(s: &str) -> Result<SubtitleOffset, String> {
+        let (locale, millis) = match s.split_once('=') {
+            Some((locale, millis)) => (Some(Locale::from(locale.to_string())), millis),
+            None => (None, s),
+        };
+        let Ok(millis) = millis.trim().parse() else {
+            return Err(format!(
+                "'{}' is not a valid offset in milliseconds",
+                millis
+            ));
+        };
+        Ok(SubtitleOffset { locale, millis })
+    }
+
+    /// The offset of subtitles with the given locale. An offset which is set for the locale
+    /// explicitly takes precedence over an offset for all subtitles.
+    pub fn for_locale(offsets: &[SubtitleOffset], locale: &Locale) -> i64 {
+        offsets
+            .iter()
+            .find(|o| o.locale.as_ref() == Some(locale))
+            .or_else(|| offsets.iter().find(|o| o.locale.is_none()))
+            .map_or(0, |o| o.millis)
+    }
+}
+
+/// Shifts all events of `ass` by `millis`. Timings which would be negative are clamped to zero,
+/// events which would end before the start of the video are removed.
+pub fn shift_ass(ass: &mut SSA, millis: i64) {
+    if millis == 0 {
+        return;
+    }
+    let shift = |time: Time| {
+        let shifted = (time_to_millis(time) as i64 + millis).max(0) as u64;
+        millis_to_time(shifted).unwrap_or(time)
+    };
+    for event in ass.events.iter_mut() {
+        event.start = shift(event.start);
+        event.end = shift(event.end);
+    }
+    ass.events.retain(|e| e.start < e.end)
+}
+
+/// Events of a dual subtitle which start or end within this many milliseconds of each other are
+/// aligned, so that the two languages appear and disappear at the same time.
+const DUAL_SUBTITLE_ALIGN_TOLERANCE: u64 = 300;
+
+/// Merges two subtitles into a single one which shows both languages at once. The dialogues of
+/// `top` are moved to the top of the video, the ones of `bottom` stay at their position. Lines
+/// which are positioned explicitly (e.g. signs) are not moved. The styles of `bottom` are prefixed
+/// with `Bottom ` to not clash with the ones of `top`.
+pub fn merge_dual_ass(mut top: SSA, bottom: SSA) -> SSA {
+    let mut top_timings = vec![];
+    for event in top.events.iter_mut() {
+        if !matches!(event.line_type, SSAEventLineType::Dialogue) {
+            continue;
+        }
+        if !ASS_POSITION_REGEX.is_match(&event.text) {
+            event.text = format!("{{\\an8}}{}", event.text)
+        }
+        top_timings.push((time_to_millis(event.start), time_to_millis(event.end)))
+    }
+
+    for mut style in bottom.styles {
+        style.name = format!("Bottom {}", style.name);
+        top.styles.push(style)
+    }
+    for mut event in bottom.events {
+        event.style = format!("Bottom {}", event.style);
+        if matches!(event.line_type, SSAEventLineType::Dialogue) {
+            let (start, end) = (time_to_millis(event.start), time_to_millis(event.end));
+            // the languages are timed independently, so a line of one language often starts or
+            // ends a few frames before the overlapping line of the other one
+            if let Some((top_start, top_end)) =
+                top_timings.iter().find(|(s, e)| *s < end && start < *e)
+            {
+                if start.abs_diff(*top_start) <= DUAL_SUBTITLE_ALIGN_TOLERANCE {
+                    event.start = millis_to_time(*top_start).unwrap_or(event.start)
+                }
+                if end.abs_diff(*top_end) <= DUAL_SUBTITLE_ALIGN_TOLERANCE {
+                    event.end = millis_to_time(*top_end).unwrap_or(event.end)
+                }
+            }
+        }
+        top.events.push(event)
+    }
+    top.events.sort_by(|a, b| a.start.cmp(&b.start));
+    top
+}
+
+#[derive(Debug, Eq, PartialEq)]
+struct Cue {
+    start: u64,
+    end: u64,
+    text: String,
+}
+
+/// Converts the events of an ASS subtitle to plain cues. All styling is stripped. Dialogues
+/// which are overlapping are merged, so that at every point of time at most one cue is shown
+/// (many SRT players can't display multiple cues at once).
+fn ass_to_cues(ass: &SSA) -> Vec<Cue> {
+    let mut events: Vec<Cue> = vec![];
+    for event in &ass.events {
+        // drawings (`{\p1}...`) are vector graphics and have no readable text
+        if ASS_DRAWING_REGEX.is_match(&event.text) {
+            continue;
+        }
+        let text = ass_plain_text(&event.text);
+        let (start, end) = (time_to_millis(event.start), time_to_millis(event.end));
+        if text.is_empty() || start >= end {
+            continue;
+        }
+        events.push(Cue { start, end, text })
+    }
+    merge_cues(events)
+}
+
+/// Strips all override tags of an ASS dialogue text and converts its line breaks.
+fn ass_plain_text(text: &str) -> String {
+    ASS_OVERRIDE_REGEX
+        .replace_all(text, "")
+        .replace("\\N", "\n")
+        .replace("\\n", "\n")
+        .replace("\\h", " ")
+        .lines()
+        .map(|l| l.trim())
+        .filter(|l| !l.is_empty())
+        .collect::<Vec<&str>>()
+        .join("\n")
+}
+
+/// Splits `events` at every start and end of an event, so that overlapping events become
+/// consecutive cues which contain the text of all events shown at that time (in the order of
+/// `events`).
+fn merge_cues(events: Vec<Cue>) -> Vec<Cue> {
+    let mut boundaries: Vec<u64> = events.iter().flat_map(|e| [e.start, e.end]).collect();
+    boundaries.sort();
+    boundaries.dedup();
+    let mut by_start: Vec<usize> = (0..events.len()).collect();
+    by_start.sort_by_key(|i| events[*i].start);
+
+    // a single pass over the boundaries which adds the events starting at a boundary and drops
+    // the ones which ended. only a few events are shown at the same time, so `active` stays small
+    let mut next = 0;
+    let mut active: Vec<usize> = vec![];
+    let mut cues: Vec<Cue> = vec![];
+    for window in boundaries.windows(2) {
+        let (start, end) = (window[0], window[1]);
+        while next < by_start.len() && events[by_start[next]].start <= start {
+            let i = by_start[next];
+            active.insert(active.partition_point(|a| *a < i), i);
+            next += 1
+        }
+        active.retain(|i| events[*i].end > start);
+        if active.is_empty() {
+            continue;
+        }
+        let text = active
+            .iter()
+            .map(|i| events[*i].text.as_str())
+            .collect::<Vec<&str>>()
+            .join("\n");
+        // extend the previous cue if it directly adjoins and has the same text
+        if let Some(last) = cues.last_mut() {
+            if last.end == start && last.text == text {
+                last.end = end;
+                continue;
+            }
+        }
+        cues.push(Cue { start, end, text })
+    }
+    cues
+}
+
+pub fn ass_to_srt(ass: &SSA) -> String {
+    let mut srt = String::new();
+    for (i, cue) in ass_to_cues(ass).into_iter().enumerate() {
+        srt.push_str(&format!(
+            "{}\n{} --> {}\n{}\n\n",
+            i + 1,
+            format_millis(cue.start, ','),
+            format_millis(cue.end, ','),
+            cue.text
+        ))
+    }
+    srt
+}
+
+pub fn ass_to_vtt(ass: &SSA) -> String {
+    let mut vtt = "WEBVTT\n\n".to_string();
+    for cue in ass_to_cues(ass) {
+        vtt.push_str(&format!(
+            "{} --> {}\n{}\n\n",
+            format_millis(cue.start, '.'),
+            format_millis(cue.end, '.'),
+            cue.text
+                .replace('&', "&amp;")
+                .replace('<', "&lt;")
+                .replace('>', "&gt;")
+        ))
+    }
+    vtt
+}
+
+/// Parses a charset label like `shift_jis` or `windows-1252` (as defined by the WHATWG encoding
+/// standard).
+pub fn parse_subtitle_encoding(s: &str) -> Result<&'static Encoding, String> {
+    let Some(encoding) = Encoding::for_label(s.trim().as_bytes()) else {
+        return Err(format!("'{}' is not a valid charset", s));
+    };
+    // utf-16 can only be decoded by encoding_rs, it gets encoded as utf-8 instead
+    if encoding.output_encoding() != encoding {
+        return Err(format!(
+            "Subtitles can not be encoded as {}",
+            encoding.name()
+        ));
+    }
+    Ok(encoding)
+}
+
+/// Encodes `content` with `encoding`. Characters which can't be represented in the charset are
+/// replaced by `?` and returned (without duplicates).
+pub fn encode_subtitle(content: &str, encoding: &'static Encoding) -> (Vec<u8>, Vec<char>) {
+    let mut encoder = encoding.new_encoder();
+    let mut buf = vec![
+        0u8;
+        encoder
+            .max_buffer_length_from_utf8_without_replacement(content.len())
+            .unwrap_or(content.len() * 4)
+            .max(16)
+    ];
+    let mut encoded = Vec::with_capacity(content.len());
+    let mut unmappable = vec![];
+    let mut src = content;
+    loop {
+        let (result, read, written) =
+            encoder.encode_from_utf8_without_replacement(src, &mut buf, true);
+        encoded.extend_from_slice(&buf[..written]);
+        src = &src[read..];
+        match result {
+            EncoderResult::InputEmpty => break,
+            EncoderResult::OutputFull => (),
+            EncoderResult::Unmappable(c) => {
+                // the encoder is always in its ascii state if it reports an unmappable character
+                encoded.push(b'?');
+                if !unmappable.contains(&c) {
+                    unmappable.push(c)
+                }
+            }
+        }
+    }
+    (encoded, unmappable)
+}
+
+fn time_to_millis(time: Time) -> u64 {
+    time.hour() as u64 * 3_600_000
+        + time.minute() as u64 * 60_000
+        + time.second() as u64 * 1000
+        + time.millisecond() as u64
+}
+
+fn millis_to_time(millis: u64) -> Option<Time> {
+    Time::from_hms_milli(
+        (millis / 3_600_000) as u8,
+        ((millis / 60_000) % 60) as u8,
+        ((millis / 1000) % 60) as u8,
+        (millis % 1000) as u16,
+    )
+    .ok()
+}
+
+fn format_millis(millis: u64, separator: char) -> String {
+    format!(
+        "{:02}:{:02}:{:02}{}{:03}",
+        millis / 3_600_000,
+        (millis / 60_000) % 60,
+        (millis / 1000) % 60,
+        separator,
+        millis % 1000
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_ASS: &str = r"[Script Info]
+ScriptType: v4.00+
+PlayResX: 1920
+PlayResY: 1080
+
+[V4+ Styles]
+Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding
+Style: Default,Arial,54,&H00FFFFFF,&H000000FF,&H00000000,&H00000000,0,0,0,0,100,100,0,0,1,2,1,2,60,60,40,1
+
+[Events]
+Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text
+Dialogue: 0,0:00:01.00,0:00:04.00,Default,,0,0,0,,{\i1}First line{\i0}\NSecond line
+Dialogue: 0,0:00:03.00,0:00:05.00,Default,,0,0,0,,Overlapping
+Dialogue: 0,0:00:06.00,0:00:07.50,Default,,0,0,0,,{\p1}m 0 0 l 100 0 100 100{\p0}
+Dialogue: 0,0:00:08.00,0:00:09.00,Default,,0,0,0,,A & B < C
+";
+
+    const MIXED_STYLES_ASS: &str = r"[Script Info]
+ScriptType: v4.00+
+
+[V4+ Styles]
+Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding
+Style: Default,Arial,54,&H00FFFFFF,&H000000FF,&H00000000,&H00000000,0,0,0,0,100,100,0,0,1,2,1,2,60,60,40,1
+Style: Signs_Top,Arial,40,&H00FFFFFF,&H000000FF,&H00000000,&H00000000,0,0,0,0,100,100,0,0,1,2,1,8,60,60,40,1
+Style: OP1,Arial,48,&H00FFFFFF,&H000000FF,&H00000000,&H00000000,0,0,0,0,100,100,0,0,1,2,1,8,60,60,40,1
+Style: Flashback_Italics,Arial,54,&H00FFFFFF,&H000000FF,&H00000000,&H00000000,0,1,0,0,100,100,0,0,1,2,1,2,60,60,40,1
+
+[Events]
+Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text
+Dialogue: 0,0:00:01.00,0:00:03.00,OP1,,0,0,0,,Opening lyrics
+Dialogue: 0,0:00:02.00,0:00:04.00,Default,,0,0,0,,Dialogue
+Dialogue: 0,0:00:05.00,0:00:06.00,Signs_Top,,0,0,0,,A sign
+Dialogue: 0,0:00:07.00,0:00:08.00,Flashback_Italics,,0,0,0,,A flashback
+";
+
+    fn texts(ass: &SSA) -> Vec<&str> {
+        ass.events.iter().map(|e| e.text.as_str()).collect()
+    }
+
+    fn default_sign_styles() -> Vec<String> {
+        DEFAULT_SIGN_STYLES.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_is_style_matching() {
+        let styles = default_sign_styles();
+        for style in [
+            "Signs_Top",
+            "Sign - Top",
+            "OP1",
+            "ED-Romaji",
+            "SongRomaji",
+            "KaraEffect",
+            "TS 2",
+            "title",
+        ] {
+            assert!(is_style_matching(style, &styles), "{}", style)
+        }
+        // only whole words match, not parts of them
+        for style in [
+            "Default",
+            "Main",
+            "Italics",
+            "Flashback_Italics",
+            "Opening",
+            "Edge",
+        ] {
+            assert!(!is_style_matching(style, &styles), "{}", style)
+        }
+        assert!(!is_style_matching("Signs", &[]))
+    }
+
+    #[test]
+    fn test_drop_ass_styles() {
+        let mut ass = SSA::parse(MIXED_STYLES_ASS).unwrap();
+        let styles = ass.styles.len();
+        assert_eq!(drop_ass_styles(&mut ass, &default_sign_styles()), 2);
+        assert_eq!(texts(&ass), ["Dialogue", "A flashback"]);
+        // the header and the timings of the remaining lines are untouched
+        assert_eq!(ass.styles.len(), styles);
+        assert_eq!(timings(&ass), [(2000, 4000), (7000, 8000)]);
+    }
+
+    #[test]
+    fn test_keep_ass_styles() {
+        let mut ass = SSA::parse(MIXED_STYLES_ASS).unwrap();
+        assert_eq!(keep_ass_styles(&mut ass, &default_sign_styles()), 2);
+        assert_eq!(texts(&ass), ["Opening lyrics", "A sign"]);
+        assert_eq!(timings(&ass), [(1000, 3000), (5000, 6000)]);
+    }
+
+    #[test]
+    fn test_subtitle_styles_parse() {
+        assert_eq!(SubtitleStyles::parse("all"), Ok(SubtitleStyles::All));
+        assert_eq!(
+            SubtitleStyles::parse("Dialogue-Only"),
+            Ok(SubtitleStyles::DialogueOnly)
+        );
+        assert!(SubtitleStyles::parse("signs").is_err())
+    }
+
+    fn cue(start: u64, end: u64, text: &str) -> Cue {
+        Cue {
+            start,
+            end,
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_ass_plain_text() {
+        assert_eq!(
+            ass_plain_text(r"{\i1}First line{\i0}\NSecond line"),
+            "First line\nSecond line"
+        );
+        assert_eq!(ass_plain_text(r"{\an8}Top\h line\n"), "Top  line");
+        assert_eq!(ass_plain_text(r"{\fad(200,200)}"), "");
+    }
+
+    #[test]
+    fn test_merge_cues() {
+        assert_eq!(
+            merge_cues(vec![
+                cue(1000, 4000, "first"),
+                cue(3000, 5000, "second"),
+                cue(8000, 9000, "third"),
+            ]),
+            vec![
+                cue(1000, 3000, "first"),
+                cue(3000, 4000, "first\nsecond"),
+                cue(4000, 5000, "second"),
+                cue(8000, 9000, "third"),
+            ]
+        );
+        // nested and unsorted events keep the order of the subtitle
+        assert_eq!(
+            merge_cues(vec![cue(2000, 3000, "inner"), cue(1000, 4000, "outer")]),
+            vec![
+                cue(1000, 2000, "outer"),
+                cue(2000, 3000, "inner\nouter"),
+                cue(3000, 4000, "outer"),
+            ]
+        );
+        // adjoining events with the same text are joined
+        assert_eq!(
+            merge_cues(vec![cue(0, 1000, "same"), cue(1000, 2000, "same")]),
+            vec![cue(0, 2000, "same")]
+        );
+        assert!(merge_cues(vec![]).is_empty());
+    }
+
+    fn timings(ass: &SSA) -> Vec<(u64, u64)> {
+        ass.events
+            .iter()
+            .map(|e| (time_to_millis(e.start), time_to_millis(e.end)))
+            .collect()
+    }
+
+    #[test]
+    fn test_shift_ass() {
+        let mut ass = SSA::parse(SAMPLE_ASS).unwrap();
+        shift_ass(&mut ass, 250);
+        assert_eq!(
+            timings(&ass),
+            [(1250, 4250), (3250, 5250), (6250, 7750), (8250, 9250)]
+        );
+
+        let mut ass = SSA::parse(SAMPLE_ASS).unwrap();
+        shift_ass(&mut ass, -3500);
+        // the starts of the first two events are clamped to zero
+        assert_eq!(
+            timings(&ass),
+            [(0, 500), (0, 1500), (2500, 4000), (4500, 5500)]
+        );
+
+        let mut ass = SSA::parse(SAMPLE_ASS).unwrap();
+        shift_ass(&mut ass, -4000);
+        // the first event would end before the video starts and is removed
+        assert_eq!(timings(&ass), [(0, 1000), (2000, 3500), (4000, 5000)]);
+    }
+
+    #[test]
+    fn test_subtitle_offset() {
+        let offsets = vec![
+            SubtitleOffset::parse("-250").unwrap(),
+            SubtitleOffset::parse("de-DE=500").unwrap(),
+        ];
+        assert_eq!(offsets[0].locale, None);
+        assert_eq!(offsets[1].locale, Some(Locale::de_DE));
+        assert_eq!(SubtitleOffset::for_locale(&offsets, &Locale::de_DE), 500);
+        assert_eq!(SubtitleOffset::for_locale(&offsets, &Locale::en_US), -250);
+        assert_eq!(SubtitleOffset::for_locale(&offsets[1..], &Locale::en_US), 0);
+
+        assert!(SubtitleOffset::parse("1.5s").is_err());
+        assert!(SubtitleOffset::parse("de-DE=").is_err());
+    }
+
+    #[test]
+    fn test_ass_to_srt() {
+        let ass = SSA::parse(SAMPLE_ASS).unwrap();
+        assert_eq!(
+            ass_to_srt(&ass),
+            "1\n00:00:01,000 --> 00:00:03,000\nFirst line\nSecond line\n\n\
+            2\n00:00:03,000 --> 00:00:04,000\nFirst line\nSecond line\nOverlapping\n\n\
+            3\n00:00:04,000 --> 00:00:05,000\nOverlapping\n\n\
+            4\n00:00:08,000 --> 00:00:09,000\nA & B < C\n\n"
+        )
+    }
+
+    #[test]
+    fn test_ass_to_vtt() {
+        let ass = SSA::parse(SAMPLE_ASS).unwrap();
+        let vtt = ass_to_vtt(&ass);
+        assert!(
+            vtt.starts_with("WEBVTT\n\n00:00:01.000 --> 00:00:03.000\nFirst line\nSecond line\n\n")
+        );
+        assert!(vtt.ends_with("00:00:08.000 --> 00:00:09.000\nA &amp; B &lt; C\n\n"));
+    }
+}
@@ -0,0 +1,88 @@
+use crate::utils::log::summary;
+use crate::utils::timing::Phase;
+use crate::utils::webhook;
+use log::{log_enabled, Level};
+use std::time::Instant;
+
+/// Counts the files processed by a command. The summary is reported when it gets dropped, so it's
+/// also shown if the command is aborted because of an error.
+pub struct Summary {
+    start: Instant,
+    downloaded: usize,
+    skipped: usize,
+    /// Titles of the episodes which were skipped because of `--strict-locale`.
+    locale_mismatches: Vec<String>,
+}
+
+impl Default for Summary {
+    fn default() -> Self {
+        Self {
+            start: Instant::now(),
+            downloaded: 0,
+            skipped: 0,
+            locale_mismatches: vec![],
+        }
+    }
+}
+
+impl Drop for Summary {
+    fn drop(&mut self) {
+        webhook::summary(self.downloaded, self.skipped, self.start.elapsed());
+        summary!(
+            "Downloaded {} file(s), skipped {} file(s) in {}",
+            self.downloaded,
+            self.skipped,
+            fmt_secs(self.start.elapsed().as_secs())
+        );
+        if !self.locale_mismatches.is_empty() {
+            summary!(
+                "Skipped {} episode(s) which aren't available with the requested locales: {}",
+                self.locale_mismatches.len(),
+                self.locale_mismatches.join(", ")
+            )
+        }
+        // the time per phase is only interesting when tuning the settings, so it's only shown
+        // with debug output (`-v`)
+        if log_enabled!(Level::Debug) {
+            let totals = Phase::totals();
+            if !totals.is_empty() {
+                summary!(
+                    "Time spent per phase: {}",
+                    totals
+                        .into_iter()
+                        .map(|(phase, duration)| format!(
+                            "{} {}",
+                            phase,
+                            fmt_secs(duration.as_secs())
+                        ))
+                        .collect::<Vec<String>>()
+                        .join(", ")
+                )
+            }
+        }
+    }
+}
+
+impl Summary {
+    pub fn downloaded(&mut self) {
+        self.downloaded += 1
+    }
+
+    pub fn skipped(&mut self) {
+        self.skipped += 1
+    }
+
+    pub fn locale_mismatch(&mut self, title: String) {
+        self.locale_mismatches.push(title)
+    }
+}
+
+fn fmt_secs(secs: u64) -> String {
+    if secs >= 3600 {
+        format!("{}h {}m {}s", secs / 3600, (secs / 60) % 60, secs % 60)
+    } else if secs >= 60 {
+        format!("{}m {}s", secs / 60, secs % 60)
+    } else {
+        format!("{}s", secs)
+    }
+}
@@ -0,0 +1,92 @@
+use crate::utils::event::{CliEventSink, EventSink};
+use log::{error, info};
+use std::fmt::Display;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// The stages of a test run (`--test-run`), in the order they're run.
+const STAGES: [&str; 5] = ["login", "selection", "streams", "segments", "mux"];
+
+/// The number of segments which are downloaded of every stream in a test run.
+pub const TEST_RUN_SEGMENTS: usize = 3;
+
+/// The number of stages which passed so far, `None` if no test run is active.
+static PASSED: Mutex<Option<usize>> = Mutex::new(None);
+
+/// Starts a test run. Without an active test run, reporting stages has no effect.
+pub fn start() {
+    *PASSED.lock().unwrap() = Some(0)
+}
+
+/// Reports `stage` as passed. Stages are passed in the order of [`STAGES`], reporting any other
+/// than the next stage has no effect.
+pub fn pass(stage: &str, detail: impl Display) {
+    let mut passed = PASSED.lock().unwrap();
+    let Some(passed) = passed.as_mut() else {
+        return;
+    };
+    if STAGES.get(*passed) != Some(&stage) {
+        return;
+    }
+    *passed += 1;
+    info!("Test run stage '{}' passed: {}", stage, detail)
+}
+
+/// Reports the stage which was running when the test run failed with `error`, all following
+/// stages are reported as skipped.
+pub fn fail(error: &anyhow::Error) {
+    let Some(passed) = *PASSED.lock().unwrap() else {
+        return;
+    };
+    let failed = passed.min(STAGES.len() - 1);
+    error!("Test run stage '{}' failed: {}", STAGES[failed], error);
+    for stage in &STAGES[failed + 1..] {
+        info!("Test run stage '{}' skipped", stage)
+    }
+}
+
+/// Forwards all events to a [`CliEventSink`] and reports the download and mux stages of a test
+/// run.
+#[derive(Default)]
+pub struct TestRunEventSink {
+    inner: CliEventSink,
+    streams: AtomicUsize,
+}
+
+impl EventSink for TestRunEventSink {
+    fn segments_started(&self, message: &str, estimated_size: u64) {
+        self.inner.segments_started(message, estimated_size)
+    }
+
+    fn segment_progress(&self, downloaded: u64, estimated_size: u64) {
+        self.inner.segment_progress(downloaded, estimated_size)
+    }
+
+    fn segments_finished(&self) {
+        self.inner.segments_finished();
+        self.streams.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn mux_started(&self, _dst: &Path) {
+        pass(
+            "segments",
+            format!(
+                "downloaded the first {} segments of {} stream(s)",
+                TEST_RUN_SEGMENTS,
+                self.streams.load(Ordering::Relaxed)
+            ),
+        )
+    }
+
+    fn episode_finished(&self, dst: &Path) {
+        self.inner.episode_finished(dst);
+        pass(
+            "mux",
+            format!(
+                "muxed into a temporary .{} file",
+                dst.extension().unwrap_or_default().to_string_lossy()
+            ),
+        )
+    }
+}
@@ -0,0 +1,84 @@
+use log::debug;
+use std::fmt::{Display, Formatter};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Total time spent in every phase, in the order of [`Phase::ALL`].
+static TOTALS: Mutex<[Duration; 4]> = Mutex::new([Duration::ZERO; 4]);
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Phase {
+    /// Logging in / creating the session.
+    Auth,
+    /// Fetching series, season, episode and stream metadata.
+    Metadata,
+    /// Downloading the segments of all streams (and subtitles).
+    Download,
+    /// Muxing the downloaded streams with ffmpeg.
+    Mux,
+}
+
+impl Display for Phase {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let value = match self {
+            Phase::Auth => "auth",
+            Phase::Metadata => "metadata",
+            Phase::Download => "download",
+            Phase::Mux => "mux",
+        };
+        write!(f, "{}", value)
+    }
+}
+
+impl Phase {
+    pub const ALL: [Phase; 4] = [Phase::Auth, Phase::Metadata, Phase::Download, Phase::Mux];
+
+    fn index(&self) -> usize {
+        Phase::ALL.iter().position(|p| p == self).unwrap()
+    }
+
+    /// Starts timing the phase. `context` describes what the phase is run for (e.g. the output
+    /// file) and is part of the log message.
+    pub fn start(self, context: impl Display) -> PhaseTimer {
+        PhaseTimer {
+            phase: self,
+            context: context.to_string(),
+            start: Instant::now(),
+        }
+    }
+
+    /// The time spent in each phase so far. Phases which never ran are omitted.
+    pub fn totals() -> Vec<(Phase, Duration)> {
+        let totals = TOTALS.lock().unwrap();
+        Phase::ALL
+            .into_iter()
+            .map(|p| (p, totals[p.index()]))
+            .filter(|(_, d)| !d.is_zero())
+            .collect()
+    }
+}
+
+/// Measures a single run of a phase. The duration is logged and added to the totals when the
+/// timer is stopped or dropped, so phases which fail are measured too.
+pub struct PhaseTimer {
+    phase: Phase,
+    context: String,
+    start: Instant,
+}
+
+impl PhaseTimer {
+    pub fn stop(self) {}
+}
+
+impl Drop for PhaseTimer {
+    fn drop(&mut self) {
+        let elapsed = self.start.elapsed();
+        debug!(
+            "Phase {} of {} took {:.2}s",
+            self.phase,
+            self.context,
+            elapsed.as_secs_f64()
+        );
+        TOTALS.lock().unwrap()[self.phase.index()] += elapsed
+    }
+}
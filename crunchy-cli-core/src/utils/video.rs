@@ -1,10 +1,15 @@
+use crate::utils::format::SingleFormat;
 use anyhow::{bail, Result};
 use crunchyroll_rs::media::{Resolution, Stream, StreamData};
 use crunchyroll_rs::Locale;
+use log::{debug, warn};
 
 pub async fn stream_data_from_stream(
     stream: &Stream,
     resolution: &Resolution,
+    min_resolution: Option<&Resolution>,
+    max_resolution: Option<&Resolution>,
+    bitrate: Option<u64>,
     hardsub_subtitle: Option<Locale>,
 ) -> Result<Option<(StreamData, StreamData, bool)>> {
     let (hardsub_locale, mut contains_hardsub) = if hardsub_subtitle.is_some() {
@@ -35,6 +40,98 @@ pub async fn stream_data_from_stream(
     videos.sort_by(|a, b| a.bandwidth.cmp(&b.bandwidth).reverse());
     audios.sort_by(|a, b| a.bandwidth.cmp(&b.bandwidth).reverse());
 
+    debug!(
+        "Available video variants: {}",
+        videos
+            .iter()
+            .map(|v| format!(
+                "{} ({} kbps)",
+                v.resolution()
+                    .map_or("unknown".to_string(), |r| r.to_string()),
+                v.bandwidth / 1000
+            ))
+            .collect::<Vec<String>>()
+            .join(", ")
+    );
+
+    // variants which exceed the maximum dimensions are removed before any other selection, so
+    // that e.g. 'best' chooses the largest variant within the bounds
+    if let Some(max_resolution) = max_resolution {
+        videos = match cap_variants(videos, max_resolution, |v| v.resolution(), |v| v.bandwidth) {
+            Ok(fitting) => fitting,
+            Err(smallest) => {
+                warn!(
+                    "No video variant within {} available, using the smallest available variant ({})",
+                    fmt_max_resolution(max_resolution),
+                    smallest
+                        .resolution()
+                        .map_or("unknown".to_string(), |r| r.to_string())
+                );
+                vec![smallest]
+            }
+        }
+    }
+
+    // a requested bitrate takes precedence over the resolution
+    if let Some(bitrate) = bitrate {
+        let video_variant = videos
+            .into_iter()
+            .min_by_key(|v| v.bandwidth.abs_diff(bitrate.saturating_mul(1000)))
+            .unwrap();
+        debug!(
+            "Selected video variant with {} kbps ({}) as closest match to {} kbps",
+            video_variant.bandwidth / 1000,
+            video_variant
+                .resolution()
+                .map_or("unknown".to_string(), |r| r.to_string()),
+            bitrate
+        );
+        return Ok(Some((
+            video_variant,
+            audios.first().unwrap().clone(),
+            contains_hardsub,
+        )));
+    }
+
+    // a minimum resolution takes precedence over 'best' and 'worst' (exact resolutions are
+    // rejected when parsing the args)
+    if let Some(min_resolution) = min_resolution {
+        // the videos are sorted by their bandwidth from high to low, so the last matching video is
+        // the lowest one meeting the minimum
+        let video_variant = match videos.iter().rposition(|v| {
+            v.resolution()
+                .is_some_and(|r| r.height >= min_resolution.height)
+        }) {
+            Some(pos) => {
+                let video_variant = videos.remove(pos);
+                debug!(
+                    "Selected video variant {} as lowest variant with at least {}p",
+                    video_variant
+                        .resolution()
+                        .map_or("unknown".to_string(), |r| r.to_string()),
+                    min_resolution.height
+                );
+                video_variant
+            }
+            None => {
+                let video_variant = videos.remove(0);
+                warn!(
+                    "No video variant with at least {}p available, using the best available variant ({})",
+                    min_resolution.height,
+                    video_variant
+                        .resolution()
+                        .map_or("unknown".to_string(), |r| r.to_string())
+                );
+                video_variant
+            }
+        };
+        return Ok(Some((
+            video_variant,
+            audios.first().unwrap().clone(),
+            contains_hardsub,
+        )));
+    }
+
     let video_variant = match resolution.height {
         u64::MAX => Some(videos.into_iter().next().unwrap()),
         u64::MIN => Some(videos.into_iter().last().unwrap()),
@@ -42,5 +139,205 @@ pub async fn stream_data_from_stream(
             .into_iter()
             .find(|v| resolution.height == v.resolution().unwrap().height),
     };
+    if let (Some(max_resolution), Some(video_variant)) = (max_resolution, &video_variant) {
+        debug!(
+            "Selected video variant {} within {}",
+            video_variant
+                .resolution()
+                .map_or("unknown".to_string(), |r| r.to_string()),
+            fmt_max_resolution(max_resolution)
+        )
+    }
     Ok(video_variant.map(|v| (v, audios.first().unwrap().clone(), contains_hardsub)))
 }
+
+/// Prints all video variants of `single_format` (for `--list`). The bitrate column contains the
+/// value which selects the variant with `--bitrate`.
+pub async fn list_variants(single_format: &SingleFormat) -> Result<()> {
+    let stream = single_format.stream().await?;
+    let (mut videos, _) = stream.stream_data(None).await?.unwrap();
+    stream.invalidate().await?;
+    videos.sort_by(|a, b| a.bandwidth.cmp(&b.bandwidth).reverse());
+
+    println!(
+        "{} [{}] (S{:02}E{})",
+        single_format.title,
+        single_format.audio,
+        single_format.season_number,
+        single_format.episode_number
+    );
+    println!(
+        "  {:<12} {:>14}  {}",
+        "RESOLUTION", "BITRATE (KBPS)", "CODECS"
+    );
+    for video in videos {
+        println!(
+            "  {:<12} {:>14}  {}",
+            video
+                .resolution()
+                .map_or("unknown".to_string(), |r| r.to_string()),
+            video.bandwidth / 1000,
+            video.codecs
+        )
+    }
+    Ok(())
+}
+
+/// Removes all variants which exceed `max_resolution` and sorts the remaining ones from the largest
+/// to the smallest. The variants with the most pixels are the largest ones, the bandwidth only
+/// decides between variants with the same dimensions. If no variant fits, the smallest one is
+/// returned as error. `variants` must be sorted by bandwidth from high to low.
+fn cap_variants<T>(
+    variants: Vec<T>,
+    max_resolution: &Resolution,
+    resolution: impl Fn(&T) -> Option<Resolution>,
+    bandwidth: impl Fn(&T) -> u64,
+) -> Result<Vec<T>, T> {
+    let (mut fitting, exceeding): (Vec<T>, Vec<T>) = variants.into_iter().partition(|v| {
+        resolution(v)
+            .is_some_and(|r| r.width <= max_resolution.width && r.height <= max_resolution.height)
+    });
+    if fitting.is_empty() {
+        // the exceeding variants are sorted from high to low, the last one is the smallest
+        if let Some(smallest) = exceeding.into_iter().last() {
+            return Err(smallest);
+        }
+    }
+    fitting.sort_by_key(|v| {
+        std::cmp::Reverse((
+            resolution(v).map_or(0, |r| r.width * r.height),
+            bandwidth(v),
+        ))
+    });
+    Ok(fitting)
+}
+
+/// Builds the maximum resolution for [`stream_data_from_stream`] from `--max-width` and
+/// `--max-height`. A missing bound is unlimited.
+pub fn max_resolution(width: Option<u64>, height: Option<u64>) -> Option<Resolution> {
+    (width.is_some() || height.is_some()).then(|| Resolution {
+        width: width.unwrap_or(u64::MAX),
+        height: height.unwrap_or(u64::MAX),
+    })
+}
+
+fn fmt_max_resolution(max_resolution: &Resolution) -> String {
+    match (max_resolution.width, max_resolution.height) {
+        (u64::MAX, height) => format!("a height of {}px", height),
+        (width, u64::MAX) => format!("a width of {}px", width),
+        (width, height) => format!("{}x{}", width, height),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Width, height and bandwidth of a video variant.
+    type Variant = (u64, u64, u64);
+
+    fn cap(
+        variants: &[Variant],
+        width: Option<u64>,
+        height: Option<u64>,
+    ) -> Result<Vec<Variant>, Variant> {
+        let mut variants = variants.to_vec();
+        variants.sort_by_key(|v| std::cmp::Reverse(v.2));
+        cap_variants(
+            variants,
+            &max_resolution(width, height).unwrap(),
+            |v| {
+                Some(Resolution {
+                    width: v.0,
+                    height: v.1,
+                })
+            },
+            |v| v.2,
+        )
+    }
+
+    #[test]
+    fn test_cap_variants_16_9() {
+        let variants = [
+            (1920, 1080, 8000),
+            (1280, 720, 4000),
+            (640, 360, 1000),
+            (3840, 2160, 16000),
+        ];
+        assert_eq!(
+            cap(&variants, None, Some(1080)),
+            Ok(vec![
+                (1920, 1080, 8000),
+                (1280, 720, 4000),
+                (640, 360, 1000)
+            ])
+        );
+        assert_eq!(
+            cap(&variants, Some(1280), None),
+            Ok(vec![(1280, 720, 4000), (640, 360, 1000)])
+        );
+        assert_eq!(
+            cap(&variants, Some(1920), Some(720)),
+            Ok(vec![(1280, 720, 4000), (640, 360, 1000)])
+        );
+        // nothing fits, the smallest variant is used
+        assert_eq!(cap(&variants, None, Some(240)), Err((640, 360, 1000)));
+        assert_eq!(cap(&[], None, Some(240)), Ok(vec![]));
+    }
+
+    #[test]
+    fn test_cap_variants_aspect_ratios() {
+        let variants = [
+            // ultrawide
+            (2560, 1080, 7000),
+            (1920, 1080, 8000),
+            // 4:3
+            (1440, 1080, 6000),
+            (960, 720, 3000),
+        ];
+        // the largest variant within a height bound is the widest one, even if another variant
+        // has a higher bandwidth
+        assert_eq!(
+            cap(&variants, None, Some(1080)).unwrap().first(),
+            Some(&(2560, 1080, 7000))
+        );
+        assert_eq!(
+            cap(&variants, Some(1920), Some(1080)),
+            Ok(vec![
+                (1920, 1080, 8000),
+                (1440, 1080, 6000),
+                (960, 720, 3000)
+            ])
+        );
+        assert_eq!(
+            cap(&variants, Some(1500), None),
+            Ok(vec![(1440, 1080, 6000), (960, 720, 3000)])
+        );
+    }
+
+    #[test]
+    fn test_cap_variants_same_dimensions() {
+        // variants with the same dimensions are sorted by bandwidth
+        let variants = [(1920, 1080, 5000), (1920, 1080, 8000), (1280, 720, 4000)];
+        assert_eq!(
+            cap(&variants, None, Some(1080)),
+            Ok(vec![
+                (1920, 1080, 8000),
+                (1920, 1080, 5000),
+                (1280, 720, 4000)
+            ])
+        );
+    }
+
+    #[test]
+    fn test_max_resolution() {
+        assert!(max_resolution(None, None).is_none());
+        let max = max_resolution(None, Some(720)).unwrap();
+        assert_eq!((max.width, max.height), (u64::MAX, 720));
+        assert_eq!(fmt_max_resolution(&max), "a height of 720px");
+        assert_eq!(
+            fmt_max_resolution(&max_resolution(Some(1920), Some(1080)).unwrap()),
+            "1920x1080"
+        );
+    }
+}
@@ -0,0 +1,280 @@
+use crate::utils::format::SingleFormat;
+use chrono::Utc;
+use log::{debug, warn};
+use reqwest::header::CONTENT_TYPE;
+use reqwest::Client;
+use serde::Serialize;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::mpsc::{self, UnboundedSender};
+use tokio::task::JoinHandle;
+
+/// How often a failed webhook request is retried before the event is dropped.
+const RETRIES: u32 = 3;
+/// How long the end of the run waits for the pending events to be sent.
+const FINISH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Which events are sent to the webhook.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) enum WebhookOn {
+    Complete,
+    Fail,
+    All,
+}
+
+impl WebhookOn {
+    pub(crate) fn parse(s: &str) -> Result<Self, String> {
+        match s.to_lowercase().as_str() {
+            "complete" => Ok(Self::Complete),
+            "fail" => Ok(Self::Fail),
+            "all" => Ok(Self::All),
+            _ => Err(format!("invalid webhook event filter '{}'", s)),
+        }
+    }
+
+    fn matches(&self, failure: bool) -> bool {
+        match self {
+            WebhookOn::Complete => !failure,
+            WebhookOn::Fail => failure,
+            WebhookOn::All => true,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct EpisodeInfo {
+    id: String,
+    title: String,
+    series_name: String,
+    season_number: u32,
+    episode_number: String,
+    url: String,
+}
+
+impl From<&SingleFormat> for EpisodeInfo {
+    fn from(value: &SingleFormat) -> Self {
+        Self {
+            id: value.episode_id.clone(),
+            title: value.title.clone(),
+            series_name: value.series_name.clone(),
+            season_number: value.season_number,
+            episode_number: value.episode_number.clone(),
+            url: value.url(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct RunSummary {
+    downloaded: usize,
+    skipped: usize,
+    failed: usize,
+    duration_secs: u64,
+}
+
+/// The json body of every webhook request. Fields which don't apply to an event are omitted. The
+/// schema is documented in the README, keep it in sync when changing this.
+#[derive(Serialize)]
+struct Payload {
+    /// `episode_completed`, `episode_failed`, `run_completed` or `run_failed`.
+    event: &'static str,
+    timestamp: String,
+    command: String,
+    /// A human readable description of the event. Discord shows this field as message.
+    content: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    episode: Option<EpisodeInfo>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    paths: Vec<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    summary: Option<RunSummary>,
+}
+
+struct Webhook {
+    on: WebhookOn,
+    command: String,
+    sender: UnboundedSender<Payload>,
+    worker: JoinHandle<()>,
+    failed: usize,
+    /// Downloaded files, skipped files and the duration of the run, set when the summary of the
+    /// command is reported.
+    summary: Option<(usize, usize, Duration)>,
+}
+
+impl Webhook {
+    fn payload(&self, event: &'static str, failure: bool, content: String) -> Option<Payload> {
+        self.on.matches(failure).then(|| Payload {
+            event,
+            timestamp: Utc::now().to_rfc3339(),
+            command: self.command.clone(),
+            content,
+            episode: None,
+            paths: vec![],
+            error: None,
+            summary: None,
+        })
+    }
+}
+
+/// The webhook of the running command, `None` if no webhook is used.
+static WEBHOOK: Mutex<Option<Webhook>> = Mutex::new(None);
+
+/// Starts sending events of `command` to `url`. Events are sent one after another in the
+/// background, so a slow or unreachable webhook never blocks (or fails) the download.
+pub fn start(client: Client, url: String, on: WebhookOn, command: &str) {
+    let (sender, mut receiver) = mpsc::unbounded_channel::<Payload>();
+    let worker = tokio::spawn(async move {
+        while let Some(payload) = receiver.recv().await {
+            post(&client, &url, &payload).await
+        }
+    });
+    *WEBHOOK.lock().unwrap() = Some(Webhook {
+        on,
+        command: command.to_string(),
+        sender,
+        worker,
+        failed: 0,
+        summary: None,
+    })
+}
+
+async fn post(client: &Client, url: &str, payload: &Payload) {
+    let body = match serde_json::to_vec(payload) {
+        Ok(body) => body,
+        Err(e) => {
+            warn!(
+                "Failed to serialize webhook event '{}': {}",
+                payload.event, e
+            );
+            return;
+        }
+    };
+
+    for retry in 0..=RETRIES {
+        let result = client
+            .post(url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(body.clone())
+            .send()
+            .await
+            .and_then(|r| r.error_for_status());
+        match result {
+            Ok(_) => {
+                debug!("Sent webhook event '{}'", payload.event);
+                return;
+            }
+            Err(e) if retry < RETRIES => {
+                debug!(
+                    "Failed to send webhook event '{}', retrying: {}",
+                    payload.event, e
+                );
+                tokio::time::sleep(Duration::from_secs(2u64.pow(retry))).await
+            }
+            Err(e) => warn!(
+                "Failed to send webhook event '{}' (tried {} times): {}",
+                payload.event,
+                RETRIES + 1,
+                e
+            ),
+        }
+    }
+}
+
+/// Reports that `single_format` was downloaded to `paths`.
+pub fn episode_completed(single_format: &SingleFormat, paths: Vec<PathBuf>) {
+    let webhook = WEBHOOK.lock().unwrap();
+    let Some(webhook) = webhook.as_ref() else {
+        return;
+    };
+    let content = format!(
+        "Downloaded {} ({} S{}E{})",
+        single_format.title,
+        single_format.series_name,
+        single_format.season_number,
+        single_format.episode_number
+    );
+    if let Some(mut payload) = webhook.payload("episode_completed", false, content) {
+        payload.episode = Some(single_format.into());
+        payload.paths = paths;
+        let _ = webhook.sender.send(payload);
+    }
+}
+
+/// Reports that the download of `single_format` failed with `error`.
+pub fn episode_failed(single_format: &SingleFormat, error: &anyhow::Error) {
+    let mut webhook = WEBHOOK.lock().unwrap();
+    let Some(webhook) = webhook.as_mut() else {
+        return;
+    };
+    webhook.failed += 1;
+    let content = format!("Failed to download {}: {}", single_format.title, error);
+    if let Some(mut payload) = webhook.payload("episode_failed", true, content) {
+        payload.episode = Some(single_format.into());
+        payload.error = Some(error.to_string());
+        let _ = webhook.sender.send(payload);
+    }
+}
+
+/// Stores the counts of the command summary, they're sent with the run event.
+pub fn summary(downloaded: usize, skipped: usize, duration: Duration) {
+    if let Some(webhook) = WEBHOOK.lock().unwrap().as_mut() {
+        webhook.summary = Some((downloaded, skipped, duration))
+    }
+}
+
+/// Reports the end of the run, failed if `error` is set, and waits until all pending events are
+/// sent, at most [`FINISH_TIMEOUT`].
+pub async fn finish(error: Option<&anyhow::Error>) {
+    let Some(mut webhook) = WEBHOOK.lock().unwrap().take() else {
+        return;
+    };
+
+    let (downloaded, skipped, duration) = webhook.summary.unwrap_or_default();
+    let summary = RunSummary {
+        downloaded,
+        skipped,
+        failed: webhook.failed,
+        duration_secs: duration.as_secs(),
+    };
+    let payload = if let Some(error) = error {
+        webhook
+            .payload(
+                "run_failed",
+                true,
+                format!("{} failed: {}", webhook.command, error),
+            )
+            .map(|mut payload| {
+                payload.error = Some(error.to_string());
+                payload
+            })
+    } else {
+        webhook.payload(
+            "run_completed",
+            false,
+            format!(
+                "{} finished: downloaded {} file(s), skipped {} file(s), {} failed",
+                webhook.command, summary.downloaded, summary.skipped, summary.failed
+            ),
+        )
+    };
+    if let Some(mut payload) = payload {
+        payload.summary = Some(summary);
+        let _ = webhook.sender.send(payload);
+    }
+
+    // closing the channel lets the worker stop after it sent all pending events
+    drop(webhook.sender);
+    if tokio::time::timeout(FINISH_TIMEOUT, &mut webhook.worker)
+        .await
+        .is_err()
+    {
+        webhook.worker.abort();
+        warn!(
+            "Webhook events couldn't be sent within {} seconds, the remaining ones are dropped",
+            FINISH_TIMEOUT.as_secs()
+        )
+    }
+}
@@ -0,0 +1,132 @@
+use crate::utils::context::Context;
+use crate::{Archive, Download, Execute};
+use anyhow::{bail, Result};
+use chrono::{Local, NaiveDate};
+use crunchyroll_rs::Locale;
+use log::{error, info, warn};
+use std::time::Duration;
+
+#[derive(Debug, clap::Parser)]
+#[clap(about = "Periodically check series for new episodes and download them")]
+#[command(arg_required_else_help(true))]
+pub struct Watch {
+    #[arg(help = "Minutes to wait between two checks")]
+    #[arg(long, default_value_t = 60)]
+    #[arg(value_parser = clap::value_parser!(u64).range(1..))]
+    interval: u64,
+
+    #[arg(help = "Check with the archive command instead of the download command")]
+    #[arg(long, default_value_t = false)]
+    archive: bool,
+
+    #[arg(help = "Also download episodes which aired before the watch was started")]
+    #[arg(
+        long_help = "Also download episodes which aired before the watch was started. \
+    By default, only new episodes, which aired on or after the day the watch was started, are downloaded. \
+    An explicit `--since` of the download or archive arguments takes precedence"
+    )]
+    #[arg(long, default_value_t = false)]
+    all_episodes: bool,
+
+    #[arg(help = "Arguments and url(s) of the download or archive command")]
+    #[arg(
+        long_help = "Arguments and url(s) of the download or archive command, e.g. `watch --interval 30 -a de-DE https://...`. \
+    On every check, only episodes which aired since the watch was started are downloaded (see `--all-episodes`). \
+    They're processed like with `--skip-existing`, so episodes which were downloaded by an earlier check are skipped"
+    )]
+    #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+    args: Vec<String>,
+}
+
+impl Execute for Watch {
+    fn pre_check(&mut self) -> Result<()> {
+        // every check parses the arguments again, so they're only validated here
+        self.command()?;
+        Ok(())
+    }
+
+    async fn execute(self, ctx: Context) -> Result<()> {
+        let interval = Duration::from_secs(self.interval.saturating_mul(60));
+        let started = Local::now().date_naive();
+        let mut check = 1u64;
+        loop {
+            info!("Checking for new episodes (check {})", check);
+            // a failed check, e.g. because of a network issue, shouldn't stop the watch. it's
+            // tried again with the next check
+            match self.check(&ctx, started).await {
+                Ok(()) => info!("Finished check {}", check),
+                Err(e) => error!("Check {} failed: {}", check, e),
+            }
+
+            let next_check = Local::now() + interval;
+            info!("Next check at {}", next_check.format("%Y-%m-%d %H:%M:%S"));
+            tokio::time::sleep(interval).await;
+            check += 1;
+        }
+    }
+}
+
+impl Watch {
+    fn command(&self) -> Result<WatchCommand> {
+        let mut command_line = vec![if self.archive { "archive" } else { "download" }.to_string()];
+        command_line.extend(self.args.iter().cloned());
+        let result = if self.archive {
+            Archive::try_parse_args(command_line).map(WatchCommand::Archive)
+        } else {
+            Download::try_parse_args(command_line).map(WatchCommand::Download)
+        };
+        match result {
+            Ok(command) => Ok(command),
+            Err(e) => bail!("Invalid watch arguments: {}", e),
+        }
+    }
+
+    /// The audio locales of the download command, which are preferred by the session when
+    /// fetching episodes. Archive doesn't prefer any audio.
+    pub(crate) fn preferred_audio_locale(&self) -> Option<Vec<Locale>> {
+        match self.command().ok()? {
+            WatchCommand::Download(download) => Some(download.audio),
+            WatchCommand::Archive(_) => None,
+        }
+    }
+
+    /// Runs the command once. It's run unattended, so interactive prompts are disabled, and all
+    /// already downloaded episodes are skipped. Unless `--all-episodes` is set, only episodes
+    /// which aired on or after `started` are downloaded.
+    async fn check(&self, ctx: &Context, started: NaiveDate) -> Result<()> {
+        let since = (!self.all_episodes).then_some(started);
+        // the session may have expired while sleeping. the next request renews it, which is done
+        // here to not fail in the middle of the check
+        if let Err(e) = ctx.crunchy.account().await {
+            warn!("Failed to refresh the session: {}", e)
+        }
+
+        let ctx = Context {
+            crunchy: ctx.crunchy.clone(),
+            client: ctx.client.clone(),
+            rate_limiter: ctx.rate_limiter.clone(),
+            webhook_client: ctx.webhook_client.clone(),
+        };
+        match self.command()? {
+            WatchCommand::Archive(mut archive) => {
+                archive.yes = true;
+                archive.skip_existing = true;
+                archive.since = archive.since.or(since);
+                archive.pre_check()?;
+                archive.execute(ctx).await
+            }
+            WatchCommand::Download(mut download) => {
+                download.yes = true;
+                download.skip_existing = true;
+                download.since = download.since.or(since);
+                download.pre_check()?;
+                download.execute(ctx).await
+            }
+        }
+    }
+}
+
+enum WatchCommand {
+    Archive(Archive),
+    Download(Download),
+}